@@ -0,0 +1,35 @@
+use radient::prelude::*;
+
+// Checkpointed reverse mode against the plain backward sweep
+//
+// x_{k+1} = x_k + h * sin(a * x_k) * y, a long chain whose intermediates `backward` keeps alive
+fn main() {
+    let n = 2000;
+    let (mut plain, x, y) = chain(n);
+    plain.forward();
+    plain.backward();
+    let expected = (plain.get_gradient(x), plain.get_gradient(y));
+
+    for stride in [1, 7, 45, n] {
+        let (mut graph, x, y) = chain(n);
+        let value = graph.backward_checkpointed(stride);
+        let grads = (graph.get_gradient(x), graph.get_gradient(y));
+        println!("stride {:>4}: value = {}, gradient = {:?}", stride, value, grads);
+        assert!((grads.0 - expected.0).abs() < 1e-10 * expected.0.abs().max(1f64));
+        assert!((grads.1 - expected.1).abs() < 1e-10 * expected.1.abs().max(1f64));
+    }
+    println!("backward   : gradient = {:?}", expected);
+}
+
+fn chain(n: usize) -> (Graph<f64>, usize, usize) {
+    let mut graph = Graph::default();
+    let x = graph.var(0.3);
+    let y = graph.var(1.1);
+    let h = 1.0 / n as f64;
+    let mut state = Expr::Symbol(x);
+    for _ in 0..n {
+        state = state.clone() + h * (2.0 * state).sin() * Expr::Symbol(y);
+    }
+    graph.compile(state);
+    (graph, x, y)
+}
@@ -81,7 +81,7 @@ fn main() {
         wx[3..6].copy_from_slice(x);
         wx[6] = y;
         graph.reset();
-        graph.subs_vars(&wx);
+        graph.subs_vars(&wx).unwrap();
         let y_hat = graph.forward();
         let c_hat = if y_hat > 0.5 { 1.0 } else { 0.0 };
         if c_hat == y {
@@ -83,7 +83,7 @@ fn main() {
             matrix(vec![y], 1, 1, Col),
         ];
         graph.reset();
-        graph.subs_vars(&vals);
+        graph.subs_vars(&vals).unwrap();
         let y_hat = graph.forward();
         let c_hat = if y_hat[(0,0)] > 0.5 { 1.0 } else { 0.0 };
         if c_hat == y {
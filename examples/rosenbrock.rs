@@ -0,0 +1,53 @@
+use radient::optim::var_values;
+use radient::prelude::*;
+
+// Every optimizer on the Rosenbrock function
+//
+// f(x, y) = (1 - x)^2 + 100 (y - x^2)^2, minimized at (1, 1) from (-1.2, 1)
+fn main() {
+    let x0 = [-1.2f64, 1.0];
+
+    // First order update rules, stepped on the compiled graph
+    run_steps("Sgd (Nesterov)", Sgd::new(1e-3).with_nesterov(0.9), 20000, 1e-3);
+    run_steps("Adam", Adam::new(1e-2), 20000, 1e-3);
+    run_steps("RmsProp", RmsProp::new(1e-3).with_momentum(0.9), 20000, 1e-3);
+
+    // Quasi-Newton & second order minimizers
+    report("Lbfgs", Lbfgs::new(5).minimize(&mut rosenbrock(&x0)), 1e-6);
+    report("Newton", Newton::new().minimize(&mut rosenbrock(&x0)), 1e-6);
+    report("Newton (CG)", Newton::new().with_cg(10, 1e-10).minimize(&mut rosenbrock(&x0)), 1e-6);
+    report("NewtonCg", NewtonCg::default().minimize(&mut rosenbrock(&x0)), 1e-6);
+    report("TrustRegion", TrustRegion::default().minimize(&mut rosenbrock(&x0)), 1e-6);
+
+    // Least squares form: r = (1 - x, 10 (y - x^2))
+    let result = lm(
+        |x| vec![1.0 - x[0].clone(), 10.0 * (x[1].clone() - x[0].powi(2))],
+        &x0,
+    );
+    report("LevenbergMarquardt", result, 1e-6);
+}
+
+fn rosenbrock(x0: &[f64]) -> Graph<f64> {
+    let mut graph = Graph::default();
+    graph.touch_vars(2);
+    graph.subs_vars(x0).unwrap();
+    let x = graph.get_symbols();
+    graph.compile((1.0 - x[0].clone()).powi(2) + 100.0 * (x[1].clone() - x[0].powi(2)).powi(2));
+    graph
+}
+
+fn run_steps<O: Optimizer>(name: &str, mut opt: O, steps: usize, tol: f64) {
+    let mut graph = rosenbrock(&[-1.2, 1.0]);
+    for _ in 0..steps {
+        opt.step_graph(&mut graph);
+    }
+    let x = var_values(&graph);
+    println!("{:>20}: {:?} after {} steps", name, x, steps);
+    assert!(x.iter().all(|x| (x - 1.0).abs() < tol), "{} did not converge", name);
+}
+
+fn report(name: &str, result: OptimResult, tol: f64) {
+    println!("{:>20}: {:?} after {} iterations", name, result.x, result.iterations);
+    assert!(result.converged, "{} did not converge", name);
+    assert!(result.x.iter().all(|x| (x - 1.0).abs() < tol), "{} missed the minimum", name);
+}
@@ -0,0 +1,40 @@
+use radient::prelude::*;
+
+// HMC & NUTS on a correlated Gaussian
+//
+// log p(x, y) = -(x^2 - 2 ρ x y + y^2) / (2 (1 - ρ^2)), with unit variances & correlation ρ
+fn main() {
+    let rho = 0.8;
+    for (name, chain) in [
+        ("Hmc", Hmc::new(20).sample(&mut gaussian(rho), 2000)),
+        ("Nuts", Nuts::new().sample(&mut gaussian(rho), 2000)),
+    ] {
+        let n = chain.samples.len() as f64;
+        let mean = (0..2)
+            .map(|i| chain.samples.iter().map(|s| s[i]).sum::<f64>() / n)
+            .collect::<Vec<_>>();
+        let var = (0..2)
+            .map(|i| chain.samples.iter().map(|s| (s[i] - mean[i]).powi(2)).sum::<f64>() / n)
+            .collect::<Vec<_>>();
+        let cov = chain.samples.iter().map(|s| (s[0] - mean[0]) * (s[1] - mean[1])).sum::<f64>() / n;
+        let corr = cov / (var[0] * var[1]).sqrt();
+        println!(
+            "{:>4}: mean = {:?}, variance = {:?}, correlation = {:.3}, acceptance = {:.2}",
+            name, mean, var, corr, chain.accept_rate
+        );
+        assert_eq!(chain.divergences, 0);
+        assert!(mean.iter().all(|m| m.abs() < 0.2));
+        assert!(var.iter().all(|v| (v - 1.0).abs() < 0.25));
+        assert!((corr - rho).abs() < 0.1);
+    }
+}
+
+fn gaussian(rho: f64) -> Graph<f64> {
+    let mut graph = Graph::default();
+    graph.touch_vars(2);
+    graph.subs_vars(&[0.5, -0.5]).unwrap();
+    let x = graph.get_symbols();
+    let quad = x[0].powi(2) - 2.0 * rho * x[0].clone() * x[1].clone() + x[1].powi(2);
+    graph.compile(-quad / (2.0 * (1.0 - rho * rho)));
+    graph
+}
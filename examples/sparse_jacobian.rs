@@ -0,0 +1,54 @@
+use radient::prelude::*;
+
+// Compressed Jacobian & Hessian against their dense counterparts
+//
+// F(x) = (x0^3, x0 * x1, x1^2 + exp(x2), x2^4)
+//
+// `reduce_powi` lowers the integer powers into multiply chains appended after their consumers,
+// so the sparsity analysis has to follow the topological order rather than the node indices.
+fn main() {
+    let mut graph = Graph::default();
+    graph.touch_vars(3);
+    let x = graph.get_symbols();
+    graph.compile_many(vec![
+        x[0].powi(3),
+        &x[0] * &x[1],
+        x[1].powi(2) + x[2].exp(),
+        x[2].powi(4),
+    ]);
+    graph.reduce_powi();
+    graph.subs_vars(&[1.5f64, -0.5, 0.25]).unwrap();
+    graph.forward();
+
+    // Jacobian
+    let pattern = graph.jacobian_sparsity();
+    let jac = graph.sparse_jacobian_with(&pattern);
+    let dense = graph.jacobian_rows(&[0, 1, 2, 3]);
+    println!("jacobian pattern: {:?}", pattern.entries());
+    for (i, row) in dense.iter().enumerate() {
+        for (j, &d) in row.iter().enumerate() {
+            assert_eq!(pattern.contains(i, j), d != 0f64, "pattern misses ({}, {})", i, j);
+            assert!((jac.get(i, j) - d).abs() < 1e-12, "J[{}][{}]: {} != {}", i, j, jac.get(i, j), d);
+        }
+    }
+    println!("sparse jacobian: {:?}", jac.to_dense());
+
+    // Hessian of the sum of the outputs
+    let mut graph = Graph::default();
+    graph.touch_vars(3);
+    let x = graph.get_symbols();
+    graph.compile(x[0].powi(3) + &x[0] * &x[1] + x[1].powi(2) + x[2].exp() + x[2].powi(4));
+    graph.reduce_powi();
+    graph.subs_vars(&[1.5f64, -0.5, 0.25]).unwrap();
+    graph.forward();
+
+    let pattern = graph.hessian_sparsity();
+    let hess = graph.sparse_hessian_with(&pattern);
+    let dense = graph.hessian();
+    for (i, row) in dense.iter().enumerate() {
+        for (j, &d) in row.iter().enumerate() {
+            assert!((hess.get(i, j) - d).abs() < 1e-12, "H[{}][{}]: {} != {}", i, j, hess.get(i, j), d);
+        }
+    }
+    println!("sparse hessian: {:?}", hess.to_dense());
+}
@@ -0,0 +1,367 @@
+use crate::core::{Expr, Graph, Node};
+use peroxide_num::{ExpLogOps, Numeric, PowOps, TrigOps};
+use std::cell::RefCell;
+use std::cmp::Ordering;
+use std::ops::{Add, Div, Mul, Neg, Sub};
+use std::rc::Rc;
+
+// ┌──────────────────────────────────────────────────────────┐
+//  Tape-recording scalar
+// └──────────────────────────────────────────────────────────┘
+/// Shared graph on which `Active` scalars record their operations
+#[derive(Clone, Default)]
+pub struct Recorder {
+    graph: Rc<RefCell<Graph<f64>>>,
+}
+
+/// Scalar recording every operation on a shared graph (operator overloading AD)
+///
+/// `Active` implements `Numeric<f64>`, so a generic function `fn f<T: Numeric<f64>>(x: &[T]) -> T`
+/// written for peroxide can be differentiated as it is (see `trace_gradient`). Unlike `Expr`,
+/// every operation is evaluated right away: comparisons (`PartialOrd`) use the current value, so
+/// data-dependent branches work, but the recorded tape only holds the branch taken at that point.
+#[derive(Clone)]
+pub struct Active {
+    recorder: Recorder,
+    index: usize,
+    value: f64,
+}
+
+impl Recorder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// New differentiable variable
+    pub fn var(&self, value: f64) -> Active {
+        let index = self.graph.borrow_mut().var(value);
+        self.active(index, value)
+    }
+
+    pub fn vars(&self, values: &[f64]) -> Vec<Active> {
+        values.iter().map(|x| self.var(*x)).collect()
+    }
+
+    pub fn constant(&self, value: f64) -> Active {
+        self.push(Node::Const(value), value)
+    }
+
+    /// Take the recording out, compiled with `output` as the default output
+    ///
+    /// The graph is an ordinary compiled `Graph`: it can be re-evaluated at other variable values
+    /// (with the branches recorded at the original point). The recorder is left empty.
+    pub fn compile(self, output: &Active) -> Graph<f64> {
+        assert!(Rc::ptr_eq(&self.graph, &output.recorder.graph), "Output recorded on another graph");
+        let mut graph = self.graph.replace(Graph::default());
+        graph.compile(Expr::Symbol(output.index));
+        graph
+    }
+
+    fn active(&self, index: usize, value: f64) -> Active {
+        Active {
+            recorder: self.clone(),
+            index,
+            value,
+        }
+    }
+
+    fn push(&self, node: Node, value: f64) -> Active {
+        let index = self.graph.borrow_mut().push_node(node);
+        self.active(index, value)
+    }
+}
+
+/// Record `f` at `x` and return the compiled graph (variables in the order of `x`)
+pub fn trace<F: Fn(&[Active]) -> Active>(f: F, x: &[f64]) -> Graph<f64> {
+    let recorder = Recorder::new();
+    let vars = recorder.vars(x);
+    let output = f(&vars);
+    let mut graph = recorder.compile(&output);
+    graph.subs_vars_exact(x);
+    graph
+}
+
+/// Value & gradient of a generic numeric function at `x`
+///
+/// E.g. `trace_gradient(rosenbrock, &[1.0, 2.0])` with `fn rosenbrock<T: Numeric<f64>>(x: &[T]) -> T`.
+pub fn trace_gradient<F: Fn(&[Active]) -> Active>(f: F, x: &[f64]) -> (f64, Vec<f64>) {
+    let mut graph = trace(f, x);
+    let value = graph.forward();
+    graph.backward();
+    (value, graph.get_gradients())
+}
+
+impl Active {
+    pub fn value(&self) -> f64 {
+        self.value
+    }
+
+    /// Node of this scalar on the recorder's graph
+    pub fn index(&self) -> usize {
+        self.index
+    }
+
+    pub fn recorder(&self) -> &Recorder {
+        &self.recorder
+    }
+
+    fn unary(&self, node: Node, value: f64) -> Active {
+        self.recorder.push(node, value)
+    }
+
+    fn binary(&self, rhs: &Active, node: Node, value: f64) -> Active {
+        assert!(
+            Rc::ptr_eq(&self.recorder.graph, &rhs.recorder.graph),
+            "Active scalars recorded on different graphs"
+        );
+        self.recorder.push(node, value)
+    }
+
+    fn custom(&self, f: fn(f64) -> f64, df: fn(f64) -> f64) -> Active {
+        let index = self.recorder.graph.borrow_mut().custom_unary(self.index, f, df);
+        self.recorder.active(index, f(self.value))
+    }
+}
+
+impl std::fmt::Debug for Active {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "Active {{ index: {:?}, value: {:?} }}", self.index, self.value)
+    }
+}
+
+impl PartialEq for Active {
+    fn eq(&self, other: &Self) -> bool {
+        self.value == other.value
+    }
+}
+
+impl PartialOrd for Active {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        self.value.partial_cmp(&other.value)
+    }
+}
+
+impl PartialEq<f64> for Active {
+    fn eq(&self, other: &f64) -> bool {
+        self.value == *other
+    }
+}
+
+impl PartialOrd<f64> for Active {
+    fn partial_cmp(&self, other: &f64) -> Option<Ordering> {
+        self.value.partial_cmp(other)
+    }
+}
+
+// ┌──────────────────────────────────────────────────────────┐
+//  Arithmetic
+// └──────────────────────────────────────────────────────────┘
+impl Neg for Active {
+    type Output = Active;
+
+    fn neg(self) -> Self::Output {
+        self.unary(Node::Neg(self.index), -self.value)
+    }
+}
+
+impl Add for Active {
+    type Output = Active;
+
+    fn add(self, rhs: Active) -> Self::Output {
+        self.binary(&rhs, Node::Add(self.index, rhs.index), self.value + rhs.value)
+    }
+}
+
+impl Sub for Active {
+    type Output = Active;
+
+    fn sub(self, rhs: Active) -> Self::Output {
+        self.binary(&rhs, Node::Sub(self.index, rhs.index), self.value - rhs.value)
+    }
+}
+
+impl Mul for Active {
+    type Output = Active;
+
+    fn mul(self, rhs: Active) -> Self::Output {
+        self.binary(&rhs, Node::Mul(self.index, rhs.index), self.value * rhs.value)
+    }
+}
+
+impl Div for Active {
+    type Output = Active;
+
+    fn div(self, rhs: Active) -> Self::Output {
+        self.binary(&rhs, Node::Div(self.index, rhs.index), self.value / rhs.value)
+    }
+}
+
+impl Add<f64> for Active {
+    type Output = Active;
+
+    fn add(self, rhs: f64) -> Self::Output {
+        self.unary(Node::Addf(rhs, self.index), self.value + rhs)
+    }
+}
+
+impl Sub<f64> for Active {
+    type Output = Active;
+
+    fn sub(self, rhs: f64) -> Self::Output {
+        self.unary(Node::Subf(self.index, rhs), self.value - rhs)
+    }
+}
+
+impl Mul<f64> for Active {
+    type Output = Active;
+
+    fn mul(self, rhs: f64) -> Self::Output {
+        self.unary(Node::Mulf(rhs, self.index), self.value * rhs)
+    }
+}
+
+impl Div<f64> for Active {
+    type Output = Active;
+
+    fn div(self, rhs: f64) -> Self::Output {
+        self.unary(Node::Mulf(rhs.recip(), self.index), self.value / rhs)
+    }
+}
+
+impl Add<Active> for f64 {
+    type Output = Active;
+
+    fn add(self, rhs: Active) -> Self::Output {
+        rhs + self
+    }
+}
+
+impl Sub<Active> for f64 {
+    type Output = Active;
+
+    fn sub(self, rhs: Active) -> Self::Output {
+        -rhs + self
+    }
+}
+
+impl Mul<Active> for f64 {
+    type Output = Active;
+
+    fn mul(self, rhs: Active) -> Self::Output {
+        rhs * self
+    }
+}
+
+impl Div<Active> for f64 {
+    type Output = Active;
+
+    fn div(self, rhs: Active) -> Self::Output {
+        let recip = rhs.unary(Node::Recip(rhs.index), rhs.value.recip());
+        recip.unary(Node::Mulf(self, recip.index), self / rhs.value)
+    }
+}
+
+// ┌──────────────────────────────────────────────────────────┐
+//  Elementary functions
+// └──────────────────────────────────────────────────────────┘
+impl PowOps for Active {
+    type Float = f64;
+
+    fn powi(&self, rhs: i32) -> Self {
+        self.unary(Node::Powi(self.index, rhs), self.value.powi(rhs))
+    }
+
+    fn powf(&self, rhs: f64) -> Self {
+        self.unary(Node::Powf(self.index, rhs), self.value.powf(rhs))
+    }
+
+    fn pow(&self, rhs: Self) -> Self {
+        self.binary(&rhs, Node::Pow(self.index, rhs.index), self.value.powf(rhs.value))
+    }
+
+    fn sqrt(&self) -> Self {
+        self.powf(0.5)
+    }
+}
+
+impl ExpLogOps for Active {
+    type Float = f64;
+
+    fn exp(&self) -> Self {
+        self.unary(Node::Exp(self.index), self.value.exp())
+    }
+
+    fn ln(&self) -> Self {
+        self.unary(Node::Ln(self.index), self.value.ln())
+    }
+
+    fn log(&self, base: f64) -> Self {
+        self.ln() / base.ln()
+    }
+
+    fn log2(&self) -> Self {
+        self.log(2f64)
+    }
+
+    fn log10(&self) -> Self {
+        self.log(10f64)
+    }
+}
+
+impl TrigOps for Active {
+    fn sin_cos(&self) -> (Self, Self) {
+        (self.sin(), self.cos())
+    }
+
+    fn sin(&self) -> Self {
+        self.unary(Node::Sin(self.index), self.value.sin())
+    }
+
+    fn cos(&self) -> Self {
+        self.unary(Node::Cos(self.index), self.value.cos())
+    }
+
+    fn tan(&self) -> Self {
+        self.unary(Node::Tan(self.index), self.value.tan())
+    }
+
+    fn sinh(&self) -> Self {
+        self.unary(Node::Sinh(self.index), self.value.sinh())
+    }
+
+    fn cosh(&self) -> Self {
+        self.unary(Node::Cosh(self.index), self.value.cosh())
+    }
+
+    fn tanh(&self) -> Self {
+        self.unary(Node::Tanh(self.index), self.value.tanh())
+    }
+
+    // Inverse functions have no node of their own, so they are recorded as custom ops
+
+    fn asin(&self) -> Self {
+        self.custom(f64::asin, |x| (1f64 - x * x).sqrt().recip())
+    }
+
+    fn acos(&self) -> Self {
+        self.custom(f64::acos, |x| -(1f64 - x * x).sqrt().recip())
+    }
+
+    fn atan(&self) -> Self {
+        self.custom(f64::atan, |x| (1f64 + x * x).recip())
+    }
+
+    fn asinh(&self) -> Self {
+        self.custom(f64::asinh, |x| (x * x + 1f64).sqrt().recip())
+    }
+
+    fn acosh(&self) -> Self {
+        self.custom(f64::acosh, |x| (x * x - 1f64).sqrt().recip())
+    }
+
+    fn atanh(&self) -> Self {
+        self.custom(f64::atanh, |x| (1f64 - x * x).recip())
+    }
+}
+
+impl Numeric<f64> for Active {}
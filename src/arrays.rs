@@ -0,0 +1,54 @@
+use crate::core::Graph;
+use crate::util::gradient_cached;
+use ndarray::{Array1, Array2, ArrayBase, Data, Ix1, Ix2};
+
+// ┌──────────────────────────────────────────────────────────┐
+//  ndarray interop
+// └──────────────────────────────────────────────────────────┘
+/// Inputs are `Array1`/`ArrayView1` (or any 1D array of `f64`) in the order of variables
+/// (same as `get_vars`).
+impl Graph<f64> {
+    /// Value & gradient of the compiled expression at `x`
+    pub fn gradient_array<S: Data<Elem = f64>>(&mut self, x: &ArrayBase<S, Ix1>) -> (f64, Array1<f64>) {
+        let (value, grads) = gradient_cached(self, &x.to_vec());
+        (value, Array1::from(grads))
+    }
+
+    /// Values & Jacobian (`n_outputs × n_vars`) of the outputs of `compile_many` at `x`
+    pub fn jacobian_array<S: Data<Elem = f64>>(&mut self, x: &ArrayBase<S, Ix1>) -> (Array1<f64>, Array2<f64>) {
+        self.reset();
+        self.subs_vars_exact(&x.to_vec());
+        let values = self.forward_all();
+        let n_vars = self.get_vars().len();
+        let rows = (0..values.len())
+            .flat_map(|i| {
+                self.backward_output(i);
+                self.get_gradients()
+            })
+            .collect::<Vec<_>>();
+        let jac = Array2::from_shape_vec((values.len(), n_vars), rows).unwrap();
+        (Array1::from(values), jac)
+    }
+
+    /// Hessian (`n_vars × n_vars`) of the compiled expression at `x` (see `hessian`)
+    pub fn hessian_array<S: Data<Elem = f64>>(&mut self, x: &ArrayBase<S, Ix1>) -> Array2<f64> {
+        self.reset();
+        self.subs_vars_exact(&x.to_vec());
+        let h = self.hessian();
+        let n = h.len();
+        Array2::from_shape_vec((n, n), h.concat()).unwrap()
+    }
+
+    /// Values & gradients at every row of `points` (`n_points × n_vars`)
+    ///
+    /// Row `k` of the returned gradient matrix belongs to row `k` of `points`.
+    pub fn gradient_rows<S: Data<Elem = f64>>(&mut self, points: &ArrayBase<S, Ix2>) -> (Array1<f64>, Array2<f64>) {
+        let (values, grads): (Vec<_>, Vec<_>) = points
+            .rows()
+            .into_iter()
+            .map(|point| gradient_cached(self, &point.to_vec()))
+            .unzip();
+        let grads = Array2::from_shape_vec((points.nrows(), points.ncols()), grads.concat()).unwrap();
+        (Array1::from(values), grads)
+    }
+}
@@ -0,0 +1,178 @@
+use crate::core::{eval_node, propagate_adjoint, Graph, Node};
+use crate::traits::{ActivationFunction, Matrizable};
+use peroxide_num::Numeric;
+use std::collections::{HashMap, HashSet};
+use std::ops::Div;
+use std::sync::Arc;
+
+// ┌──────────────────────────────────────────────────────────┐
+//  Checkpointed reverse mode
+// └──────────────────────────────────────────────────────────┘
+impl<T: std::fmt::Debug + Numeric<f64> + Default + ActivationFunction + Matrizable> Graph<T>
+where
+    f64: Div<T, Output = T>,
+{
+    /// Forward & backward sweeps storing values only at segment boundaries
+    ///
+    /// The topological order is cut into segments of `stride` nodes. The forward sweep keeps only
+    /// checkpoints (values read by a later segment) in a sparse map, and every segment is
+    /// recomputed from them right before its reverse sweep. Adjoints are allocated when first
+    /// reached and dropped once propagated.
+    ///
+    /// Besides the tape and the graph's per-node slots, which stay empty except at leaves and the
+    /// output, at most the checkpoints plus one segment's values and pending adjoints are held. A
+    /// sequential chain of `n` nodes with `stride ≈ √n` therefore holds `O(√n)` values. A value
+    /// read by many segments stays a checkpoint until the end, so wide tapes hold more.
+    ///
+    /// Returns the value of the compiled output. Gradients of variables and parameters are read
+    /// as after `backward`, but adjoints of intermediate nodes are not kept.
+    pub fn backward_checkpointed(&mut self, stride: usize) -> T {
+        let root = self.compiled.unwrap();
+        if self.grad_mask.as_ref().is_none_or(|mask| mask.len() != self.nodes.len()) {
+            self.update_grad_mask();
+        }
+        self.schedule();
+        let order = Arc::clone(self.topological_order.as_ref().unwrap());
+        let stride = stride.max(1);
+
+        // Values which have to survive their own segment
+        let mut boundary = HashSet::new();
+        for chunk in order.chunks(stride) {
+            let inside = chunk.iter().copied().collect::<HashSet<_>>();
+            for &index in chunk {
+                for child_index in self.nodes.node(index).operand_iter() {
+                    if !inside.contains(&child_index) && !self.is_leaf(child_index) {
+                        boundary.insert(child_index);
+                    }
+                }
+            }
+        }
+
+        // Forward sweep keeping only the checkpoints
+        let mut checkpoints = HashMap::new();
+        for chunk in order.chunks(stride) {
+            for (index, value) in self.eval_segment(chunk, &checkpoints) {
+                if boundary.contains(&index) || index == root {
+                    checkpoints.insert(index, value);
+                }
+            }
+        }
+        let value = self.stored(root, &HashMap::new(), &checkpoints).clone();
+
+        // Reverse sweep recomputing each segment from the checkpoints
+        let mask = self.grad_mask.take();
+        let mut adjoints = HashMap::new();
+        adjoints.insert(root, value.ones_like());
+        for chunk in order.chunks(stride).rev() {
+            let local = self.eval_segment(chunk, &checkpoints);
+            for &index in chunk.iter().rev() {
+                if self.is_leaf(index) {
+                    continue;
+                }
+                let Some(gradient) = adjoints.remove(&index) else {
+                    continue;
+                };
+                if mask.as_ref().is_some_and(|mask| !mask[index]) {
+                    continue;
+                }
+                let slots = operand_slots(&self.nodes.node(index));
+                let node = self.nodes.node(index).map_indices(|i| slot_of(&slots, i));
+                let mut buffer = slots
+                    .iter()
+                    .map(|&i| Some(self.stored(i, &local, &checkpoints).clone()))
+                    .collect::<Vec<_>>();
+                let mut gradients = buffer
+                    .iter()
+                    .map(|x| x.as_ref().unwrap().zeros_like())
+                    .collect::<Vec<_>>();
+                buffer.push(Some(local[&index].clone()));
+                gradients.push(gradient);
+                propagate_adjoint(&node, &buffer, &mut gradients, slots.len(), &self.custom_ops);
+                for (&child_index, grad) in slots.iter().zip(gradients) {
+                    let grad = match adjoints.remove(&child_index) {
+                        Some(acc) => acc + grad,
+                        None => grad,
+                    };
+                    adjoints.insert(child_index, grad);
+                }
+            }
+            // Later segments are done, so checkpoints computed here are no longer read
+            for index in chunk {
+                checkpoints.remove(index);
+            }
+        }
+
+        // Only leaves keep a value & an adjoint; pruned leaves get a zero gradient
+        self.invalidate_values();
+        for index in 0..self.nodes.len() {
+            self.gradients[index] = if self.is_leaf(index) {
+                match adjoints.remove(&index) {
+                    Some(grad) if mask.as_ref().is_none_or(|mask| mask[index]) => grad,
+                    _ => self.buffer[index].as_ref().unwrap().zeros_like(),
+                }
+            } else {
+                T::default()
+            };
+        }
+        if !self.is_leaf(root) {
+            self.buffer[root] = Some(value.clone());
+        }
+        self.grad_mask = mask;
+
+        value
+    }
+
+    fn is_leaf(&self, index: usize) -> bool {
+        matches!(self.nodes.node(index), Node::Var(_) | Node::Param(_))
+    }
+
+    /// Value of `index` from the current segment, the checkpoints or the leaves
+    fn stored<'a>(
+        &'a self,
+        index: usize,
+        local: &'a HashMap<usize, T>,
+        checkpoints: &'a HashMap<usize, T>,
+    ) -> &'a T {
+        local
+            .get(&index)
+            .or_else(|| checkpoints.get(&index))
+            .or(self.buffer[index].as_ref())
+            .expect("Value is neither in the segment nor checkpointed")
+    }
+
+    /// Values of the non-leaf nodes of a segment
+    fn eval_segment(&self, chunk: &[usize], checkpoints: &HashMap<usize, T>) -> HashMap<usize, T> {
+        let mut local = HashMap::with_capacity(chunk.len());
+        for &index in chunk {
+            if self.is_leaf(index) {
+                continue;
+            }
+            let slots = operand_slots(&self.nodes.node(index));
+            let node = self.nodes.node(index).map_indices(|i| slot_of(&slots, i));
+            let mut buffer = slots
+                .iter()
+                .map(|&i| Some(self.stored(i, &local, checkpoints).clone()))
+                .collect::<Vec<_>>();
+            buffer.push(None);
+            let value = eval_node(&node, &buffer, slots.len(), &self.custom_ops);
+            local.insert(index, value);
+        }
+        local
+    }
+}
+
+/// Distinct operands of a node; operand `slots[k]` lives in slot `k` of a local buffer whose last
+/// slot is the node itself (argument slots of `External` stay contiguous)
+fn operand_slots(node: &Node) -> Vec<usize> {
+    let mut slots = Vec::new();
+    for index in node.operand_iter() {
+        if !slots.contains(&index) {
+            slots.push(index);
+        }
+    }
+    slots
+}
+
+fn slot_of(slots: &[usize], index: usize) -> usize {
+    slots.iter().position(|&i| i == index).unwrap()
+}
@@ -0,0 +1,236 @@
+use crate::core::{Graph, Node};
+use crate::traits::ActivationFunction;
+use std::ops::{Add, Div, Mul, Neg, Sub};
+use std::sync::Arc;
+
+// ┌──────────────────────────────────────────────────────────┐
+//  Complex evaluation (complex-step derivatives)
+// └──────────────────────────────────────────────────────────┘
+/// Minimal complex number for `Graph::forward_complex`
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct Complex {
+    pub re: f64,
+    pub im: f64,
+}
+
+impl Complex {
+    pub fn new(re: f64, im: f64) -> Self {
+        Complex { re, im }
+    }
+
+    pub fn real(re: f64) -> Self {
+        Complex { re, im: 0f64 }
+    }
+
+    fn norm_sqr(self) -> f64 {
+        self.re * self.re + self.im * self.im
+    }
+
+    fn recip(self) -> Self {
+        let d = self.norm_sqr();
+        Complex::new(self.re / d, -self.im / d)
+    }
+
+    fn exp(self) -> Self {
+        let r = self.re.exp();
+        Complex::new(r * self.im.cos(), r * self.im.sin())
+    }
+
+    fn ln(self) -> Self {
+        Complex::new(0.5 * self.norm_sqr().ln(), self.im.atan2(self.re))
+    }
+
+    fn powi(self, n: i32) -> Self {
+        // Repeated squaring keeps negative real parts exact (no polar form)
+        let mut base = if n < 0 { self.recip() } else { self };
+        let mut k = n.unsigned_abs();
+        let mut result = Complex::real(1f64);
+        while k > 0 {
+            if k & 1 == 1 {
+                result = result * base;
+            }
+            base = base * base;
+            k >>= 1;
+        }
+        result
+    }
+
+    /// `z^w` by `exp(w ln z)`, or by the first order extension for `Re z <= 0`
+    ///
+    /// On the negative real axis `arg z = π - h/|x|` rounds to `π` for a tiny step `h`, so the
+    /// polar form gives a spurious imaginary part of order `|x|^p sin(pπ)`; the extension
+    /// `a^b + i (α b a^(b-1) + β a^b ln a)` of `z = a + iα`, `w = b + iβ` keeps the step exact.
+    fn powc(self, w: Complex) -> Self {
+        if self.re > 0f64 {
+            return (w * self.ln()).exp();
+        }
+        let value = self.re.powf(w.re);
+        let mut im = 0f64;
+        if self.im != 0f64 {
+            im += self.im * w.re * self.re.powf(w.re - 1f64);
+        }
+        if w.im != 0f64 {
+            im += w.im * value * self.re.ln();
+        }
+        Complex::new(value, im)
+    }
+
+    fn sin(self) -> Self {
+        Complex::new(self.re.sin() * self.im.cosh(), self.re.cos() * self.im.sinh())
+    }
+
+    fn cos(self) -> Self {
+        Complex::new(self.re.cos() * self.im.cosh(), -self.re.sin() * self.im.sinh())
+    }
+
+    fn sinh(self) -> Self {
+        Complex::new(self.re.sinh() * self.im.cos(), self.re.cosh() * self.im.sin())
+    }
+
+    fn cosh(self) -> Self {
+        Complex::new(self.re.cosh() * self.im.cos(), self.re.sinh() * self.im.sin())
+    }
+
+    fn scale(self, k: f64) -> Self {
+        Complex::new(self.re * k, self.im * k)
+    }
+}
+
+impl Add for Complex {
+    type Output = Complex;
+
+    fn add(self, rhs: Complex) -> Complex {
+        Complex::new(self.re + rhs.re, self.im + rhs.im)
+    }
+}
+
+impl Sub for Complex {
+    type Output = Complex;
+
+    fn sub(self, rhs: Complex) -> Complex {
+        Complex::new(self.re - rhs.re, self.im - rhs.im)
+    }
+}
+
+impl Mul for Complex {
+    type Output = Complex;
+
+    fn mul(self, rhs: Complex) -> Complex {
+        Complex::new(
+            self.re * rhs.re - self.im * rhs.im,
+            self.re * rhs.im + self.im * rhs.re,
+        )
+    }
+}
+
+impl Div for Complex {
+    type Output = Complex;
+
+    #[allow(clippy::suspicious_arithmetic_impl)]
+    fn div(self, rhs: Complex) -> Complex {
+        self * rhs.recip()
+    }
+}
+
+impl Neg for Complex {
+    type Output = Complex;
+
+    fn neg(self) -> Complex {
+        Complex::new(-self.re, -self.im)
+    }
+}
+
+impl Graph<f64> {
+    /// Evaluate the compiled output with complex variables (in the order of `get_vars`)
+    ///
+    /// Parameters keep their real values. `ReLU` & `Heaviside` are not analytic and act on the real
+    /// part only; custom ops use their first order extension `f(a) + i b f'(a)`. The real buffer & gradients are left untouched.
+    pub fn forward_complex(&mut self, vals: &[Complex]) -> Complex {
+        assert!(self.value_ics.len() >= vals.len());
+        let root = self.compiled.unwrap();
+        self.schedule();
+        let order = Arc::clone(self.topological_order.as_ref().unwrap());
+
+        let mut v = vec![Complex::default(); self.nodes.len()];
+        for &index in order.iter() {
+            if let Node::Var(_) | Node::Param(_) = self.nodes.node(index) {
+                v[index] = Complex::real(self.buffer[index].unwrap());
+            }
+        }
+        for (&i, val) in self.value_ics.iter().zip(vals) {
+            v[i] = *val;
+        }
+        for &index in order.iter() {
+            v[index] = match self.nodes.node(index) {
+                Node::Var(_) | Node::Param(_) => continue,
+                Node::Const(x) => Complex::real(x),
+                Node::Add(l, r) => v[l] + v[r],
+                Node::Sub(l, r) => v[l] - v[r],
+                Node::Mul(l, r) | Node::Hadamard(l, r) => v[l] * v[r],
+                Node::Fma(a, b, c) => v[a] * v[b] + v[c],
+                Node::Select(c, a, b) => {
+                    if v[c].re.is_sign_positive() {
+                        v[a]
+                    } else {
+                        v[b]
+                    }
+                }
+                Node::Div(l, r) => v[l] / v[r],
+                Node::Pow(l, r) => v[l].powc(v[r]),
+                Node::Addf(x, i) => v[i] + Complex::real(x),
+                Node::Subf(i, x) => v[i] - Complex::real(x),
+                Node::Mulf(x, i) => v[i].scale(x),
+                Node::Powf(i, p) => v[i].powc(Complex::real(p)),
+                Node::Powi(i, n) => v[i].powi(n),
+                Node::Transpose(i) => v[i],
+                Node::Neg(i) => -v[i],
+                Node::Recip(i) => v[i].recip(),
+                Node::Exp(i) => v[i].exp(),
+                Node::Ln(i) => v[i].ln(),
+                Node::Sin(i) => v[i].sin(),
+                Node::Cos(i) => v[i].cos(),
+                Node::Tan(i) => v[i].sin() / v[i].cos(),
+                Node::Sinh(i) => v[i].sinh(),
+                Node::Cosh(i) => v[i].cosh(),
+                Node::Tanh(i) => v[i].sinh() / v[i].cosh(),
+                Node::Sigmoid(i) => (Complex::real(1f64) + (-v[i]).exp()).recip(),
+                Node::ReLU(i) => v[i].scale(v[i].re.heaviside_zero()),
+                Node::Heaviside(i) => Complex::real(v[i].re.heaviside_zero()),
+                Node::Custom(id, i) => {
+                    // First order extension `f(a) + i b f'(a)`, exact for the complex step
+                    let op = &self.custom_ops[id];
+                    let z = v[i];
+                    Complex::new(op.eval(z.re, 0f64), z.im * op.partials(z.re, 0f64).0)
+                }
+                Node::CustomBinary(id, l, r) => {
+                    let op = &self.custom_ops[id];
+                    let (z, w) = (v[l], v[r]);
+                    let (fz, fw) = op.partials(z.re, w.re);
+                    Complex::new(op.eval(z.re, w.re), z.im * fz + w.im * fw)
+                }
+                Node::Arg(i) => v[i],
+                Node::External(id, first, n) => {
+                    let op = &self.custom_ops[id];
+                    let re = v[first..first + n].iter().map(|z| z.re).collect::<Vec<_>>();
+                    let im = (0..n).map(|j| v[first + j].im * op.partial_n(&re, j)).sum();
+                    Complex::new(op.eval_n(&re), im)
+                }
+            };
+        }
+        v[root]
+    }
+
+    /// Derivative w.r.t. the `i`-th variable by the complex step `Im f(x + ih e_i) / h`
+    ///
+    /// Free of subtractive cancellation, so `h` may be tiny (e.g. `1e-30`) and the result is
+    /// accurate to machine precision for analytic expressions.
+    pub fn complex_step(&mut self, i: usize, h: f64) -> f64 {
+        let mut vals = self
+            .value_ics
+            .iter()
+            .map(|x| Complex::real(self.buffer[*x].unwrap()))
+            .collect::<Vec<_>>();
+        vals[i].im = h;
+        self.forward_complex(&vals).im / h
+    }
+}
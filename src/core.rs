@@ -1,20 +1,58 @@
 use casey::pascal;
 use peroxide_num::{ExpLogOps, Numeric, PowOps, TrigOps};
+use std::collections::HashMap;
+use std::cell::RefCell;
+use std::rc::Rc;
 use std::ops::{Add, Div, Mul, Neg, Sub};
+use std::panic::Location;
+use std::sync::Arc;
+use std::time::Instant;
+use crate::custom::CustomOp;
+use crate::error::GraphError;
+use crate::profile::Profile;
+use crate::provenance::Provenance;
+use crate::tape::NodeStore;
 use crate::traits::{ActivationFunction, Matrizable};
 
-#[derive(Default)]
+/// `Clone` shares the tape (nodes, schedules & CSE table) and only copies values & gradients.
+/// The tape is copied on write when a clone records new nodes or runs a pass.
+#[derive(Default, Clone)]
 pub struct Graph<T> {
     pub gradients: Vec<T>,
     pub buffer: Vec<Option<T>>,
-    pub nodes: Vec<Node>, // Added to store the nodes
+    pub nodes: Arc<NodeStore>,
     pub value_ics: Vec<usize>,
+    pub param_ics: Vec<usize>,
     pub compiled: Option<usize>,
-    pub topological_order: Option<Vec<usize>>,
+    pub outputs: Vec<usize>,
+    pub output_names: HashMap<String, usize>,
+    pub groups: HashMap<String, Vec<usize>>,
+    pub labels: HashMap<usize, String>,
+    pub topological_order: Option<Arc<Vec<usize>>>,
+    pub(crate) reverse_order: Option<Arc<Vec<usize>>>,
+    pub requires_grad: Vec<bool>,
+    pub grad_mask: Option<Vec<bool>>,
+    pub(crate) cse: Arc<HashMap<NodeKey, usize>>,
+    pub(crate) profile: Option<Profile>,
+    pub(crate) provenance: Option<Vec<Option<Provenance>>>,
+    pub(crate) custom_ops: Arc<Vec<CustomOp>>,
 }
 
+/// Length of the tape at some point (see `Graph::mark`)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TapePos {
+    nodes: usize,
+    vars: usize,
+    params: usize,
+    outputs: usize,
+    custom_ops: usize,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
 pub enum Node {
     Var(usize),        // Index in the value buffer
+    Param(usize),      // Index in the value buffer (not differentiated)
+    Const(f64),
     Add(usize, usize), // Indices of the left and right operands
     Addf(f64, usize),
     Sub(usize, usize),
@@ -22,6 +60,8 @@ pub enum Node {
     Mul(usize, usize),
     Mulf(f64, usize),
     Hadamard(usize, usize),
+    Fma(usize, usize, usize), // a * b + c
+    Select(usize, usize, usize), // if cond >= +0 { if_pos } else { if_neg }
     Transpose(usize),
     Div(usize, usize),
     Pow(usize, usize),
@@ -39,16 +79,498 @@ pub enum Node {
     Tanh(usize),
     Sigmoid(usize),
     ReLU(usize),
+    Heaviside(usize),
+    Custom(usize, usize),             // Registry index (see `CustomOp`) & operand
+    CustomBinary(usize, usize, usize), // Registry index, left & right operands
+    Arg(usize),                        // Argument slot of an `External` call (identity)
+    External(usize, usize, usize),     // Registry index, first `Arg` slot & number of arguments
+}
+
+/// Operands of a node (see `Node::operand_iter`): up to three inline, or the argument range of
+/// an `External`
+pub type Operands = std::iter::Chain<std::iter::Take<std::array::IntoIter<usize, 3>>, std::ops::Range<usize>>;
+
+impl Node {
+    /// Indices of the operands (none for leaves)
+    pub fn operands(&self) -> Vec<usize> {
+        self.operand_iter().collect()
+    }
+
+    /// `operands` without allocating (used by the sweeps)
+    #[inline]
+    pub fn operand_iter(&self) -> Operands {
+        let (fixed, len, rest) = match *self {
+            Node::Var(_) | Node::Param(_) | Node::Const(_) => ([0; 3], 0, 0..0),
+            Node::Add(l, r)
+            | Node::Sub(l, r)
+            | Node::Mul(l, r)
+            | Node::Div(l, r)
+            | Node::Pow(l, r)
+            | Node::Hadamard(l, r)
+            | Node::CustomBinary(_, l, r) => ([l, r, 0], 2, 0..0),
+            Node::Fma(a, b, c) | Node::Select(a, b, c) => ([a, b, c], 3, 0..0),
+            Node::External(_, first, n) => ([0; 3], 0, first..first + n),
+            Node::Addf(_, i)
+            | Node::Mulf(_, i)
+            | Node::Subf(i, _)
+            | Node::Neg(i)
+            | Node::Recip(i)
+            | Node::Exp(i)
+            | Node::Ln(i)
+            | Node::Sin(i)
+            | Node::Cos(i)
+            | Node::Tan(i)
+            | Node::Sinh(i)
+            | Node::Cosh(i)
+            | Node::Tanh(i)
+            | Node::Sigmoid(i)
+            | Node::ReLU(i)
+            | Node::Heaviside(i)
+            | Node::Transpose(i)
+            | Node::Powf(i, _)
+            | Node::Powi(i, _)
+            | Node::Custom(_, i)
+            | Node::Arg(i) => ([i, 0, 0], 1, 0..0),
+        };
+        fixed.into_iter().take(len).chain(rest)
+    }
+
+    /// Name of the operation (variant name)
+    pub fn name(&self) -> &'static str {
+        match self {
+            Node::Var(_) => "Var",
+            Node::Param(_) => "Param",
+            Node::Const(_) => "Const",
+            Node::Add(..) => "Add",
+            Node::Addf(..) => "Addf",
+            Node::Sub(..) => "Sub",
+            Node::Subf(..) => "Subf",
+            Node::Mul(..) => "Mul",
+            Node::Mulf(..) => "Mulf",
+            Node::Hadamard(..) => "Hadamard",
+            Node::Fma(..) => "Fma",
+            Node::Select(..) => "Select",
+            Node::Transpose(_) => "Transpose",
+            Node::Div(..) => "Div",
+            Node::Pow(..) => "Pow",
+            Node::Powf(..) => "Powf",
+            Node::Powi(..) => "Powi",
+            Node::Neg(_) => "Neg",
+            Node::Recip(_) => "Recip",
+            Node::Exp(_) => "Exp",
+            Node::Ln(_) => "Ln",
+            Node::Sin(_) => "Sin",
+            Node::Cos(_) => "Cos",
+            Node::Tan(_) => "Tan",
+            Node::Sinh(_) => "Sinh",
+            Node::Cosh(_) => "Cosh",
+            Node::Tanh(_) => "Tanh",
+            Node::Sigmoid(_) => "Sigmoid",
+            Node::ReLU(_) => "ReLU",
+            Node::Heaviside(_) => "Heaviside",
+            Node::Custom(..) => "Custom",
+            Node::CustomBinary(..) => "CustomBinary",
+            Node::Arg(_) => "Arg",
+            Node::External(..) => "External",
+        }
+    }
+
+    /// Same operation with every index (operands & own slot of leaves) mapped by `f`
+    pub fn map_indices<F: Fn(usize) -> usize>(&self, f: F) -> Node {
+        match *self {
+            Node::Var(i) => Node::Var(f(i)),
+            Node::Param(i) => Node::Param(f(i)),
+            Node::Const(value) => Node::Const(value),
+            Node::Add(l, r) => Node::Add(f(l), f(r)),
+            Node::Sub(l, r) => Node::Sub(f(l), f(r)),
+            Node::Mul(l, r) => Node::Mul(f(l), f(r)),
+            Node::Hadamard(l, r) => Node::Hadamard(f(l), f(r)),
+            Node::Fma(a, b, c) => Node::Fma(f(a), f(b), f(c)),
+            Node::Select(c, a, b) => Node::Select(f(c), f(a), f(b)),
+            Node::Div(l, r) => Node::Div(f(l), f(r)),
+            Node::Pow(l, r) => Node::Pow(f(l), f(r)),
+            Node::Addf(num, i) => Node::Addf(num, f(i)),
+            Node::Subf(i, num) => Node::Subf(f(i), num),
+            Node::Mulf(num, i) => Node::Mulf(num, f(i)),
+            Node::Powf(i, p) => Node::Powf(f(i), p),
+            Node::Powi(i, n) => Node::Powi(f(i), n),
+            Node::Neg(i) => Node::Neg(f(i)),
+            Node::Recip(i) => Node::Recip(f(i)),
+            Node::Exp(i) => Node::Exp(f(i)),
+            Node::Ln(i) => Node::Ln(f(i)),
+            Node::Sin(i) => Node::Sin(f(i)),
+            Node::Cos(i) => Node::Cos(f(i)),
+            Node::Tan(i) => Node::Tan(f(i)),
+            Node::Sinh(i) => Node::Sinh(f(i)),
+            Node::Cosh(i) => Node::Cosh(f(i)),
+            Node::Tanh(i) => Node::Tanh(f(i)),
+            Node::Sigmoid(i) => Node::Sigmoid(f(i)),
+            Node::ReLU(i) => Node::ReLU(f(i)),
+            Node::Heaviside(i) => Node::Heaviside(f(i)),
+            Node::Transpose(i) => Node::Transpose(f(i)),
+            Node::Custom(id, i) => Node::Custom(id, f(i)),
+            Node::CustomBinary(id, l, r) => Node::CustomBinary(id, f(l), f(r)),
+            Node::Arg(i) => Node::Arg(f(i)),
+            // Argument slots are contiguous and re-indexed in order
+            Node::External(id, first, n) => Node::External(id, f(first), n),
+        }
+    }
+}
+
+/// Structural identity of an operation node (variant, operands & constants)
+pub(crate) type NodeKey = (std::mem::Discriminant<Node>, usize, usize, u64);
+
+pub(crate) fn node_key(node: &Node) -> NodeKey {
+    let (a, b, c) = match node {
+        Node::Var(i) | Node::Param(i) => (*i, 0, 0),
+        Node::Const(value) => (0, 0, value.to_bits()),
+        Node::Add(l, r)
+        | Node::Sub(l, r)
+        | Node::Mul(l, r)
+        | Node::Div(l, r)
+        | Node::Pow(l, r)
+        | Node::Hadamard(l, r) => (*l, *r, 0),
+        Node::Fma(a, b, c) | Node::Select(a, b, c) => (*a, *b, *c as u64),
+        Node::Addf(num, i) | Node::Subf(i, num) | Node::Mulf(num, i) | Node::Powf(i, num) => {
+            (*i, 0, num.to_bits())
+        }
+        Node::Powi(i, n) => (*i, 0, *n as u64),
+        Node::Custom(id, i) => (*i, 0, *id as u64),
+        Node::CustomBinary(id, l, r) => (*l, *r, *id as u64),
+        Node::Arg(i) => (*i, 0, 0),
+        Node::External(id, first, n) => (*first, *n, *id as u64),
+        Node::Neg(i)
+        | Node::Recip(i)
+        | Node::Exp(i)
+        | Node::Ln(i)
+        | Node::Sin(i)
+        | Node::Cos(i)
+        | Node::Tan(i)
+        | Node::Sinh(i)
+        | Node::Cosh(i)
+        | Node::Tanh(i)
+        | Node::Sigmoid(i)
+        | Node::ReLU(i)
+        | Node::Heaviside(i)
+        | Node::Transpose(i) => (*i, 0, 0),
+    };
+    (std::mem::discriminant(node), a, b, c)
+}
+
+/// Value of a node from the (already evaluated) values of its operands
+pub(crate) fn eval_node<T: std::fmt::Debug + Numeric<f64> + Default + ActivationFunction + Matrizable>(
+    node: &Node,
+    buffer: &[Option<T>],
+    index: usize,
+    ops: &[CustomOp],
+) -> T
+where
+    f64: Div<T, Output = T>,
+{
+    match node {
+        Node::Var(_) | Node::Param(_) => {
+            buffer[index].clone().unwrap()
+        }
+        Node::Const(value) => T::from_f64(*value),
+        Node::Add(left_index, right_index) => {
+            buffer[*left_index].clone().unwrap()
+                + buffer[*right_index].clone().unwrap()
+        }
+        Node::Addf(num, right_index) => {
+            buffer[*right_index].clone().unwrap() + *num
+        }
+        Node::Sub(left_index, right_index) => {
+            buffer[*left_index].clone().unwrap()
+                - buffer[*right_index].clone().unwrap()
+        }
+        Node::Subf(left_index, num) => {
+            buffer[*left_index].clone().unwrap() - *num
+        }
+        Node::Mul(left_index, right_index) => {
+            buffer[*left_index].clone().unwrap()
+                * buffer[*right_index].clone().unwrap()
+        }
+        Node::Mulf(num, right_index) => {
+            buffer[*right_index].clone().unwrap() * *num
+        }
+        Node::Hadamard(left_index, right_index) => {
+            buffer[*left_index].clone().unwrap()
+                .hadamard(&buffer[*right_index].clone().unwrap())
+        }
+        Node::Fma(a_index, b_index, c_index) => {
+            buffer[*a_index].as_ref().unwrap().mul_add(
+                buffer[*b_index].as_ref().unwrap(),
+                buffer[*c_index].as_ref().unwrap(),
+            )
+        }
+        Node::Select(cond_index, pos_index, neg_index) => {
+            let args = [cond_index, pos_index, neg_index].map(|i| buffer[*i].as_ref().unwrap());
+            T::map_many(&args, |x| if x[0].is_sign_positive() { x[1] } else { x[2] })
+        }
+        Node::Transpose(operand_index) => {
+            buffer[*operand_index].clone().unwrap().transpose()
+        }
+        Node::Div(left_index, right_index) => {
+            buffer[*left_index].clone().unwrap()
+                / buffer[*right_index].clone().unwrap()
+        }
+        Node::Pow(left_index, right_index) => {
+            buffer[*left_index].clone().unwrap()
+                .pow(buffer[*right_index].clone().unwrap())
+        }
+        Node::Powf(operand_index, power) => {
+            buffer[*operand_index].clone().unwrap().powf(*power)
+        }
+        Node::Powi(operand_index, power) => {
+            buffer[*operand_index].clone().unwrap().powi(*power)
+        }
+        Node::Neg(operand_index) => {
+            -buffer[*operand_index].clone().unwrap()
+        }
+        Node::Recip(operand_index) => {
+            1.0 / buffer[*operand_index].clone().unwrap()
+        }
+        Node::Exp(operand_index) => {
+            buffer[*operand_index].clone().unwrap().exp()
+        }
+        Node::Ln(operand_index) => {
+            buffer[*operand_index].clone().unwrap().ln()
+        }
+        Node::Sin(operand_index) => {
+            buffer[*operand_index].clone().unwrap().sin()
+        }
+        Node::Cos(operand_index) => {
+            buffer[*operand_index].clone().unwrap().cos()
+        }
+        Node::Tan(operand_index) => {
+            buffer[*operand_index].clone().unwrap().tan()
+        }
+        Node::Sinh(operand_index) => {
+            buffer[*operand_index].clone().unwrap().sinh()
+        }
+        Node::Cosh(operand_index) => {
+            buffer[*operand_index].clone().unwrap().cosh()
+        }
+        Node::Tanh(operand_index) => {
+            buffer[*operand_index].clone().unwrap().tanh()
+        }
+        Node::Sigmoid(operand_index) => {
+            buffer[*operand_index].clone().unwrap().sigmoid()
+        }
+        Node::ReLU(operand_index) => {
+            buffer[*operand_index].clone().unwrap().relu()
+        }
+        Node::Heaviside(operand_index) => {
+            buffer[*operand_index].clone().unwrap().heaviside_zero()
+        }
+        Node::Custom(id, operand_index) => {
+            let op = &ops[*id];
+            buffer[*operand_index].as_ref().unwrap().map_elements(|x| op.eval(x, 0f64))
+        }
+        Node::CustomBinary(id, left_index, right_index) => {
+            let op = &ops[*id];
+            buffer[*left_index].as_ref().unwrap()
+                .zip_elements(buffer[*right_index].as_ref().unwrap(), |x, y| op.eval(x, y))
+        }
+        Node::Arg(operand_index) => {
+            buffer[*operand_index].clone().unwrap()
+        }
+        Node::External(id, first, n) => {
+            let op = &ops[*id];
+            let args = buffer[*first..*first + *n].iter().map(|x| x.as_ref().unwrap()).collect::<Vec<_>>();
+            T::map_many(&args, |x| op.eval_n(x))
+        }
+    }
+}
+
+/// Accumulate the adjoint of a node into its operands
+pub(crate) fn propagate_adjoint<T: std::fmt::Debug + Numeric<f64> + Default + ActivationFunction + Matrizable>(
+    node: &Node,
+    buffer: &[Option<T>],
+    gradients: &mut [T],
+    index: usize,
+    ops: &[CustomOp],
+) where
+    f64: Div<T, Output = T>,
+{
+    let gradient = gradients[index].clone();
+    match node {
+        Node::Var(_) | Node::Param(_) | Node::Const(_) | Node::Heaviside(_) => {}
+        Node::Add(left_index, right_index) => {
+            gradients[*left_index] = gradients[*left_index].clone() + gradient.clone();
+            gradients[*right_index] = gradients[*right_index].clone() + gradient.clone(); 
+        }
+        Node::Addf(_, right_index) => {
+            gradients[*right_index] = gradients[*right_index].clone() + gradient.clone();
+        }
+        Node::Sub(left_index, right_index) => {
+            gradients[*left_index] = gradients[*left_index].clone() + gradient.clone();
+            gradients[*right_index] = gradients[*right_index].clone() - gradient.clone();
+        }
+        Node::Subf(left_index, _) => {
+            gradients[*left_index] = gradients[*left_index].clone() + gradient.clone();
+        }
+        Node::Mul(left_index, right_index) => {
+            let left_val = buffer[*left_index].as_ref().unwrap();
+            let right_val = buffer[*right_index].as_ref().unwrap();
+            gradients[*left_index] = gradients[*left_index].clone()
+                + gradient.clone() * right_val.transpose();
+            gradients[*right_index] = gradients[*right_index].clone()
+                + left_val.transpose() * gradient.clone();
+        }
+        Node::Mulf(num, right_index) => {
+            gradients[*right_index] = gradients[*right_index].clone() + gradient.clone() * *num;
+        }
+        Node::Hadamard(left_index, right_index) => {
+            let left_val = buffer[*left_index].as_ref().unwrap();
+            let right_val = buffer[*right_index].as_ref().unwrap();
+            gradients[*left_index] = gradients[*left_index].clone()
+                + right_val.hadamard(&gradient);
+            gradients[*right_index] = gradients[*right_index].clone()
+                + left_val.hadamard(&gradient);
+        }
+        Node::Fma(a_index, b_index, c_index) => {
+            let a_val = buffer[*a_index].as_ref().unwrap();
+            let b_val = buffer[*b_index].as_ref().unwrap();
+            gradients[*a_index] = gradients[*a_index].clone()
+                + gradient.clone() * b_val.transpose();
+            gradients[*b_index] = gradients[*b_index].clone()
+                + a_val.transpose() * gradient.clone();
+            gradients[*c_index] = gradients[*c_index].clone() + gradient.clone();
+        }
+        Node::Select(cond_index, pos_index, neg_index) => {
+            // Route the adjoint to the taken branch only (no `0 * inf` in the other one)
+            let cond_val = buffer[*cond_index].as_ref().unwrap();
+            let pos = T::map_many(&[cond_val, &gradient], |x| if x[0].is_sign_positive() { x[1] } else { 0f64 });
+            let neg = T::map_many(&[cond_val, &gradient], |x| if x[0].is_sign_positive() { 0f64 } else { x[1] });
+            gradients[*pos_index] = gradients[*pos_index].clone() + pos;
+            gradients[*neg_index] = gradients[*neg_index].clone() + neg;
+        }
+        Node::Transpose(operand_index) => {
+            gradients[*operand_index] = gradients[*operand_index].clone()
+                + gradient.transpose();
+        }
+        Node::Div(left_index, right_index) => {
+            let left_val = buffer[*left_index].as_ref().unwrap();
+            let right_val = buffer[*right_index].as_ref().unwrap();
+            gradients[*left_index] = gradients[*left_index].clone()
+                + gradient.clone() / right_val.clone();
+            gradients[*right_index] = gradients[*right_index].clone()
+                - (left_val.clone() / right_val.hadamard(right_val)).hadamard(&gradient);
+        }
+        Node::Pow(left_index, right_index) => {
+            let left_val = buffer[*left_index].as_ref().unwrap();
+            let right_val = buffer[*right_index].as_ref().unwrap();
+            let result = buffer[index].as_ref().unwrap();
+            gradients[*left_index] = gradients[*left_index].clone()
+                + right_val.hadamard(&left_val.pow(right_val.clone() - 1f64)).hadamard(&gradient);
+            gradients[*right_index] = gradients[*right_index].clone()
+                + result.hadamard(&left_val.ln()).hadamard(&gradient);
+        }
+        Node::Powf(left_index, num) => {
+            let x = buffer[*left_index].as_ref().unwrap();
+            gradients[*left_index] = gradients[*left_index].clone() + gradient.clone() * *num * x.powf(*num - 1.0);
+        }
+        Node::Powi(left_index, num) => {
+            let x = buffer[*left_index].as_ref().unwrap();
+            gradients[*left_index] = gradients[*left_index].clone() + gradient.clone() * (*num as f64) * x.powi(*num - 1);
+        }
+        Node::Neg(operand_index) => {
+            gradients[*operand_index] = gradients[*operand_index].clone() - gradient.clone();
+        }
+        Node::Recip(operand_index) => {
+            let operand_val = buffer[*operand_index].as_ref().unwrap();
+            gradients[*operand_index] = gradients[*operand_index].clone()
+                - gradient.clone() / operand_val.hadamard(operand_val);
+        }
+        Node::Exp(operand_index) => {
+            let operand_val = buffer[*operand_index].as_ref().unwrap();
+            gradients[*operand_index] = gradients[*operand_index].clone()
+                + operand_val.exp().hadamard(&gradient);
+        }
+        Node::Ln(operand_index) => {
+            let operand_val = buffer[*operand_index].as_ref().unwrap();
+            gradients[*operand_index] = gradients[*operand_index].clone()
+                + gradient.clone() / operand_val.clone();
+        }
+        Node::Sin(operand_index) => {
+            let operand_val = buffer[*operand_index].as_ref().unwrap();
+            gradients[*operand_index] = gradients[*operand_index].clone()
+                + operand_val.cos().hadamard(&gradient);
+        }
+        Node::Cos(operand_index) => {
+            let operand_val = buffer[*operand_index].as_ref().unwrap();
+            gradients[*operand_index] = gradients[*operand_index].clone()
+                - operand_val.sin().hadamard(&gradient);
+        }
+        Node::Tan(operand_index) => {
+            let operand_val = buffer[*operand_index].as_ref().unwrap();
+            let tan = operand_val.tan();
+            gradients[*operand_index] = gradients[*operand_index].clone()
+                + (tan.hadamard(&tan) + 1f64).hadamard(&gradient);
+        }
+        Node::Sinh(operand_index) => {
+            let operand_val = buffer[*operand_index].as_ref().unwrap();
+            gradients[*operand_index] = gradients[*operand_index].clone()
+                + operand_val.cosh().hadamard(&gradient);
+        }
+        Node::Cosh(operand_index) => {
+            let operand_val = buffer[*operand_index].as_ref().unwrap();
+            gradients[*operand_index] = gradients[*operand_index].clone()
+                + operand_val.sinh().hadamard(&gradient);
+        }
+        Node::Tanh(operand_index) => {
+            let operand_val = buffer[*operand_index].as_ref().unwrap();
+            let tanh = operand_val.tanh();
+            gradients[*operand_index] = gradients[*operand_index].clone()
+                + (-(tanh.hadamard(&tanh) - 1f64)).hadamard(&gradient);
+        }
+        Node::Sigmoid(operand_index) => {
+            let operand_val = buffer[*operand_index].as_ref().unwrap();
+            let sigmoid = operand_val.sigmoid();
+            let diff_sigmoid = -sigmoid.clone() + 1f64;
+            gradients[*operand_index] = gradients[*operand_index].clone()
+                + sigmoid.hadamard(&diff_sigmoid).hadamard(&gradient);
+        }
+        Node::ReLU(operand_index) => {
+            let operand_val = buffer[*operand_index].as_ref().unwrap();
+            let relu = operand_val.heaviside_zero();
+            gradients[*operand_index] = gradients[*operand_index].clone()
+                + relu.hadamard(&gradient);
+        }
+        Node::Custom(id, operand_index) => {
+            let op = &ops[*id];
+            let operand_val = buffer[*operand_index].as_ref().unwrap();
+            let df = operand_val.map_elements(|x| op.partials(x, 0f64).0);
+            gradients[*operand_index] = gradients[*operand_index].clone() + df.hadamard(&gradient);
+        }
+        Node::CustomBinary(id, left_index, right_index) => {
+            let op = &ops[*id];
+            let left_val = buffer[*left_index].as_ref().unwrap();
+            let right_val = buffer[*right_index].as_ref().unwrap();
+            let df_l = left_val.zip_elements(right_val, |x, y| op.partials(x, y).0);
+            let df_r = left_val.zip_elements(right_val, |x, y| op.partials(x, y).1);
+            gradients[*left_index] = gradients[*left_index].clone() + df_l.hadamard(&gradient);
+            gradients[*right_index] = gradients[*right_index].clone() + df_r.hadamard(&gradient);
+        }
+        Node::Arg(operand_index) => {
+            gradients[*operand_index] = gradients[*operand_index].clone() + gradient.clone();
+        }
+        Node::External(id, first, n) => {
+            let op = &ops[*id];
+            let args = buffer[*first..*first + *n].iter().map(|x| x.as_ref().unwrap()).collect::<Vec<_>>();
+            for j in 0..*n {
+                let df = T::map_many(&args, |x| op.partial_n(x, j));
+                gradients[*first + j] = gradients[*first + j].clone() + df.hadamard(&gradient);
+            }
+        }
+    }
 }
 
 macro_rules! impl_unary_op {
     ($name:ident, $t:ty) => {
         pub fn $name(&mut self, operand: usize) -> usize {
-            let index = self.nodes.len();
-            self.buffer.push(None);
-            self.gradients.push(<$t>::default());
-            self.nodes.push(pascal!(Node::$name)(operand));
-            index
+            self.push_node(pascal!(Node::$name)(operand))
         }
     };
 }
@@ -56,25 +578,82 @@ macro_rules! impl_unary_op {
 macro_rules! impl_binary_op {
     ($name:ident, $t:ty) => {
         pub fn $name(&mut self, left: usize, right: usize) -> usize {
-            let index = self.nodes.len();
-            self.buffer.push(None);
-            self.gradients.push(<$t>::default());
-            self.nodes.push(pascal!(Node::$name)(left, right));
-            index
+            self.push_node(pascal!(Node::$name)(left, right))
         }
     };
 }
 
+impl<T> Graph<T> {
+    /// Topological sort (iterative DFS, so graph depth is only bounded by memory)
+    pub(crate) fn topological_sort(&self) -> Vec<usize> {
+        if let Some(order) = &self.topological_order {
+            return order.to_vec();
+        }
+        let mut visited = vec![false; self.nodes.len()];
+        let mut order = Vec::with_capacity(self.nodes.len());
+        // (index, whether children are already pushed)
+        let mut stack: Vec<(usize, bool)> = Vec::new();
+
+        for i in 0..self.nodes.len() {
+            if visited[i] {
+                continue;
+            }
+            stack.push((i, false));
+            while let Some((index, expanded)) = stack.pop() {
+                if expanded {
+                    order.push(index);
+                    continue;
+                }
+                if visited[index] {
+                    continue;
+                }
+                visited[index] = true;
+                stack.push((index, true));
+                for child_index in self.get_children(index).rev() {
+                    if !visited[child_index] {
+                        stack.push((child_index, false));
+                    }
+                }
+            }
+        }
+
+        order
+    }
+
+    /// Get children of a node
+    pub(crate) fn get_children(&self, index: usize) -> Operands {
+        self.nodes.node(index).operand_iter()
+    }
+}
+
 impl<T: std::fmt::Debug + Numeric<f64> + Default + ActivationFunction + Matrizable> Graph<T>
 where
     f64: Div<T, Output = T>,
 {
+    /// Empty graph with room for `nodes` nodes and `vars` variables
+    pub fn with_capacity(nodes: usize, vars: usize) -> Self {
+        let mut graph = Self::default();
+        graph.reserve(nodes);
+        graph.value_ics.reserve(vars);
+        graph.requires_grad.reserve(vars);
+        graph
+    }
+
+    /// Reserve room for at least `additional` more nodes
+    pub fn reserve(&mut self, additional: usize) {
+        self.gradients.reserve(additional);
+        self.buffer.reserve(additional);
+        Arc::make_mut(&mut self.nodes).reserve(additional);
+        Arc::make_mut(&mut self.cse).reserve(additional);
+    }
+
     pub fn var(&mut self, value: T) -> usize {
         let index = self.buffer.len();
         self.gradients.push(value.zeros_like());
         self.buffer.push(Some(value));
-        self.nodes.push(Node::Var(index));
+        Arc::make_mut(&mut self.nodes).push(Node::Var(index));
         self.value_ics.push(index);
+        self.requires_grad.push(true);
         index // The index is used to refer to this variable
     }
 
@@ -84,10 +663,12 @@ where
         self.buffer.resize(start_index + n_vars, None);
         self.gradients.resize(start_index + n_vars, T::default());
         for i in 0..n_vars {
-            self.nodes.push(Node::Var(start_index + i));
+            Arc::make_mut(&mut self.nodes).push(Node::Var(start_index + i));
             self.value_ics.push(start_index + i);
         }
+        self.requires_grad.resize(self.value_ics.len(), true);
         self.topological_order = None;
+        self.grad_mask = None;
     }
 
     /// Declare symbol (not initialize variable)
@@ -95,12 +676,40 @@ where
         let index = self.buffer.len();
         self.buffer.push(None);
         self.gradients.push(T::default());
-        self.nodes.push(Node::Var(index));
+        Arc::make_mut(&mut self.nodes).push(Node::Var(index));
         self.value_ics.push(index);
+        self.requires_grad.push(true);
         self.topological_order = None;
+        self.grad_mask = None;
         Expr::Symbol(index)
     }
 
+    /// Declare parameter
+    ///
+    /// A parameter is a leaf whose value survives `reset` and can be replaced by `set_param`
+    /// without recompiling. It is not a variable, so it is not included in `get_gradients`.
+    pub fn param(&mut self, value: T) -> usize {
+        let index = self.buffer.len();
+        self.gradients.push(value.zeros_like());
+        self.buffer.push(Some(value));
+        Arc::make_mut(&mut self.nodes).push(Node::Param(index));
+        self.param_ics.push(index);
+        self.topological_order = None;
+        self.grad_mask = None;
+        index
+    }
+
+    /// Replace the value of a parameter (call `reset` before the next `forward`)
+    pub fn set_param(&mut self, index: usize, value: T) {
+        assert!(matches!(self.nodes.node(index), Node::Param(_)), "Not a parameter");
+        self.gradients[index] = value.zeros_like();
+        self.buffer[index] = Some(value);
+    }
+
+    pub fn get_params(&self) -> Vec<usize> {
+        self.param_ics.clone()
+    }
+
     pub fn get_var(&self, var_order: usize) -> usize {
         self.value_ics[var_order]
     }
@@ -118,14 +727,119 @@ where
         self.buffer[index] = Some(value);
     }
 
-    pub fn subs_vars(&mut self, vals: &[T]) {
-        let value_ics = &self.value_ics;
-        assert!(value_ics.len() >= vals.len());
-
-        for (i, val) in value_ics.iter().zip(vals) {
+    /// Substitute every variable in the order of variables
+    ///
+    /// Nothing is substituted unless there is exactly one value per variable.
+    pub fn subs_vars(&mut self, vals: &[T]) -> Result<(), GraphError> {
+        if vals.len() != self.value_ics.len() {
+            return Err(GraphError::LengthMismatch {
+                expected: self.value_ics.len(),
+                found: vals.len(),
+            });
+        }
+        for (i, val) in self.value_ics.iter().zip(vals) {
             self.buffer[*i] = Some(val.clone());
             self.gradients[*i] = val.zeros_like();
         }
+        Ok(())
+    }
+
+    /// `subs_vars` panicking on a length mismatch (for the infallible helpers built on it)
+    pub(crate) fn subs_vars_exact(&mut self, vals: &[T]) {
+        if let Err(e) = self.subs_vars(vals) {
+            panic!("{}", e);
+        }
+    }
+
+    /// Mark a variable as (non-)differentiable
+    ///
+    /// Backward never visits nodes which only depend on non-differentiable variables.
+    pub fn set_requires_grad(&mut self, var: usize, requires_grad: bool) {
+        let var_order = self
+            .value_ics
+            .iter()
+            .position(|x| *x == var)
+            .expect("Not a variable");
+        self.requires_grad[var_order] = requires_grad;
+        self.grad_mask = None;
+    }
+
+    pub fn get_requires_grad(&self, var: usize) -> bool {
+        self.value_ics
+            .iter()
+            .zip(self.requires_grad.iter())
+            .find(|(x, _)| **x == var)
+            .map(|(_, flag)| *flag)
+            .expect("Not a variable")
+    }
+
+    /// Exclude a variable from differentiation until `unfreeze`
+    ///
+    /// Its gradient stays zero and backward skips the nodes which reach neither a differentiable
+    /// variable nor a parameter, so a subset of the variables can be fitted without rebuilding
+    /// the graph.
+    pub fn freeze(&mut self, var: usize) {
+        self.set_requires_grad(var, false);
+    }
+
+    pub fn unfreeze(&mut self, var: usize) {
+        self.set_requires_grad(var, true);
+    }
+
+    pub fn is_frozen(&self, var: usize) -> bool {
+        !self.get_requires_grad(var)
+    }
+
+    /// Frozen variables in the order of variables
+    pub fn get_frozen(&self) -> Vec<usize> {
+        self.value_ics
+            .iter()
+            .zip(self.requires_grad.iter())
+            .filter_map(|(x, flag)| (!flag).then_some(*x))
+            .collect()
+    }
+
+    /// Precompute nodes which can reach a differentiable variable or a parameter
+    ///
+    /// `grad_mask` stays `None` when every variable is differentiable.
+    pub(crate) fn update_grad_mask(&mut self) {
+        if self.requires_grad.iter().all(|x| *x) {
+            self.grad_mask = None;
+            return;
+        }
+        let targets = self
+            .value_ics
+            .iter()
+            .zip(self.requires_grad.iter())
+            .filter_map(|(x, flag)| flag.then_some(*x))
+            .chain(self.param_ics.iter().copied())
+            .collect::<Vec<_>>();
+        self.grad_mask = Some(self.reachable_to(&targets));
+    }
+
+    /// Attach a label to a node (shown in records, DOT exports & error reports)
+    pub fn label(&mut self, index: usize, name: &str) {
+        assert!(index < self.nodes.len(), "Node index out of bounds");
+        self.labels.insert(index, name.to_string());
+    }
+
+    /// New variable labeled `name` (see `subs_vars_map`)
+    pub fn var_named(&mut self, name: &str, value: T) -> usize {
+        let index = self.var(value);
+        self.label(index, name);
+        index
+    }
+
+    pub fn get_label(&self, index: usize) -> Option<&str> {
+        self.labels.get(&index).map(|x| x.as_str())
+    }
+
+    /// Node carrying the label `name` (the smallest index if several do)
+    pub fn find_label(&self, name: &str) -> Option<usize> {
+        self.labels
+            .iter()
+            .filter_map(|(index, label)| (label == name).then_some(*index))
+            .min()
     }
 
     pub fn get_symbol(&self, var_order: usize) -> Expr {
@@ -140,75 +854,31 @@ where
     }
 
     pub fn get_topological_order(&mut self) -> Vec<usize> {
-        if self.topological_order.is_none() {
-            self.topological_order = Some(self.topological_sort());
-        }
-        self.topological_order.as_ref().unwrap().clone()
+        self.schedule();
+        self.topological_order.as_ref().unwrap().to_vec()
     }
 
-    /// Topological sort
-    fn topological_sort(&self) -> Vec<usize> {
-        if self.topological_order.is_some() {
-            return self.topological_order.as_ref().unwrap().clone();
+    /// Cache the evaluation order & its reverse used by the forward & backward sweeps
+    pub(crate) fn schedule(&mut self) {
+        if self.topological_order.is_none() || self.reverse_order.is_none() {
+            let order = self.topological_sort();
+            self.reverse_order = Some(Arc::new(order.iter().rev().copied().collect()));
+            self.topological_order = Some(Arc::new(order));
         }
-        let mut visited = vec![false; self.nodes.len()];
-        let mut order = Vec::with_capacity(self.nodes.len());
-
-        for i in 0..self.nodes.len() {
-            if !visited[i] {
-                self.topological_sort_dfs(i, &mut visited, &mut order);
-            }
-        }
-
-        order
     }
 
-    /// Helper function for topological sort via DFS
-    fn topological_sort_dfs(
-        &self,
-        index: usize,
-        visited: &mut Vec<bool>,
-        order: &mut Vec<usize>,
-    ) {
-        visited[index] = true;
-
-        for &child_index in self.get_children(index).iter() {
-            if !visited[child_index] {
-                self.topological_sort_dfs(child_index, visited, order);
-            }
-        }
-
-        order.push(index);
-    }
-
-    /// Get children of a node
-    fn get_children(&self, index: usize) -> Vec<usize> {
-        match &self.nodes[index] {
-            Node::Var(_) => vec![],
-            Node::Add(l, r)
-            | Node::Sub(l, r)
-            | Node::Mul(l, r)
-            | Node::Div(l, r)
-            | Node::Pow(l, r)
-            | Node::Hadamard(l, r) => vec![*l, *r],
-            Node::Addf(_, r) | Node::Mulf(_, r) => vec![*r],
-            Node::Subf(l, _) => vec![*l],
-            Node::Neg(i)
-            | Node::Recip(i)
-            | Node::Exp(i)
-            | Node::Ln(i)
-            | Node::Sin(i)
-            | Node::Cos(i)
-            | Node::Tan(i)
-            | Node::Sinh(i)
-            | Node::Cosh(i)
-            | Node::Tanh(i)
-            | Node::Sigmoid(i)
-            | Node::ReLU(i)
-            | Node::Transpose(i)
-            | Node::Powf(i, _)
-            | Node::Powi(i, _) => vec![*i],
+    /// Push an operation node, reusing a structurally identical node if it already exists
+    pub(crate) fn push_node(&mut self, node: Node) -> usize {
+        let key = node_key(&node);
+        if let Some(index) = self.cse.get(&key) {
+            return *index;
         }
+        let index = self.nodes.len();
+        self.buffer.push(None);
+        self.gradients.push(T::default());
+        Arc::make_mut(&mut self.nodes).push(node);
+        Arc::make_mut(&mut self.cse).insert(key, index);
+        index
     }
 
     // Implement the unary operators
@@ -224,6 +894,7 @@ where
     impl_unary_op!(tanh, T);
     impl_unary_op!(sigmoid, T);
     impl_unary_op!(transpose, T);
+    impl_unary_op!(heaviside, T);
 
     // Implement the binary operators
     impl_binary_op!(add, T);
@@ -233,209 +904,144 @@ where
     impl_binary_op!(pow, T);
     impl_binary_op!(hadamard, T);
 
+    pub fn constant(&mut self, value: f64) -> usize {
+        self.push_node(Node::Const(value))
+    }
+
+    pub fn fma(&mut self, a: usize, b: usize, c: usize) -> usize {
+        self.push_node(Node::Fma(a, b, c))
+    }
+
+    /// `if_pos` where `cond` is positive (including `+0`), `if_neg` elsewhere
+    pub fn select_sign(&mut self, cond: usize, if_pos: usize, if_neg: usize) -> usize {
+        self.push_node(Node::Select(cond, if_pos, if_neg))
+    }
+
     pub fn addf(&mut self, num: f64, right: usize) -> usize {
-        let index = self.nodes.len();
-        self.buffer.push(None);
-        self.gradients.push(T::default());
-        self.nodes.push(Node::Addf(num, right));
-        index
+        self.push_node(Node::Addf(num, right))
     }
 
     pub fn subf(&mut self, left: usize, num: f64) -> usize {
-        let index = self.nodes.len();
-        self.buffer.push(None);
-        self.gradients.push(T::default());
-        self.nodes.push(Node::Subf(left, num));
-        index
+        self.push_node(Node::Subf(left, num))
     }
 
     pub fn mulf(&mut self, num: f64, right: usize) -> usize {
-        let index = self.nodes.len();
-        self.buffer.push(None);
-        self.gradients.push(T::default());
-        self.nodes.push(Node::Mulf(num, right));
-        index
+        self.push_node(Node::Mulf(num, right))
     }
 
     pub fn powf(&mut self, operand: usize, power: f64) -> usize {
-        let index = self.nodes.len();
-        self.buffer.push(None);
-        self.gradients.push(T::default());
-        self.nodes.push(Node::Powf(operand, power));
-        index
+        self.push_node(Node::Powf(operand, power))
     }
 
     pub fn powi(&mut self, operand: usize, power: i32) -> usize {
-        let index = self.nodes.len();
-        self.buffer.push(None);
-        self.gradients.push(T::default());
-        self.nodes.push(Node::Powi(operand, power));
-        index
+        self.push_node(Node::Powi(operand, power))
     }
 
     pub fn relu(&mut self, operand: usize) -> usize {
-        let index = self.nodes.len();
-        self.buffer.push(None);
-        self.gradients.push(T::default());
-        self.nodes.push(Node::ReLU(operand));
-        index
+        self.push_node(Node::ReLU(operand))
     }
 
-    //pub fn forward_step(&mut self, index: usize) -> T {
-    //    match &self.buffer[index] {
-    //        Some(value) => value.clone(),
-    //        None => {
-    //            let result = match self.nodes[index] {
-    //                Node::Var(_) => unreachable!(),
-    //                Node::Add(left_index, right_index) => {
-    //                    self.forward_step(left_index) + self.forward_step(right_index)
-    //                }
-    //                Node::Sub(left_index, right_index) => {
-    //                    self.forward_step(left_index) - self.forward_step(right_index)
-    //                }
-    //                Node::Addf(num, right_index) => self.forward_step(right_index) + num,
-    //                Node::Subf(left_index, num) => self.forward_step(left_index) - num,
-    //                Node::Mul(left_index, right_index) => {
-    //                    self.forward_step(left_index) * self.forward_step(right_index)
-    //                }
-    //                Node::Mulf(num, right_index) => self.forward_step(right_index) * num,
-    //                Node::Hadamard(left_index, right_index) => {
-    //                    self.forward_step(left_index).hadamard(&self.forward_step(right_index))
-    //                }
-    //                Node::Transpose(operand_index) => {
-    //                    self.forward_step(operand_index).transpose()
-    //                }
-    //                Node::Div(left_index, right_index) => {
-    //                    self.forward_step(left_index) / self.forward_step(right_index)
-    //                }
-    //                Node::Pow(left_index, right_index) => self
-    //                    .forward_step(left_index)
-    //                    .pow(self.forward_step(right_index)),
-    //                Node::Powf(operand_index, power) => {
-    //                    self.forward_step(operand_index).powf(power)
-    //                }
-    //                Node::Powi(operand_index, power) => {
-    //                    self.forward_step(operand_index).powi(power)
-    //                }
-    //                Node::Neg(operand_index) => -self.forward_step(operand_index),
-    //                Node::Recip(operand_index) => 1.0 / self.forward_step(operand_index),
-    //                Node::Exp(operand_index) => self.forward_step(operand_index).exp(),
-    //                Node::Ln(operand_index) => self.forward_step(operand_index).ln(),
-    //                Node::Sin(operand_index) => self.forward_step(operand_index).sin(),
-    //                Node::Cos(operand_index) => self.forward_step(operand_index).cos(),
-    //                Node::Tan(operand_index) => self.forward_step(operand_index).tan(),
-    //                Node::Sinh(operand_index) => self.forward_step(operand_index).sinh(),
-    //                Node::Cosh(operand_index) => self.forward_step(operand_index).cosh(),
-    //                Node::Tanh(operand_index) => self.forward_step(operand_index).tanh(),
-    //                Node::Sigmoid(operand_index) => self.forward_step(operand_index).sigmoid(),
-    //                Node::ReLU(operand_index) => self.forward_step(operand_index).relu(),
-    //            };
-    //            self.buffer[index] = Some(result.clone());
-    //            result
-    //        }
-    //    }
-    //}
-
     /// Iterative forward
     pub fn forward(&mut self) -> T {
-        let order = self.get_topological_order();
-        for index in order {
+        self.forward_sweep();
+        // Return compiled value
+        self.buffer[self.compiled.unwrap()].clone().unwrap()
+    }
+
+    /// Iterative forward returning values of every output (see `compile_many`)
+    pub fn forward_all(&mut self) -> Vec<T> {
+        self.forward_sweep();
+        self.outputs
+            .iter()
+            .map(|x| self.buffer[*x].clone().unwrap())
+            .collect()
+    }
+
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(level = "debug", skip_all, fields(nodes = self.nodes.len()))
+    )]
+    fn forward_sweep(&mut self) {
+        self.schedule();
+        let order = Arc::clone(self.topological_order.as_ref().unwrap());
+        for &index in order.iter() {
             if self.buffer[index].is_some() {
                 continue;
             }
-            let result = match &self.nodes[index] {
-                Node::Var(_) => {
-                    self.buffer[index].clone().unwrap()
-                }
-                Node::Add(left_index, right_index) => {
-                    self.buffer[*left_index].clone().unwrap()
-                        + self.buffer[*right_index].clone().unwrap()
-                }
-                Node::Addf(num, right_index) => {
-                    self.buffer[*right_index].clone().unwrap() + *num
-                }
-                Node::Sub(left_index, right_index) => {
-                    self.buffer[*left_index].clone().unwrap()
-                        - self.buffer[*right_index].clone().unwrap()
-                }
-                Node::Subf(left_index, num) => {
-                    self.buffer[*left_index].clone().unwrap() - *num
-                }
-                Node::Mul(left_index, right_index) => {
-                    self.buffer[*left_index].clone().unwrap()
-                        * self.buffer[*right_index].clone().unwrap()
-                }
-                Node::Mulf(num, right_index) => {
-                    self.buffer[*right_index].clone().unwrap() * *num
-                }
-                Node::Hadamard(left_index, right_index) => {
-                    self.buffer[*left_index].clone().unwrap()
-                        .hadamard(&self.buffer[*right_index].clone().unwrap())
-                }
-                Node::Transpose(operand_index) => {
-                    self.buffer[*operand_index].clone().unwrap().transpose()
-                }
-                Node::Div(left_index, right_index) => {
-                    self.buffer[*left_index].clone().unwrap()
-                        / self.buffer[*right_index].clone().unwrap()
-                }
-                Node::Pow(left_index, right_index) => {
-                    self.buffer[*left_index].clone().unwrap()
-                        .pow(self.buffer[*right_index].clone().unwrap())
-                }
-                Node::Powf(operand_index, power) => {
-                    self.buffer[*operand_index].clone().unwrap().powf(*power)
-                }
-                Node::Powi(operand_index, power) => {
-                    self.buffer[*operand_index].clone().unwrap().powi(*power)
-                }
-                Node::Neg(operand_index) => {
-                    -self.buffer[*operand_index].clone().unwrap()
-                }
-                Node::Recip(operand_index) => {
-                    1.0 / self.buffer[*operand_index].clone().unwrap()
-                }
-                Node::Exp(operand_index) => {
-                    self.buffer[*operand_index].clone().unwrap().exp()
-                }
-                Node::Ln(operand_index) => {
-                    self.buffer[*operand_index].clone().unwrap().ln()
-                }
-                Node::Sin(operand_index) => {
-                    self.buffer[*operand_index].clone().unwrap().sin()
-                }
-                Node::Cos(operand_index) => {
-                    self.buffer[*operand_index].clone().unwrap().cos()
-                }
-                Node::Tan(operand_index) => {
-                    self.buffer[*operand_index].clone().unwrap().tan()
-                }
-                Node::Sinh(operand_index) => {
-                    self.buffer[*operand_index].clone().unwrap().sinh()
-                }
-                Node::Cosh(operand_index) => {
-                    self.buffer[*operand_index].clone().unwrap().cosh()
-                }
-                Node::Tanh(operand_index) => {
-                    self.buffer[*operand_index].clone().unwrap().tanh()
-                }
-                Node::Sigmoid(operand_index) => {
-                    self.buffer[*operand_index].clone().unwrap().sigmoid()
-                }
-                Node::ReLU(operand_index) => {
-                    self.buffer[*operand_index].clone().unwrap().relu()
+            let node = &self.nodes.node(index);
+            let result = match self.profile.as_mut() {
+                Some(profile) => {
+                    let start = Instant::now();
+                    let result = eval_node(node, &self.buffer, index, &self.custom_ops);
+                    profile.record_forward(index, node.name(), start.elapsed());
+                    result
                 }
+                None => eval_node(node, &self.buffer, index, &self.custom_ops),
             };
             self.buffer[index] = Some(result);
         }
-        // Return compiled value
-        self.buffer[self.compiled.unwrap()].clone().unwrap()
     }
 
     /// Iterative backward
     pub fn backward(&mut self) {
+        self.backward_from(self.compiled.unwrap());
+    }
+
+    /// Iterative backward of the `i`-th output (see `compile_many`)
+    pub fn backward_output(&mut self, i: usize) {
+        self.backward_from(self.outputs[i]);
+    }
+
+    fn backward_from(&mut self, root: usize) {
+        if self.grad_mask.as_ref().is_none_or(|mask| mask.len() != self.nodes.len()) {
+            self.update_grad_mask();
+        }
+        let mask = self.grad_mask.take();
+        self.backward_sweep(root, mask.as_deref());
+        self.grad_mask = mask;
+    }
+
+    /// Iterative backward restricted to the given variables
+    ///
+    /// Only nodes lying on a path to one of `vars` are visited, so gradients of other variables stay zero.
+    pub fn backward_for(&mut self, vars: &[usize]) {
+        let targets = vars
+            .iter()
+            .filter(|x| self.get_requires_grad(**x))
+            .copied()
+            .collect::<Vec<_>>();
+        let active = self.reachable_to(&targets);
+        self.backward_sweep(self.compiled.unwrap(), Some(&active));
+    }
+
+    /// Mark nodes which can reach one of `targets`
+    fn reachable_to(&mut self, targets: &[usize]) -> Vec<bool> {
         let order = self.get_topological_order();
-        let reverse_order = order.into_iter().rev();
+        let mut reach = vec![false; self.nodes.len()];
+        for &target in targets {
+            reach[target] = true;
+        }
+        for index in order {
+            if !reach[index] {
+                reach[index] = self.get_children(index).any(|c| reach[c]);
+            }
+        }
+        reach
+    }
+
+    /// Single reverse sweep accumulating one adjoint per node
+    ///
+    /// Nodes are visited in reverse topological order, so each adjoint is complete before it is
+    /// propagated to the operands. Nodes not reached from `root` are skipped.
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(level = "debug", skip_all, fields(nodes = self.nodes.len(), root))
+    )]
+    fn backward_sweep(&mut self, root: usize, active: Option<&[bool]>) {
+        self.schedule();
+        let reverse_order = Arc::clone(self.reverse_order.as_ref().unwrap());
+        let mut reached = vec![false; self.nodes.len()];
+        reached[root] = true;
 
         // Initialize gradients
         self.gradients.iter_mut()
@@ -443,355 +1049,631 @@ where
             .for_each(|(grad, val)| {
                 *grad = val.as_ref().unwrap().zeros_like();
             });
-        let compiled = self.compiled.unwrap();
-        self.gradients[compiled] = self.buffer[compiled].as_ref().unwrap().ones_like();
+        self.gradients[root] = self.buffer[root].as_ref().unwrap().ones_like();
 
-        for index in reverse_order {
-            let gradient = self.gradients[index].clone();
-            match &self.nodes[index] {
-                Node::Var(_) => {
-                    continue;
-                }
-                Node::Add(left_index, right_index) => {
-                    self.gradients[*left_index] = self.gradients[*left_index].clone() + gradient.clone();
-                    self.gradients[*right_index] = self.gradients[*right_index].clone() + gradient.clone(); 
-                }
-                Node::Addf(_, right_index) => {
-                    self.gradients[*right_index] = self.gradients[*right_index].clone() + gradient.clone();
-                }
-                Node::Sub(left_index, right_index) => {
-                    self.gradients[*left_index] = self.gradients[*left_index].clone() + gradient.clone();
-                    self.gradients[*right_index] = self.gradients[*right_index].clone() - gradient.clone();
-                }
-                Node::Subf(left_index, _) => {
-                    self.gradients[*left_index] = self.gradients[*left_index].clone() + gradient.clone();
-                }
-                Node::Mul(left_index, right_index) => {
-                    let left_val = self.buffer[*left_index].as_ref().unwrap();
-                    let right_val = self.buffer[*right_index].as_ref().unwrap();
-                    self.gradients[*left_index] = self.gradients[*left_index].clone()
-                        + gradient.clone() * right_val.transpose();
-                    self.gradients[*right_index] = self.gradients[*right_index].clone()
-                        + left_val.transpose() * gradient.clone();
-                }
-                Node::Mulf(num, right_index) => {
-                    self.gradients[*right_index] = self.gradients[*right_index].clone() + gradient.clone() * *num;
-                }
-                Node::Hadamard(left_index, right_index) => {
-                    let left_val = self.buffer[*left_index].as_ref().unwrap();
-                    let right_val = self.buffer[*right_index].as_ref().unwrap();
-                    self.gradients[*left_index] = self.gradients[*left_index].clone()
-                        + right_val.hadamard(&gradient);
-                    self.gradients[*right_index] = self.gradients[*right_index].clone()
-                        + left_val.hadamard(&gradient);
-                }
-                Node::Transpose(operand_index) => {
-                    self.gradients[*operand_index] = self.gradients[*operand_index].clone()
-                        + gradient.transpose();
-                }
-                Node::Div(left_index, right_index) => {
-                    let left_val = self.buffer[*left_index].as_ref().unwrap();
-                    let right_val = self.buffer[*right_index].as_ref().unwrap();
-                    self.gradients[*left_index] = self.gradients[*left_index].clone()
-                        + gradient.clone() / right_val.clone();
-                    self.gradients[*right_index] = self.gradients[*right_index].clone()
-                        - (left_val.clone() / right_val.hadamard(right_val)).hadamard(&gradient);
-                }
-                Node::Pow(_left_index, _right_index) => {
-                    todo!()
-                }
-                Node::Powf(left_index, num) => {
-                    let x = self.buffer[*left_index].as_ref().unwrap();
-                    self.gradients[*left_index] = self.gradients[*left_index].clone() + gradient.clone() * *num * x.powf(*num - 1.0);
-                }
-                Node::Powi(left_index, num) => {
-                    let x = self.buffer[*left_index].as_ref().unwrap();
-                    self.gradients[*left_index] = self.gradients[*left_index].clone() + gradient.clone() * (*num as f64) * x.powi(*num - 1);
-                }
-                Node::Neg(operand_index) => {
-                    let operand_val = self.buffer[*operand_index].as_ref().unwrap();
-                    self.gradients[*operand_index] = self.gradients[*operand_index].clone()
-                        - operand_val.hadamard(&gradient);
-                }
-                Node::Recip(operand_index) => {
-                    let operand_val = self.buffer[*operand_index].as_ref().unwrap();
-                    self.gradients[*operand_index] = self.gradients[*operand_index].clone()
-                        - gradient.clone() / operand_val.hadamard(operand_val);
-                }
-                Node::Exp(operand_index) => {
-                    let operand_val = self.buffer[*operand_index].as_ref().unwrap();
-                    self.gradients[*operand_index] = self.gradients[*operand_index].clone()
-                        + operand_val.exp().hadamard(&gradient);
-                }
-                Node::Ln(operand_index) => {
-                    let operand_val = self.buffer[*operand_index].as_ref().unwrap();
-                    self.gradients[*operand_index] = self.gradients[*operand_index].clone()
-                        + gradient.clone() / operand_val.clone();
-                }
-                Node::Sin(operand_index) => {
-                    let operand_val = self.buffer[*operand_index].as_ref().unwrap();
-                    self.gradients[*operand_index] = self.gradients[*operand_index].clone()
-                        + operand_val.cos().hadamard(&gradient);
-                }
-                Node::Cos(operand_index) => {
-                    let operand_val = self.buffer[*operand_index].as_ref().unwrap();
-                    self.gradients[*operand_index] = self.gradients[*operand_index].clone()
-                        - operand_val.sin().hadamard(&gradient);
-                }
-                Node::Tan(operand_index) => {
-                    let operand_val = self.buffer[*operand_index].as_ref().unwrap();
-                    let tan = operand_val.tan();
-                    self.gradients[*operand_index] = self.gradients[*operand_index].clone()
-                        + (tan.hadamard(&tan) + 1f64).hadamard(&gradient);
-                }
-                Node::Sinh(operand_index) => {
-                    let operand_val = self.buffer[*operand_index].as_ref().unwrap();
-                    self.gradients[*operand_index] = self.gradients[*operand_index].clone()
-                        + operand_val.cosh().hadamard(&gradient);
-                }
-                Node::Cosh(operand_index) => {
-                    let operand_val = self.buffer[*operand_index].as_ref().unwrap();
-                    self.gradients[*operand_index] = self.gradients[*operand_index].clone()
-                        + operand_val.sinh().hadamard(&gradient);
-                }
-                Node::Tanh(operand_index) => {
-                    let operand_val = self.buffer[*operand_index].as_ref().unwrap();
-                    let tanh = operand_val.tanh();
-                    self.gradients[*operand_index] = self.gradients[*operand_index].clone()
-                        + (-(tanh.hadamard(&tanh) - 1f64)).hadamard(&gradient);
-                }
-                Node::Sigmoid(operand_index) => {
-                    let operand_val = self.buffer[*operand_index].as_ref().unwrap();
-                    let sigmoid = operand_val.sigmoid();
-                    let diff_sigmoid = -sigmoid.clone() + 1f64;
-                    self.gradients[*operand_index] = self.gradients[*operand_index].clone()
-                        + sigmoid.hadamard(&diff_sigmoid).hadamard(&gradient);
-                }
-                Node::ReLU(operand_index) => {
-                    let operand_val = self.buffer[*operand_index].as_ref().unwrap();
-                    let relu = operand_val.heaviside_zero();
-                    self.gradients[*operand_index] = self.gradients[*operand_index].clone()
-                        + relu.hadamard(&gradient);
+        for &index in reverse_order.iter() {
+            if !reached[index] || active.is_some_and(|active| !active[index]) {
+                continue;
+            }
+            for child_index in self.get_children(index) {
+                reached[child_index] = true;
+            }
+            let node = &self.nodes.node(index);
+            match self.profile.as_mut() {
+                Some(profile) => {
+                    let start = Instant::now();
+                    propagate_adjoint(node, &self.buffer, &mut self.gradients, index, &self.custom_ops);
+                    profile.record_backward(index, node.name(), start.elapsed());
                 }
+                None => propagate_adjoint(node, &self.buffer, &mut self.gradients, index, &self.custom_ops),
+            }
+        }
+
+        // Discard adjoints leaked into pruned operands
+        if let Some(active) = active {
+            for (grad, val) in self.gradients.iter_mut()
+                .zip(self.buffer.iter())
+                .zip(active)
+                .filter_map(|(x, is_active)| (!is_active).then_some(x))
+            {
+                *grad = val.as_ref().unwrap().zeros_like();
             }
         }
     }
 
-    /// Reset values & gradients without variables
+    /// Reset values & gradients without variables & parameters
     pub fn reset(&mut self) {
-        let except_ics = &self.value_ics;
-        let reset_ics = (0..self.buffer.len()).filter(|x| !except_ics.contains(x));
+        self.zero_grad();
+        self.invalidate_values();
+    }
+
+    /// Drop cached values of the nodes depending on `leaves` only
+    ///
+    /// Use instead of `reset` after changing a few variables or parameters (`subs_var`, `set_param`):
+    /// the next `forward` recomputes the downstream nodes and reuses every other cached value.
+    pub fn invalidate_downstream(&mut self, leaves: &[usize]) {
+        self.schedule();
+        let order = Arc::clone(self.topological_order.as_ref().unwrap());
+        let mut dirty = vec![false; self.nodes.len()];
+        for &leaf in leaves {
+            dirty[leaf] = true;
+        }
+        for &index in order.iter() {
+            if !dirty[index] && self.get_children(index).any(|c| dirty[c]) {
+                dirty[index] = true;
+                self.buffer[index] = None;
+            }
+        }
+    }
 
+    /// Remember the current end of the tape
+    pub fn mark(&self) -> TapePos {
+        TapePos {
+            nodes: self.nodes.len(),
+            vars: self.value_ics.len(),
+            params: self.param_ics.len(),
+            outputs: self.outputs.len(),
+            custom_ops: self.custom_ops.len(),
+        }
+    }
+
+    /// Remove every node, variable, parameter, output & custom op added after `pos`
+    ///
+    /// Cached values & gradients of the remaining nodes are kept. Nodes before the mark are not
+    /// restored if a pass rewrote them in the meantime, and the mark is meaningless after `prune`
+    /// (which re-indexes the tape).
+    pub fn rollback(&mut self, pos: TapePos) {
+        assert!(pos.nodes <= self.nodes.len(), "Tape is shorter than the mark");
+        Arc::make_mut(&mut self.nodes).truncate(pos.nodes);
+        self.buffer.truncate(pos.nodes);
+        self.gradients.truncate(pos.nodes);
+        self.value_ics.truncate(pos.vars);
+        self.requires_grad.truncate(pos.vars);
+        self.param_ics.truncate(pos.params);
+        self.outputs.truncate(pos.outputs);
+        self.outputs.retain(|index| *index < pos.nodes);
+        self.output_names.retain(|_, index| *index < pos.nodes);
+        self.groups.values_mut().for_each(|vars| vars.retain(|x| *x < pos.nodes));
+        self.labels.retain(|index, _| *index < pos.nodes);
+        if let Some(origins) = self.provenance.as_mut() {
+            origins.truncate(pos.nodes);
+        }
+        if self.compiled.is_some_and(|index| index >= pos.nodes) {
+            self.compiled = self.outputs.first().copied();
+        }
+        Arc::make_mut(&mut self.cse).retain(|_, index| *index < pos.nodes);
+        if pos.custom_ops < self.custom_ops.len() {
+            Arc::make_mut(&mut self.custom_ops).truncate(pos.custom_ops);
+        }
+        self.topological_order = None;
+        self.reverse_order = None;
+        self.grad_mask = None;
+    }
+
+    /// Record, evaluate & discard a suffix on top of the current tape
+    ///
+    /// `f` may add variables, parameters & nodes and compile (e.g. one iteration of an algorithm
+    /// extending a fixed model); afterwards the tape is rolled back to its current end and the
+    /// outputs are restored. Values of the prefix computed inside `f` stay cached, so the prefix is
+    /// evaluated once across iterations as long as its leaves are not changed (and `reset` is not
+    /// called). Compile the prefix before the first suffix, so that compiling inside `f` does
+    /// not rewrite prefix nodes in terms of suffix nodes.
+    pub fn with_suffix<R, F: FnOnce(&mut Self) -> R>(&mut self, f: F) -> R {
+        let pos = self.mark();
+        let outputs = self.outputs.clone();
+        let output_names = self.output_names.clone();
+        let compiled = self.compiled;
+        let result = f(self);
+        self.rollback(pos);
+        self.outputs = outputs;
+        self.output_names = output_names;
+        self.compiled = compiled;
+        result
+    }
+
+    /// Remove every node, variable & output while keeping the allocated capacity
+    pub fn clear(&mut self) {
+        self.gradients.clear();
+        self.buffer.clear();
+        Arc::make_mut(&mut self.nodes).clear();
+        self.value_ics.clear();
+        self.param_ics.clear();
+        self.compiled = None;
+        self.outputs.clear();
+        self.output_names.clear();
+        self.groups.clear();
+        self.labels.clear();
+        if let Some(origins) = self.provenance.as_mut() {
+            origins.clear();
+        }
+        self.topological_order = None;
+        self.reverse_order = None;
+        self.requires_grad.clear();
+        self.grad_mask = None;
+        Arc::make_mut(&mut self.cse).clear();
+        Arc::make_mut(&mut self.custom_ops).clear();
+    }
+
+    /// Zero every gradient while keeping the cached forward values
+    pub fn zero_grad(&mut self) {
         for i in 0 .. self.buffer.len() {
             self.gradients[i] = match self.buffer[i].as_ref() {
                 Some(x) => x.zeros_like(),
                 None => T::default(),
             }
         }
-        for i in reset_ics {
-            self.buffer[i] = None;
-        }
-    }
-
-    //#[allow(unused_variables)]
-    //pub fn backward_step(&mut self, index: usize, upstream_gradient: T) {
-    //    match self.nodes[index] {
-    //        Node::Var(value_index) => {
-    //            self.gradients[value_index] =
-    //                self.gradients[value_index].clone() + upstream_gradient;
-    //        }
-    //        Node::Add(left_index, right_index) => {
-    //            self.backward_step(left_index, upstream_gradient.clone());
-    //            self.backward_step(right_index, upstream_gradient);
-    //        }
-    //        Node::Addf(_, right_index) => {
-    //            self.backward_step(right_index, upstream_gradient);
-    //        }
-    //        Node::Sub(left_index, right_index) => {
-    //            self.backward_step(left_index, upstream_gradient.clone());
-    //            self.backward_step(right_index, -upstream_gradient);
-    //        }
-    //        Node::Subf(left_index, _) => {
-    //            self.backward_step(left_index, upstream_gradient);
-    //        }
-    //        Node::Mul(left_index, right_index) => {
-    //            let left_val = self.forward_step(left_index);
-    //            let right_val = self.forward_step(right_index);
-    //            self.backward_step(left_index, upstream_gradient.clone() * right_val.transpose());
-    //            self.backward_step(right_index, left_val.transpose() * upstream_gradient);
-    //        }
-    //        Node::Mulf(num, right_index) => {
-    //            self.backward_step(right_index, upstream_gradient * num);
-    //        }
-    //        Node::Hadamard(left_index, right_index) => {
-    //            let left_val = self.forward_step(left_index);
-    //            let right_val = self.forward_step(right_index);
-    //            self.backward_step(
-    //                left_index,
-    //                right_val.hadamard(&upstream_gradient),
-    //            );
-    //            self.backward_step(
-    //                right_index,
-    //                left_val.hadamard(&upstream_gradient),
-    //            );
-    //        }
-    //        Node::Transpose(operand_index) => {
-    //            self.backward_step(operand_index, upstream_gradient.transpose());
-    //        }
-    //        Node::Div(left_index, right_index) => {
-    //            let left_val = self.forward_step(left_index);
-    //            let right_val = self.forward_step(right_index);
-    //            self.backward_step(left_index, upstream_gradient.clone() / right_val.clone());
-    //            self.backward_step(
-    //                right_index,
-    //                -(left_val / right_val.hadamard(&right_val)).hadamard(&upstream_gradient),
-    //            );
-    //        }
-    //        Node::Pow(left_index, right_index) => {
-    //            todo!()
-    //        }
-    //        Node::Powf(operand_index, power) => {
-    //            todo!()
-    //        }
-    //        Node::Powi(operand_index, power) => {
-    //            todo!()
-    //        }
-    //        Node::Neg(operand_index) => {
-    //            let operand_val = self.forward_step(operand_index);
-    //            self.backward_step(operand_index, -operand_val.hadamard(&upstream_gradient));
-    //        }
-    //        Node::Recip(operand_index) => {
-    //            let operand_val = self.forward_step(operand_index);
-    //            self.backward_step(operand_index, -upstream_gradient / (operand_val.hadamard(&operand_val)));
-    //        }
-    //        Node::Exp(operand_index) => {
-    //            let operand_val = self.forward_step(operand_index);
-    //            self.backward_step(operand_index, operand_val.exp().hadamard(&upstream_gradient));
-    //        }
-    //        Node::Ln(operand_index) => {
-    //            let operand_val = self.forward_step(operand_index);
-    //            self.backward_step(operand_index, upstream_gradient / operand_val);
-    //        }
-    //        Node::Sin(operand_index) => {
-    //            let operand_val = self.forward_step(operand_index);
-    //            self.backward_step(operand_index, operand_val.cos().hadamard(&upstream_gradient));
-    //        }
-    //        Node::Cos(operand_index) => {
-    //            let operand_val = self.forward_step(operand_index);
-    //            self.backward_step(operand_index, -operand_val.sin().hadamard(&upstream_gradient));
-    //        }
-    //        Node::Tan(operand_index) => {
-    //            let operand_val = self.forward_step(operand_index);
-    //            let tan = operand_val.tan();
-    //            self.backward_step(
-    //                operand_index,
-    //                (tan.hadamard(&tan) + 1f64).hadamard(&upstream_gradient),
-    //            )
-    //        }
-    //        Node::Sinh(operand_index) => {
-    //            let operand_val = self.forward_step(operand_index);
-    //            self.backward_step(operand_index, operand_val.cosh().hadamard(&upstream_gradient));
-    //        }
-    //        Node::Cosh(operand_index) => {
-    //            let operand_val = self.forward_step(operand_index);
-    //            self.backward_step(operand_index, operand_val.sinh().hadamard(&upstream_gradient));
-    //        }
-    //        Node::Tanh(operand_index) => {
-    //            let operand_val = self.forward_step(operand_index);
-    //            let tanh = operand_val.tanh();
-    //            self.backward_step(
-    //                operand_index,
-    //                (-(tanh.hadamard(&tanh) - 1f64)).hadamard(&upstream_gradient),
-    //            );
-    //        }
-    //        Node::Sigmoid(operand_index) => {
-    //            let operand_val = self.forward_step(operand_index).sigmoid();
-    //            let diff_from_one = -operand_val.clone() + 1f64;
-    //            self.backward_step(
-    //                operand_index,
-    //                operand_val.hadamard(&diff_from_one).hadamard(&upstream_gradient),
-    //            );
-    //        }
-    //        Node::ReLU(operand_index) => {
-    //            let operand_val = self.forward_step(operand_index).heaviside_zero();
-    //            self.backward_step(operand_index, operand_val.hadamard(&upstream_gradient));
-    //        }
-    //    }
-    //}
+    }
+
+    /// Drop cached forward values except variables & parameters (gradients are kept)
+    ///
+    /// Leaves are recognized by their own node, so this is a single linear pass.
+    pub fn invalidate_values(&mut self) {
+        for (value, node) in self.buffer.iter_mut().zip(self.nodes.iter()) {
+            if !matches!(node, Node::Var(_) | Node::Param(_)) {
+                *value = None;
+            }
+        }
+    }
 
     pub fn get_gradient(&self, index: usize) -> T {
         self.gradients[index].clone()
     }
 
+    /// Adjoint `∂root/∂node` of any node (not only variables) after `backward`
+    ///
+    /// Nodes which cannot reach a differentiable variable are skipped by `backward`, so their adjoint is zero.
+    pub fn adjoint(&self, index: usize) -> T {
+        self.gradients[index].clone()
+    }
+
     pub fn get_gradients(&self) -> Vec<T> {
         let value_ics = self.get_vars();
         value_ics.iter().map(|x| self.get_gradient(*x)).collect()
     }
 
+    #[track_caller]
     pub fn compile(&mut self, expr: Expr) {
-        self.compiled = Some(parse_expr(expr, self));
+        self.compile_many(vec![expr]);
+    }
+
+    /// Compile several expressions onto one shared tape
+    ///
+    /// The first expression becomes the default output of `forward` & `backward`.
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(level = "debug", skip_all, fields(nodes = self.nodes.len()))
+    )]
+    #[track_caller]
+    pub fn compile_many(&mut self, exprs: Vec<Expr>) {
+        assert!(!exprs.is_empty(), "No expression to compile");
+        let location = Location::caller();
+        self.outputs = exprs.into_iter().map(|expr| parse_expr_at(expr, self, location)).collect();
+        self.output_names.clear();
+        self.compiled = Some(self.outputs[0]);
         self.topological_order = None;
+        self.fold_constants();
+        self.reduce_powi();
+        self.update_grad_mask();
+        self.schedule();
+        #[cfg(feature = "tracing")]
+        tracing::debug!(nodes = self.nodes.len(), vars = self.value_ics.len(), "compiled");
+    }
+
+    pub fn get_outputs(&self) -> Vec<usize> {
+        self.outputs.clone()
+    }
+
+    /// Compile an expression as a named output on the shared tape
+    ///
+    /// Previously compiled outputs are kept. If nothing is compiled yet, it becomes the default output.
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(level = "debug", skip_all, fields(nodes = self.nodes.len(), name))
+    )]
+    #[track_caller]
+    pub fn compile_named(&mut self, name: &str, expr: Expr) -> usize {
+        let index = parse_expr(expr, self);
+        self.outputs.push(index);
+        self.output_names.insert(name.to_string(), index);
+        if self.compiled.is_none() {
+            self.compiled = Some(index);
+        }
+        self.topological_order = None;
+        self.fold_constants();
+        self.reduce_powi();
+        self.update_grad_mask();
+        self.schedule();
+        #[cfg(feature = "tracing")]
+        tracing::debug!(nodes = self.nodes.len(), vars = self.value_ics.len(), "compiled");
+        index
+    }
+
+    pub fn get_output(&self, name: &str) -> usize {
+        match self.output_names.get(name) {
+            Some(index) => *index,
+            None => panic!("No output named {}", name),
+        }
+    }
+
+    /// Make the named output the default of `forward` & `backward`
+    pub fn select(&mut self, name: &str) {
+        self.compiled = Some(self.get_output(name));
+    }
+
+    pub fn forward_named(&mut self, name: &str) -> T {
+        let index = self.get_output(name);
+        self.forward_sweep();
+        self.buffer[index].clone().unwrap()
+    }
+
+    pub fn backward_named(&mut self, name: &str) {
+        let index = self.get_output(name);
+        self.backward_from(index);
     }
 
     pub fn get_compiled(&self) -> Option<usize> {
         self.compiled
     }
 
-    //pub fn forward(&mut self) -> T {
-    //    match self.compiled {
-    //        Some(idx) => self.forward_step(idx),
-    //        None => panic!("No compiled expression"),
-    //    }
-    //}
-
-    //pub fn backward(&mut self) {
-    //    match self.compiled {
-    //        Some(idx) => {
-    //            let value = self.buffer[idx].as_ref().unwrap().ones_like();
-    //            //println!("Value: {:?}", value);
-    //            self.backward_step(idx, value);
-    //        },
-    //        None => panic!("No compiled expression"),
-    //    }
-    //}
+    /// `forward` reporting missing compiled expression, uninitialized leaves or failed implicit
+    /// solves instead of panicking
+    pub fn try_forward(&mut self) -> Result<T, GraphError> {
+        let root = self.compiled.ok_or(GraphError::NotCompiled)?;
+        if let Some((index, _)) = self
+            .nodes
+            .iter()
+            .zip(self.buffer.iter())
+            .enumerate()
+            .find(|(_, (node, value))| matches!(node, Node::Var(_) | Node::Param(_)) && value.is_none())
+        {
+            return Err(GraphError::Uninitialized(index));
+        }
+        let value = self.forward();
+        let reached = self.reached_from(root);
+        for (index, node) in self.nodes.iter().enumerate() {
+            if let Node::External(id, ..) = node {
+                if let CustomOp::Implicit { system, .. } = &self.custom_ops[id] {
+                    if reached[index] && !system.converged() {
+                        return Err(GraphError::NotConverged(index));
+                    }
+                }
+            }
+        }
+        Ok(value)
+    }
+
+    /// `backward` reporting missing compiled expression or forward values instead of panicking
+    pub fn try_backward(&mut self) -> Result<(), GraphError> {
+        self.compiled.ok_or(GraphError::NotCompiled)?;
+        if self.buffer.iter().any(|x| x.is_none()) {
+            return Err(GraphError::NotEvaluated);
+        }
+        self.backward();
+        Ok(())
+    }
+
+    /// `subs_var` checking that `index` is a variable of this graph
+    pub fn try_subs_var(&mut self, index: usize, value: T) -> Result<(), GraphError> {
+        match self.nodes.get(index) {
+            None => Err(GraphError::IndexOutOfBounds { index, len: self.nodes.len() }),
+            Some(Node::Var(_)) => {
+                self.subs_var(index, value);
+                Ok(())
+            }
+            Some(_) => Err(GraphError::NotAVariable(index)),
+        }
+    }
+
+    /// Substitute variables by label (see `label` & `var_named`)
+    ///
+    /// Every name has to label a variable; nothing is substituted otherwise.
+    pub fn subs_vars_map(&mut self, vals: &HashMap<&str, T>) -> Result<(), GraphError> {
+        let mut targets = Vec::with_capacity(vals.len());
+        for (name, val) in vals.iter() {
+            let index = self
+                .find_label(name)
+                .ok_or_else(|| GraphError::UnknownLabel(name.to_string()))?;
+            if !matches!(self.nodes.node(index), Node::Var(_)) {
+                return Err(GraphError::NotAVariable(index));
+            }
+            targets.push((index, val));
+        }
+        for (index, val) in targets {
+            self.subs_var(index, val.clone());
+        }
+        Ok(())
+    }
 }
 
 // ┌──────────────────────────────────────────────────────────┐
 //  Symbol for generating Abstract Expressions
 // └──────────────────────────────────────────────────────────┘
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq)]
 pub enum Expr {
     Symbol(usize),
-    Add(Box<Expr>, Box<Expr>),
-    Addf(f64, Box<Expr>),
-    Sub(Box<Expr>, Box<Expr>),
-    Subf(Box<Expr>, f64),
-    Mul(Box<Expr>, Box<Expr>),
-    Mulf(f64, Box<Expr>),
-    Hadamard(Box<Expr>, Box<Expr>),
-    Div(Box<Expr>, Box<Expr>),
-    Pow(Box<Expr>, Box<Expr>),
-    Powf(Box<Expr>, f64),
-    Powi(Box<Expr>, i32),
-    Neg(Box<Expr>),
-    Recip(Box<Expr>),
-    Exp(Box<Expr>),
-    Ln(Box<Expr>),
-    Sin(Box<Expr>),
-    Cos(Box<Expr>),
-    Tan(Box<Expr>),
-    Sinh(Box<Expr>),
-    Cosh(Box<Expr>),
-    Tanh(Box<Expr>),
-    Sigmoid(Box<Expr>),
-    ReLU(Box<Expr>),
+    Const(f64),
+    Add(Rc<Expr>, Rc<Expr>),
+    Addf(f64, Rc<Expr>),
+    Sub(Rc<Expr>, Rc<Expr>),
+    Subf(Rc<Expr>, f64),
+    Mul(Rc<Expr>, Rc<Expr>),
+    Mulf(f64, Rc<Expr>),
+    Hadamard(Rc<Expr>, Rc<Expr>),
+    Div(Rc<Expr>, Rc<Expr>),
+    Pow(Rc<Expr>, Rc<Expr>),
+    Powf(Rc<Expr>, f64),
+    Powi(Rc<Expr>, i32),
+    Neg(Rc<Expr>),
+    Recip(Rc<Expr>),
+    Exp(Rc<Expr>),
+    Ln(Rc<Expr>),
+    Sin(Rc<Expr>),
+    Cos(Rc<Expr>),
+    Tan(Rc<Expr>),
+    Sinh(Rc<Expr>),
+    Cosh(Rc<Expr>),
+    Tanh(Rc<Expr>),
+    Sigmoid(Rc<Expr>),
+    ReLU(Rc<Expr>),
+    Heaviside(Rc<Expr>),
+    Select(Rc<Expr>, Rc<Expr>, Rc<Expr>),
+}
+
+thread_local! {
+    static LEAF: Rc<Expr> = Rc::new(Expr::Const(0.0));
+    /// Shared leaves: `Symbol(i)` for small `i` & integer constants in `[-SMALL_INT, SMALL_INT]`
+    static SYMBOLS: RefCell<Vec<Option<Rc<Expr>>>> = const { RefCell::new(Vec::new()) };
+    static INTS: Vec<Rc<Expr>> = (-SMALL_INT..=SMALL_INT).map(|k| Rc::new(Expr::Const(k as f64))).collect();
+}
+
+const SMALL_SYMBOL: usize = 1 << 16;
+const SMALL_INT: i32 = 16;
+
+/// Operand of an expression node
+///
+/// Leaves are interned (symbols below `SMALL_SYMBOL` & small integer constants), so wrapping a
+/// leaf or building a unary op over one only bumps a reference count instead of allocating.
+pub(crate) fn rc(expr: Expr) -> Rc<Expr> {
+    match expr {
+        Expr::Symbol(index) if index < SMALL_SYMBOL => SYMBOLS.with(|symbols| {
+            let mut symbols = symbols.borrow_mut();
+            if symbols.len() <= index {
+                symbols.resize(index + 1, None);
+            }
+            Rc::clone(symbols[index].get_or_insert_with(|| Rc::new(Expr::Symbol(index))))
+        }),
+        Expr::Const(value)
+            if value.fract() == 0f64
+                && value.abs() <= SMALL_INT as f64
+                && (value != 0f64 || value.is_sign_positive()) =>
+        {
+            INTS.with(|ints| Rc::clone(&ints[(value as i32 + SMALL_INT) as usize]))
+        }
+        _ => Rc::new(expr),
+    }
+}
+
+impl Drop for Expr {
+    /// Unlink uniquely owned children iteratively, so dropping a long chain does not overflow the stack
+    fn drop(&mut self) {
+        let mut stack = Vec::new();
+        let detach = |expr: &mut Expr, stack: &mut Vec<Rc<Expr>>| {
+            for child in expr.children_mut() {
+                if Rc::strong_count(child) == 1 {
+                    stack.push(std::mem::replace(child, LEAF.with(Rc::clone)));
+                }
+            }
+        };
+        detach(self, &mut stack);
+        while let Some(child) = stack.pop() {
+            if let Ok(mut expr) = Rc::try_unwrap(child) {
+                detach(&mut expr, &mut stack);
+            }
+        }
+    }
+}
+
+impl Expr {
+    /// Rebuild the expression with every direct child replaced by `f(child)`
+    pub(crate) fn map_children<F: FnMut(&Expr) -> Expr>(&self, mut f: F) -> Expr {
+        match self {
+            Expr::Symbol(index) => Expr::Symbol(*index),
+            Expr::Const(value) => Expr::Const(*value),
+            Expr::Add(l, r) => Expr::Add(rc(f(l)), rc(f(r))),
+            Expr::Sub(l, r) => Expr::Sub(rc(f(l)), rc(f(r))),
+            Expr::Mul(l, r) => Expr::Mul(rc(f(l)), rc(f(r))),
+            Expr::Hadamard(l, r) => Expr::Hadamard(rc(f(l)), rc(f(r))),
+            Expr::Div(l, r) => Expr::Div(rc(f(l)), rc(f(r))),
+            Expr::Pow(l, r) => Expr::Pow(rc(f(l)), rc(f(r))),
+            Expr::Addf(num, r) => Expr::Addf(*num, rc(f(r))),
+            Expr::Subf(l, num) => Expr::Subf(rc(f(l)), *num),
+            Expr::Mulf(num, r) => Expr::Mulf(*num, rc(f(r))),
+            Expr::Powf(l, num) => Expr::Powf(rc(f(l)), *num),
+            Expr::Powi(l, num) => Expr::Powi(rc(f(l)), *num),
+            Expr::Neg(x) => Expr::Neg(rc(f(x))),
+            Expr::Recip(x) => Expr::Recip(rc(f(x))),
+            Expr::Exp(x) => Expr::Exp(rc(f(x))),
+            Expr::Ln(x) => Expr::Ln(rc(f(x))),
+            Expr::Sin(x) => Expr::Sin(rc(f(x))),
+            Expr::Cos(x) => Expr::Cos(rc(f(x))),
+            Expr::Tan(x) => Expr::Tan(rc(f(x))),
+            Expr::Sinh(x) => Expr::Sinh(rc(f(x))),
+            Expr::Cosh(x) => Expr::Cosh(rc(f(x))),
+            Expr::Tanh(x) => Expr::Tanh(rc(f(x))),
+            Expr::Sigmoid(x) => Expr::Sigmoid(rc(f(x))),
+            Expr::ReLU(x) => Expr::ReLU(rc(f(x))),
+            Expr::Heaviside(x) => Expr::Heaviside(rc(f(x))),
+            Expr::Select(c, a, b) => Expr::Select(rc(f(c)), rc(f(a)), rc(f(b))),
+        }
+    }
+
+    /// Name of the constructor (e.g. `"Ln"`)
+    pub fn name(&self) -> &'static str {
+        match self {
+            Expr::Symbol(_) => "Symbol",
+            Expr::Const(_) => "Const",
+            Expr::Add(..) => "Add",
+            Expr::Addf(..) => "Addf",
+            Expr::Sub(..) => "Sub",
+            Expr::Subf(..) => "Subf",
+            Expr::Mul(..) => "Mul",
+            Expr::Mulf(..) => "Mulf",
+            Expr::Hadamard(..) => "Hadamard",
+            Expr::Div(..) => "Div",
+            Expr::Pow(..) => "Pow",
+            Expr::Powf(..) => "Powf",
+            Expr::Powi(..) => "Powi",
+            Expr::Neg(_) => "Neg",
+            Expr::Recip(_) => "Recip",
+            Expr::Exp(_) => "Exp",
+            Expr::Ln(_) => "Ln",
+            Expr::Sin(_) => "Sin",
+            Expr::Cos(_) => "Cos",
+            Expr::Tan(_) => "Tan",
+            Expr::Sinh(_) => "Sinh",
+            Expr::Cosh(_) => "Cosh",
+            Expr::Tanh(_) => "Tanh",
+            Expr::Sigmoid(_) => "Sigmoid",
+            Expr::ReLU(_) => "ReLU",
+            Expr::Heaviside(_) => "Heaviside",
+            Expr::Select(..) => "Select",
+        }
+    }
+
+    /// Direct children (operands) from left to right
+    pub(crate) fn children(&self) -> Vec<&Rc<Expr>> {
+        match self {
+            Expr::Symbol(_) | Expr::Const(_) => vec![],
+            Expr::Add(l, r)
+            | Expr::Sub(l, r)
+            | Expr::Mul(l, r)
+            | Expr::Hadamard(l, r)
+            | Expr::Div(l, r)
+            | Expr::Pow(l, r) => vec![l, r],
+            Expr::Select(c, a, b) => vec![c, a, b],
+            Expr::Addf(_, x)
+            | Expr::Subf(x, _)
+            | Expr::Mulf(_, x)
+            | Expr::Powf(x, _)
+            | Expr::Powi(x, _)
+            | Expr::Neg(x)
+            | Expr::Recip(x)
+            | Expr::Exp(x)
+            | Expr::Ln(x)
+            | Expr::Sin(x)
+            | Expr::Cos(x)
+            | Expr::Tan(x)
+            | Expr::Sinh(x)
+            | Expr::Cosh(x)
+            | Expr::Tanh(x)
+            | Expr::Sigmoid(x)
+            | Expr::ReLU(x)
+            | Expr::Heaviside(x) => vec![x],
+        }
+    }
+
+    fn children_mut(&mut self) -> Vec<&mut Rc<Expr>> {
+        match self {
+            Expr::Symbol(_) | Expr::Const(_) => vec![],
+            Expr::Add(l, r)
+            | Expr::Sub(l, r)
+            | Expr::Mul(l, r)
+            | Expr::Hadamard(l, r)
+            | Expr::Div(l, r)
+            | Expr::Pow(l, r) => vec![l, r],
+            Expr::Select(c, a, b) => vec![c, a, b],
+            Expr::Addf(_, x)
+            | Expr::Subf(x, _)
+            | Expr::Mulf(_, x)
+            | Expr::Powf(x, _)
+            | Expr::Powi(x, _)
+            | Expr::Neg(x)
+            | Expr::Recip(x)
+            | Expr::Exp(x)
+            | Expr::Ln(x)
+            | Expr::Sin(x)
+            | Expr::Cos(x)
+            | Expr::Tan(x)
+            | Expr::Sinh(x)
+            | Expr::Cosh(x)
+            | Expr::Tanh(x)
+            | Expr::Sigmoid(x)
+            | Expr::ReLU(x)
+            | Expr::Heaviside(x) => vec![x],
+        }
+    }
+
+    /// Replace every occurrence of `Symbol(symbol)` by `expr`
+    pub fn substitute(&self, symbol: usize, expr: &Expr) -> Expr {
+        match self {
+            Expr::Symbol(index) if *index == symbol => expr.clone(),
+            _ => self.map_children(|x| x.substitute(symbol, expr)),
+        }
+    }
+
+    /// Replace symbols simultaneously according to `map` (symbol index → expression)
+    pub fn substitute_map(&self, map: &HashMap<usize, Expr>) -> Expr {
+        match self {
+            Expr::Symbol(index) => match map.get(index) {
+                Some(expr) => expr.clone(),
+                None => Expr::Symbol(*index),
+            },
+            _ => self.map_children(|x| x.substitute_map(map)),
+        }
+    }
+
+    /// `if_pos` where `self` is positive (including `+0`), `if_neg` elsewhere
+    ///
+    /// Only the taken branch receives gradient; `self` receives none.
+    pub fn select(&self, if_pos: Expr, if_neg: Expr) -> Expr {
+        Expr::Select(rc(self.clone()), rc(if_pos), rc(if_neg))
+    }
+
+    /// Value of the first `(cond, value)` piece whose condition is positive, `default` if none is
+    ///
+    /// Built as a chain of `select`s, so only the taken piece receives gradient.
+    pub fn piecewise(pieces: &[(Expr, Expr)], default: Expr) -> Expr {
+        pieces
+            .iter()
+            .rev()
+            .fold(default, |acc, (cond, value)| cond.select(value.clone(), acc))
+    }
+
+    /// `piecewise` with sigmoid-weighted transitions of `width` around each `cond = 0`
+    ///
+    /// Every piece receives gradient; as `width → 0` this approaches `piecewise`.
+    pub fn piecewise_smooth(pieces: &[(Expr, Expr)], default: Expr, width: f64) -> Expr {
+        pieces.iter().rev().fold(default, |acc, (cond, value)| {
+            let w = (cond.clone() / width).sigmoid();
+            w.clone() * value.clone() + (1f64 - w) * acc
+        })
+    }
+
+    /// `f` applied `n` times starting from `self` (`f(f(...f(self)))`)
+    ///
+    /// Each step only wraps the previous state (children are shared through `Rc`), so the tree
+    /// grows linearly in `n`, and `parse_expr` visits every step once.
+    pub fn iterate<F: Fn(&Expr) -> Expr>(&self, n: usize, f: F) -> Expr {
+        (0..n).fold(self.clone(), |state, _| f(&state))
+    }
+
+    /// `iterate` for a vector state (e.g. a time stepper of several variables)
+    pub fn iterate_many<F: Fn(&[Expr]) -> Vec<Expr>>(init: &[Expr], n: usize, f: F) -> Vec<Expr> {
+        (0..n).fold(init.to_vec(), |state, _| f(&state))
+    }
+}
+
+impl From<f64> for Expr {
+    fn from(value: f64) -> Self {
+        Expr::Const(value)
+    }
 }
 
 impl Neg for Expr {
     type Output = Expr;
 
     fn neg(self) -> Self::Output {
-        Expr::Neg(Box::new(self))
+        Expr::Neg(rc(self))
     }
 }
 
@@ -799,7 +1681,7 @@ impl Neg for &Expr {
     type Output = Expr;
 
     fn neg(self) -> Self::Output {
-        Expr::Neg(Box::new(self.clone()))
+        Expr::Neg(rc(self.clone()))
     }
 }
 
@@ -807,7 +1689,7 @@ impl Add for Expr {
     type Output = Expr;
 
     fn add(self, rhs: Self) -> Self::Output {
-        Expr::Add(Box::new(self), Box::new(rhs))
+        Expr::Add(rc(self), rc(rhs))
     }
 }
 
@@ -815,7 +1697,7 @@ impl Add for &Expr {
     type Output = Expr;
 
     fn add(self, rhs: Self) -> Self::Output {
-        Expr::Add(Box::new(self.clone()), Box::new(rhs.clone()))
+        Expr::Add(rc(self.clone()), rc(rhs.clone()))
     }
 }
 
@@ -823,7 +1705,7 @@ impl Add<Expr> for f64 {
     type Output = Expr;
 
     fn add(self, rhs: Expr) -> Self::Output {
-        Expr::Addf(self, Box::new(rhs))
+        Expr::Addf(self, rc(rhs))
     }
 }
 
@@ -831,7 +1713,7 @@ impl Add<f64> for Expr {
     type Output = Expr;
 
     fn add(self, rhs: f64) -> Self::Output {
-        Expr::Addf(rhs, Box::new(self))
+        Expr::Addf(rhs, rc(self))
     }
 }
 
@@ -839,7 +1721,7 @@ impl Add<&Expr> for f64 {
     type Output = Expr;
 
     fn add(self, rhs: &Expr) -> Self::Output {
-        Expr::Addf(self, Box::new(rhs.clone()))
+        Expr::Addf(self, rc(rhs.clone()))
     }
 }
 
@@ -847,7 +1729,7 @@ impl Add<f64> for &Expr {
     type Output = Expr;
 
     fn add(self, rhs: f64) -> Self::Output {
-        Expr::Addf(rhs, Box::new(self.clone()))
+        Expr::Addf(rhs, rc(self.clone()))
     }
 }
 
@@ -855,7 +1737,7 @@ impl Sub for Expr {
     type Output = Expr;
 
     fn sub(self, rhs: Self) -> Self::Output {
-        Expr::Sub(Box::new(self), Box::new(rhs))
+        Expr::Sub(rc(self), rc(rhs))
     }
 }
 
@@ -863,7 +1745,7 @@ impl Sub for &Expr {
     type Output = Expr;
 
     fn sub(self, rhs: Self) -> Self::Output {
-        Expr::Sub(Box::new(self.clone()), Box::new(rhs.clone()))
+        Expr::Sub(rc(self.clone()), rc(rhs.clone()))
     }
 }
 
@@ -871,7 +1753,7 @@ impl Sub<Expr> for f64 {
     type Output = Expr;
 
     fn sub(self, rhs: Expr) -> Self::Output {
-        Expr::Neg(Box::new(Expr::Subf(Box::new(rhs), self)))
+        Expr::Neg(rc(Expr::Subf(rc(rhs), self)))
     }
 }
 
@@ -879,7 +1761,7 @@ impl Sub<f64> for Expr {
     type Output = Expr;
 
     fn sub(self, rhs: f64) -> Self::Output {
-        Expr::Subf(Box::new(self), rhs)
+        Expr::Subf(rc(self), rhs)
     }
 }
 
@@ -887,7 +1769,7 @@ impl Sub<&Expr> for f64 {
     type Output = Expr;
 
     fn sub(self, rhs: &Expr) -> Self::Output {
-        Expr::Subf(Box::new(rhs.clone()), self)
+        Expr::Neg(rc(Expr::Subf(rc(rhs.clone()), self)))
     }
 }
 
@@ -895,7 +1777,7 @@ impl Sub<f64> for &Expr {
     type Output = Expr;
 
     fn sub(self, rhs: f64) -> Self::Output {
-        Expr::Subf(Box::new(self.clone()), rhs)
+        Expr::Subf(rc(self.clone()), rhs)
     }
 }
 
@@ -903,7 +1785,7 @@ impl Mul for Expr {
     type Output = Expr;
 
     fn mul(self, rhs: Self) -> Self::Output {
-        Expr::Mul(Box::new(self), Box::new(rhs))
+        Expr::Mul(rc(self), rc(rhs))
     }
 }
 
@@ -911,7 +1793,7 @@ impl Mul for &Expr {
     type Output = Expr;
 
     fn mul(self, rhs: Self) -> Self::Output {
-        Expr::Mul(Box::new(self.clone()), Box::new(rhs.clone()))
+        Expr::Mul(rc(self.clone()), rc(rhs.clone()))
     }
 }
 
@@ -919,7 +1801,7 @@ impl Mul<Expr> for f64 {
     type Output = Expr;
 
     fn mul(self, rhs: Expr) -> Self::Output {
-        Expr::Mulf(self, Box::new(rhs))
+        Expr::Mulf(self, rc(rhs))
     }
 }
 
@@ -927,7 +1809,7 @@ impl Mul<f64> for Expr {
     type Output = Expr;
 
     fn mul(self, rhs: f64) -> Self::Output {
-        Expr::Mulf(rhs, Box::new(self))
+        Expr::Mulf(rhs, rc(self))
     }
 }
 
@@ -935,7 +1817,7 @@ impl Mul<&Expr> for f64 {
     type Output = Expr;
 
     fn mul(self, rhs: &Expr) -> Self::Output {
-        Expr::Mulf(self, Box::new(rhs.clone()))
+        Expr::Mulf(self, rc(rhs.clone()))
     }
 }
 
@@ -943,7 +1825,7 @@ impl Mul<f64> for &Expr {
     type Output = Expr;
 
     fn mul(self, rhs: f64) -> Self::Output {
-        Expr::Mulf(rhs, Box::new(self.clone()))
+        Expr::Mulf(rhs, rc(self.clone()))
     }
 }
 
@@ -951,7 +1833,7 @@ impl Div for Expr {
     type Output = Expr;
 
     fn div(self, rhs: Self) -> Self::Output {
-        Expr::Div(Box::new(self), Box::new(rhs))
+        Expr::Div(rc(self), rc(rhs))
     }
 }
 
@@ -959,7 +1841,7 @@ impl Div for &Expr {
     type Output = Expr;
 
     fn div(self, rhs: Self) -> Self::Output {
-        Expr::Div(Box::new(self.clone()), Box::new(rhs.clone()))
+        Expr::Div(rc(self.clone()), rc(rhs.clone()))
     }
 }
 
@@ -967,7 +1849,7 @@ impl Div<Expr> for f64 {
     type Output = Expr;
 
     fn div(self, rhs: Expr) -> Self::Output {
-        Expr::Recip(Box::new(rhs))
+        Expr::Mulf(self, rc(Expr::Recip(rc(rhs))))
     }
 }
 
@@ -983,7 +1865,7 @@ impl Div<&Expr> for f64 {
     type Output = Expr;
 
     fn div(self, rhs: &Expr) -> Self::Output {
-        Expr::Recip(Box::new(rhs.clone()))
+        Expr::Mulf(self, rc(Expr::Recip(rc(rhs.clone()))))
     }
 }
 
@@ -998,33 +1880,33 @@ impl Div<f64> for &Expr {
 impl TrigOps for Expr {
     fn sin_cos(&self) -> (Self, Self) {
         (
-            Expr::Sin(Box::new(self.clone())),
-            Expr::Cos(Box::new(self.clone())),
+            Expr::Sin(rc(self.clone())),
+            Expr::Cos(rc(self.clone())),
         )
     }
 
     fn sin(&self) -> Self {
-        Expr::Sin(Box::new(self.clone()))
+        Expr::Sin(rc(self.clone()))
     }
 
     fn cos(&self) -> Self {
-        Expr::Cos(Box::new(self.clone()))
+        Expr::Cos(rc(self.clone()))
     }
 
     fn tan(&self) -> Self {
-        Expr::Tan(Box::new(self.clone()))
+        Expr::Tan(rc(self.clone()))
     }
 
     fn sinh(&self) -> Self {
-        Expr::Sinh(Box::new(self.clone()))
+        Expr::Sinh(rc(self.clone()))
     }
 
     fn cosh(&self) -> Self {
-        Expr::Cosh(Box::new(self.clone()))
+        Expr::Cosh(rc(self.clone()))
     }
 
     fn tanh(&self) -> Self {
-        Expr::Tanh(Box::new(self.clone()))
+        Expr::Tanh(rc(self.clone()))
     }
 
     fn asin(&self) -> Self {
@@ -1056,19 +1938,19 @@ impl PowOps for Expr {
     type Float = f64;
 
     fn powi(&self, rhs: i32) -> Self {
-        Expr::Powi(Box::new(self.clone()), rhs)
+        Expr::Powi(rc(self.clone()), rhs)
     }
 
     fn powf(&self, rhs: f64) -> Self {
-        Expr::Powf(Box::new(self.clone()), rhs)
+        Expr::Powf(rc(self.clone()), rhs)
     }
 
     fn pow(&self, rhs: Self) -> Self {
-        Expr::Pow(Box::new(self.clone()), Box::new(rhs))
+        Expr::Pow(rc(self.clone()), rc(rhs))
     }
 
     fn sqrt(&self) -> Self {
-        Expr::Powf(Box::new(self.clone()), 0.5)
+        Expr::Powf(rc(self.clone()), 0.5)
     }
 }
 
@@ -1076,11 +1958,11 @@ impl ExpLogOps for Expr {
     type Float = f64;
 
     fn exp(&self) -> Self {
-        Expr::Exp(Box::new(self.clone()))
+        Expr::Exp(rc(self.clone()))
     }
 
     fn ln(&self) -> Self {
-        Expr::Ln(Box::new(self.clone()))
+        Expr::Ln(rc(self.clone()))
     }
 
     fn log(&self, _base: f64) -> Self {
@@ -1101,6 +1983,7 @@ impl Numeric<f64> for Expr {}
 // ┌──────────────────────────────────────────────────────────┐
 //  Parsing Expr to Graph
 // └──────────────────────────────────────────────────────────┘
+#[track_caller]
 pub fn parse_expr<T: std::fmt::Debug + Numeric<f64> + Default + ActivationFunction + Matrizable>(
     expr: Expr,
     graph: &mut Graph<T>,
@@ -1108,119 +1991,164 @@ pub fn parse_expr<T: std::fmt::Debug + Numeric<f64> + Default + ActivationFuncti
 where
     f64: Div<T, Output = T>,
 {
-    match expr {
-        Expr::Symbol(index) => index,
-        Expr::Add(left, right) => {
-            let left_index = parse_expr(*left, graph);
-            let right_index = parse_expr(*right, graph);
-            graph.add(left_index, right_index)
-        }
-        Expr::Addf(num, right) => {
-            let right_index = parse_expr(*right, graph);
-            graph.addf(num, right_index)
-        }
-        Expr::Sub(left, right) => {
-            let left_index = parse_expr(*left, graph);
-            let right_index = parse_expr(*right, graph);
-            graph.sub(left_index, right_index)
-        }
-        Expr::Subf(left, num) => {
-            let left_index = parse_expr(*left, graph);
-            graph.subf(left_index, num)
-        }
-        Expr::Mul(left, right) => {
-            let left_index = parse_expr(*left, graph);
-            let right_index = parse_expr(*right, graph);
-            graph.mul(left_index, right_index)
-        }
-        Expr::Mulf(num, right) => {
-            let right_index = parse_expr(*right, graph);
-            graph.mulf(num, right_index)
-        }
-        Expr::Hadamard(left, right) => {
-            let left_index = parse_expr(*left, graph);
-            let right_index = parse_expr(*right, graph);
-            graph.hadamard(left_index, right_index)
-        }
-        Expr::Div(left, right) => {
-            let left_index = parse_expr(*left, graph);
-            let right_index = parse_expr(*right, graph);
-            graph.div(left_index, right_index)
-        }
-        Expr::Pow(left, right) => {
-            let left_index = parse_expr(*left, graph);
-            let right_index = parse_expr(*right, graph);
-            graph.pow(left_index, right_index)
-        }
-        Expr::Powf(left, right) => {
-            let left_index = parse_expr(*left, graph);
-            graph.powf(left_index, right)
-        }
-        Expr::Powi(left, right) => {
-            let left_index = parse_expr(*left, graph);
-            graph.powi(left_index, right)
-        }
-        Expr::Neg(expr) => {
-            let index = parse_expr(*expr, graph);
-            graph.neg(index)
-        }
-        Expr::Recip(expr) => {
-            let index = parse_expr(*expr, graph);
-            graph.recip(index)
-        }
-        Expr::Exp(expr) => {
-            let index = parse_expr(*expr, graph);
-            graph.exp(index)
-        }
-        Expr::Ln(expr) => {
-            let index = parse_expr(*expr, graph);
-            graph.ln(index)
-        }
-        Expr::Sin(expr) => {
-            let index = parse_expr(*expr, graph);
-            graph.sin(index)
-        }
-        Expr::Cos(expr) => {
-            let index = parse_expr(*expr, graph);
-            graph.cos(index)
-        }
-        Expr::Tan(expr) => {
-            let index = parse_expr(*expr, graph);
-            graph.tan(index)
-        }
-        Expr::Sinh(expr) => {
-            let index = parse_expr(*expr, graph);
-            graph.sinh(index)
-        }
-        Expr::Cosh(expr) => {
-            let index = parse_expr(*expr, graph);
-            graph.cosh(index)
-        }
-        Expr::Tanh(expr) => {
-            let index = parse_expr(*expr, graph);
-            graph.tanh(index)
-        }
-        Expr::Sigmoid(expr) => {
-            let index = parse_expr(*expr, graph);
-            graph.sigmoid(index)
-        }
-        Expr::ReLU(expr) => {
-            let index = parse_expr(*expr, graph);
-            graph.relu(index)
+    parse_expr_at(expr, graph, Location::caller())
+}
+
+/// `parse_expr` attributing new nodes to `location` (see `Graph::enable_provenance`)
+pub(crate) fn parse_expr_at<T: std::fmt::Debug + Numeric<f64> + Default + ActivationFunction + Matrizable>(
+    expr: Expr,
+    graph: &mut Graph<T>,
+    location: &'static Location<'static>,
+) -> usize
+where
+    f64: Div<T, Output = T>,
+{
+    // Post-order traversal with an explicit stack, so depth is only bounded by memory.
+    // Subtrees shared through `Rc` are parsed once (memoized by address).
+    let mut stack: Vec<(&Expr, bool)> = vec![(&expr, false)];
+    let mut results: Vec<usize> = Vec::new();
+    let mut parsed: HashMap<*const Expr, usize> = HashMap::new();
+    while let Some((expr, expanded)) = stack.pop() {
+        let key = expr as *const Expr;
+        if !expanded {
+            if let Some(index) = parsed.get(&key) {
+                results.push(*index);
+                continue;
+            }
+            stack.push((expr, true));
+            stack.extend(expr.children().into_iter().rev().map(|child| (&**child, false)));
+            continue;
         }
+        let before = graph.nodes.len();
+        let index = match expr {
+            Expr::Symbol(index) => *index,
+            Expr::Const(value) => graph.constant(*value),
+            Expr::Add(..) => {
+                let right_index = results.pop().unwrap();
+                let left_index = results.pop().unwrap();
+                graph.add(left_index, right_index)
+            }
+            Expr::Addf(num, _) => {
+                let right_index = results.pop().unwrap();
+                graph.addf(*num, right_index)
+            }
+            Expr::Sub(..) => {
+                let right_index = results.pop().unwrap();
+                let left_index = results.pop().unwrap();
+                graph.sub(left_index, right_index)
+            }
+            Expr::Subf(_, num) => {
+                let left_index = results.pop().unwrap();
+                graph.subf(left_index, *num)
+            }
+            Expr::Mul(..) => {
+                let right_index = results.pop().unwrap();
+                let left_index = results.pop().unwrap();
+                graph.mul(left_index, right_index)
+            }
+            Expr::Mulf(num, _) => {
+                let right_index = results.pop().unwrap();
+                graph.mulf(*num, right_index)
+            }
+            Expr::Hadamard(..) => {
+                let right_index = results.pop().unwrap();
+                let left_index = results.pop().unwrap();
+                graph.hadamard(left_index, right_index)
+            }
+            Expr::Div(..) => {
+                let right_index = results.pop().unwrap();
+                let left_index = results.pop().unwrap();
+                graph.div(left_index, right_index)
+            }
+            Expr::Pow(..) => {
+                let right_index = results.pop().unwrap();
+                let left_index = results.pop().unwrap();
+                graph.pow(left_index, right_index)
+            }
+            Expr::Powf(_, right) => {
+                let left_index = results.pop().unwrap();
+                graph.powf(left_index, *right)
+            }
+            Expr::Powi(_, right) => {
+                let left_index = results.pop().unwrap();
+                graph.powi(left_index, *right)
+            }
+            Expr::Neg(_) => {
+                let index = results.pop().unwrap();
+                graph.neg(index)
+            }
+            Expr::Recip(_) => {
+                let index = results.pop().unwrap();
+                graph.recip(index)
+            }
+            Expr::Exp(_) => {
+                let index = results.pop().unwrap();
+                graph.exp(index)
+            }
+            Expr::Ln(_) => {
+                let index = results.pop().unwrap();
+                graph.ln(index)
+            }
+            Expr::Sin(_) => {
+                let index = results.pop().unwrap();
+                graph.sin(index)
+            }
+            Expr::Cos(_) => {
+                let index = results.pop().unwrap();
+                graph.cos(index)
+            }
+            Expr::Tan(_) => {
+                let index = results.pop().unwrap();
+                graph.tan(index)
+            }
+            Expr::Sinh(_) => {
+                let index = results.pop().unwrap();
+                graph.sinh(index)
+            }
+            Expr::Cosh(_) => {
+                let index = results.pop().unwrap();
+                graph.cosh(index)
+            }
+            Expr::Tanh(_) => {
+                let index = results.pop().unwrap();
+                graph.tanh(index)
+            }
+            Expr::Sigmoid(_) => {
+                let index = results.pop().unwrap();
+                graph.sigmoid(index)
+            }
+            Expr::ReLU(_) => {
+                let index = results.pop().unwrap();
+                graph.relu(index)
+            }
+            Expr::Heaviside(_) => {
+                let index = results.pop().unwrap();
+                graph.heaviside(index)
+            }
+            Expr::Select(..) => {
+                let neg_index = results.pop().unwrap();
+                let pos_index = results.pop().unwrap();
+                let cond_index = results.pop().unwrap();
+                graph.select_sign(cond_index, pos_index, neg_index)
+            }
+        };
+        graph.note_provenance(before, expr.name(), location);
+        parsed.insert(key, index);
+        results.push(index);
     }
+    results.pop().unwrap()
 }
 
 impl std::iter::Sum for Expr {
     fn sum<I: Iterator<Item = Self>>(iter: I) -> Self {
-        iter.reduce(|a, b| Expr::Add(Box::new(a), Box::new(b)))
+        iter.reduce(|a, b| Expr::Add(rc(a), rc(b)))
             .unwrap()
     }
 }
 
 impl std::iter::Product for Expr {
     fn product<I: Iterator<Item = Self>>(iter: I) -> Self {
-        iter.reduce(|a, b| Expr::Mul(Box::new(a), Box::new(b)))
+        iter.reduce(|a, b| Expr::Mul(rc(a), rc(b)))
             .unwrap()
     }
 }
@@ -0,0 +1,214 @@
+use crate::core::{Graph, Node};
+use crate::implicit::ImplicitSystem;
+use crate::traits::{ActivationFunction, Matrizable};
+use peroxide_num::Numeric;
+use std::ops::Div;
+use std::sync::Arc;
+
+// ┌──────────────────────────────────────────────────────────┐
+//  User-defined operations
+// └──────────────────────────────────────────────────────────┘
+/// Black-box function of `Node::External`
+pub type ExternalFn = Arc<dyn Fn(&[f64]) -> f64 + Send + Sync>;
+
+/// Scalar function & its derivative, referenced by `Node::Custom`, `Node::CustomBinary` &
+/// `Node::External` (black boxes & implicit roots)
+///
+/// Applied elementwise to non-scalar values.
+#[derive(Clone)]
+pub enum CustomOp {
+    Unary {
+        f: fn(f64) -> f64,
+        df: fn(f64) -> f64,
+    },
+    /// `df` returns `(∂f/∂x, ∂f/∂y)`
+    Binary {
+        f: fn(f64, f64) -> f64,
+        df: fn(f64, f64) -> (f64, f64),
+    },
+    /// Black box without derivative, differentiated by central differences with relative `step`
+    External {
+        f: ExternalFn,
+        step: f64,
+    },
+    /// `k`-th component of the root of an implicit system (see `Graph::implicit`)
+    Implicit {
+        system: Arc<ImplicitSystem>,
+        k: usize,
+    },
+}
+
+impl std::fmt::Debug for CustomOp {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            CustomOp::Unary { f: g, df } => write!(f, "Unary {{ f: {:?}, df: {:?} }}", g, df),
+            CustomOp::Binary { f: g, df } => write!(f, "Binary {{ f: {:?}, df: {:?} }}", g, df),
+            CustomOp::External { step, .. } => write!(f, "External {{ step: {:?} }}", step),
+            CustomOp::Implicit { system, k } => write!(f, "Implicit {{ system: {:?}, k: {:?} }}", system, k),
+        }
+    }
+}
+
+impl CustomOp {
+    /// Value at `(x, y)` (`y` is ignored by unary ops)
+    pub fn eval(&self, x: f64, y: f64) -> f64 {
+        match self {
+            CustomOp::Unary { f, .. } => f(x),
+            CustomOp::Binary { f, .. } => f(x, y),
+            CustomOp::External { .. } | CustomOp::Implicit { .. } => self.eval_n(&[x, y]),
+        }
+    }
+
+    /// First partials at `(x, y)` (the second one is zero for unary ops)
+    pub fn partials(&self, x: f64, y: f64) -> (f64, f64) {
+        match self {
+            CustomOp::Unary { df, .. } => (df(x), 0f64),
+            CustomOp::Binary { df, .. } => df(x, y),
+            CustomOp::External { .. } | CustomOp::Implicit { .. } => (self.partial_n(&[x, y], 0), self.partial_n(&[x, y], 1)),
+        }
+    }
+
+    /// Value at `args` (any arity)
+    pub fn eval_n(&self, args: &[f64]) -> f64 {
+        match self {
+            CustomOp::External { f, .. } => f(args),
+            CustomOp::Implicit { system, k } => system.value(args, *k),
+            _ => self.eval(args[0], args.get(1).copied().unwrap_or_default()),
+        }
+    }
+
+    /// Partial w.r.t. `args[j]` (central difference for `External`, implicit function theorem for
+    /// `Implicit`)
+    pub fn partial_n(&self, args: &[f64], j: usize) -> f64 {
+        match self {
+            CustomOp::External { f, step } => {
+                let h = step * args[j].abs().max(1f64);
+                let mut x = args.to_vec();
+                x[j] = args[j] + h;
+                let upper = f(&x);
+                x[j] = args[j] - h;
+                let lower = f(&x);
+                (upper - lower) / (2f64 * h)
+            }
+            CustomOp::Implicit { system, k } => system.partial(args, *k, j),
+            _ => {
+                let (fx, fy) = self.partials(args[0], args.get(1).copied().unwrap_or_default());
+                if j == 0 {
+                    fx
+                } else {
+                    fy
+                }
+            }
+        }
+    }
+
+    /// Second partials `(f_xx, f_xy, f_yy)` by central differences of the first ones
+    pub fn second_partials(&self, x: f64, y: f64) -> (f64, f64, f64) {
+        let hx = f64::EPSILON.cbrt() * x.abs().max(1f64);
+        let hy = f64::EPSILON.cbrt() * y.abs().max(1f64);
+        let (xp, xm) = (self.partials(x + hx, y), self.partials(x - hx, y));
+        match self {
+            CustomOp::Unary { .. } => ((xp.0 - xm.0) / (2f64 * hx), 0f64, 0f64),
+            CustomOp::Binary { .. } | CustomOp::External { .. } | CustomOp::Implicit { .. } => {
+                let (yp, ym) = (self.partials(x, y + hy), self.partials(x, y - hy));
+                (
+                    (xp.0 - xm.0) / (2f64 * hx),
+                    (yp.0 - ym.0) / (2f64 * hy),
+                    (yp.1 - ym.1) / (2f64 * hy),
+                )
+            }
+        }
+    }
+
+    /// Derivative of the first partials along `dir` (i.e. `H·dir`) at `args`, by central
+    /// differences of `partial_n`
+    pub fn second_partials_n(&self, args: &[f64], dir: &[f64]) -> Vec<f64> {
+        let scale = dir.iter().fold(0f64, |m, d| m.max(d.abs()));
+        if scale == 0f64 {
+            return vec![0f64; args.len()];
+        }
+        let h = f64::EPSILON.cbrt() * args.iter().fold(1f64, |m, x| m.max(x.abs())) / scale;
+        let upper = args.iter().zip(dir).map(|(x, d)| x + h * d).collect::<Vec<_>>();
+        let lower = args.iter().zip(dir).map(|(x, d)| x - h * d).collect::<Vec<_>>();
+        (0..args.len())
+            .map(|j| (self.partial_n(&upper, j) - self.partial_n(&lower, j)) / (2f64 * h))
+            .collect()
+    }
+
+    fn same(&self, other: &CustomOp) -> bool {
+        match (self, other) {
+            (CustomOp::Unary { f, df }, CustomOp::Unary { f: g, df: dg }) => {
+                std::ptr::fn_addr_eq(*f, *g) && std::ptr::fn_addr_eq(*df, *dg)
+            }
+            (CustomOp::Binary { f, df }, CustomOp::Binary { f: g, df: dg }) => {
+                std::ptr::fn_addr_eq(*f, *g) && std::ptr::fn_addr_eq(*df, *dg)
+            }
+            _ => false,
+        }
+    }
+}
+
+impl<T> Graph<T> {
+    /// Registry index of `op` (registered on first use)
+    pub(crate) fn register(&mut self, op: CustomOp) -> usize {
+        match self.custom_ops.iter().position(|x| x.same(&op)) {
+            Some(id) => id,
+            None => {
+                Arc::make_mut(&mut self.custom_ops).push(op);
+                self.custom_ops.len() - 1
+            }
+        }
+    }
+
+    /// Registry of custom ops (indexed by `Node::Custom` & `Node::CustomBinary`)
+    pub fn get_custom_ops(&self) -> Vec<CustomOp> {
+        self.custom_ops.to_vec()
+    }
+}
+
+impl<T: std::fmt::Debug + Numeric<f64> + Default + ActivationFunction + Matrizable> Graph<T>
+where
+    f64: Div<T, Output = T>,
+{
+    /// `f(operand)` with the user supplied derivative `df`
+    pub fn custom_unary(&mut self, operand: usize, f: fn(f64) -> f64, df: fn(f64) -> f64) -> usize {
+        let id = self.register(CustomOp::Unary { f, df });
+        self.push_node(Node::Custom(id, operand))
+    }
+
+    /// `f(left, right)` with the user supplied partials `df = (∂f/∂x, ∂f/∂y)`
+    pub fn custom_binary(
+        &mut self,
+        left: usize,
+        right: usize,
+        f: fn(f64, f64) -> f64,
+        df: fn(f64, f64) -> (f64, f64),
+    ) -> usize {
+        let id = self.register(CustomOp::Binary { f, df });
+        self.push_node(Node::CustomBinary(id, left, right))
+    }
+
+    /// `f(args)` of a black box without derivative
+    ///
+    /// The backward sweep takes central differences of `f` (two calls per argument) with step
+    /// `step · max(1, |x_j|)`, e.g. `step = 1e-6`. Each argument gets its own `Node::Arg` slot, so
+    /// `Node::External` refers to a contiguous range of the tape.
+    pub fn external<F: Fn(&[f64]) -> f64 + Send + Sync + 'static>(
+        &mut self,
+        args: &[usize],
+        f: F,
+        step: f64,
+    ) -> usize {
+        assert!(!args.is_empty(), "External function without arguments");
+        Arc::make_mut(&mut self.custom_ops).push(CustomOp::External { f: Arc::new(f), step });
+        let id = self.custom_ops.len() - 1;
+        // Argument slots bypass CSE to stay contiguous
+        let first = self.nodes.len();
+        for &arg in args {
+            self.buffer.push(None);
+            self.gradients.push(T::default());
+            Arc::make_mut(&mut self.nodes).push(Node::Arg(arg));
+        }
+        self.push_node(Node::External(id, first, args.len()))
+    }
+}
@@ -0,0 +1,323 @@
+use crate::core::{eval_node, propagate_adjoint, Graph, Node};
+use crate::error::{GraphError, TapeIssue};
+use crate::traits::{ActivationFunction, Matrizable};
+use peroxide_num::Numeric;
+use std::fmt::Write as _;
+use std::ops::Div;
+use std::sync::Arc;
+
+// ┌──────────────────────────────────────────────────────────┐
+//  Checked evaluation
+// └──────────────────────────────────────────────────────────┘
+impl Graph<f64> {
+    /// `forward` stopping at the first node evaluated outside of its domain
+    ///
+    /// Reports `ln` of a non-positive value, division (or reciprocal) by zero and `0^negative`
+    /// as `GraphError::Domain` with the offending node and its operand values.
+    pub fn forward_checked(&mut self) -> Result<f64, GraphError> {
+        let root = self.compiled.ok_or(GraphError::NotCompiled)?;
+        self.schedule();
+        let order = Arc::clone(self.topological_order.as_ref().unwrap());
+        for &index in order.iter() {
+            if self.buffer[index].is_some() {
+                continue;
+            }
+            let node = self.nodes.node(index);
+            if let Node::Var(_) | Node::Param(_) = node {
+                return Err(GraphError::Uninitialized(index));
+            }
+            if let Some(reason) = domain_error(&node, &self.buffer) {
+                return Err(GraphError::Domain {
+                    index,
+                    label: self.labels.get(&index).cloned(),
+                    origin: self.provenance(index).map(Box::new),
+                    node,
+                    operands: self.operand_values(&node),
+                    reason,
+                });
+            }
+            self.buffer[index] = Some(eval_node(&node, &self.buffer, index, &self.custom_ops));
+        }
+        Ok(self.buffer[root].unwrap())
+    }
+
+    pub(crate) fn operand_values(&self, node: &Node) -> Vec<f64> {
+        node.operands()
+            .into_iter()
+            .map(|i| self.buffer[i].unwrap())
+            .collect()
+    }
+}
+
+fn domain_error(node: &Node, v: &[Option<f64>]) -> Option<&'static str> {
+    let x = |i: usize| v[i].unwrap();
+    match *node {
+        Node::Ln(i) if x(i) <= 0f64 => Some("ln of a non-positive value"),
+        Node::Div(_, r) if x(r) == 0f64 => Some("division by zero"),
+        Node::Recip(i) if x(i) == 0f64 => Some("division by zero"),
+        Node::Pow(l, r) if x(l) == 0f64 && x(r) < 0f64 => Some("zero to a negative power"),
+        Node::Powf(i, p) if x(i) == 0f64 && p < 0f64 => Some("zero to a negative power"),
+        Node::Powi(i, n) if x(i) == 0f64 && n < 0 => Some("zero to a negative power"),
+        _ => None,
+    }
+}
+
+// ┌──────────────────────────────────────────────────────────┐
+//  NaN/Inf tracing
+// └──────────────────────────────────────────────────────────┘
+impl Graph<f64> {
+    /// `forward` stopping at the first node whose value is NaN or infinite
+    ///
+    /// Returns `GraphError::NonFinite` with the node and its operand values. Variables and
+    /// parameters are checked as well, so a non-finite input is reported at its own node.
+    pub fn forward_traced(&mut self) -> Result<f64, GraphError> {
+        let root = self.compiled.ok_or(GraphError::NotCompiled)?;
+        self.schedule();
+        let order = Arc::clone(self.topological_order.as_ref().unwrap());
+        for &index in order.iter() {
+            let node = self.nodes.node(index);
+            let value = match self.buffer[index] {
+                Some(value) => value,
+                None if matches!(node, Node::Var(_) | Node::Param(_)) => {
+                    return Err(GraphError::Uninitialized(index));
+                }
+                None => {
+                    let value = eval_node(&node, &self.buffer, index, &self.custom_ops);
+                    self.buffer[index] = Some(value);
+                    value
+                }
+            };
+            if !value.is_finite() {
+                return Err(GraphError::NonFinite {
+                    index,
+                    label: self.labels.get(&index).cloned(),
+                    origin: self.provenance(index).map(Box::new),
+                    node,
+                    operands: self.operand_values(&node),
+                    adjoint: None,
+                });
+            }
+        }
+        Ok(self.buffer[root].unwrap())
+    }
+
+    /// `backward` stopping at the first node whose propagation yields a NaN or infinite adjoint
+    ///
+    /// Returns `GraphError::NonFinite` with the propagating node, its operand values and its
+    /// (incoming) adjoint. Gradients are left as they were at the point of failure.
+    pub fn backward_traced(&mut self) -> Result<(), GraphError> {
+        let root = self.compiled.ok_or(GraphError::NotCompiled)?;
+        if self.buffer.iter().any(|x| x.is_none()) {
+            return Err(GraphError::NotEvaluated);
+        }
+        if self.grad_mask.as_ref().is_none_or(|mask| mask.len() != self.nodes.len()) {
+            self.update_grad_mask();
+        }
+        self.schedule();
+        let reverse_order = Arc::clone(self.reverse_order.as_ref().unwrap());
+        let mask = self.grad_mask.clone();
+        let mut reached = vec![false; self.nodes.len()];
+        reached[root] = true;
+
+        self.gradients.iter_mut().for_each(|grad| *grad = 0f64);
+        self.gradients[root] = 1f64;
+
+        for &index in reverse_order.iter() {
+            if !reached[index] || mask.as_ref().is_some_and(|mask| !mask[index]) {
+                continue;
+            }
+            let node = self.nodes.node(index);
+            let children = node.operands();
+            for &child_index in children.iter() {
+                reached[child_index] = true;
+            }
+            propagate_adjoint(&node, &self.buffer, &mut self.gradients, index, &self.custom_ops);
+            let leaked = children
+                .iter()
+                .any(|&i| mask.as_ref().is_none_or(|mask| mask[i]) && !self.gradients[i].is_finite());
+            if leaked {
+                return Err(GraphError::NonFinite {
+                    index,
+                    label: self.labels.get(&index).cloned(),
+                    origin: self.provenance(index).map(Box::new),
+                    node,
+                    operands: self.operand_values(&node),
+                    adjoint: Some(self.gradients[index]),
+                });
+            }
+        }
+
+        // Discard adjoints leaked into pruned operands
+        if let Some(mask) = mask {
+            for (grad, active) in self.gradients.iter_mut().zip(mask) {
+                if !active {
+                    *grad = 0f64;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+// ┌──────────────────────────────────────────────────────────┐
+//  Graphviz export
+// └──────────────────────────────────────────────────────────┘
+impl<T: std::fmt::Debug + Numeric<f64> + Default + ActivationFunction + Matrizable> Graph<T>
+where
+    f64: Div<T, Output = T>,
+{
+    /// Tape in Graphviz DOT format
+    ///
+    /// Nodes show their index, operation & label (see `label`), edges point from operands to
+    /// their node, and outputs are drawn with a double border.
+    pub fn to_dot(&self) -> String {
+        let mut dot = String::from("digraph radient {\n");
+        for (index, node) in self.nodes.iter().enumerate() {
+            let mut text = format!("{}: {}", index, node.name());
+            if let Node::Const(value) = node {
+                let _ = write!(text, " {}", value);
+            }
+            if let Some(label) = self.labels.get(&index) {
+                let _ = write!(text, "\n{}", label);
+            }
+            let border = if self.outputs.contains(&index) { ", peripheries=2" } else { "" };
+            let _ = writeln!(dot, "    n{} [label={:?}{}];", index, text, border);
+        }
+        for (index, node) in self.nodes.iter().enumerate() {
+            for operand in node.operand_iter() {
+                let _ = writeln!(dot, "    n{} -> n{};", operand, index);
+            }
+        }
+        dot.push_str("}\n");
+        dot
+    }
+}
+
+// ┌──────────────────────────────────────────────────────────┐
+//  Structural validation
+// └──────────────────────────────────────────────────────────┘
+impl<T: std::fmt::Debug + Numeric<f64> + Default + ActivationFunction + Matrizable> Graph<T>
+where
+    f64: Div<T, Output = T>,
+{
+    /// Check the tape structure without evaluating anything
+    ///
+    /// Reports operands outside of the tape, misplaced leaves, unknown custom ops, malformed
+    /// `External` argument ranges, cycles, inconsistent variable/parameter registrations, outputs
+    /// outside of the tape and a stale cached topological order. Use it on hand-built or
+    /// deserialized tapes before a sweep panics on them.
+    pub fn validate(&self) -> Result<(), Vec<TapeIssue>> {
+        let n = self.nodes.len();
+        let mut issues = Vec::new();
+        if self.buffer.len() != n || self.gradients.len() != n {
+            issues.push(TapeIssue::LengthMismatch {
+                nodes: n,
+                buffer: self.buffer.len(),
+                gradients: self.gradients.len(),
+            });
+        }
+
+        for (index, node) in self.nodes.iter().enumerate() {
+            match node {
+                Node::Var(slot) | Node::Param(slot) if slot != index => {
+                    issues.push(TapeIssue::LeafSlot { index, slot });
+                }
+                Node::Custom(id, _) | Node::CustomBinary(id, ..) | Node::External(id, ..)
+                    if id >= self.custom_ops.len() =>
+                {
+                    issues.push(TapeIssue::UnknownCustomOp { index, id });
+                }
+                _ => {}
+            }
+            for operand in node.operand_iter() {
+                if operand >= n {
+                    issues.push(TapeIssue::OperandOutOfBounds { index, operand });
+                }
+            }
+            if let Node::External(_, first, len) = node {
+                let is_arg = |i: usize| matches!(self.nodes.get(i), Some(Node::Arg(_)));
+                if len == 0 || !(first..first + len).all(is_arg) {
+                    issues.push(TapeIssue::ExternalArgs { index });
+                }
+            }
+        }
+
+        // Iterative DFS: a node still being expanded is an ancestor of the current one
+        let mut state = vec![0u8; n];
+        let mut on_cycle = vec![false; n];
+        for start in 0..n {
+            let mut stack = vec![(start, false)];
+            while let Some((index, expanded)) = stack.pop() {
+                if expanded {
+                    state[index] = 2;
+                    continue;
+                }
+                if state[index] != 0 {
+                    continue;
+                }
+                state[index] = 1;
+                stack.push((index, true));
+                for operand in self.nodes.node(index).operands().into_iter().filter(|x| *x < n) {
+                    match state[operand] {
+                        0 => stack.push((operand, false)),
+                        1 => on_cycle[operand] = true,
+                        _ => {}
+                    }
+                }
+            }
+        }
+        issues.extend(
+            on_cycle
+                .iter()
+                .enumerate()
+                .filter_map(|(index, cycle)| cycle.then_some(TapeIssue::Cycle { index })),
+        );
+
+        let vars = self.value_ics.iter().chain(self.groups.values().flatten()).map(|x| (x, true));
+        for (&index, is_var) in vars.chain(self.param_ics.iter().map(|x| (x, false))) {
+            match self.nodes.get(index) {
+                Some(Node::Var(_)) if is_var => {}
+                Some(Node::Param(_)) if !is_var => {}
+                _ => issues.push(TapeIssue::NotALeaf { index }),
+            }
+        }
+        if self.requires_grad.len() != self.value_ics.len() {
+            issues.push(TapeIssue::FlagMismatch {
+                vars: self.value_ics.len(),
+                flags: self.requires_grad.len(),
+            });
+        }
+        let roots = self.compiled.iter().chain(self.outputs.iter()).chain(self.output_names.values());
+        for &root in roots {
+            if root >= n {
+                issues.push(TapeIssue::RootOutOfBounds { root });
+            }
+        }
+
+        if let Some(order) = self.topological_order.as_ref() {
+            let mut position = vec![usize::MAX; n];
+            for (k, &index) in order.iter().enumerate() {
+                match position.get_mut(index) {
+                    Some(p) if *p == usize::MAX => *p = k,
+                    _ => issues.push(TapeIssue::StaleOrder { index }),
+                }
+            }
+            for (index, node) in self.nodes.iter().enumerate() {
+                let misplaced = position[index] == usize::MAX
+                    || node
+                        .operands()
+                        .into_iter()
+                        .any(|operand| operand < n && position[operand] >= position[index]);
+                if misplaced {
+                    issues.push(TapeIssue::StaleOrder { index });
+                }
+            }
+        }
+
+        if issues.is_empty() {
+            Ok(())
+        } else {
+            Err(issues)
+        }
+    }
+}
@@ -0,0 +1,184 @@
+use crate::core::{Graph, Node};
+use crate::traits::{ActivationFunction, Matrizable};
+use peroxide_num::Numeric;
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::ops::Div;
+
+// ┌──────────────────────────────────────────────────────────┐
+//  Tape diffing
+// └──────────────────────────────────────────────────────────┘
+/// Pair of aligned nodes (`self` first) which differ (see `TapeDiff`)
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct NodeChange {
+    pub index: (usize, usize),
+    pub nodes: (Node, Node),
+}
+
+/// Structural differences between two tapes (see `Graph::diff_tape`)
+///
+/// Nodes are compared by structure, not by index: leaves by their registration order (k-th
+/// variable, k-th parameter), constants by value and operations by their operands, so nodes
+/// which only moved on the tape are not reported.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct TapeDiff {
+    /// Nodes of `other` with no structural counterpart in `self`
+    pub added: Vec<(usize, Node)>,
+    /// Nodes of `self` with no structural counterpart in `other`
+    pub removed: Vec<(usize, Node)>,
+    /// Aligned nodes whose operation, operand count or custom op differs
+    pub changed: Vec<NodeChange>,
+    /// Aligned nodes of the same operation whose constant differs (`Const`, `Addf`, `Powi`, ...)
+    pub constants: Vec<NodeChange>,
+}
+
+impl TapeDiff {
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty()
+            && self.removed.is_empty()
+            && self.changed.is_empty()
+            && self.constants.is_empty()
+    }
+}
+
+impl fmt::Display for TapeDiff {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.is_empty() {
+            return writeln!(f, "Tapes are structurally identical");
+        }
+        for change in self.constants.iter() {
+            let (a, b) = change.index;
+            writeln!(f, "constant {:>5} {:?} -> {} {:?}", a, change.nodes.0, b, change.nodes.1)?;
+        }
+        for change in self.changed.iter() {
+            let (a, b) = change.index;
+            writeln!(f, "changed  {:>5} {:?} -> {} {:?}", a, change.nodes.0, b, change.nodes.1)?;
+        }
+        for (index, node) in self.removed.iter() {
+            writeln!(f, "removed  {:>5} {:?}", index, node)?;
+        }
+        for (index, node) in self.added.iter() {
+            writeln!(f, "added    {:>5} {:?}", index, node)?;
+        }
+        Ok(())
+    }
+}
+
+/// Constant carried by a node, if any
+fn literal(node: &Node) -> Option<f64> {
+    match *node {
+        Node::Const(value) => Some(value),
+        Node::Addf(num, _) | Node::Subf(_, num) | Node::Mulf(num, _) | Node::Powf(_, num) => Some(num),
+        Node::Powi(_, n) => Some(n as f64),
+        _ => None,
+    }
+}
+
+/// Operation, operand classes & extra identity (literal bits, leaf position or custom op)
+type ClassKey = (&'static str, Vec<usize>, u64);
+
+/// Structural class of every node; equal classes mean equal subtrees, also across graphs
+fn classify<T: std::fmt::Debug + Numeric<f64> + Default + ActivationFunction + Matrizable>(
+    graph: &Graph<T>,
+    classes: &mut HashMap<ClassKey, usize>,
+) -> Vec<usize>
+where
+    f64: Div<T, Output = T>,
+{
+    let mut position = vec![0u64; graph.nodes.len()];
+    for (k, &index) in graph.value_ics.iter().enumerate() {
+        position[index] = k as u64;
+    }
+    for (k, &index) in graph.param_ics.iter().enumerate() {
+        position[index] = k as u64;
+    }
+    let mut class = vec![usize::MAX; graph.nodes.len()];
+    for index in graph.topological_sort() {
+        let node = &graph.nodes.node(index);
+        let extra = match *node {
+            Node::Var(_) | Node::Param(_) => position[index],
+            Node::Custom(id, _) | Node::CustomBinary(id, ..) | Node::External(id, ..) => id as u64,
+            _ => literal(node).map_or(0, f64::to_bits),
+        };
+        let key = (node.name(), node.operands().into_iter().map(|i| class[i]).collect(), extra);
+        let n = classes.len();
+        class[index] = *classes.entry(key).or_insert(n);
+    }
+    class
+}
+
+impl<T: std::fmt::Debug + Numeric<f64> + Default + ActivationFunction + Matrizable> Graph<T>
+where
+    f64: Div<T, Output = T>,
+{
+    /// Structural differences from this tape to `other`
+    ///
+    /// Both tapes are walked in parallel from their roots (compiled output, outputs by position
+    /// and named outputs by name). Aligned nodes of the same operation are descended into, so a
+    /// changed constant is reported once instead of as a rewrite of every node above it; nodes
+    /// left unaligned and without a structural counterpart are reported as added or removed.
+    pub fn diff_tape(&self, other: &Graph<T>) -> TapeDiff {
+        let mut classes = HashMap::new();
+        let class_a = classify(self, &mut classes);
+        let class_b = classify(other, &mut classes);
+
+        let mut stack = self.compiled.into_iter().zip(other.compiled).collect::<Vec<_>>();
+        stack.extend(self.outputs.iter().copied().zip(other.outputs.iter().copied()));
+        for (name, &a) in self.output_names.iter() {
+            if let Some(&b) = other.output_names.get(name) {
+                stack.push((a, b));
+            }
+        }
+
+        let mut diff = TapeDiff::default();
+        let mut aligned_a = vec![false; self.nodes.len()];
+        let mut aligned_b = vec![false; other.nodes.len()];
+        while let Some((a, b)) = stack.pop() {
+            if aligned_a[a] || aligned_b[b] {
+                continue;
+            }
+            aligned_a[a] = true;
+            aligned_b[b] = true;
+            if class_a[a] == class_b[b] {
+                continue;
+            }
+            let (x, y) = (self.nodes.node(a), other.nodes.node(b));
+            let (ops_x, ops_y) = (x.operands(), y.operands());
+            let change = NodeChange {
+                index: (a, b),
+                nodes: (x, y),
+            };
+            let same_op = x.name() == y.name()
+                && ops_x.len() == ops_y.len()
+                && match (x, y) {
+                    (Node::Custom(i, _), Node::Custom(j, _))
+                    | (Node::CustomBinary(i, ..), Node::CustomBinary(j, ..))
+                    | (Node::External(i, ..), Node::External(j, ..)) => i == j,
+                    (Node::Var(_), Node::Var(_)) | (Node::Param(_), Node::Param(_)) => false,
+                    _ => true,
+                };
+            if !same_op {
+                diff.changed.push(change);
+                continue;
+            }
+            if literal(&x).map(f64::to_bits) != literal(&y).map(f64::to_bits) {
+                diff.constants.push(change);
+            }
+            stack.extend(ops_x.into_iter().zip(ops_y));
+        }
+
+        let present_a = class_a.iter().copied().collect::<HashSet<_>>();
+        let present_b = class_b.iter().copied().collect::<HashSet<_>>();
+        diff.removed = (0..self.nodes.len())
+            .filter(|&i| !aligned_a[i] && !present_b.contains(&class_a[i]))
+            .map(|i| (i, self.nodes.node(i)))
+            .collect();
+        diff.added = (0..other.nodes.len())
+            .filter(|&i| !aligned_b[i] && !present_a.contains(&class_b[i]))
+            .map(|i| (i, other.nodes.node(i)))
+            .collect();
+        diff.changed.sort_by_key(|x| x.index);
+        diff.constants.sort_by_key(|x| x.index);
+        diff
+    }
+}
@@ -0,0 +1,171 @@
+use crate::core::Node;
+use crate::provenance::Provenance;
+use std::fmt;
+
+/// Errors reported by the fallible (`try_*`) graph API
+#[derive(Debug, Clone, PartialEq)]
+pub enum GraphError {
+    /// Nothing is compiled yet
+    NotCompiled,
+    /// Node index outside of the tape
+    IndexOutOfBounds { index: usize, len: usize },
+    /// The node is not a variable
+    NotAVariable(usize),
+    /// Not one value per variable
+    LengthMismatch { expected: usize, found: usize },
+    /// No node carries the label
+    UnknownLabel(String),
+    /// A variable or parameter has no value
+    Uninitialized(usize),
+    /// `backward` before `forward`
+    NotEvaluated,
+    /// The implicit solve behind the node found no root (see `Graph::implicit`)
+    NotConverged(usize),
+    /// Operation evaluated outside of its domain (see `Graph::forward_checked`)
+    Domain {
+        index: usize,
+        label: Option<String>,
+        origin: Option<Box<Provenance>>,
+        node: Node,
+        operands: Vec<f64>,
+        reason: &'static str,
+    },
+    /// NaN or infinite value (`adjoint: None`) or adjoint (see `Graph::forward_traced`)
+    NonFinite {
+        index: usize,
+        label: Option<String>,
+        origin: Option<Box<Provenance>>,
+        node: Node,
+        operands: Vec<f64>,
+        adjoint: Option<f64>,
+    },
+}
+
+impl fmt::Display for GraphError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            GraphError::NotCompiled => write!(f, "No compiled expression"),
+            GraphError::IndexOutOfBounds { index, len } => {
+                write!(f, "Node index {} is out of bounds (tape length {})", index, len)
+            }
+            GraphError::NotAVariable(index) => write!(f, "Node {} is not a variable", index),
+            GraphError::LengthMismatch { expected, found } => {
+                write!(f, "Expected {} values, found {}", expected, found)
+            }
+            GraphError::UnknownLabel(name) => write!(f, "No node labeled {:?}", name),
+            GraphError::Uninitialized(index) => write!(f, "Leaf {} has no value", index),
+            GraphError::NotEvaluated => write!(f, "Forward values are missing (call forward first)"),
+            GraphError::NotConverged(index) => write!(f, "Implicit solve of node {} did not converge", index),
+            GraphError::Domain { index, label, origin, node, operands, reason } => write!(
+                f,
+                "{} at {} ({:?}) with operands {:?}{}",
+                reason,
+                NodeRef(*index, label),
+                node,
+                operands,
+                Origin(origin)
+            ),
+            GraphError::NonFinite { index, label, origin, node, operands, adjoint: None } => write!(
+                f,
+                "Non-finite value at {} ({:?}) with operands {:?}{}",
+                NodeRef(*index, label),
+                node,
+                operands,
+                Origin(origin)
+            ),
+            GraphError::NonFinite { index, label, origin, node, operands, adjoint: Some(adjoint) } => write!(
+                f,
+                "Non-finite adjoint propagated from {} ({:?}) with operands {:?} and adjoint {}{}",
+                NodeRef(*index, label),
+                node,
+                operands,
+                adjoint,
+                Origin(origin)
+            ),
+        }
+    }
+}
+
+impl std::error::Error for GraphError {}
+
+/// Structural defect of a tape (see `Graph::validate`)
+#[derive(Debug, Clone, PartialEq)]
+pub enum TapeIssue {
+    /// Values, gradients & nodes do not have the same length
+    LengthMismatch { nodes: usize, buffer: usize, gradients: usize },
+    /// Operand index outside of the tape
+    OperandOutOfBounds { index: usize, operand: usize },
+    /// Leaf whose slot is not its own index
+    LeafSlot { index: usize, slot: usize },
+    /// Custom op index outside of the registry
+    UnknownCustomOp { index: usize, id: usize },
+    /// `External` whose argument range does not consist of `Arg` slots
+    ExternalArgs { index: usize },
+    /// Node lying on a cycle
+    Cycle { index: usize },
+    /// Registered variable or parameter which is not a leaf of that kind
+    NotALeaf { index: usize },
+    /// `requires_grad` does not have one flag per variable
+    FlagMismatch { vars: usize, flags: usize },
+    /// Output (compiled, listed or named) outside of the tape
+    RootOutOfBounds { root: usize },
+    /// Cached topological order missing a node or placing it before one of its operands
+    StaleOrder { index: usize },
+}
+
+impl fmt::Display for TapeIssue {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            TapeIssue::LengthMismatch { nodes, buffer, gradients } => write!(
+                f,
+                "{} nodes but {} values and {} gradients",
+                nodes, buffer, gradients
+            ),
+            TapeIssue::OperandOutOfBounds { index, operand } => {
+                write!(f, "Node {} refers to operand {} outside of the tape", index, operand)
+            }
+            TapeIssue::LeafSlot { index, slot } => write!(f, "Leaf {} refers to slot {}", index, slot),
+            TapeIssue::UnknownCustomOp { index, id } => {
+                write!(f, "Node {} refers to unregistered custom op {}", index, id)
+            }
+            TapeIssue::ExternalArgs { index } => {
+                write!(f, "External node {} has arguments which are not Arg slots", index)
+            }
+            TapeIssue::Cycle { index } => write!(f, "Node {} lies on a cycle", index),
+            TapeIssue::NotALeaf { index } => {
+                write!(f, "Registered leaf {} is not a variable or parameter of that kind", index)
+            }
+            TapeIssue::FlagMismatch { vars, flags } => {
+                write!(f, "{} variables but {} requires_grad flags", vars, flags)
+            }
+            TapeIssue::RootOutOfBounds { root } => write!(f, "Output {} is outside of the tape", root),
+            TapeIssue::StaleOrder { index } => {
+                write!(f, "Cached topological order is inconsistent at node {}", index)
+            }
+        }
+    }
+}
+
+/// `node 12` or `node 12 "discount_factor"`
+struct NodeRef<'a>(usize, &'a Option<String>);
+
+impl fmt::Display for NodeRef<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.1 {
+            Some(label) => write!(f, "node {} {:?}", self.0, label),
+            None => write!(f, "node {}", self.0),
+        }
+    }
+}
+
+/// `, from Expr::Ln compiled at src/model.rs:12:5` if the origin is known
+struct Origin<'a>(&'a Option<Box<Provenance>>);
+
+impl fmt::Display for Origin<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.0 {
+            Some(origin) => write!(f, ", from {}", origin),
+            None => Ok(()),
+        }
+    }
+}
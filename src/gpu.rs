@@ -0,0 +1,356 @@
+use crate::core::Node;
+use crate::tape::Tape;
+use std::fmt::Write;
+
+// ┌──────────────────────────────────────────────────────────┐
+//  WGSL kernel generation
+// └──────────────────────────────────────────────────────────┘
+const WORKGROUP_SIZE: usize = 64;
+
+/// Straight-line WGSL compute kernel evaluating value & gradient of the compiled output
+///
+/// One invocation handles one point. Bindings of group 0:
+/// * `0`: `inputs` — `n_points × n_vars` variables (row major, order of `get_vars`)
+/// * `1`: `params` — values of the parameters in the order they appear on the tape
+/// * `2`: `outputs` — `n_points × (1 + n_vars)` rows of `[value, gradient...]`
+///
+/// GPUs evaluate in `f32`, so results carry single precision. Custom ops (Rust callbacks) have no
+/// kernel counterpart and panic.
+pub fn wgsl(tape: &Tape) -> String {
+    let nodes = (0..tape.len()).map(|i| tape.node(i)).collect::<Vec<_>>();
+    let vars = tape.get_vars();
+    let n_vars = vars.len();
+    let mut var_slot = vec![usize::MAX; nodes.len()];
+    for (k, x) in vars.iter().enumerate() {
+        var_slot[*x] = k;
+    }
+    let mut param_slot = vec![usize::MAX; nodes.len()];
+    for (j, index) in nodes
+        .iter()
+        .enumerate()
+        .filter_map(|(i, node)| matches!(node, Node::Param(_)).then_some(i))
+        .enumerate()
+    {
+        param_slot[index] = j;
+    }
+
+    let mut src = String::new();
+    src.push_str(
+        "@group(0) @binding(0) var<storage, read> inputs: array<f32>;\n\
+         @group(0) @binding(1) var<storage, read> params: array<f32>;\n\
+         @group(0) @binding(2) var<storage, read_write> outputs: array<f32>;\n\n\
+         fn powi(x: f32, n: i32) -> f32 {\n\
+         \x20   var base = x;\n\
+         \x20   var e = abs(n);\n\
+         \x20   var r = 1.0;\n\
+         \x20   loop {\n\
+         \x20       if e == 0 { break; }\n\
+         \x20       if (e & 1) == 1 { r = r * base; }\n\
+         \x20       base = base * base;\n\
+         \x20       e = e >> 1u;\n\
+         \x20   }\n\
+         \x20   return select(r, 1.0 / r, n < 0);\n\
+         }\n\n\
+         fn heaviside(x: f32) -> f32 {\n\
+         \x20   return select(0.0, 1.0, (bitcast<u32>(x) >> 31u) == 0u);\n\
+         }\n\n",
+    );
+    writeln!(src, "@compute @workgroup_size({})", WORKGROUP_SIZE).unwrap();
+    src.push_str("fn main(@builtin(global_invocation_id) id: vec3<u32>) {\n");
+    src.push_str("    _ = inputs[0];\n    _ = params[0];\n");
+    writeln!(src, "    let p = id.x;").unwrap();
+    writeln!(src, "    if p >= arrayLength(&outputs) / {}u {{ return; }}", n_vars + 1).unwrap();
+
+    // Forward
+    for index in tape.order() {
+        let expr = match nodes[index] {
+            Node::Var(_) => format!("inputs[p * {}u + {}u]", n_vars, var_slot[index]),
+            Node::Param(_) => format!("params[{}]", param_slot[index]),
+            Node::Const(value) => literal(value),
+            Node::Add(l, r) => format!("v{} + v{}", l, r),
+            Node::Sub(l, r) => format!("v{} - v{}", l, r),
+            Node::Mul(l, r) | Node::Hadamard(l, r) => format!("v{} * v{}", l, r),
+            Node::Fma(a, b, c) => format!("fma(v{}, v{}, v{})", a, b, c),
+            Node::Select(c, a, b) => format!("select(v{}, v{}, heaviside(v{}) == 1.0)", b, a, c),
+            Node::Div(l, r) => format!("v{} / v{}", l, r),
+            Node::Pow(l, r) => format!("pow(v{}, v{})", l, r),
+            Node::Addf(num, i) => format!("v{} + {}", i, literal(num)),
+            Node::Subf(i, num) => format!("v{} - {}", i, literal(num)),
+            Node::Mulf(num, i) => format!("v{} * {}", i, literal(num)),
+            Node::Powf(i, p) => format!("pow(v{}, {})", i, literal(p)),
+            Node::Powi(i, n) => format!("powi(v{}, {})", i, n),
+            Node::Transpose(i) => format!("v{}", i),
+            Node::Neg(i) => format!("-v{}", i),
+            Node::Recip(i) => format!("1.0 / v{}", i),
+            Node::Exp(i) => format!("exp(v{})", i),
+            Node::Ln(i) => format!("log(v{})", i),
+            Node::Sin(i) => format!("sin(v{})", i),
+            Node::Cos(i) => format!("cos(v{})", i),
+            Node::Tan(i) => format!("tan(v{})", i),
+            Node::Sinh(i) => format!("sinh(v{})", i),
+            Node::Cosh(i) => format!("cosh(v{})", i),
+            Node::Tanh(i) => format!("tanh(v{})", i),
+            Node::Sigmoid(i) => format!("1.0 / (1.0 + exp(-v{}))", i),
+            Node::ReLU(i) => format!("max(v{}, 0.0)", i),
+            Node::Heaviside(i) => format!("heaviside(v{})", i),
+            Node::Arg(i) => format!("v{}", i),
+            Node::Custom(..) | Node::CustomBinary(..) | Node::External(..) => {
+                panic!("Custom ops can't be compiled to WGSL")
+            }
+        };
+        writeln!(src, "    let v{}: f32 = {};", index, expr).unwrap();
+    }
+
+    // Reverse
+    let root = tape.root();
+    let mask = tape.grad_mask();
+    let mut reached = vec![false; nodes.len()];
+    reached[root] = true;
+    let mut body = String::new();
+    for index in tape.order().rev() {
+        if !reached[index] || mask.is_some_and(|mask| !mask[index]) {
+            continue;
+        }
+        for child_index in nodes[index].operands() {
+            reached[child_index] = true;
+        }
+        let a = format!("a{}", index);
+        let mut acc = |target: usize, term: String| {
+            if mask.is_none_or(|mask| mask[target]) {
+                writeln!(body, "    a{} += {};", target, term).unwrap();
+            }
+        };
+        match nodes[index] {
+            Node::Var(_) | Node::Param(_) | Node::Const(_) | Node::Heaviside(_) => {}
+            Node::Add(l, r) => {
+                acc(l, a.clone());
+                acc(r, a);
+            }
+            Node::Sub(l, r) => {
+                acc(l, a.clone());
+                acc(r, format!("-{}", a));
+            }
+            Node::Addf(_, i) | Node::Subf(i, _) | Node::Transpose(i) | Node::Arg(i) => acc(i, a),
+            Node::Mulf(num, i) => acc(i, format!("{} * {}", a, literal(num))),
+            Node::Neg(i) => acc(i, format!("-{}", a)),
+            Node::Mul(l, r) | Node::Hadamard(l, r) => {
+                acc(l, format!("{} * v{}", a, r));
+                acc(r, format!("{} * v{}", a, l));
+            }
+            Node::Select(c, x, y) => {
+                acc(x, format!("select(0.0, {}, heaviside(v{}) == 1.0)", a, c));
+                acc(y, format!("select({}, 0.0, heaviside(v{}) == 1.0)", a, c));
+            }
+            Node::Fma(x, y, z) => {
+                acc(x, format!("{} * v{}", a, y));
+                acc(y, format!("{} * v{}", a, x));
+                acc(z, a);
+            }
+            Node::Div(l, r) => {
+                acc(l, format!("{} / v{}", a, r));
+                acc(r, format!("-{} * v{} / v{}", a, index, r));
+            }
+            Node::Pow(l, r) => {
+                acc(l, format!("{} * v{} * pow(v{}, v{} - 1.0)", a, r, l, r));
+                acc(r, format!("{} * v{} * log(v{})", a, index, l));
+            }
+            Node::Powf(i, p) => acc(i, format!("{} * {} * pow(v{}, {})", a, literal(p), i, literal(p - 1.0))),
+            Node::Powi(i, n) => acc(i, format!("{} * {} * powi(v{}, {})", a, literal(n as f64), i, n - 1)),
+            Node::Recip(i) => acc(i, format!("-{} * v{} * v{}", a, index, index)),
+            Node::Exp(i) => acc(i, format!("{} * v{}", a, index)),
+            Node::Ln(i) => acc(i, format!("{} / v{}", a, i)),
+            Node::Sin(i) => acc(i, format!("{} * cos(v{})", a, i)),
+            Node::Cos(i) => acc(i, format!("-{} * sin(v{})", a, i)),
+            Node::Tan(i) => acc(i, format!("{} * (1.0 + v{} * v{})", a, index, index)),
+            Node::Sinh(i) => acc(i, format!("{} * cosh(v{})", a, i)),
+            Node::Cosh(i) => acc(i, format!("{} * sinh(v{})", a, i)),
+            Node::Tanh(i) => acc(i, format!("{} * (1.0 - v{} * v{})", a, index, index)),
+            Node::Sigmoid(i) => acc(i, format!("{} * v{} * (1.0 - v{})", a, index, index)),
+            Node::ReLU(i) => acc(i, format!("{} * heaviside(v{})", a, i)),
+            Node::Custom(..) | Node::CustomBinary(..) | Node::External(..) => unreachable!(),
+        }
+    }
+    for (index, _) in reached.iter().enumerate().filter(|(_, r)| **r) {
+        let init = if index == root { "1.0" } else { "0.0" };
+        writeln!(src, "    var a{}: f32 = {};", index, init).unwrap();
+    }
+    src.push_str(&body);
+
+    // Outputs
+    writeln!(src, "    let o = p * {}u;", n_vars + 1).unwrap();
+    writeln!(src, "    outputs[o] = v{};", root).unwrap();
+    for (k, x) in vars.iter().enumerate() {
+        if reached[*x] && mask.is_none_or(|mask| mask[*x]) {
+            writeln!(src, "    outputs[o + {}u] = a{};", k + 1, x).unwrap();
+        } else {
+            writeln!(src, "    outputs[o + {}u] = 0.0;", k + 1).unwrap();
+        }
+    }
+    src.push_str("}\n");
+    src
+}
+
+fn literal(x: f64) -> String {
+    let x = x as f32;
+    if x.is_nan() {
+        "bitcast<f32>(0x7fc00000u)".to_string()
+    } else if x.is_infinite() {
+        let bits = if x > 0.0 { "0x7f800000u" } else { "0xff800000u" };
+        format!("bitcast<f32>({})", bits)
+    } else {
+        format!("{:?}f", x)
+    }
+}
+
+// ┌──────────────────────────────────────────────────────────┐
+//  wgpu backend
+// └──────────────────────────────────────────────────────────┘
+#[cfg(feature = "gpu")]
+pub use backend::GpuEvaluator;
+
+#[cfg(feature = "gpu")]
+mod backend {
+    use super::{wgsl, WORKGROUP_SIZE};
+    use crate::core::{Graph, Node};
+    use wgpu::util::DeviceExt;
+
+    /// Batched value & gradient evaluation of a compiled graph on the GPU
+    pub struct GpuEvaluator {
+        device: wgpu::Device,
+        queue: wgpu::Queue,
+        pipeline: wgpu::ComputePipeline,
+        n_vars: usize,
+        params: Vec<f32>,
+    }
+
+    impl GpuEvaluator {
+        /// Compile the kernel of `graph` (parameter values are captured now)
+        pub fn new(graph: &mut Graph<f64>) -> Self {
+            let tape = graph.tape();
+            let params = graph
+                .nodes
+                .iter()
+                .zip(graph.buffer.iter())
+                .filter_map(|(node, value)| match node {
+                    Node::Param(_) => Some(value.expect("Parameter without value") as f32),
+                    _ => None,
+                })
+                .collect::<Vec<_>>();
+
+            let instance = wgpu::Instance::default();
+            let adapter = pollster::block_on(instance.request_adapter(&wgpu::RequestAdapterOptions::default()))
+                .expect("No GPU adapter");
+            let (device, queue) = pollster::block_on(adapter.request_device(
+                &wgpu::DeviceDescriptor {
+                    label: None,
+                    required_features: wgpu::Features::empty(),
+                    required_limits: wgpu::Limits::downlevel_defaults(),
+                },
+                None,
+            ))
+            .expect("No GPU device");
+            let module = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+                label: None,
+                source: wgpu::ShaderSource::Wgsl(wgsl(&tape).into()),
+            });
+            let pipeline = device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+                label: None,
+                layout: None,
+                module: &module,
+                entry_point: "main",
+            });
+
+            GpuEvaluator {
+                device,
+                queue,
+                pipeline,
+                n_vars: tape.get_vars().len(),
+                params,
+            }
+        }
+
+        /// Values & gradients at every point (each point holds the variables in the order of `get_vars`)
+        pub fn evaluate(&self, points: &[Vec<f64>]) -> Vec<(f64, Vec<f64>)> {
+            if points.is_empty() {
+                return vec![];
+            }
+            let stride = self.n_vars + 1;
+            let mut inputs = points
+                .iter()
+                .flat_map(|x| {
+                    assert_eq!(x.len(), self.n_vars);
+                    x.iter().map(|v| *v as f32)
+                })
+                .collect::<Vec<_>>();
+            // Empty storage buffers are not allowed
+            inputs.resize(inputs.len().max(1), 0.0);
+            let mut params = self.params.clone();
+            params.resize(params.len().max(1), 0.0);
+            let output_size = (points.len() * stride * std::mem::size_of::<f32>()) as u64;
+
+            let input_buffer = self.device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                label: None,
+                contents: &to_bytes(&inputs),
+                usage: wgpu::BufferUsages::STORAGE,
+            });
+            let param_buffer = self.device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                label: None,
+                contents: &to_bytes(&params),
+                usage: wgpu::BufferUsages::STORAGE,
+            });
+            let output_buffer = self.device.create_buffer(&wgpu::BufferDescriptor {
+                label: None,
+                size: output_size,
+                usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_SRC,
+                mapped_at_creation: false,
+            });
+            let staging_buffer = self.device.create_buffer(&wgpu::BufferDescriptor {
+                label: None,
+                size: output_size,
+                usage: wgpu::BufferUsages::MAP_READ | wgpu::BufferUsages::COPY_DST,
+                mapped_at_creation: false,
+            });
+            let bind_group = self.device.create_bind_group(&wgpu::BindGroupDescriptor {
+                label: None,
+                layout: &self.pipeline.get_bind_group_layout(0),
+                entries: &[
+                    wgpu::BindGroupEntry { binding: 0, resource: input_buffer.as_entire_binding() },
+                    wgpu::BindGroupEntry { binding: 1, resource: param_buffer.as_entire_binding() },
+                    wgpu::BindGroupEntry { binding: 2, resource: output_buffer.as_entire_binding() },
+                ],
+            });
+
+            let mut encoder = self.device.create_command_encoder(&wgpu::CommandEncoderDescriptor { label: None });
+            {
+                let mut pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+                    label: None,
+                    timestamp_writes: None,
+                });
+                pass.set_pipeline(&self.pipeline);
+                pass.set_bind_group(0, &bind_group, &[]);
+                pass.dispatch_workgroups(points.len().div_ceil(WORKGROUP_SIZE) as u32, 1, 1);
+            }
+            encoder.copy_buffer_to_buffer(&output_buffer, 0, &staging_buffer, 0, output_size);
+            self.queue.submit(Some(encoder.finish()));
+
+            let slice = staging_buffer.slice(..);
+            slice.map_async(wgpu::MapMode::Read, |result| result.expect("Failed to map GPU buffer"));
+            self.device.poll(wgpu::Maintain::Wait);
+            let outputs = slice
+                .get_mapped_range()
+                .chunks_exact(4)
+                .map(|b| f32::from_le_bytes([b[0], b[1], b[2], b[3]]) as f64)
+                .collect::<Vec<_>>();
+            staging_buffer.unmap();
+
+            outputs
+                .chunks_exact(stride)
+                .map(|row| (row[0], row[1..].to_vec()))
+                .collect()
+        }
+    }
+
+    fn to_bytes(data: &[f32]) -> Vec<u8> {
+        data.iter().flat_map(|x| x.to_le_bytes()).collect()
+    }
+}
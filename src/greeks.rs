@@ -0,0 +1,209 @@
+use crate::core::{Expr, Graph};
+use crate::sample::Rng;
+use crate::tape::{Tape, Workspace};
+#[cfg(feature = "parallel")]
+use rayon::prelude::*;
+
+// ┌──────────────────────────────────────────────────────────┐
+//  Pathwise Greeks by adjoint differentiation
+// └──────────────────────────────────────────────────────────┘
+/// Discounted payoff recorded once & differentiated along many Monte Carlo paths
+///
+/// The payoff is a function of the market/model parameters (spot, volatility, rate, ...) and the
+/// random shocks of one path. It is compiled into a single `Tape` shared by every path; each path
+/// (each thread with `parallel`) runs on its own `Workspace`, and one reverse sweep per path gives
+/// the pathwise derivative w.r.t. every parameter at once.
+///
+/// The pathwise estimator needs a payoff that is continuous in the parameters (e.g. vanilla &
+/// Asian options through `relu`); the derivatives of a digital payoff vanish almost surely.
+///
+/// ```ignore
+/// // European call under Black-Scholes: p = [spot, vol, rate, maturity, strike]
+/// let pricer = Pathwise::new(5, 1, |p, z| {
+///     let (s, v, r, t, k) = (p[0].clone(), p[1].clone(), p[2].clone(), p[3].clone(), p[4].clone());
+///     let drift = (r.clone() - 0.5 * v.powi(2)) * t.clone();
+///     let st = s * (drift + v * t.sqrt() * z[0].clone()).exp();
+///     (-(r * t)).exp() * (st - k).relu()
+/// });
+/// let greeks = pricer.simulate(&[100.0, 0.2, 0.05, 1.0, 100.0], 100_000, 42);
+/// let (delta, vega) = (&greeks.sensitivities[0], &greeks.sensitivities[1]);
+/// ```
+pub struct Pathwise {
+    tape: Tape,
+    workspace: Workspace<f64>,
+    n_params: usize,
+    n_shocks: usize,
+}
+
+/// Monte Carlo mean with the sample variance of the per-path values
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Estimate {
+    pub mean: f64,
+    pub variance: f64,
+    /// `sqrt(variance / paths)`
+    pub std_err: f64,
+}
+
+/// Price & pathwise sensitivities (see `Pathwise::evaluate`)
+#[derive(Debug, Clone)]
+pub struct Greeks {
+    pub price: Estimate,
+    /// `∂price/∂p_i` in the order of the parameters
+    pub sensitivities: Vec<Estimate>,
+    pub paths: usize,
+}
+
+impl Pathwise {
+    /// `payoff(params, shocks)` with `n_params` parameters & `n_shocks` random draws per path
+    pub fn new<F: Fn(&[Expr], &[Expr]) -> Expr>(n_params: usize, n_shocks: usize, payoff: F) -> Self {
+        let mut graph = Graph::default();
+        graph.touch_vars(n_params + n_shocks);
+        let symbols = graph.get_symbols();
+        let (params, shocks) = symbols.split_at(n_params);
+        graph.compile(payoff(params, shocks));
+        Pathwise {
+            tape: graph.tape(),
+            workspace: graph.workspace(),
+            n_params,
+            n_shocks,
+        }
+    }
+
+    pub fn n_params(&self) -> usize {
+        self.n_params
+    }
+
+    pub fn n_shocks(&self) -> usize {
+        self.n_shocks
+    }
+
+    /// Payoff & its gradient w.r.t. the parameters along a single path
+    pub fn path(&self, params: &[f64], shocks: &[f64]) -> (f64, Vec<f64>) {
+        let mut ws = self.workspace.clone();
+        self.eval(&mut ws, params, shocks)
+    }
+
+    /// Price & Greeks averaged over `paths` (one vector of `n_shocks` draws each)
+    pub fn evaluate(&self, params: &[f64], paths: &[Vec<f64>]) -> Greeks {
+        assert_eq!(params.len(), self.n_params, "Parameters have the wrong length");
+        assert!(!paths.is_empty(), "Monte Carlo estimate without paths");
+
+        #[cfg(not(feature = "parallel"))]
+        let moments = {
+            let mut ws = self.workspace.clone();
+            let mut moments = Moments::new(self.n_params + 1);
+            for shocks in paths {
+                let (value, grads) = self.eval(&mut ws, params, shocks);
+                moments.push(value, &grads);
+            }
+            moments
+        };
+        #[cfg(feature = "parallel")]
+        let moments = paths
+            .par_chunks(256)
+            .map(|chunk| {
+                let mut ws = self.workspace.clone();
+                let mut moments = Moments::new(self.n_params + 1);
+                for shocks in chunk {
+                    let (value, grads) = self.eval(&mut ws, params, shocks);
+                    moments.push(value, &grads);
+                }
+                moments
+            })
+            .reduce(|| Moments::new(self.n_params + 1), Moments::merge);
+
+        let mut estimates = moments.estimates();
+        let price = estimates.remove(0);
+        Greeks {
+            price,
+            sensitivities: estimates,
+            paths: paths.len(),
+        }
+    }
+
+    /// `evaluate` over `n_paths` paths of independent standard normal shocks
+    ///
+    /// The same `seed` reproduces the same paths, so Greeks at nearby parameters share their noise.
+    pub fn simulate(&self, params: &[f64], n_paths: usize, seed: u64) -> Greeks {
+        let mut rng = Rng::new(seed);
+        let paths = (0..n_paths)
+            .map(|_| (0..self.n_shocks).map(|_| rng.normal()).collect())
+            .collect::<Vec<_>>();
+        self.evaluate(params, &paths)
+    }
+
+    fn eval(&self, ws: &mut Workspace<f64>, params: &[f64], shocks: &[f64]) -> (f64, Vec<f64>) {
+        assert_eq!(shocks.len(), self.n_shocks, "Path has the wrong number of shocks");
+        let point = params.iter().chain(shocks).copied().collect::<Vec<_>>();
+        self.tape.subs_vars(ws, &point);
+        let value = self.tape.forward(ws);
+        self.tape.backward(ws);
+        let mut grads = self.tape.get_gradients(ws);
+        grads.truncate(self.n_params);
+        (value, grads)
+    }
+}
+
+/// Running means & variances of the payoff followed by its gradient (Welford, with Chan's merge)
+struct Moments {
+    count: f64,
+    mean: Vec<f64>,
+    m2: Vec<f64>,
+}
+
+impl Moments {
+    fn new(dim: usize) -> Self {
+        Moments {
+            count: 0f64,
+            mean: vec![0f64; dim],
+            m2: vec![0f64; dim],
+        }
+    }
+
+    fn push(&mut self, value: f64, grads: &[f64]) {
+        self.count += 1f64;
+        let xs = std::iter::once(&value).chain(grads);
+        for ((mean, m2), x) in self.mean.iter_mut().zip(self.m2.iter_mut()).zip(xs) {
+            let d = x - *mean;
+            *mean += d / self.count;
+            *m2 += d * (x - *mean);
+        }
+    }
+
+    #[cfg(feature = "parallel")]
+    fn merge(self, other: Moments) -> Moments {
+        if self.count == 0f64 {
+            return other;
+        }
+        if other.count == 0f64 {
+            return self;
+        }
+        let count = self.count + other.count;
+        let (mean, m2) = self
+            .mean
+            .iter()
+            .zip(&self.m2)
+            .zip(other.mean.iter().zip(&other.m2))
+            .map(|((ma, sa), (mb, sb))| {
+                let d = mb - ma;
+                (ma + d * other.count / count, sa + sb + d * d * self.count * other.count / count)
+            })
+            .unzip();
+        Moments { count, mean, m2 }
+    }
+
+    fn estimates(&self) -> Vec<Estimate> {
+        self.mean
+            .iter()
+            .zip(&self.m2)
+            .map(|(mean, m2)| {
+                let variance = m2 / (self.count - 1f64).max(1f64);
+                Estimate {
+                    mean: *mean,
+                    variance,
+                    std_err: (variance / self.count).sqrt(),
+                }
+            })
+            .collect()
+    }
+}
@@ -0,0 +1,109 @@
+use crate::core::{Expr, Graph, Node};
+use crate::traits::{ActivationFunction, Matrizable};
+use peroxide_num::Numeric;
+use std::ops::Div;
+
+// ┌──────────────────────────────────────────────────────────┐
+//  Variable groups
+// └──────────────────────────────────────────────────────────┘
+// A group is a named list of variables (in registration order). Groups only label variables:
+// `get_vars` & `get_gradients` keep the flat order, and a variable may belong to several groups.
+impl<T: std::fmt::Debug + Numeric<f64> + Default + ActivationFunction + Matrizable> Graph<T>
+where
+    f64: Div<T, Output = T>,
+{
+    /// New variable registered under `group`
+    pub fn var_in(&mut self, group: &str, value: T) -> usize {
+        let index = self.var(value);
+        self.add_to_group(group, &[index]);
+        index
+    }
+
+    pub fn vars_in(&mut self, group: &str, values: &[T]) -> Vec<usize> {
+        values.iter().map(|x| self.var_in(group, x.clone())).collect()
+    }
+
+    /// Declare `n` symbols registered under `group` (see `symbol`)
+    pub fn symbols_in(&mut self, group: &str, n: usize) -> Vec<Expr> {
+        let symbols = (0..n).map(|_| self.symbol()).collect::<Vec<_>>();
+        let vars = symbols
+            .iter()
+            .map(|x| match x {
+                Expr::Symbol(index) => *index,
+                _ => unreachable!(),
+            })
+            .collect::<Vec<_>>();
+        self.add_to_group(group, &vars);
+        symbols
+    }
+
+    /// Register existing variables under `group` (the group is created if needed)
+    pub fn add_to_group(&mut self, group: &str, vars: &[usize]) {
+        assert!(
+            vars.iter().all(|x| matches!(self.nodes.get(*x), Some(Node::Var(_)))),
+            "Not a variable"
+        );
+        let members = self.groups.entry(group.to_string()).or_default();
+        for var in vars {
+            if !members.contains(var) {
+                members.push(*var);
+            }
+        }
+    }
+
+    /// Variables of `group` in registration order
+    pub fn get_group(&self, group: &str) -> Vec<usize> {
+        match self.groups.get(group) {
+            Some(vars) => vars.clone(),
+            None => panic!("No group named {}", group),
+        }
+    }
+
+    /// Names of every group (sorted)
+    pub fn get_group_names(&self) -> Vec<String> {
+        let mut names = self.groups.keys().cloned().collect::<Vec<_>>();
+        names.sort();
+        names
+    }
+
+    pub fn get_group_symbols(&self, group: &str) -> Vec<Expr> {
+        self.get_group(group).into_iter().map(Expr::Symbol).collect()
+    }
+
+    /// Gradients of the variables of `group` (after `backward`)
+    pub fn get_group_gradients(&self, group: &str) -> Vec<T> {
+        self.get_group(group).iter().map(|x| self.get_gradient(*x)).collect()
+    }
+
+    /// Substitute the variables of `group` in registration order
+    pub fn subs_group(&mut self, group: &str, vals: &[T]) {
+        let vars = self.get_group(group);
+        assert_eq!(vars.len(), vals.len(), "Group {} has {} variables", group, vars.len());
+        for (var, val) in vars.into_iter().zip(vals) {
+            self.subs_var(var, val.clone());
+        }
+    }
+
+    /// Zero the gradients of the variables of `group` only
+    pub fn zero_grad_group(&mut self, group: &str) {
+        for var in self.get_group(group) {
+            self.gradients[var] = match self.buffer[var].as_ref() {
+                Some(x) => x.zeros_like(),
+                None => T::default(),
+            }
+        }
+    }
+
+    /// Freeze every variable of `group` (see `freeze`)
+    pub fn freeze_group(&mut self, group: &str) {
+        for var in self.get_group(group) {
+            self.freeze(var);
+        }
+    }
+
+    pub fn unfreeze_group(&mut self, group: &str) {
+        for var in self.get_group(group) {
+            self.unfreeze(var);
+        }
+    }
+}
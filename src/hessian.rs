@@ -0,0 +1,304 @@
+use crate::core::{Graph, Node};
+use crate::custom::CustomOp;
+use crate::traits::ActivationFunction;
+#[cfg(feature = "parallel")]
+use rayon::prelude::*;
+
+// ┌──────────────────────────────────────────────────────────┐
+//  Local first & second order partials of a node (scalar only)
+// └──────────────────────────────────────────────────────────┘
+pub(crate) enum Partials {
+    Leaf,
+    /// (operand, f', f'')
+    Unary(usize, f64, f64),
+    /// (left, right, f_l, f_r, f_ll, f_lr, f_rr)
+    Binary(usize, usize, f64, f64, f64, f64, f64),
+    /// (a, b, c, value of a, value of b) of `a * b + c`
+    Fma(usize, usize, usize, f64, f64),
+}
+
+pub(crate) fn partials(node: &Node, buffer: &[Option<f64>], ops: &[CustomOp]) -> Partials {
+    let val = |i: &usize| buffer[*i].unwrap();
+    match node {
+        Node::Var(_) | Node::Param(_) | Node::Const(_) => Partials::Leaf,
+        Node::Add(l, r) => Partials::Binary(*l, *r, 1.0, 1.0, 0.0, 0.0, 0.0),
+        Node::Sub(l, r) => Partials::Binary(*l, *r, 1.0, -1.0, 0.0, 0.0, 0.0),
+        Node::Mul(l, r) | Node::Hadamard(l, r) => {
+            Partials::Binary(*l, *r, val(r), val(l), 0.0, 1.0, 0.0)
+        }
+        Node::Fma(a, b, c) => Partials::Fma(*a, *b, *c, val(a), val(b)),
+        Node::Select(c, a, b) => {
+            let h = if val(c).is_sign_positive() { 1.0 } else { 0.0 };
+            Partials::Binary(*a, *b, h, 1.0 - h, 0.0, 0.0, 0.0)
+        }
+        Node::Div(l, r) => {
+            let (u, w) = (val(l), val(r));
+            Partials::Binary(
+                *l,
+                *r,
+                1.0 / w,
+                -u / (w * w),
+                0.0,
+                -1.0 / (w * w),
+                2.0 * u / (w * w * w),
+            )
+        }
+        Node::Pow(l, r) => {
+            let (u, w) = (val(l), val(r));
+            let f = u.powf(w);
+            let ln_u = u.ln();
+            Partials::Binary(
+                *l,
+                *r,
+                w * u.powf(w - 1.0),
+                f * ln_u,
+                w * (w - 1.0) * u.powf(w - 2.0),
+                u.powf(w - 1.0) * (1.0 + w * ln_u),
+                f * ln_u * ln_u,
+            )
+        }
+        Node::Addf(_, i) | Node::Subf(i, _) | Node::Transpose(i) | Node::Arg(i) => {
+            Partials::Unary(*i, 1.0, 0.0)
+        }
+        Node::Mulf(num, i) => Partials::Unary(*i, *num, 0.0),
+        Node::Neg(i) => Partials::Unary(*i, -1.0, 0.0),
+        Node::Powf(i, p) => {
+            let u = val(i);
+            Partials::Unary(*i, p * u.powf(p - 1.0), p * (p - 1.0) * u.powf(p - 2.0))
+        }
+        Node::Powi(i, n) => {
+            let u = val(i);
+            let p = *n as f64;
+            Partials::Unary(*i, p * u.powi(n - 1), p * (p - 1.0) * u.powi(n - 2))
+        }
+        Node::Recip(i) => {
+            let u = val(i);
+            Partials::Unary(*i, -1.0 / (u * u), 2.0 / (u * u * u))
+        }
+        Node::Exp(i) => {
+            let e = val(i).exp();
+            Partials::Unary(*i, e, e)
+        }
+        Node::Ln(i) => {
+            let u = val(i);
+            Partials::Unary(*i, 1.0 / u, -1.0 / (u * u))
+        }
+        Node::Sin(i) => {
+            let u = val(i);
+            Partials::Unary(*i, u.cos(), -u.sin())
+        }
+        Node::Cos(i) => {
+            let u = val(i);
+            Partials::Unary(*i, -u.sin(), -u.cos())
+        }
+        Node::Tan(i) => {
+            let t = val(i).tan();
+            let d = 1.0 + t * t;
+            Partials::Unary(*i, d, 2.0 * t * d)
+        }
+        Node::Sinh(i) => {
+            let u = val(i);
+            Partials::Unary(*i, u.cosh(), u.sinh())
+        }
+        Node::Cosh(i) => {
+            let u = val(i);
+            Partials::Unary(*i, u.sinh(), u.cosh())
+        }
+        Node::Tanh(i) => {
+            let t = val(i).tanh();
+            let d = 1.0 - t * t;
+            Partials::Unary(*i, d, -2.0 * t * d)
+        }
+        Node::Sigmoid(i) => {
+            let s = 1.0 / (1.0 + (-val(i)).exp());
+            let d = s * (1.0 - s);
+            Partials::Unary(*i, d, d * (1.0 - 2.0 * s))
+        }
+        // Same kink convention as `propagate_adjoint`
+        Node::ReLU(i) => Partials::Unary(*i, val(i).heaviside_zero(), 0.0),
+        Node::Heaviside(i) => Partials::Unary(*i, 0.0, 0.0),
+        Node::Custom(id, i) => {
+            let u = val(i);
+            Partials::Unary(*i, ops[*id].partials(u, 0.0).0, ops[*id].second_partials(u, 0.0).0)
+        }
+        Node::CustomBinary(id, l, r) => {
+            let (u, w) = (val(l), val(r));
+            let (fl, fr) = ops[*id].partials(u, w);
+            let (fll, flr, frr) = ops[*id].second_partials(u, w);
+            Partials::Binary(*l, *r, fl, fr, fll, flr, frr)
+        }
+        // Handled by the sweeps themselves (any arity)
+        Node::External(..) => unreachable!("External functions have no fixed arity"),
+    }
+}
+
+// ┌──────────────────────────────────────────────────────────┐
+//  Second order derivatives via forward-over-reverse sweeps
+// └──────────────────────────────────────────────────────────┘
+impl Graph<f64> {
+    /// Hessian-vector product `H·v` of the compiled expression
+    ///
+    /// `v` is given in the order of variables (same as `get_vars`).
+    /// One forward tangent sweep and one reverse sweep are performed.
+    pub fn hvp(&mut self, v: &[f64]) -> Vec<f64> {
+        assert_eq!(self.value_ics.len(), v.len());
+        self.forward();
+        let order = self.get_topological_order();
+        self.hvp_sweep(&order, v)
+    }
+
+    /// `H·v` on up to date values, touching nothing but its own tangent & adjoint buffers
+    ///
+    /// Nodes the compiled output does not depend on are skipped. External functions only provide
+    /// first partials, so their second order term is a central difference of those along the
+    /// tangent of their arguments.
+    fn hvp_sweep(&self, order: &[usize], v: &[f64]) -> Vec<f64> {
+        let n = self.nodes.len();
+        let root = self.compiled.unwrap();
+        let reached = self.reached_from(root);
+        let dot = self.tangent_sweep(order, v);
+
+        // Reverse sweep of adjoints & their tangents
+        let mut adj = vec![0f64; n];
+        let mut adj_dot = vec![0f64; n];
+        adj[root] = 1.0;
+        for &index in order.iter().rev() {
+            if !reached[index] {
+                continue;
+            }
+            let (a, a_dot) = (adj[index], adj_dot[index]);
+            if let Node::External(id, first, m) = self.nodes.node(index) {
+                let x = (first..first + m).map(|j| self.buffer[j].unwrap()).collect::<Vec<_>>();
+                let op = &self.custom_ops[id];
+                let hv = op.second_partials_n(&x, &dot[first..first + m]);
+                for (j, hv_j) in hv.into_iter().enumerate() {
+                    let d = op.partial_n(&x, j);
+                    adj[first + j] += a * d;
+                    adj_dot[first + j] += a_dot * d + a * hv_j;
+                }
+                continue;
+            }
+            match partials(&self.nodes.node(index), &self.buffer, &self.custom_ops) {
+                Partials::Leaf => {}
+                Partials::Unary(i, d, dd) => {
+                    adj[i] += a * d;
+                    adj_dot[i] += a_dot * d + a * dd * dot[i];
+                }
+                Partials::Binary(l, r, fl, fr, fll, flr, frr) => {
+                    adj[l] += a * fl;
+                    adj[r] += a * fr;
+                    adj_dot[l] += a_dot * fl + a * (fll * dot[l] + flr * dot[r]);
+                    adj_dot[r] += a_dot * fr + a * (flr * dot[l] + frr * dot[r]);
+                }
+                Partials::Fma(x, y, z, vx, vy) => {
+                    adj[x] += a * vy;
+                    adj[y] += a * vx;
+                    adj[z] += a;
+                    adj_dot[x] += a_dot * vy + a * dot[y];
+                    adj_dot[y] += a_dot * vx + a * dot[x];
+                    adj_dot[z] += a_dot;
+                }
+            }
+        }
+
+        self.value_ics.iter().map(|x| adj_dot[*x]).collect()
+    }
+
+    /// Jacobian-vector product `J·v` of every output (in the order of `get_outputs`)
+    ///
+    /// One forward tangent sweep, so a full Jacobian takes one sweep per variable (see
+    /// `sparse_jacobian` to share sweeps between structurally orthogonal columns).
+    pub fn jvp(&mut self, v: &[f64]) -> Vec<f64> {
+        assert_eq!(self.value_ics.len(), v.len());
+        self.forward_all();
+        let order = self.get_topological_order();
+        let dot = self.tangent_sweep(&order, v);
+        self.outputs.iter().map(|x| dot[*x]).collect()
+    }
+
+    /// Tangents of every node along `v` (values must be up to date)
+    fn tangent_sweep(&self, order: &[usize], v: &[f64]) -> Vec<f64> {
+        let mut dot = vec![0f64; self.nodes.len()];
+        for (var, dv) in self.value_ics.iter().zip(v) {
+            dot[*var] = *dv;
+        }
+        for &index in order.iter() {
+            // External functions only have first partials, which is all a tangent needs
+            if let Node::External(id, first, n) = self.nodes.node(index) {
+                let x = (first..first + n).map(|j| self.buffer[j].unwrap()).collect::<Vec<_>>();
+                let op = &self.custom_ops[id];
+                dot[index] = (0..n).map(|j| op.partial_n(&x, j) * dot[first + j]).sum();
+                continue;
+            }
+            dot[index] = match partials(&self.nodes.node(index), &self.buffer, &self.custom_ops) {
+                Partials::Leaf => dot[index],
+                Partials::Unary(i, d, _) => d * dot[i],
+                Partials::Binary(l, r, fl, fr, ..) => fl * dot[l] + fr * dot[r],
+                Partials::Fma(a, b, c, va, vb) => vb * dot[a] + va * dot[b] + dot[c],
+            };
+        }
+        dot
+    }
+
+    /// Diagonal of the Hessian of the compiled expression
+    ///
+    /// Computed by `n` forward-over-reverse sweeps (one per variable), so the full Hessian is never formed.
+    pub fn hessian_diag(&mut self) -> Vec<f64> {
+        let n_vars = self.value_ics.len();
+        let mut e = vec![0f64; n_vars];
+        (0..n_vars)
+            .map(|i| {
+                e[i] = 1.0;
+                let hv = self.hvp(&e);
+                e[i] = 0.0;
+                hv[i]
+            })
+            .collect()
+    }
+
+    /// Dense Hessian of the compiled expression (row `i` is `H·e_i`)
+    ///
+    /// Computed by `n` forward-over-reverse sweeps and symmetrized to wash out rounding.
+    pub fn hessian(&mut self) -> Vec<Vec<f64>> {
+        let n_vars = self.value_ics.len();
+        let mut e = vec![0f64; n_vars];
+        let h = (0..n_vars)
+            .map(|i| {
+                e[i] = 1.0;
+                let hv = self.hvp(&e);
+                e[i] = 0.0;
+                hv
+            })
+            .collect::<Vec<_>>();
+        symmetrize(&h)
+    }
+
+    /// `hessian` with the columns spread over threads
+    ///
+    /// The tape & the forward values are shared read-only; every sweep owns its tangent & adjoint
+    /// buffers, so columns are independent. They are collected in order & symmetrized as in
+    /// `hessian`, so the result does not depend on the scheduling.
+    #[cfg(feature = "parallel")]
+    pub fn hessian_par(&mut self) -> Vec<Vec<f64>> {
+        let n_vars = self.value_ics.len();
+        self.forward();
+        let order = self.get_topological_order();
+        let graph = &*self;
+        let h = (0..n_vars)
+            .into_par_iter()
+            .map(|i| {
+                let mut e = vec![0f64; n_vars];
+                e[i] = 1.0;
+                graph.hvp_sweep(&order, &e)
+            })
+            .collect::<Vec<_>>();
+        symmetrize(&h)
+    }
+}
+
+fn symmetrize(h: &[Vec<f64>]) -> Vec<Vec<f64>> {
+    let n = h.len();
+    (0..n)
+        .map(|i| (0..n).map(|j| 0.5 * (h[i][j] + h[j][i])).collect())
+        .collect()
+}
@@ -0,0 +1,240 @@
+use crate::core::{Expr, Graph, Node};
+
+// ┌──────────────────────────────────────────────────────────┐
+//  Differentiable reverse sweep (scalar only)
+// └──────────────────────────────────────────────────────────┘
+impl Graph<f64> {
+    /// Record the reverse sweep of `root` onto the tape and return the node holding
+    /// `∂root/∂var` for each of `vars`
+    ///
+    /// Unlike `backward`, which accumulates adjoints numerically, every adjoint is built from
+    /// ordinary nodes sharing the leaves of the tape, so the returned nodes can be compiled,
+    /// combined with other expressions and differentiated again (to any order). Variables not
+    /// reached from `root` get a zero constant. Custom ops have no symbolic derivative and panic.
+    pub fn grad_nodes(&mut self, root: usize, vars: &[usize]) -> Vec<usize> {
+        assert!(root < self.nodes.len(), "Node index out of bounds");
+        let n = self.nodes.len();
+        let order = self.topological_sort();
+
+        // Nodes on a path from `root` down to one of `vars`
+        let mut active = vec![false; n];
+        for &var in vars {
+            assert!(matches!(self.nodes.get(var), Some(Node::Var(_))), "Not a variable");
+            active[var] = true;
+        }
+        for &index in order.iter() {
+            if !active[index] {
+                active[index] = self.nodes.node(index).operands().iter().any(|&x| active[x]);
+            }
+        }
+        let mut reached = vec![false; n];
+        reached[root] = true;
+
+        let mut adjoints: Vec<Option<usize>> = vec![None; n];
+        if active[root] {
+            adjoints[root] = Some(self.constant(1f64));
+        }
+        for &index in order.iter().rev() {
+            if !reached[index] || !active[index] {
+                continue;
+            }
+            for operand in self.nodes.node(index).operand_iter() {
+                reached[operand] = true;
+            }
+            let Some(g) = adjoints[index] else {
+                continue;
+            };
+            self.record_adjoint(index, g, &active, &mut adjoints);
+        }
+
+        let grads = vars
+            .iter()
+            .map(|var| adjoints[*var].unwrap_or_else(|| self.constant(0f64)))
+            .collect();
+        self.topological_order = None;
+        self.reverse_order = None;
+        self.grad_mask = None;
+        grads
+    }
+
+    /// Gradient of the compiled output with respect to every variable (see `grad_nodes`), as
+    /// symbols to build further expressions from
+    ///
+    /// E.g. a gradient penalty `f + λ·|∇f|²` is `f` plus the squares of these symbols; compiling
+    /// it and calling `backward` differentiates through the recorded reverse sweep.
+    pub fn grad_exprs(&mut self) -> Vec<Expr> {
+        let root = self.compiled.expect("Graph is not compiled");
+        let vars = self.get_vars();
+        self.grad_nodes(root, &vars).into_iter().map(Expr::Symbol).collect()
+    }
+
+    /// Emit the contributions of node `index` with adjoint `g` to the adjoints of its operands
+    ///
+    /// Operands of unary nodes are always active (an active node has an active operand); the
+    /// others are checked so no dead adjoint is recorded.
+    fn record_adjoint(&mut self, index: usize, g: usize, active: &[bool], adjoints: &mut [Option<usize>]) {
+        match self.nodes.node(index) {
+            Node::Var(_) | Node::Param(_) | Node::Const(_) | Node::Heaviside(_) | Node::Powi(_, 0) => {}
+            Node::Add(l, r) | Node::Sub(l, r) => {
+                if active[l] {
+                    self.accumulate(adjoints, l, g, false);
+                }
+                if active[r] {
+                    self.accumulate(adjoints, r, g, matches!(self.nodes.node(index), Node::Sub(..)));
+                }
+            }
+            Node::Addf(_, i) | Node::Subf(i, _) | Node::Transpose(i) | Node::Arg(i) | Node::Powi(i, 1) => {
+                self.accumulate(adjoints, i, g, false)
+            }
+            Node::Neg(i) => self.accumulate(adjoints, i, g, true),
+            Node::Mul(l, r) | Node::Hadamard(l, r) => {
+                if active[l] {
+                    let d = self.mul(g, r);
+                    self.accumulate(adjoints, l, d, false);
+                }
+                if active[r] {
+                    let d = self.mul(l, g);
+                    self.accumulate(adjoints, r, d, false);
+                }
+            }
+            Node::Mulf(num, i) => {
+                let d = self.mulf(num, g);
+                self.accumulate(adjoints, i, d, false);
+            }
+            Node::Fma(a, b, c) => {
+                if active[a] {
+                    let d = self.mul(g, b);
+                    self.accumulate(adjoints, a, d, false);
+                }
+                if active[b] {
+                    let d = self.mul(a, g);
+                    self.accumulate(adjoints, b, d, false);
+                }
+                if active[c] {
+                    self.accumulate(adjoints, c, g, false);
+                }
+            }
+            Node::Select(c, a, b) => {
+                let zero = self.constant(0f64);
+                if active[a] {
+                    let d = self.select_sign(c, g, zero);
+                    self.accumulate(adjoints, a, d, false);
+                }
+                if active[b] {
+                    let d = self.select_sign(c, zero, g);
+                    self.accumulate(adjoints, b, d, false);
+                }
+            }
+            Node::Div(l, r) => {
+                if active[l] {
+                    let d = self.div(g, r);
+                    self.accumulate(adjoints, l, d, false);
+                }
+                if active[r] {
+                    // ∂(l/r)/∂r = -(l/r)/r
+                    let d = self.div(index, r);
+                    let d = self.mul(g, d);
+                    self.accumulate(adjoints, r, d, true);
+                }
+            }
+            Node::Pow(l, r) => {
+                if active[l] {
+                    let p = self.subf(r, 1f64);
+                    let d = self.pow(l, p);
+                    let d = self.mul(r, d);
+                    let d = self.mul(d, g);
+                    self.accumulate(adjoints, l, d, false);
+                }
+                if active[r] {
+                    let d = self.ln(l);
+                    let d = self.mul(index, d);
+                    let d = self.mul(d, g);
+                    self.accumulate(adjoints, r, d, false);
+                }
+            }
+            Node::Powf(i, p) => {
+                let d = self.powf(i, p - 1f64);
+                let d = self.mulf(p, d);
+                let d = self.mul(d, g);
+                self.accumulate(adjoints, i, d, false);
+            }
+            Node::Powi(i, k) => {
+                let d = self.powi(i, k - 1);
+                let d = self.mulf(k as f64, d);
+                let d = self.mul(d, g);
+                self.accumulate(adjoints, i, d, false);
+            }
+            Node::Recip(i) => {
+                let d = self.powi(index, 2);
+                let d = self.mul(g, d);
+                self.accumulate(adjoints, i, d, true);
+            }
+            Node::Exp(i) => {
+                let d = self.mul(g, index);
+                self.accumulate(adjoints, i, d, false);
+            }
+            Node::Ln(i) => {
+                let d = self.div(g, i);
+                self.accumulate(adjoints, i, d, false);
+            }
+            Node::Sin(i) => {
+                let d = self.cos(i);
+                let d = self.mul(g, d);
+                self.accumulate(adjoints, i, d, false);
+            }
+            Node::Cos(i) => {
+                let d = self.sin(i);
+                let d = self.mul(g, d);
+                self.accumulate(adjoints, i, d, true);
+            }
+            Node::Tan(i) => {
+                let d = self.powi(index, 2);
+                let d = self.addf(1f64, d);
+                let d = self.mul(d, g);
+                self.accumulate(adjoints, i, d, false);
+            }
+            Node::Sinh(i) => {
+                let d = self.cosh(i);
+                let d = self.mul(g, d);
+                self.accumulate(adjoints, i, d, false);
+            }
+            Node::Cosh(i) => {
+                let d = self.sinh(i);
+                let d = self.mul(g, d);
+                self.accumulate(adjoints, i, d, false);
+            }
+            Node::Tanh(i) => {
+                // 1 - tanh² = -(tanh² - 1)
+                let d = self.powi(index, 2);
+                let d = self.subf(d, 1f64);
+                let d = self.mul(d, g);
+                self.accumulate(adjoints, i, d, true);
+            }
+            Node::Sigmoid(i) => {
+                let d = self.neg(index);
+                let d = self.addf(1f64, d);
+                let d = self.mul(index, d);
+                let d = self.mul(d, g);
+                self.accumulate(adjoints, i, d, false);
+            }
+            Node::ReLU(i) => {
+                let d = self.heaviside(i);
+                let d = self.mul(d, g);
+                self.accumulate(adjoints, i, d, false);
+            }
+            Node::Custom(..) | Node::CustomBinary(..) | Node::External(..) => {
+                panic!("Custom op at node {} has no symbolic derivative", index)
+            }
+        }
+    }
+
+    /// `adjoint += x` (or `-= x`) as nodes
+    fn accumulate(&mut self, adjoints: &mut [Option<usize>], operand: usize, x: usize, negate: bool) {
+        adjoints[operand] = Some(match (adjoints[operand], negate) {
+            (None, false) => x,
+            (None, true) => self.neg(x),
+            (Some(a), false) => self.add(a, x),
+            (Some(a), true) => self.sub(a, x),
+        });
+    }
+}
@@ -0,0 +1,294 @@
+use crate::core::{Expr, Graph, Node};
+use crate::custom::CustomOp;
+use peroxide::fuga::{py_matrix, LinearAlgebra, SolveKind};
+use std::sync::{Arc, Mutex};
+
+// ┌──────────────────────────────────────────────────────────┐
+//  Implicit differentiation of fixed points
+// └──────────────────────────────────────────────────────────┘
+/// Root `x*(θ)` of a residual system `r(x, θ) = 0`, referenced by `CustomOp::Implicit`
+///
+/// The residual lives on its own tape (variables `x` followed by `θ`, one output per equation).
+/// Whenever `θ` changes, `x*` is re-solved by Newton's method (safeguarded by bisection for a
+/// bracketed scalar root) warm started from the previous root, and the sensitivities come from the implicit function theorem
+/// `dx*/dθ = -(∂r/∂x)⁻¹ ∂r/∂θ`, so no solver iteration is ever recorded on the outer tape.
+pub struct ImplicitSystem {
+    state: Mutex<State>,
+    solver: Solver,
+    tol: f64,
+    max_iter: usize,
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Solver {
+    Newton,
+    /// Safeguarded Newton-bisection of a scalar root inside `[a, b]`
+    Bracket(f64, f64),
+}
+
+struct State {
+    residual: Graph<f64>,
+    theta: Option<Vec<f64>>,
+    x: Vec<f64>,
+    /// Whether the solve at `theta` found a root (`x` keeps the last root otherwise)
+    converged: bool,
+    /// `dx*/dθ` at the current root (row per component of `x`)
+    sensitivity: Option<Vec<Vec<f64>>>,
+}
+
+impl std::fmt::Debug for ImplicitSystem {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        let state = self.state.lock().unwrap();
+        write!(f, "ImplicitSystem {{ x: {:?}, theta: {:?}, tol: {:?} }}", state.x, state.theta, self.tol)
+    }
+}
+
+impl ImplicitSystem {
+    /// `residual(x, θ)` with `n_theta` parameters & the root `x_star` as initial guess
+    pub fn new<F: Fn(&[Expr], &[Expr]) -> Vec<Expr>>(
+        x_star: &[f64],
+        n_theta: usize,
+        residual: F,
+        tol: f64,
+    ) -> Self {
+        Self::build(x_star, n_theta, residual, Solver::Newton, tol)
+    }
+
+    /// Scalar `residual(x, θ)` whose root is bracketed by `[a, b]` for every `θ` of interest
+    ///
+    /// Iterates until the bracket shrinks to machine precision, so no tolerance is needed.
+    pub fn bracketed<F: Fn(&Expr, &[Expr]) -> Expr>(bracket: (f64, f64), n_theta: usize, residual: F) -> Self {
+        let (a, b) = bracket;
+        Self::build(
+            &[0.5 * (a + b)],
+            n_theta,
+            |x, theta| vec![residual(&x[0], theta)],
+            Solver::Bracket(a.min(b), a.max(b)),
+            0f64,
+        )
+    }
+
+    fn build<F: Fn(&[Expr], &[Expr]) -> Vec<Expr>>(
+        x_star: &[f64],
+        n_theta: usize,
+        residual: F,
+        solver: Solver,
+        tol: f64,
+    ) -> Self {
+        let mut graph = Graph::default();
+        graph.touch_vars(x_star.len() + n_theta);
+        let symbols = graph.get_symbols();
+        let (x, theta) = symbols.split_at(x_star.len());
+        let r = residual(x, theta);
+        assert_eq!(r.len(), x_star.len(), "Residual system must be square in x");
+        graph.compile_many(r);
+        ImplicitSystem {
+            state: Mutex::new(State {
+                residual: graph,
+                theta: None,
+                x: x_star.to_vec(),
+                converged: true,
+                sensitivity: None,
+            }),
+            solver,
+            tol,
+            max_iter: 100,
+        }
+    }
+
+    /// Current root (as of the last evaluation, NaN if that solve failed)
+    pub fn root(&self) -> Vec<f64> {
+        let state = self.state.lock().unwrap();
+        if state.converged {
+            state.x.clone()
+        } else {
+            vec![f64::NAN; state.x.len()]
+        }
+    }
+
+    /// Whether the last evaluation found a root (see `Graph::try_forward`)
+    pub fn converged(&self) -> bool {
+        self.state.lock().unwrap().converged
+    }
+
+    /// `k`-th component of `x*(θ)` (NaN if the solve fails)
+    pub fn value(&self, theta: &[f64], k: usize) -> f64 {
+        let mut state = self.state.lock().unwrap();
+        self.sync(&mut state, theta);
+        if state.converged {
+            state.x[k]
+        } else {
+            f64::NAN
+        }
+    }
+
+    /// `∂x*_k/∂θ_j` (NaN if the solve fails)
+    pub fn partial(&self, theta: &[f64], k: usize, j: usize) -> f64 {
+        let mut state = self.state.lock().unwrap();
+        self.sync(&mut state, theta);
+        if !state.converged {
+            return f64::NAN;
+        }
+        if state.sensitivity.is_none() {
+            let m = state.x.len();
+            let (_, jac) = state.jacobian(theta);
+            let jx = jac.iter().map(|row| row[..m].to_vec()).collect::<Vec<_>>();
+            let columns = (0..theta.len())
+                .map(|j| {
+                    let rhs = jac.iter().map(|row| -row[m + j]).collect::<Vec<_>>();
+                    py_matrix(jx.clone()).solve(&rhs, SolveKind::LU)
+                })
+                .collect::<Vec<_>>();
+            state.sensitivity = Some(
+                (0..m)
+                    .map(|k| columns.iter().map(|c| c[k]).collect())
+                    .collect(),
+            );
+        }
+        state.sensitivity.as_ref().unwrap()[k][j]
+    }
+
+    /// Re-solve when `theta` differs from the last evaluation
+    ///
+    /// A failed solve keeps the previous root as the next warm start.
+    fn sync(&self, state: &mut State, theta: &[f64]) {
+        if state.theta.as_deref() == Some(theta) {
+            return;
+        }
+        let warm = state.x.clone();
+        state.converged = match self.solver {
+            Solver::Newton => self.newton(state, theta),
+            Solver::Bracket(a, b) => self.bisect(state, theta, a, b),
+        };
+        if !state.converged {
+            state.x = warm;
+        }
+        state.theta = Some(theta.to_vec());
+        state.sensitivity = None;
+    }
+
+    fn newton(&self, state: &mut State, theta: &[f64]) -> bool {
+        let m = state.x.len();
+        for _ in 0..self.max_iter {
+            let (r, jac) = state.jacobian(theta);
+            if r.iter().all(|r| r.abs() <= self.tol) {
+                return true;
+            }
+            let jx = jac.into_iter().map(|row| row[..m].to_vec()).collect::<Vec<_>>();
+            let step = py_matrix(jx).solve(&r, SolveKind::LU);
+            for (x, dx) in state.x.iter_mut().zip(step) {
+                *x -= dx;
+            }
+        }
+        false
+    }
+
+    /// Newton steps falling back to bisection whenever they leave the bracket (warm started from
+    /// the previous root if it lies inside); fails if the root is not bracketed
+    fn bisect(&self, state: &mut State, theta: &[f64], a: f64, b: f64) -> bool {
+        let warm = state.x[0];
+        let mut eval = |x: f64| {
+            state.x[0] = x;
+            let (r, jac) = state.jacobian(theta);
+            (r[0], jac[0][0])
+        };
+        let (fa, fb) = (eval(a).0, eval(b).0);
+        if fa * fb > 0f64 || (fa * fb).is_nan() {
+            return false;
+        }
+        // Orient the bracket so that f(lo) <= 0 <= f(hi)
+        let (mut lo, mut hi) = if fa <= 0f64 { (a, b) } else { (b, a) };
+        let mut x = warm;
+        if !(a < x && x < b) {
+            x = 0.5 * (a + b);
+        }
+        if fa == 0f64 || fb == 0f64 {
+            x = if fa == 0f64 { a } else { b };
+        }
+        for _ in 0..self.max_iter {
+            let (fx, dfx) = eval(x);
+            if fx == 0f64 || (hi - lo).abs() <= 4f64 * f64::EPSILON * x.abs().max(1f64) {
+                break;
+            }
+            if fx < 0f64 {
+                lo = x;
+            } else {
+                hi = x;
+            }
+            let newton = x - fx / dfx;
+            let next = if newton > lo.min(hi) && newton < lo.max(hi) {
+                newton
+            } else {
+                0.5 * (lo + hi)
+            };
+            let done = (next - x).abs() <= 4f64 * f64::EPSILON * x.abs().max(1f64);
+            x = next;
+            if done {
+                break;
+            }
+        }
+        state.x[0] = x;
+        true
+    }
+}
+
+impl State {
+    /// Residual & its Jacobian `[∂r/∂x | ∂r/∂θ]` at `(self.x, theta)`
+    fn jacobian(&mut self, theta: &[f64]) -> (Vec<f64>, Vec<Vec<f64>>) {
+        let point = self.x.iter().chain(theta).copied().collect::<Vec<_>>();
+        let graph = &mut self.residual;
+        graph.reset();
+        graph.subs_vars_exact(&point);
+        let r = graph.forward_all();
+        let jac = (0..r.len())
+            .map(|i| {
+                graph.backward_output(i);
+                graph.get_gradients()
+            })
+            .collect();
+        (r, jac)
+    }
+}
+
+impl Graph<f64> {
+    /// Differentiable root `x*(θ)` of `residual(x, θ) = 0`
+    ///
+    /// `theta` are nodes of this graph and `x_star` is a root at their current values (it is
+    /// checked and refined on the first forward sweep). Returns one node per component of `x*`.
+    /// Newton iterations stop once every residual is within `tol`, and the backward sweep uses
+    /// the implicit function theorem, so `∂r/∂x` has to be invertible at the root. Without
+    /// convergence the nodes evaluate to NaN (`try_forward` reports `GraphError::NotConverged`).
+    pub fn implicit<F: Fn(&[Expr], &[Expr]) -> Vec<Expr>>(
+        &mut self,
+        theta: &[usize],
+        x_star: &[f64],
+        residual: F,
+        tol: f64,
+    ) -> Vec<usize> {
+        assert!(!theta.is_empty(), "Implicit function without parameters");
+        let system = ImplicitSystem::new(x_star, theta.len(), residual, tol);
+        self.push_implicit(theta, system)
+    }
+
+    /// One node per component of the root of `system`, with parameters `theta`
+    pub fn push_implicit(&mut self, theta: &[usize], system: ImplicitSystem) -> Vec<usize> {
+        let system = Arc::new(system);
+        // Argument slots bypass CSE to stay contiguous & are shared by every component
+        let first = self.nodes.len();
+        for &arg in theta {
+            self.buffer.push(None);
+            self.gradients.push(0f64);
+            Arc::make_mut(&mut self.nodes).push(Node::Arg(arg));
+        }
+        (0..system.root().len())
+            .map(|k| {
+                Arc::make_mut(&mut self.custom_ops).push(CustomOp::Implicit {
+                    system: Arc::clone(&system),
+                    k,
+                });
+                let id = self.custom_ops.len() - 1;
+                self.push_node(Node::External(id, first, theta.len()))
+            })
+            .collect()
+    }
+}
@@ -0,0 +1,224 @@
+use crate::core::{Graph, Node};
+use crate::custom::CustomOp;
+use crate::tape::Tape;
+use crate::traits::ActivationFunction;
+
+// ┌──────────────────────────────────────────────────────────┐
+//  Lane-batched evaluation
+// └──────────────────────────────────────────────────────────┘
+/// Values & adjoints of `N` points swept through a `Tape` simultaneously
+///
+/// Every slot holds `[f64; N]` (one lane per point) and every operation is a fixed-length loop
+/// over the lanes, which the compiler lowers to SIMD instructions (`N = 4` or `8` fits `f64x4`/`f64x8`).
+#[derive(Debug, Clone)]
+pub struct Lanes<const N: usize> {
+    buffer: Vec<[f64; N]>,
+    gradients: Vec<[f64; N]>,
+}
+
+impl Graph<f64> {
+    /// Lane workspace holding the current values of variables & parameters in every lane
+    pub fn lanes<const N: usize>(&self) -> Lanes<N> {
+        let buffer = self
+            .nodes
+            .iter()
+            .zip(self.buffer.iter())
+            .map(|(node, value)| match (node, value) {
+                (Node::Var(_) | Node::Param(_), Some(x)) => [*x; N],
+                _ => [0f64; N],
+            })
+            .collect::<Vec<_>>();
+        Lanes {
+            gradients: vec![[0f64; N]; buffer.len()],
+            buffer,
+        }
+    }
+}
+
+impl Tape {
+    /// Substitute variables in the order of `get_vars` (`vals[i][k]` is variable `i` in lane `k`)
+    pub fn subs_vars_lanes<const N: usize>(&self, ws: &mut Lanes<N>, vals: &[[f64; N]]) {
+        let value_ics = self.get_vars();
+        assert_eq!(value_ics.len(), vals.len(), "Expected one value per variable");
+        for (i, val) in value_ics.iter().zip(vals) {
+            ws.buffer[*i] = *val;
+        }
+    }
+
+    /// Evaluate every lane and return the values of the compiled output
+    pub fn forward_lanes<const N: usize>(&self, ws: &mut Lanes<N>) -> [f64; N] {
+        for index in self.order() {
+            let node = self.node(index);
+            if !matches!(node, Node::Var(_) | Node::Param(_)) {
+                ws.buffer[index] = lane_value(&node, &ws.buffer, self.custom_ops());
+            }
+        }
+        ws.buffer[self.root()]
+    }
+
+    /// Reverse sweep of every lane (after `forward_lanes` on the same workspace)
+    pub fn backward_lanes<const N: usize>(&self, ws: &mut Lanes<N>) {
+        let root = self.root();
+        let mask = self.grad_mask();
+        ws.gradients.iter_mut().for_each(|g| *g = [0f64; N]);
+        ws.gradients[root] = [1f64; N];
+
+        let mut reached = vec![false; self.len()];
+        reached[root] = true;
+        for index in self.order().rev() {
+            if !reached[index] || mask.is_some_and(|mask| !mask[index]) {
+                continue;
+            }
+            let node = self.node(index);
+            for child_index in node.operand_iter() {
+                reached[child_index] = true;
+            }
+            lane_adjoint(&node, index, &ws.buffer, &mut ws.gradients, self.custom_ops());
+        }
+
+        // Discard adjoints leaked into pruned operands
+        if let Some(mask) = mask {
+            for (grad, active) in ws.gradients.iter_mut().zip(mask) {
+                if !active {
+                    *grad = [0f64; N];
+                }
+            }
+        }
+    }
+
+    /// Gradients of the variables in the order of `get_vars` (`[i][k]` is variable `i` in lane `k`)
+    pub fn get_gradients_lanes<const N: usize>(&self, ws: &Lanes<N>) -> Vec<[f64; N]> {
+        self.get_vars().iter().map(|x| ws.gradients[*x]).collect()
+    }
+}
+
+fn map<const N: usize, F: Fn(f64) -> f64>(x: &[f64; N], f: F) -> [f64; N] {
+    std::array::from_fn(|k| f(x[k]))
+}
+
+fn zip<const N: usize, F: Fn(f64, f64) -> f64>(x: &[f64; N], y: &[f64; N], f: F) -> [f64; N] {
+    std::array::from_fn(|k| f(x[k], y[k]))
+}
+
+fn accumulate<const N: usize>(gradients: &mut [[f64; N]], index: usize, d: [f64; N]) {
+    for (g, d) in gradients[index].iter_mut().zip(d) {
+        *g += d;
+    }
+}
+
+fn lane_value<const N: usize>(node: &Node, v: &[[f64; N]], ops: &[CustomOp]) -> [f64; N] {
+    match *node {
+        Node::Var(i) | Node::Param(i) => v[i],
+        Node::Const(value) => [value; N],
+        Node::Add(l, r) => zip(&v[l], &v[r], |x, y| x + y),
+        Node::Sub(l, r) => zip(&v[l], &v[r], |x, y| x - y),
+        Node::Mul(l, r) | Node::Hadamard(l, r) => zip(&v[l], &v[r], |x, y| x * y),
+        Node::Fma(a, b, c) => std::array::from_fn(|k| v[a][k].mul_add(v[b][k], v[c][k])),
+        Node::Select(c, a, b) => std::array::from_fn(|k| {
+            if v[c][k].is_sign_positive() {
+                v[a][k]
+            } else {
+                v[b][k]
+            }
+        }),
+        Node::Div(l, r) => zip(&v[l], &v[r], |x, y| x / y),
+        Node::Pow(l, r) => zip(&v[l], &v[r], f64::powf),
+        Node::Addf(num, i) => map(&v[i], |x| x + num),
+        Node::Subf(i, num) => map(&v[i], |x| x - num),
+        Node::Mulf(num, i) => map(&v[i], |x| x * num),
+        Node::Powf(i, p) => map(&v[i], |x| x.powf(p)),
+        Node::Powi(i, n) => map(&v[i], |x| x.powi(n)),
+        Node::Transpose(i) | Node::Arg(i) => v[i],
+        Node::Neg(i) => map(&v[i], |x| -x),
+        Node::Recip(i) => map(&v[i], |x| 1.0 / x),
+        Node::Exp(i) => map(&v[i], f64::exp),
+        Node::Ln(i) => map(&v[i], f64::ln),
+        Node::Sin(i) => map(&v[i], f64::sin),
+        Node::Cos(i) => map(&v[i], f64::cos),
+        Node::Tan(i) => map(&v[i], f64::tan),
+        Node::Sinh(i) => map(&v[i], f64::sinh),
+        Node::Cosh(i) => map(&v[i], f64::cosh),
+        Node::Tanh(i) => map(&v[i], f64::tanh),
+        Node::Sigmoid(i) => map(&v[i], |x| x.sigmoid()),
+        Node::ReLU(i) => map(&v[i], |x| x.relu()),
+        Node::Heaviside(i) => map(&v[i], |x| x.heaviside_zero()),
+        Node::Custom(id, i) => map(&v[i], |x| ops[id].eval(x, 0f64)),
+        Node::CustomBinary(id, l, r) => zip(&v[l], &v[r], |x, y| ops[id].eval(x, y)),
+        Node::External(id, first, n) => std::array::from_fn(|k| {
+            ops[id].eval_n(&v[first..first + n].iter().map(|x| x[k]).collect::<Vec<_>>())
+        }),
+    }
+}
+
+fn lane_adjoint<const N: usize>(
+    node: &Node,
+    index: usize,
+    v: &[[f64; N]],
+    g: &mut [[f64; N]],
+    ops: &[CustomOp],
+) {
+    let a = g[index];
+    let y = &v[index];
+    match *node {
+        Node::Var(_) | Node::Param(_) | Node::Const(_) | Node::Heaviside(_) => {}
+        Node::Add(l, r) => {
+            accumulate(g, l, a);
+            accumulate(g, r, a);
+        }
+        Node::Sub(l, r) => {
+            accumulate(g, l, a);
+            accumulate(g, r, map(&a, |a| -a));
+        }
+        Node::Addf(_, i) | Node::Subf(i, _) | Node::Transpose(i) | Node::Arg(i) => accumulate(g, i, a),
+        Node::Mulf(num, i) => accumulate(g, i, map(&a, |a| a * num)),
+        Node::Neg(i) => accumulate(g, i, map(&a, |a| -a)),
+        Node::Mul(l, r) | Node::Hadamard(l, r) => {
+            accumulate(g, l, zip(&a, &v[r], |a, x| a * x));
+            accumulate(g, r, zip(&a, &v[l], |a, x| a * x));
+        }
+        Node::Select(c, x, y) => {
+            accumulate(g, x, std::array::from_fn(|k| if v[c][k].is_sign_positive() { a[k] } else { 0f64 }));
+            accumulate(g, y, std::array::from_fn(|k| if v[c][k].is_sign_positive() { 0f64 } else { a[k] }));
+        }
+        Node::Fma(x, w, z) => {
+            accumulate(g, x, zip(&a, &v[w], |a, u| a * u));
+            accumulate(g, w, zip(&a, &v[x], |a, u| a * u));
+            accumulate(g, z, a);
+        }
+        Node::Div(l, r) => {
+            accumulate(g, l, zip(&a, &v[r], |a, w| a / w));
+            accumulate(g, r, zip(&a, &zip(y, &v[r], |q, w| q / w), |a, d| -a * d));
+        }
+        Node::Pow(l, r) => {
+            accumulate(g, l, std::array::from_fn(|k| a[k] * v[r][k] * v[l][k].powf(v[r][k] - 1.0)));
+            accumulate(g, r, std::array::from_fn(|k| a[k] * y[k] * v[l][k].ln()));
+        }
+        Node::Powf(i, p) => accumulate(g, i, zip(&a, &v[i], |a, x| a * p * x.powf(p - 1.0))),
+        Node::Powi(i, n) => accumulate(g, i, zip(&a, &v[i], |a, x| a * n as f64 * x.powi(n - 1))),
+        Node::Recip(i) => accumulate(g, i, zip(&a, y, |a, q| -a * q * q)),
+        Node::Exp(i) => accumulate(g, i, zip(&a, y, |a, e| a * e)),
+        Node::Ln(i) => accumulate(g, i, zip(&a, &v[i], |a, x| a / x)),
+        Node::Sin(i) => accumulate(g, i, zip(&a, &v[i], |a, x| a * x.cos())),
+        Node::Cos(i) => accumulate(g, i, zip(&a, &v[i], |a, x| -a * x.sin())),
+        Node::Tan(i) => accumulate(g, i, zip(&a, y, |a, t| a * (1.0 + t * t))),
+        Node::Sinh(i) => accumulate(g, i, zip(&a, &v[i], |a, x| a * x.cosh())),
+        Node::Cosh(i) => accumulate(g, i, zip(&a, &v[i], |a, x| a * x.sinh())),
+        Node::Tanh(i) => accumulate(g, i, zip(&a, y, |a, t| a * (1.0 - t * t))),
+        Node::Sigmoid(i) => accumulate(g, i, zip(&a, y, |a, s| a * s * (1.0 - s))),
+        Node::ReLU(i) => accumulate(g, i, zip(&a, &v[i], |a, x| a * x.heaviside_zero())),
+        Node::Custom(id, i) => accumulate(g, i, zip(&a, &v[i], |a, x| a * ops[id].partials(x, 0f64).0)),
+        Node::CustomBinary(id, l, r) => {
+            let d = std::array::from_fn::<_, N, _>(|k| ops[id].partials(v[l][k], v[r][k]));
+            accumulate(g, l, std::array::from_fn(|k| a[k] * d[k].0));
+            accumulate(g, r, std::array::from_fn(|k| a[k] * d[k].1));
+        }
+        Node::External(id, first, n) => {
+            for k in 0..N {
+                let x = v[first..first + n].iter().map(|x| x[k]).collect::<Vec<_>>();
+                for j in 0..n {
+                    g[first + j][k] += a[k] * ops[id].partial_n(&x, j);
+                }
+            }
+        }
+    }
+}
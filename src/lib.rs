@@ -1,4 +1,41 @@
+pub mod active;
+#[cfg(feature = "ndarray")]
+pub mod arrays;
+pub mod checkpoint;
+pub mod complex;
 pub mod core;
+pub mod custom;
+pub mod debug;
+pub mod density;
+pub mod diff;
+pub mod error;
+pub mod gpu;
+pub mod greeks;
+pub mod groups;
+pub mod hessian;
+pub mod higher;
+pub mod implicit;
+pub mod lanes;
+pub mod merge;
+pub mod multi;
+pub mod nn;
+pub mod objective;
+pub mod ode;
+pub mod optim;
+pub mod passes;
 pub mod prelude;
+pub mod profile;
+pub mod provenance;
+pub mod record;
+pub mod sample;
+pub mod sparsity;
+pub mod stats;
+#[cfg(feature = "proptest")]
+pub mod strategy;
+pub mod symbolic;
+pub mod tape;
+pub mod taylor;
 pub mod util;
 pub mod traits;
+#[cfg(feature = "uom")]
+pub mod units;
@@ -0,0 +1,135 @@
+use crate::core::{Expr, Graph, Node};
+use crate::traits::{ActivationFunction, Matrizable};
+use peroxide_num::Numeric;
+use std::collections::HashMap;
+use std::ops::Div;
+use std::sync::Arc;
+
+// ┌──────────────────────────────────────────────────────────┐
+//  Subgraph import
+// └──────────────────────────────────────────────────────────┘
+impl<T: std::fmt::Debug + Numeric<f64> + Default + ActivationFunction + Matrizable> Graph<T>
+where
+    f64: Div<T, Output = T>,
+{
+    /// Copy the subtape of `other` reachable from its node `root` into this graph
+    ///
+    /// Variables & parameters of `other` become new variables & parameters of this graph (in the
+    /// order of `other`, with their values, freeze state, groups & labels). Returns the index of
+    /// the imported root, e.g. to compile `Expr::Symbol(root)`.
+    pub fn import(&mut self, other: &Graph<T>, root: usize) -> usize {
+        self.import_with(other, root, &[])
+    }
+
+    /// `import` where the leaves of `other` listed in `bindings` as `(leaf, node)` are replaced
+    /// by existing nodes of this graph, so components can share their inputs
+    pub fn import_with(&mut self, other: &Graph<T>, root: usize, bindings: &[(usize, usize)]) -> usize {
+        assert!(root < other.nodes.len(), "Node index out of bounds");
+        let mut reached = vec![false; other.nodes.len()];
+        let mut stack = vec![root];
+        while let Some(index) = stack.pop() {
+            if !reached[index] {
+                reached[index] = true;
+                stack.extend(other.get_children(index));
+            }
+        }
+
+        let mut map = vec![usize::MAX; other.nodes.len()];
+        for &(leaf, node) in bindings {
+            assert!(
+                matches!(other.nodes.node(leaf), Node::Var(_) | Node::Param(_)),
+                "Only variables & parameters can be bound"
+            );
+            assert!(node < self.nodes.len(), "Node index out of bounds");
+            map[leaf] = node;
+        }
+        for (&var, &requires_grad) in other.value_ics.iter().zip(other.requires_grad.iter()) {
+            if !reached[var] || map[var] != usize::MAX {
+                continue;
+            }
+            map[var] = match other.buffer[var].clone() {
+                Some(value) => self.var(value),
+                None => match self.symbol() {
+                    Expr::Symbol(index) => index,
+                    _ => unreachable!(),
+                },
+            };
+            if !requires_grad {
+                self.freeze(map[var]);
+            }
+        }
+        for &param in other.param_ics.iter() {
+            if reached[param] && map[param] == usize::MAX {
+                let value = other.buffer[param].clone().expect("Parameter without value");
+                map[param] = self.param(value);
+            }
+        }
+
+        let mut ids = HashMap::new();
+        for index in other.topological_sort() {
+            if !reached[index] || map[index] != usize::MAX {
+                continue;
+            }
+            let node = other.nodes.node(index);
+            let mut op_id = |graph: &mut Self, id: usize| {
+                *ids.entry(id).or_insert_with(|| graph.register(other.custom_ops[id].clone()))
+            };
+            map[index] = match node {
+                Node::Const(value) => self.push_node(Node::Const(value)),
+                // Argument slots are copied along with their `External` to stay contiguous
+                Node::Arg(_) => continue,
+                Node::External(id, first, n) => {
+                    let id = op_id(self, id);
+                    let start = self.nodes.len();
+                    for arg in first..first + n {
+                        let Node::Arg(operand) = other.nodes.node(arg) else {
+                            unreachable!()
+                        };
+                        self.buffer.push(None);
+                        self.gradients.push(T::default());
+                        Arc::make_mut(&mut self.nodes).push(Node::Arg(map[operand]));
+                        map[arg] = start + arg - first;
+                    }
+                    self.push_node(Node::External(id, start, n))
+                }
+                Node::Custom(id, i) => {
+                    let id = op_id(self, id);
+                    self.push_node(Node::Custom(id, map[i]))
+                }
+                Node::CustomBinary(id, l, r) => {
+                    let id = op_id(self, id);
+                    self.push_node(Node::CustomBinary(id, map[l], map[r]))
+                }
+                _ => self.push_node(node.map_indices(|i| map[i])),
+            };
+        }
+
+        if let Some(origins) = self.provenance.as_mut() {
+            origins.resize(self.nodes.len(), None);
+            for (index, reached) in reached.iter().enumerate() {
+                if *reached && map[index] != usize::MAX && origins[map[index]].is_none() {
+                    origins[map[index]] = other.provenance(index);
+                }
+            }
+        }
+        for (index, label) in other.labels.iter() {
+            if reached[*index] && !bindings.iter().any(|(leaf, _)| leaf == index) {
+                self.labels.entry(map[*index]).or_insert_with(|| label.clone());
+            }
+        }
+        for (group, vars) in other.groups.iter() {
+            let imported = vars
+                .iter()
+                .filter(|var| reached[**var] && !bindings.iter().any(|(leaf, _)| leaf == *var))
+                .map(|var| map[*var])
+                .collect::<Vec<_>>();
+            if !imported.is_empty() {
+                self.add_to_group(group, &imported);
+            }
+        }
+        self.topological_order = None;
+        self.reverse_order = None;
+        self.grad_mask = None;
+        map[root]
+    }
+}
@@ -0,0 +1,148 @@
+use crate::core::{Graph, Node};
+use crate::hessian::{partials, Partials};
+
+// ┌──────────────────────────────────────────────────────────┐
+//  Vector mode sweeps (several seeds per pass over the tape)
+// └──────────────────────────────────────────────────────────┘
+// Adjoints & tangents are stored node-major (`k` lanes of a node are contiguous), so the local
+// partials of every node are computed once and applied to all lanes.
+impl Graph<f64> {
+    /// Gradients of `k` weighted sums of the outputs in one reverse sweep
+    ///
+    /// `seeds[s]` weights the outputs (in the order of `get_outputs`), and row `s` of the result
+    /// is `seeds[s]ᵀ J` in the order of variables. Unit seeds give rows of the Jacobian (see
+    /// `jacobian_rows`).
+    pub fn backward_many(&mut self, seeds: &[Vec<f64>]) -> Vec<Vec<f64>> {
+        let k = seeds.len();
+        assert!(
+            seeds.iter().all(|seed| seed.len() == self.outputs.len()),
+            "Every seed needs one weight per output"
+        );
+        self.forward_all();
+        if self.grad_mask.as_ref().is_some_and(|mask| mask.len() != self.nodes.len()) {
+            self.update_grad_mask();
+        }
+        let order = self.get_topological_order();
+
+        let mut adj = vec![0f64; self.nodes.len() * k];
+        for (lane, seed) in seeds.iter().enumerate() {
+            for (output, w) in self.outputs.iter().zip(seed) {
+                adj[output * k + lane] += w;
+            }
+        }
+        let mut a = vec![0f64; k];
+        for &index in order.iter().rev() {
+            if self.grad_mask.as_ref().is_some_and(|mask| !mask[index]) {
+                continue;
+            }
+            a.copy_from_slice(&adj[index * k..(index + 1) * k]);
+            if a.iter().all(|x| *x == 0f64) {
+                continue;
+            }
+            let mut acc = |target: usize, d: f64| {
+                for (t, a) in adj[target * k..(target + 1) * k].iter_mut().zip(&a) {
+                    *t += d * a;
+                }
+            };
+            if let Node::External(id, first, n) = self.nodes.node(index) {
+                let args = (first..first + n).map(|i| self.buffer[i].unwrap()).collect::<Vec<_>>();
+                for j in 0..n {
+                    acc(first + j, self.custom_ops[id].partial_n(&args, j));
+                }
+                continue;
+            }
+            match partials(&self.nodes.node(index), &self.buffer, &self.custom_ops) {
+                Partials::Leaf => {}
+                Partials::Unary(i, d, _) => acc(i, d),
+                Partials::Binary(l, r, fl, fr, ..) => {
+                    acc(l, fl);
+                    acc(r, fr);
+                }
+                Partials::Fma(x, y, z, vx, vy) => {
+                    acc(x, vy);
+                    acc(y, vx);
+                    acc(z, 1f64);
+                }
+            }
+        }
+
+        let active = |var: usize| self.grad_mask.as_ref().is_none_or(|mask| mask[var]);
+        (0..k)
+            .map(|lane| {
+                self.value_ics
+                    .iter()
+                    .map(|var| if active(*var) { adj[var * k + lane] } else { 0f64 })
+                    .collect()
+            })
+            .collect()
+    }
+
+    /// `J·v` of every output for each of the directions `vs` in one forward tangent sweep
+    ///
+    /// Directions are given in the order of variables; row `s` of the result holds the tangents
+    /// of the outputs (in the order of `get_outputs`) along `vs[s]`, i.e. `J V` column by column.
+    pub fn jvp_many(&mut self, vs: &[Vec<f64>]) -> Vec<Vec<f64>> {
+        let k = vs.len();
+        assert!(
+            vs.iter().all(|v| v.len() == self.value_ics.len()),
+            "Every direction needs one component per variable"
+        );
+        self.forward_all();
+        let order = self.get_topological_order();
+
+        let mut dot = vec![0f64; self.nodes.len() * k];
+        for (lane, v) in vs.iter().enumerate() {
+            for (var, dv) in self.value_ics.iter().zip(v) {
+                dot[var * k + lane] = *dv;
+            }
+        }
+        let mut d = vec![0f64; k];
+        for &index in order.iter() {
+            d.iter_mut().for_each(|x| *x = 0f64);
+            let mut acc = |source: usize, p: f64| {
+                for (d, s) in d.iter_mut().zip(&dot[source * k..(source + 1) * k]) {
+                    *d += p * s;
+                }
+            };
+            if let Node::External(id, first, n) = self.nodes.node(index) {
+                let args = (first..first + n).map(|i| self.buffer[i].unwrap()).collect::<Vec<_>>();
+                for j in 0..n {
+                    acc(first + j, self.custom_ops[id].partial_n(&args, j));
+                }
+            } else {
+                match partials(&self.nodes.node(index), &self.buffer, &self.custom_ops) {
+                    Partials::Leaf => continue,
+                    Partials::Unary(i, p, _) => acc(i, p),
+                    Partials::Binary(l, r, fl, fr, ..) => {
+                        acc(l, fl);
+                        acc(r, fr);
+                    }
+                    Partials::Fma(x, y, z, vx, vy) => {
+                        acc(x, vy);
+                        acc(y, vx);
+                        acc(z, 1f64);
+                    }
+                }
+            }
+            dot[index * k..(index + 1) * k].copy_from_slice(&d);
+        }
+
+        (0..k)
+            .map(|lane| self.outputs.iter().map(|out| dot[out * k + lane]).collect())
+            .collect()
+    }
+
+    /// Rows of the Jacobian for the outputs `rows` (indices into `get_outputs`), in one sweep
+    pub fn jacobian_rows(&mut self, rows: &[usize]) -> Vec<Vec<f64>> {
+        let n_outputs = self.outputs.len();
+        let seeds = rows
+            .iter()
+            .map(|&i| {
+                let mut seed = vec![0f64; n_outputs];
+                seed[i] = 1f64;
+                seed
+            })
+            .collect::<Vec<_>>();
+        self.backward_many(&seeds)
+    }
+}
@@ -0,0 +1,123 @@
+use crate::core::{Expr, Graph};
+use crate::sample::Rng;
+use crate::traits::ActivationFunction;
+use peroxide_num::{ExpLogOps, TrigOps};
+
+// ┌──────────────────────────────────────────────────────────┐
+//  Dense layers & MLPs
+// └──────────────────────────────────────────────────────────┘
+/// Elementwise activation of a `Dense` layer
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Activation {
+    Identity,
+    ReLU,
+    Tanh,
+    Sigmoid,
+    /// `ln(1 + eˣ)`
+    Softplus,
+    Sin,
+}
+
+impl Activation {
+    pub fn apply(&self, x: Expr) -> Expr {
+        match self {
+            Activation::Identity => x,
+            Activation::ReLU => x.relu(),
+            Activation::Tanh => x.tanh(),
+            Activation::Sigmoid => x.sigmoid(),
+            Activation::Softplus => (x.exp() + 1f64).ln(),
+            Activation::Sin => x.sin(),
+        }
+    }
+}
+
+/// Fully connected layer `y = σ(W x + b)` whose weights & biases are variables of a graph
+#[derive(Debug, Clone)]
+pub struct Dense {
+    /// Variable of `W[j][i]` (row per output)
+    pub weights: Vec<Vec<usize>>,
+    pub biases: Vec<usize>,
+    pub activation: Activation,
+}
+
+impl Dense {
+    /// New layer with zero biases and normal weights of variance `2 / n_in` for `ReLU` (He) or
+    /// `2 / (n_in + n_out)` otherwise (Glorot); the same `seed` gives the same weights
+    pub fn new(graph: &mut Graph<f64>, n_in: usize, n_out: usize, activation: Activation, seed: u64) -> Self {
+        assert!(n_in > 0 && n_out > 0, "Empty layer");
+        let mut rng = Rng::new(seed);
+        let std = match activation {
+            Activation::ReLU => (2f64 / n_in as f64).sqrt(),
+            _ => (2f64 / (n_in + n_out) as f64).sqrt(),
+        };
+        let weights = (0..n_out)
+            .map(|_| (0..n_in).map(|_| graph.var(std * rng.normal())).collect())
+            .collect();
+        let biases = (0..n_out).map(|_| graph.var(0f64)).collect();
+        Dense {
+            weights,
+            biases,
+            activation,
+        }
+    }
+
+    pub fn n_in(&self) -> usize {
+        self.weights[0].len()
+    }
+
+    pub fn n_out(&self) -> usize {
+        self.biases.len()
+    }
+
+    /// Outputs of the layer for the inputs `x`
+    pub fn forward(&self, x: &[Expr]) -> Vec<Expr> {
+        assert_eq!(x.len(), self.n_in(), "Layer takes {} inputs", self.n_in());
+        self.weights
+            .iter()
+            .zip(self.biases.iter())
+            .map(|(row, b)| {
+                let z = row
+                    .iter()
+                    .zip(x)
+                    .fold(Expr::Symbol(*b), |z, (w, x)| z + Expr::Symbol(*w) * x.clone());
+                self.activation.apply(z)
+            })
+            .collect()
+    }
+
+    /// Variables of the layer: weights row by row, then biases
+    pub fn vars(&self) -> Vec<usize> {
+        self.weights.iter().flatten().chain(self.biases.iter()).copied().collect()
+    }
+}
+
+/// Chain of `Dense` layers
+#[derive(Debug, Clone)]
+pub struct Mlp {
+    pub layers: Vec<Dense>,
+}
+
+impl Mlp {
+    /// Layers of `sizes = [n_in, hidden.., n_out]` with `hidden` activations and `output` on
+    /// the last layer (layer `k` is initialized from `seed + k`)
+    pub fn new(graph: &mut Graph<f64>, sizes: &[usize], hidden: Activation, output: Activation, seed: u64) -> Self {
+        assert!(sizes.len() >= 2, "An MLP needs input & output sizes");
+        let n = sizes.len() - 1;
+        let layers = (0..n)
+            .map(|k| {
+                let activation = if k + 1 == n { output } else { hidden };
+                Dense::new(graph, sizes[k], sizes[k + 1], activation, seed.wrapping_add(k as u64))
+            })
+            .collect();
+        Mlp { layers }
+    }
+
+    pub fn forward(&self, x: &[Expr]) -> Vec<Expr> {
+        self.layers.iter().fold(x.to_vec(), |x, layer| layer.forward(&x))
+    }
+
+    /// Variables of every layer in order (see `Dense::vars`)
+    pub fn vars(&self) -> Vec<usize> {
+        self.layers.iter().flat_map(|layer| layer.vars()).collect()
+    }
+}
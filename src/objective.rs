@@ -0,0 +1,132 @@
+use crate::core::{Expr, Graph};
+use crate::util::gradient_cached;
+use peroxide::fuga::{AD, AD1};
+use std::cell::RefCell;
+use std::sync::Mutex;
+
+// ┌──────────────────────────────────────────────────────────┐
+//  Objective functions for external solvers
+// └──────────────────────────────────────────────────────────┘
+/// Compiled scalar objective evaluated through `&self`
+///
+/// Solver crates take the objective by shared reference, so the graph sits behind a `Mutex`.
+/// Points are given in the order of variables (same as `get_vars`).
+pub struct Objective {
+    graph: Mutex<Graph<f64>>,
+}
+
+impl Objective {
+    /// Wrap an already compiled graph
+    pub fn new(graph: Graph<f64>) -> Self {
+        assert!(graph.get_compiled().is_some(), "Objective graph is not compiled");
+        Objective {
+            graph: Mutex::new(graph),
+        }
+    }
+
+    /// Compile `f` of `n_vars` variables
+    pub fn from_fn<F: Fn(&[Expr]) -> Expr>(f: F, n_vars: usize) -> Self {
+        let mut graph = Graph::default();
+        graph.touch_vars(n_vars);
+        let symbols = graph.get_symbols();
+        graph.compile(f(&symbols));
+        Self::new(graph)
+    }
+
+    pub fn n_vars(&self) -> usize {
+        self.graph.lock().unwrap().get_vars().len()
+    }
+
+    pub fn value(&self, x: &[f64]) -> f64 {
+        let mut graph = self.graph.lock().unwrap();
+        graph.reset();
+        graph.subs_vars_exact(x);
+        graph.forward()
+    }
+
+    pub fn value_and_gradient(&self, x: &[f64]) -> (f64, Vec<f64>) {
+        gradient_cached(&mut self.graph.lock().unwrap(), x)
+    }
+
+    pub fn gradient(&self, x: &[f64]) -> Vec<f64> {
+        self.value_and_gradient(x).1
+    }
+
+    /// Dense Hessian at `x` (see `Graph::hessian`)
+    pub fn hessian(&self, x: &[f64]) -> Vec<Vec<f64>> {
+        let mut graph = self.graph.lock().unwrap();
+        graph.reset();
+        graph.subs_vars_exact(x);
+        graph.hessian()
+    }
+
+    pub fn into_inner(self) -> Graph<f64> {
+        self.graph.into_inner().unwrap()
+    }
+}
+
+// ┌──────────────────────────────────────────────────────────┐
+//  argmin
+// └──────────────────────────────────────────────────────────┘
+#[cfg(feature = "argmin")]
+impl ::argmin::core::CostFunction for Objective {
+    type Param = Vec<f64>;
+    type Output = f64;
+
+    fn cost(&self, param: &Self::Param) -> Result<Self::Output, ::argmin::core::Error> {
+        Ok(self.value(param))
+    }
+}
+
+#[cfg(feature = "argmin")]
+impl ::argmin::core::Gradient for Objective {
+    type Param = Vec<f64>;
+    type Gradient = Vec<f64>;
+
+    fn gradient(&self, param: &Self::Param) -> Result<Self::Gradient, ::argmin::core::Error> {
+        Ok(Objective::gradient(self, param))
+    }
+}
+
+#[cfg(feature = "argmin")]
+impl ::argmin::core::Hessian for Objective {
+    type Param = Vec<f64>;
+    type Hessian = Vec<Vec<f64>>;
+
+    fn hessian(&self, param: &Self::Param) -> Result<Self::Hessian, ::argmin::core::Error> {
+        Ok(Objective::hessian(self, param))
+    }
+}
+
+// ┌──────────────────────────────────────────────────────────┐
+//  peroxide
+// └──────────────────────────────────────────────────────────┘
+/// Model `y = f(x, p)` in the form of peroxide's `Optimizer` (curve fitting)
+///
+/// The returned closure maps the data points `x` and the parameters `p` (as `AD` numbers) to the
+/// predictions, whose tangents are `Σ_j ∂f/∂p_j · p_j.dx()` with the partials taken by reverse
+/// sweeps of one compiled tape. Non-finite predictions yield `None`, which peroxide treats as a
+/// failed evaluation. E.g. `Optimizer::new(data, peroxide_model(|x, p| p[0].clone() * x.exp(), 1))`.
+pub fn peroxide_model<F: Fn(&Expr, &[Expr]) -> Expr>(
+    f: F,
+    n_params: usize,
+) -> impl Fn(&Vec<f64>, Vec<AD>) -> Option<Vec<AD>> {
+    let mut graph = Graph::default();
+    graph.touch_vars(1 + n_params);
+    let symbols = graph.get_symbols();
+    graph.compile(f(&symbols[0], &symbols[1..]));
+    let graph = RefCell::new(graph);
+
+    move |x: &Vec<f64>, p: Vec<AD>| {
+        let mut graph = graph.borrow_mut();
+        let mut point = std::iter::once(0f64).chain(p.iter().map(|p| p.x())).collect::<Vec<_>>();
+        x.iter()
+            .map(|x| {
+                point[0] = *x;
+                let (value, grads) = gradient_cached(&mut graph, &point);
+                let tangent = grads[1..].iter().zip(p.iter()).map(|(g, p)| g * p.dx()).sum::<f64>();
+                (value.is_finite() && tangent.is_finite()).then_some(AD1(value, tangent))
+            })
+            .collect()
+    }
+}
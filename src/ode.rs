@@ -0,0 +1,190 @@
+use crate::core::{Expr, Graph};
+use crate::util::gradient;
+use peroxide::fuga::{ODEIntegrator, ODEProblem, RK4};
+use std::cell::RefCell;
+
+// ┌──────────────────────────────────────────────────────────┐
+//  ODE sensitivities by the adjoint method
+// └──────────────────────────────────────────────────────────┘
+/// `dy/dt = f(t, y, p)` with the right-hand side built from `Expr`
+///
+/// `f` lives on its own tape (variables `t`, `y`, `p` followed by the output `f_1..f_n`) and an
+/// extra output `Σ λ_i f_i` seeded by parameters `λ`, so one reverse sweep yields the
+/// vector-Jacobian product `λᵀ [∂f/∂y | ∂f/∂p]` needed by the adjoint equations.
+/// Integration uses peroxide's fixed-step `RK4`.
+pub struct Ode {
+    rhs: RefCell<Graph<f64>>,
+    lambda: Vec<usize>,
+    n_state: usize,
+    n_params: usize,
+}
+
+/// Value & gradients of a loss on an ODE trajectory (see `Ode::sensitivities`)
+#[derive(Debug, Clone)]
+pub struct OdeGradient {
+    pub loss: f64,
+    /// States at the observation times
+    pub states: Vec<Vec<f64>>,
+    /// `dL/dy(t_0)`
+    pub y0: Vec<f64>,
+    /// `dL/dp`
+    pub params: Vec<f64>,
+}
+
+impl Ode {
+    /// `f(t, y, p)` with `n_state` states & `n_params` parameters
+    pub fn new<F: Fn(&Expr, &[Expr], &[Expr]) -> Vec<Expr>>(n_state: usize, n_params: usize, f: F) -> Self {
+        let mut graph = Graph::default();
+        graph.touch_vars(1 + n_state + n_params);
+        let symbols = graph.get_symbols();
+        let rhs = f(&symbols[0], &symbols[1..1 + n_state], &symbols[1 + n_state..]);
+        assert_eq!(rhs.len(), n_state, "Right-hand side must have one component per state");
+        let lambda = (0..n_state).map(|_| graph.param(0f64)).collect::<Vec<_>>();
+        let vjp = rhs
+            .iter()
+            .zip(lambda.iter())
+            .map(|(f, l)| f.clone() * Expr::Symbol(*l))
+            .sum::<Expr>();
+        graph.compile_many(rhs);
+        graph.compile_named("vjp", vjp);
+        Ode {
+            rhs: RefCell::new(graph),
+            lambda,
+            n_state,
+            n_params,
+        }
+    }
+
+    /// `f(t, y, p)`
+    pub fn eval(&self, t: f64, y: &[f64], p: &[f64]) -> Vec<f64> {
+        let mut graph = self.rhs.borrow_mut();
+        graph.reset();
+        graph.subs_vars_exact(&point(t, y, p));
+        let mut values = graph.forward_all();
+        values.truncate(self.n_state);
+        values
+    }
+
+    /// `(λᵀ ∂f/∂y, λᵀ ∂f/∂p)` at `(t, y, p)`
+    pub fn vjp(&self, t: f64, y: &[f64], p: &[f64], lambda: &[f64]) -> (Vec<f64>, Vec<f64>) {
+        let mut graph = self.rhs.borrow_mut();
+        graph.reset();
+        graph.subs_vars_exact(&point(t, y, p));
+        for (index, value) in self.lambda.iter().zip(lambda) {
+            graph.set_param(*index, *value);
+        }
+        graph.forward_named("vjp");
+        graph.backward_named("vjp");
+        let mut grads = graph.get_gradients();
+        let dp = grads.split_off(1 + self.n_state);
+        grads.remove(0);
+        (grads, dp)
+    }
+
+    /// States at `times` (`times[0]` is the initial time of `y0`) with RK4 steps of at most `dt`
+    pub fn solve(&self, y0: &[f64], p: &[f64], times: &[f64], dt: f64) -> Vec<Vec<f64>> {
+        assert_eq!(y0.len(), self.n_state, "Initial state has the wrong length");
+        assert_eq!(p.len(), self.n_params, "Parameters have the wrong length");
+        let problem = Forward { ode: self, p };
+        let mut y = y0.to_vec();
+        let mut states = vec![y.clone()];
+        for w in times.windows(2) {
+            let (steps, h) = steps(w[0], w[1], dt);
+            for k in 0..steps {
+                RK4.step(&problem, w[0] + k as f64 * h, &mut y, h)
+                    .expect("ODE step failed");
+            }
+            states.push(y.clone());
+        }
+        states
+    }
+
+    /// Gradient of `loss(states)` w.r.t. the initial state & the parameters
+    ///
+    /// `loss` receives the states at `times` (as in `solve`). The adjoint `a(t) = dL/dy(t)` is
+    /// integrated backward together with the state & `∫ aᵀ ∂f/∂p dt`, jumping by `dL/dy(t_i)` at
+    /// every observation time. The state is restarted from the stored forward solution at each
+    /// observation, so backward drift stays within one interval.
+    pub fn sensitivities<L: Fn(&[Vec<Expr>]) -> Expr>(
+        &self,
+        y0: &[f64],
+        p: &[f64],
+        times: &[f64],
+        dt: f64,
+        loss: L,
+    ) -> OdeGradient {
+        let n = self.n_state;
+        let states = self.solve(y0, p, times, dt);
+        let flat = states.concat();
+        let (value, dl) = gradient(
+            |x| loss(&x.chunks(n).map(|c| c.to_vec()).collect::<Vec<_>>()),
+            &flat,
+        );
+
+        let problem = Adjoint { ode: self, p };
+        let mut a = dl[(times.len() - 1) * n..].to_vec();
+        let mut g = vec![0f64; self.n_params];
+        for i in (1..times.len()).rev() {
+            let (steps, h) = steps(times[i - 1], times[i], dt);
+            let mut z = [states[i].as_slice(), &a, &g].concat();
+            for k in 0..steps {
+                RK4.step(&problem, times[i] - k as f64 * h, &mut z, -h)
+                    .expect("ODE step failed");
+            }
+            a = z[n..2 * n].to_vec();
+            g = z[2 * n..].to_vec();
+            for (a, d) in a.iter_mut().zip(&dl[(i - 1) * n..i * n]) {
+                *a += d;
+            }
+        }
+
+        OdeGradient {
+            loss: value,
+            states,
+            y0: a,
+            params: g,
+        }
+    }
+}
+
+fn point(t: f64, y: &[f64], p: &[f64]) -> Vec<f64> {
+    std::iter::once(t).chain(y.iter().copied()).chain(p.iter().copied()).collect()
+}
+
+/// Number of steps & equal step size covering `[t0, t1]` with steps of at most `dt`
+fn steps(t0: f64, t1: f64, dt: f64) -> (usize, f64) {
+    assert!(t1 > t0 && dt > 0f64, "Observation times must increase & dt must be positive");
+    let steps = ((t1 - t0) / dt).ceil().max(1f64) as usize;
+    (steps, (t1 - t0) / steps as f64)
+}
+
+struct Forward<'a> {
+    ode: &'a Ode,
+    p: &'a [f64],
+}
+
+impl ODEProblem for Forward<'_> {
+    fn rhs(&self, t: f64, y: &[f64], dy: &mut [f64]) -> anyhow::Result<()> {
+        dy.copy_from_slice(&self.ode.eval(t, y, self.p));
+        Ok(())
+    }
+}
+
+/// Augmented state `[y, a, g]` with `dy/dt = f`, `da/dt = -aᵀ ∂f/∂y` & `dg/dt = -aᵀ ∂f/∂p`
+struct Adjoint<'a> {
+    ode: &'a Ode,
+    p: &'a [f64],
+}
+
+impl ODEProblem for Adjoint<'_> {
+    fn rhs(&self, t: f64, z: &[f64], dz: &mut [f64]) -> anyhow::Result<()> {
+        let n = self.ode.n_state;
+        let (y, a) = (&z[..n], &z[n..2 * n]);
+        dz[..n].copy_from_slice(&self.ode.eval(t, y, self.p));
+        let (dy, dp) = self.ode.vjp(t, y, self.p, a);
+        for (d, v) in dz[n..].iter_mut().zip(dy.iter().chain(dp.iter())) {
+            *d = -v;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,1326 @@
+use crate::core::{Expr, Graph};
+use crate::traits::ActivationFunction;
+use crate::util::{clip_grad_norm, clip_grad_value, gradient_cached};
+use peroxide::fuga::{py_matrix, LinearAlgebra, SolveKind};
+use peroxide_num::PowOps;
+
+// ┌──────────────────────────────────────────────────────────┐
+//  First order optimizers
+// └──────────────────────────────────────────────────────────┘
+/// Update rule of a gradient-based minimizer
+///
+/// State (velocities, moments, ...) is allocated lazily on the first step, so an optimizer can be
+/// built before the number of variables is known.
+pub trait Optimizer {
+    /// Update `x` in place given the gradient `grad` at `x`
+    fn step(&mut self, x: &mut [f64], grad: &[f64]);
+
+    /// Forget the accumulated state (the next step is a fresh start)
+    fn reset(&mut self);
+
+    /// One step on the variables of a compiled graph, returning the value before the update
+    fn step_graph(&mut self, graph: &mut Graph<f64>) -> f64 {
+        let mut x = var_values(graph);
+        let (value, grad) = gradient_cached(graph, &x);
+        self.step(&mut x, &grad);
+        graph.subs_vars_exact(&x);
+        value
+    }
+
+    /// Clip every gradient before it reaches this optimizer
+    fn clipped(self, clip: GradClip) -> Clipped<Self>
+    where
+        Self: Sized,
+    {
+        Clipped { inner: self, clip }
+    }
+}
+
+/// Gradient clipping rule (see `Optimizer::clipped`)
+#[derive(Debug, Clone, Copy)]
+pub enum GradClip {
+    /// Rescale to Euclidean norm at most the given value (`clip_grad_norm`)
+    Norm(f64),
+    /// Clamp every component to `[-v, v]` (`clip_grad_value`)
+    Value(f64),
+}
+
+/// Optimizer applying a `GradClip` to the gradients before the wrapped update rule
+#[derive(Debug, Clone)]
+pub struct Clipped<O> {
+    pub inner: O,
+    pub clip: GradClip,
+}
+
+impl<O: Optimizer> Optimizer for Clipped<O> {
+    fn step(&mut self, x: &mut [f64], grad: &[f64]) {
+        let mut grad = grad.to_vec();
+        match self.clip {
+            GradClip::Norm(max_norm) => {
+                clip_grad_norm(&mut grad, max_norm);
+            }
+            GradClip::Value(max) => clip_grad_value(&mut grad, max),
+        }
+        self.inner.step(x, &grad);
+    }
+
+    fn reset(&mut self) {
+        self.inner.reset();
+    }
+}
+
+/// Current values of the variables of `graph` (order of `get_vars`)
+pub fn var_values(graph: &Graph<f64>) -> Vec<f64> {
+    graph
+        .get_vars()
+        .iter()
+        .map(|x| graph.buffer[*x].expect("Uninitialized variable"))
+        .collect()
+}
+
+/// Stochastic gradient descent with optional (Nesterov) momentum
+///
+/// `v ← μ v + g` and `x ← x - η v` (or `x ← x - η (g + μ v)` with Nesterov).
+#[derive(Debug, Clone)]
+pub struct Sgd {
+    pub lr: f64,
+    pub momentum: f64,
+    pub nesterov: bool,
+    velocity: Vec<f64>,
+}
+
+impl Sgd {
+    /// Plain gradient descent with learning rate `lr`
+    pub fn new(lr: f64) -> Self {
+        Sgd {
+            lr,
+            momentum: 0f64,
+            nesterov: false,
+            velocity: vec![],
+        }
+    }
+
+    pub fn with_momentum(mut self, momentum: f64) -> Self {
+        self.momentum = momentum;
+        self
+    }
+
+    pub fn with_nesterov(mut self, momentum: f64) -> Self {
+        self.momentum = momentum;
+        self.nesterov = true;
+        self
+    }
+}
+
+impl Optimizer for Sgd {
+    fn step(&mut self, x: &mut [f64], grad: &[f64]) {
+        assert_eq!(x.len(), grad.len());
+        if self.momentum == 0f64 {
+            for (x, g) in x.iter_mut().zip(grad) {
+                *x -= self.lr * g;
+            }
+            return;
+        }
+        if self.velocity.len() != x.len() {
+            self.velocity = vec![0f64; x.len()];
+        }
+        for ((x, g), v) in x.iter_mut().zip(grad).zip(self.velocity.iter_mut()) {
+            *v = self.momentum * *v + g;
+            let update = if self.nesterov { g + self.momentum * *v } else { *v };
+            *x -= self.lr * update;
+        }
+    }
+
+    fn reset(&mut self) {
+        self.velocity.clear();
+    }
+}
+
+/// Adam with bias-corrected moment estimates, and AdamW (decoupled weight decay)
+///
+/// `m ← β₁ m + (1 - β₁) g`, `v ← β₂ v + (1 - β₂) g²` and
+/// `x ← x - η (m̂ / (√v̂ + ε) + λ x)` with `m̂ = m / (1 - β₁ᵗ)` & `v̂ = v / (1 - β₂ᵗ)`.
+#[derive(Debug, Clone)]
+pub struct Adam {
+    pub lr: f64,
+    pub beta1: f64,
+    pub beta2: f64,
+    pub eps: f64,
+    /// Decoupled weight decay `λ` (zero for plain Adam)
+    pub weight_decay: f64,
+    m: Vec<f64>,
+    v: Vec<f64>,
+    t: i32,
+}
+
+impl Adam {
+    /// Adam with `β₁ = 0.9`, `β₂ = 0.999` & `ε = 1e-8`
+    pub fn new(lr: f64) -> Self {
+        Adam {
+            lr,
+            beta1: 0.9,
+            beta2: 0.999,
+            eps: 1e-8,
+            weight_decay: 0f64,
+            m: vec![],
+            v: vec![],
+            t: 0,
+        }
+    }
+
+    /// AdamW with decoupled weight decay `weight_decay`
+    pub fn adamw(lr: f64, weight_decay: f64) -> Self {
+        Self::new(lr).with_weight_decay(weight_decay)
+    }
+
+    pub fn with_betas(mut self, beta1: f64, beta2: f64) -> Self {
+        self.beta1 = beta1;
+        self.beta2 = beta2;
+        self
+    }
+
+    pub fn with_eps(mut self, eps: f64) -> Self {
+        self.eps = eps;
+        self
+    }
+
+    pub fn with_weight_decay(mut self, weight_decay: f64) -> Self {
+        self.weight_decay = weight_decay;
+        self
+    }
+}
+
+impl Optimizer for Adam {
+    fn step(&mut self, x: &mut [f64], grad: &[f64]) {
+        assert_eq!(x.len(), grad.len());
+        if self.m.len() != x.len() {
+            self.m = vec![0f64; x.len()];
+            self.v = vec![0f64; x.len()];
+            self.t = 0;
+        }
+        self.t += 1;
+        let c1 = 1f64 - self.beta1.powi(self.t);
+        let c2 = 1f64 - self.beta2.powi(self.t);
+        for (((x, g), m), v) in x.iter_mut().zip(grad).zip(self.m.iter_mut()).zip(self.v.iter_mut()) {
+            *m = self.beta1 * *m + (1f64 - self.beta1) * g;
+            *v = self.beta2 * *v + (1f64 - self.beta2) * g * g;
+            let update = (*m / c1) / ((*v / c2).sqrt() + self.eps);
+            *x -= self.lr * (update + self.weight_decay * *x);
+        }
+    }
+
+    fn reset(&mut self) {
+        self.m.clear();
+        self.v.clear();
+        self.t = 0;
+    }
+}
+
+/// RMSProp with optional momentum
+///
+/// `s ← α s + (1 - α) g²`, `b ← μ b + g / (√s + ε)` and `x ← x - η b`.
+#[derive(Debug, Clone)]
+pub struct RmsProp {
+    pub lr: f64,
+    pub alpha: f64,
+    pub eps: f64,
+    pub momentum: f64,
+    square: Vec<f64>,
+    buffer: Vec<f64>,
+}
+
+impl RmsProp {
+    /// RMSProp with `α = 0.99` & `ε = 1e-8`
+    pub fn new(lr: f64) -> Self {
+        RmsProp {
+            lr,
+            alpha: 0.99,
+            eps: 1e-8,
+            momentum: 0f64,
+            square: vec![],
+            buffer: vec![],
+        }
+    }
+
+    pub fn with_alpha(mut self, alpha: f64) -> Self {
+        self.alpha = alpha;
+        self
+    }
+
+    pub fn with_eps(mut self, eps: f64) -> Self {
+        self.eps = eps;
+        self
+    }
+
+    pub fn with_momentum(mut self, momentum: f64) -> Self {
+        self.momentum = momentum;
+        self
+    }
+}
+
+impl Optimizer for RmsProp {
+    fn step(&mut self, x: &mut [f64], grad: &[f64]) {
+        assert_eq!(x.len(), grad.len());
+        if self.square.len() != x.len() {
+            self.square = vec![0f64; x.len()];
+            self.buffer = vec![0f64; x.len()];
+        }
+        for (((x, g), s), b) in x.iter_mut().zip(grad).zip(self.square.iter_mut()).zip(self.buffer.iter_mut()) {
+            *s = self.alpha * *s + (1f64 - self.alpha) * g * g;
+            *b = self.momentum * *b + g / (s.sqrt() + self.eps);
+            *x -= self.lr * *b;
+        }
+    }
+
+    fn reset(&mut self) {
+        self.square.clear();
+        self.buffer.clear();
+    }
+}
+
+// ┌──────────────────────────────────────────────────────────┐
+//  Quasi-Newton
+// └──────────────────────────────────────────────────────────┘
+/// Outcome of a minimizer run
+#[derive(Debug, Clone)]
+pub struct OptimResult {
+    pub x: Vec<f64>,
+    pub value: f64,
+    pub grad: Vec<f64>,
+    pub iterations: usize,
+    /// Whether the gradient tolerance was reached (otherwise the iteration or line search gave up)
+    pub converged: bool,
+}
+
+/// Limited-memory BFGS with a strong Wolfe line search
+///
+/// The inverse Hessian is approximated from the last `memory` pairs `(s, y)` by the two-loop
+/// recursion (`memory = 0` keeps none, i.e. steepest descent). Iterations stop once
+/// `max |∇f| ≤ tol`.
+#[derive(Debug, Clone)]
+pub struct Lbfgs {
+    pub memory: usize,
+    pub max_iter: usize,
+    pub tol: f64,
+    /// Sufficient decrease (Armijo) constant
+    pub c1: f64,
+    /// Curvature constant
+    pub c2: f64,
+}
+
+impl Lbfgs {
+    /// `memory` correction pairs, `max_iter = 1000`, `tol = 1e-8`, `c1 = 1e-4` & `c2 = 0.9`
+    pub fn new(memory: usize) -> Self {
+        Lbfgs {
+            memory,
+            max_iter: 1000,
+            tol: 1e-8,
+            c1: 1e-4,
+            c2: 0.9,
+        }
+    }
+
+    pub fn with_max_iter(mut self, max_iter: usize) -> Self {
+        self.max_iter = max_iter;
+        self
+    }
+
+    pub fn with_tol(mut self, tol: f64) -> Self {
+        self.tol = tol;
+        self
+    }
+
+    pub fn with_wolfe(mut self, c1: f64, c2: f64) -> Self {
+        self.c1 = c1;
+        self.c2 = c2;
+        self
+    }
+
+    /// Minimize the compiled expression starting from the current variables
+    ///
+    /// The graph is left at the returned point.
+    pub fn minimize(&self, graph: &mut Graph<f64>) -> OptimResult {
+        let mut x = var_values(graph);
+        let (mut value, mut grad) = gradient_cached(graph, &x);
+        let mut pairs: std::collections::VecDeque<(Vec<f64>, Vec<f64>, f64)> = Default::default();
+        let mut iterations = 0;
+        let mut converged = norm_inf(&grad) <= self.tol;
+
+        while !converged && iterations < self.max_iter {
+            let d = if pairs.is_empty() {
+                let scale = 1f64 / dot(&grad, &grad).sqrt().max(1f64);
+                grad.iter().map(|g| -scale * g).collect()
+            } else {
+                two_loop(&grad, &pairs)
+            };
+            let step = match strong_wolfe(graph, &x, value, &grad, &d, self.c1, self.c2) {
+                Some(step) => step,
+                None if !pairs.is_empty() => {
+                    // Stale curvature information: restart from steepest descent
+                    pairs.clear();
+                    continue;
+                }
+                None => break,
+            };
+            let s = step.x.iter().zip(&x).map(|(a, b)| a - b).collect::<Vec<_>>();
+            let y = step.grad.iter().zip(&grad).map(|(a, b)| a - b).collect::<Vec<_>>();
+            let sy = dot(&s, &y);
+            if self.memory > 0 && sy > f64::EPSILON * dot(&y, &y).sqrt() * dot(&s, &s).sqrt() {
+                if pairs.len() == self.memory {
+                    pairs.pop_front();
+                }
+                pairs.push_back((s, y, 1f64 / sy));
+            }
+            x = step.x;
+            value = step.value;
+            grad = step.grad;
+            iterations += 1;
+            converged = norm_inf(&grad) <= self.tol;
+        }
+
+        graph.subs_vars_exact(&x);
+        OptimResult {
+            x,
+            value,
+            grad,
+            iterations,
+            converged,
+        }
+    }
+}
+
+/// `-H g` with `H` the L-BFGS inverse Hessian of the pairs `(s, y, 1 / sᵀy)`
+fn two_loop(grad: &[f64], pairs: &std::collections::VecDeque<(Vec<f64>, Vec<f64>, f64)>) -> Vec<f64> {
+    let mut q = grad.to_vec();
+    let mut alphas = vec![0f64; pairs.len()];
+    for (k, (s, y, rho)) in pairs.iter().enumerate().rev() {
+        alphas[k] = rho * dot(s, &q);
+        axpy(-alphas[k], y, &mut q);
+    }
+    let (s, y, _) = pairs.back().unwrap();
+    let gamma = dot(s, y) / dot(y, y);
+    q.iter_mut().for_each(|q| *q *= gamma);
+    for ((s, y, rho), alpha) in pairs.iter().zip(alphas) {
+        let beta = rho * dot(y, &q);
+        axpy(alpha - beta, s, &mut q);
+    }
+    q.iter().map(|q| -q).collect()
+}
+
+// ┌──────────────────────────────────────────────────────────┐
+//  Newton
+// └──────────────────────────────────────────────────────────┘
+/// Linear solver of the Newton system `(H + λI) p = -∇f`
+#[derive(Debug, Clone, Copy)]
+pub enum NewtonSolve {
+    /// Dense Hessian (`n` Hessian-vector products) & LU decomposition
+    Direct,
+    /// Hessian-free conjugate gradient on Hessian-vector products, stopped at relative residual
+    /// `tol` or on negative curvature
+    Cg { max_iter: usize, tol: f64 },
+}
+
+/// Damped Newton's method with AD Hessians
+///
+/// Each step solves `(H + λI) p = -∇f`. Accepted steps shrink `λ` (towards the pure Newton step)
+/// and rejected ones grow it (towards scaled steepest descent), as in Levenberg-Marquardt.
+/// Iterations stop once `max |∇f| ≤ tol`.
+#[derive(Debug, Clone)]
+pub struct Newton {
+    pub max_iter: usize,
+    pub tol: f64,
+    /// Initial damping `λ`
+    pub damping: f64,
+    pub solve: NewtonSolve,
+}
+
+impl Default for Newton {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Newton {
+    /// Direct solves, `max_iter = 100`, `tol = 1e-8` & `λ = 1e-3`
+    pub fn new() -> Self {
+        Newton {
+            max_iter: 100,
+            tol: 1e-8,
+            damping: 1e-3,
+            solve: NewtonSolve::Direct,
+        }
+    }
+
+    /// Hessian-free inner solves (for problems whose dense Hessian is too large)
+    pub fn with_cg(mut self, max_iter: usize, tol: f64) -> Self {
+        self.solve = NewtonSolve::Cg { max_iter, tol };
+        self
+    }
+
+    pub fn with_max_iter(mut self, max_iter: usize) -> Self {
+        self.max_iter = max_iter;
+        self
+    }
+
+    pub fn with_tol(mut self, tol: f64) -> Self {
+        self.tol = tol;
+        self
+    }
+
+    pub fn with_damping(mut self, damping: f64) -> Self {
+        self.damping = damping;
+        self
+    }
+
+    /// Minimize the compiled expression starting from the current variables
+    ///
+    /// The graph is left at the returned point.
+    pub fn minimize(&self, graph: &mut Graph<f64>) -> OptimResult {
+        let mut x = var_values(graph);
+        let (mut value, mut grad) = gradient_cached(graph, &x);
+        let mut lambda = self.damping;
+        let mut iterations = 0;
+        let mut converged = norm_inf(&grad) <= self.tol;
+
+        while !converged && iterations < self.max_iter {
+            iterations += 1;
+            // The graph is at `x` here, and retries only change the damping
+            let hessian = matches!(self.solve, NewtonSolve::Direct).then(|| graph.hessian());
+            // Retry with growing damping until the step decreases the objective
+            let mut accepted = false;
+            while lambda < 1e16 {
+                let p = match self.solve {
+                    NewtonSolve::Direct => {
+                        let mut h = hessian.clone().unwrap();
+                        for (i, row) in h.iter_mut().enumerate() {
+                            row[i] += lambda;
+                        }
+                        let rhs = grad.iter().map(|g| -g).collect::<Vec<_>>();
+                        py_matrix(h).solve(&rhs, SolveKind::LU)
+                    }
+                    NewtonSolve::Cg { max_iter, tol } => {
+                        conjugate_gradient(|v| graph.hvp(v), &grad, lambda, max_iter, tol)
+                    }
+                };
+                if p.iter().all(|p| p.is_finite()) && dot(&p, &grad) < 0f64 {
+                    let mut trial = x.clone();
+                    axpy(1f64, &p, &mut trial);
+                    let (trial_value, trial_grad) = gradient_cached(graph, &trial);
+                    if trial_value.is_finite() && trial_value <= value {
+                        x = trial;
+                        value = trial_value;
+                        grad = trial_grad;
+                        lambda = (lambda / 3f64).max(1e-12);
+                        accepted = true;
+                        break;
+                    }
+                    // Back to `x` for the next inner solve
+                    graph.reset();
+                    graph.subs_vars_exact(&x);
+                }
+                lambda = (lambda * 4f64).max(1e-8);
+            }
+            converged = norm_inf(&grad) <= self.tol;
+            if !accepted {
+                break;
+            }
+        }
+
+        graph.subs_vars_exact(&x);
+        OptimResult {
+            x,
+            value,
+            grad,
+            iterations,
+            converged,
+        }
+    }
+}
+
+/// Approximate solution of `(H + λI) p = -g` by conjugate gradients on `hvp(v) = H v`
+///
+/// Stops at relative residual `tol`, after `max_iter` iterations or on a direction of non-positive
+/// curvature (returning the iterate so far, or `-g` if that is the first direction).
+pub(crate) fn conjugate_gradient<F: FnMut(&[f64]) -> Vec<f64>>(
+    mut hvp: F,
+    g: &[f64],
+    lambda: f64,
+    max_iter: usize,
+    tol: f64,
+) -> Vec<f64> {
+    let mut p = vec![0f64; g.len()];
+    let mut r = g.iter().map(|g| -g).collect::<Vec<_>>();
+    let mut d = r.clone();
+    let mut rr = dot(&r, &r);
+    let stop = tol * tol * rr;
+    for k in 0..max_iter {
+        let mut hd = hvp(&d);
+        axpy(lambda, &d, &mut hd);
+        let curvature = dot(&d, &hd);
+        if curvature <= 0f64 {
+            return if k == 0 { r } else { p };
+        }
+        let alpha = rr / curvature;
+        axpy(alpha, &d, &mut p);
+        axpy(-alpha, &hd, &mut r);
+        let rr_next = dot(&r, &r);
+        if rr_next <= stop {
+            break;
+        }
+        let beta = rr_next / rr;
+        d.iter_mut().zip(&r).for_each(|(d, r)| *d = r + beta * *d);
+        rr = rr_next;
+    }
+    p
+}
+
+// ┌──────────────────────────────────────────────────────────┐
+//  Newton-CG
+// └──────────────────────────────────────────────────────────┘
+/// Forcing sequence `η` of inexact Newton solves `|H p + ∇f| ≤ η |∇f|`
+#[derive(Debug, Clone, Copy)]
+pub enum Forcing {
+    Constant(f64),
+    /// `η = min(1/2, √|∇f|)` (Nocedal & Wright, Algorithm 7.1)
+    Superlinear,
+    /// Eisenstat-Walker choice 2: `η = γ (|∇f| / |∇f_prev|)^α`, safeguarded against sudden drops
+    /// & oversolving, and capped at `eta_max` (the gradient norm of a minimization is not
+    /// monotone, and caps much above `1/2` degrade the steps to steepest descent)
+    EisenstatWalker { gamma: f64, alpha: f64, eta_max: f64 },
+}
+
+impl Default for Forcing {
+    fn default() -> Self {
+        Forcing::EisenstatWalker {
+            gamma: 0.9,
+            alpha: 2f64,
+            eta_max: 0.5,
+        }
+    }
+}
+
+/// Line-search Newton-CG (truncated Newton) on Hessian-vector products
+///
+/// Each step solves the Newton system by conjugate gradients to the relative residual of the
+/// forcing sequence, so the Hessian is never formed: a step costs a few Hessian-vector products
+/// and the memory stays linear in the number of variables. CG stops on negative curvature (falling
+/// back to steepest descent on the first direction) and the step is taken with a strong Wolfe line
+/// search starting from the full Newton step. Iterations stop once `max |∇f| ≤ tol`.
+#[derive(Debug, Clone)]
+pub struct NewtonCg {
+    pub max_iter: usize,
+    pub tol: f64,
+    /// Iteration limit of each CG solve (defaults to the number of variables)
+    pub cg_max_iter: Option<usize>,
+    pub forcing: Forcing,
+    /// Sufficient decrease (Armijo) constant
+    pub c1: f64,
+    /// Curvature constant
+    pub c2: f64,
+}
+
+impl Default for NewtonCg {
+    /// `max_iter = 200`, `tol = 1e-8`, Eisenstat-Walker forcing (`γ = 0.9`, `α = 2`,
+    /// `eta_max = 1/2`), `c1 = 1e-4` & `c2 = 0.9`
+    fn default() -> Self {
+        NewtonCg {
+            max_iter: 200,
+            tol: 1e-8,
+            cg_max_iter: None,
+            forcing: Forcing::default(),
+            c1: 1e-4,
+            c2: 0.9,
+        }
+    }
+}
+
+impl NewtonCg {
+    pub fn with_max_iter(mut self, max_iter: usize) -> Self {
+        self.max_iter = max_iter;
+        self
+    }
+
+    pub fn with_tol(mut self, tol: f64) -> Self {
+        self.tol = tol;
+        self
+    }
+
+    pub fn with_cg_max_iter(mut self, cg_max_iter: usize) -> Self {
+        self.cg_max_iter = Some(cg_max_iter);
+        self
+    }
+
+    pub fn with_forcing(mut self, forcing: Forcing) -> Self {
+        self.forcing = forcing;
+        self
+    }
+
+    pub fn with_wolfe(mut self, c1: f64, c2: f64) -> Self {
+        self.c1 = c1;
+        self.c2 = c2;
+        self
+    }
+
+    /// Minimize the compiled expression starting from the current variables
+    ///
+    /// The graph is left at the returned point.
+    pub fn minimize(&self, graph: &mut Graph<f64>) -> OptimResult {
+        let mut x = var_values(graph);
+        let (mut value, mut grad) = gradient_cached(graph, &x);
+        let cg_max_iter = self.cg_max_iter.unwrap_or(x.len().max(1));
+        let mut eta = 0.5;
+        let mut prev_norm = f64::NAN;
+        let mut iterations = 0;
+        let mut converged = norm_inf(&grad) <= self.tol;
+
+        while !converged && iterations < self.max_iter {
+            iterations += 1;
+            let norm = dot(&grad, &grad).sqrt();
+            eta = match self.forcing {
+                Forcing::Constant(eta) => eta,
+                Forcing::Superlinear => norm.sqrt().min(0.5),
+                Forcing::EisenstatWalker { gamma, alpha, eta_max } if prev_norm.is_finite() => {
+                    let mut next = gamma * (norm / prev_norm).powf(alpha);
+                    let safeguard = gamma * eta.powf(alpha);
+                    if safeguard > 0.1 {
+                        next = next.max(safeguard);
+                    }
+                    // No point in solving beyond the final tolerance
+                    next.max(0.5 * self.tol / norm).min(eta_max)
+                }
+                Forcing::EisenstatWalker { eta_max, .. } => eta.min(eta_max),
+            };
+            prev_norm = norm;
+
+            // Values at `x` for the Hessian-vector products (the line search may end elsewhere)
+            graph.reset();
+            graph.subs_vars_exact(&x);
+            let p = conjugate_gradient(|v| graph.hvp(v), &grad, 0f64, cg_max_iter, eta);
+            let step = match strong_wolfe(graph, &x, value, &grad, &p, self.c1, self.c2) {
+                Some(step) => step,
+                None => {
+                    let d = grad.iter().map(|g| -g / norm.max(1f64)).collect::<Vec<_>>();
+                    match strong_wolfe(graph, &x, value, &grad, &d, self.c1, self.c2) {
+                        Some(step) => step,
+                        None => break,
+                    }
+                }
+            };
+            x = step.x;
+            value = step.value;
+            grad = step.grad;
+            converged = norm_inf(&grad) <= self.tol;
+        }
+
+        graph.subs_vars_exact(&x);
+        OptimResult {
+            x,
+            value,
+            grad,
+            iterations,
+            converged,
+        }
+    }
+}
+
+/// Newton-CG minimization of `f` from `x0` with default settings (see `NewtonCg`)
+pub fn newton_cg<F: Fn(&[Expr]) -> Expr>(f: F, x0: &[f64]) -> OptimResult {
+    let mut graph = Graph::default();
+    graph.touch_vars(x0.len());
+    let symbols = graph.get_symbols();
+    graph.compile(f(&symbols));
+    graph.subs_vars_exact(x0);
+    NewtonCg::default().minimize(&mut graph)
+}
+
+// ┌──────────────────────────────────────────────────────────┐
+//  Trust region
+// └──────────────────────────────────────────────────────────┘
+/// Trust-region Newton method with Steihaug-CG subproblems on Hessian-vector products
+///
+/// The quadratic model is minimized within the radius `Δ` by truncated conjugate gradients, which
+/// follow directions of negative curvature to the boundary instead of failing like line-search
+/// Newton. A step is accepted when the actual over predicted decrease exceeds `eta`, and `Δ`
+/// shrinks or grows with that ratio. Iterations stop once `max |∇f| ≤ tol`.
+#[derive(Debug, Clone)]
+pub struct TrustRegion {
+    pub max_iter: usize,
+    pub tol: f64,
+    /// Initial radius `Δ`
+    pub radius: f64,
+    pub max_radius: f64,
+    pub eta: f64,
+    /// Iteration limit of each CG subproblem (defaults to the number of variables)
+    pub cg_max_iter: Option<usize>,
+}
+
+impl Default for TrustRegion {
+    fn default() -> Self {
+        TrustRegion {
+            max_iter: 200,
+            tol: 1e-8,
+            radius: 1f64,
+            max_radius: 1e3,
+            eta: 0.1,
+            cg_max_iter: None,
+        }
+    }
+}
+
+impl TrustRegion {
+    pub fn with_max_iter(mut self, max_iter: usize) -> Self {
+        self.max_iter = max_iter;
+        self
+    }
+
+    pub fn with_tol(mut self, tol: f64) -> Self {
+        self.tol = tol;
+        self
+    }
+
+    pub fn with_radius(mut self, radius: f64, max_radius: f64) -> Self {
+        self.radius = radius;
+        self.max_radius = max_radius;
+        self
+    }
+
+    pub fn with_cg_max_iter(mut self, cg_max_iter: usize) -> Self {
+        self.cg_max_iter = Some(cg_max_iter);
+        self
+    }
+
+    /// Minimize the compiled expression starting from the current variables
+    ///
+    /// The graph is left at the returned point.
+    pub fn minimize(&self, graph: &mut Graph<f64>) -> OptimResult {
+        let mut x = var_values(graph);
+        let (mut value, mut grad) = gradient_cached(graph, &x);
+        let cg_max_iter = self.cg_max_iter.unwrap_or(x.len().max(1));
+        let mut radius = self.radius;
+        let mut iterations = 0;
+        let mut converged = norm_inf(&grad) <= self.tol;
+
+        while !converged && iterations < self.max_iter && radius > f64::EPSILON * norm_inf(&x).max(1f64) {
+            iterations += 1;
+            gradient_cached(graph, &x);
+            let (p, on_boundary) = steihaug(|v| graph.hvp(v), &grad, radius, cg_max_iter);
+            let hp = graph.hvp(&p);
+            let predicted = -(dot(&grad, &p) + 0.5 * dot(&p, &hp));
+
+            let mut trial = x.clone();
+            axpy(1f64, &p, &mut trial);
+            let (trial_value, trial_grad) = gradient_cached(graph, &trial);
+            let rho = if trial_value.is_finite() && predicted > 0f64 {
+                (value - trial_value) / predicted
+            } else {
+                f64::NEG_INFINITY
+            };
+
+            if rho < 0.25 {
+                radius *= 0.25;
+            } else if rho > 0.75 && on_boundary {
+                radius = (2f64 * radius).min(self.max_radius);
+            }
+            if rho > self.eta {
+                x = trial;
+                value = trial_value;
+                grad = trial_grad;
+            }
+            converged = norm_inf(&grad) <= self.tol;
+        }
+
+        graph.subs_vars_exact(&x);
+        OptimResult {
+            x,
+            value,
+            grad,
+            iterations,
+            converged,
+        }
+    }
+}
+
+/// Steihaug's truncated CG for `min gᵀp + ½ pᵀHp` subject to `|p| ≤ radius`
+///
+/// Returns the step & whether it lies on the boundary.
+fn steihaug<F: FnMut(&[f64]) -> Vec<f64>>(mut hvp: F, g: &[f64], radius: f64, max_iter: usize) -> (Vec<f64>, bool) {
+    let mut z = vec![0f64; g.len()];
+    let mut r = g.to_vec();
+    let mut d = g.iter().map(|g| -g).collect::<Vec<_>>();
+    let g_norm = dot(g, g).sqrt();
+    // Superlinear forcing sequence min(1/2, √|g|)·|g|
+    let stop = g_norm * g_norm.sqrt().min(0.5);
+    let mut rr = dot(&r, &r);
+    for _ in 0..max_iter {
+        let hd = hvp(&d);
+        let curvature = dot(&d, &hd);
+        if curvature <= 0f64 {
+            return (to_boundary(&z, &d, radius), true);
+        }
+        let alpha = rr / curvature;
+        let mut z_next = z.clone();
+        axpy(alpha, &d, &mut z_next);
+        if dot(&z_next, &z_next).sqrt() >= radius {
+            return (to_boundary(&z, &d, radius), true);
+        }
+        z = z_next;
+        axpy(alpha, &hd, &mut r);
+        let rr_next = dot(&r, &r);
+        if rr_next.sqrt() <= stop {
+            break;
+        }
+        let beta = rr_next / rr;
+        d.iter_mut().zip(&r).for_each(|(d, r)| *d = -r + beta * *d);
+        rr = rr_next;
+    }
+    (z, false)
+}
+
+/// `z + τ d` with `τ ≥ 0` such that `|z + τ d| = radius`
+fn to_boundary(z: &[f64], d: &[f64], radius: f64) -> Vec<f64> {
+    let (a, b, c) = (dot(d, d), 2f64 * dot(z, d), dot(z, z) - radius * radius);
+    let tau = (-b + (b * b - 4f64 * a * c).max(0f64).sqrt()) / (2f64 * a);
+    let mut p = z.to_vec();
+    axpy(tau, d, &mut p);
+    p
+}
+
+// ┌──────────────────────────────────────────────────────────┐
+//  Constrained
+// └──────────────────────────────────────────────────────────┘
+/// Augmented Lagrangian method for `min f(x)` subject to `h(x) = 0` & `g(x) ≤ 0`
+///
+/// The augmented Lagrangian
+/// `f + Σ λ_i h_i + μ/2 Σ h_i² + 1/(2μ) Σ (max(0, ν_j + μ g_j)² - ν_j²)`
+/// is compiled once with the multipliers `λ`, `ν` & the penalty `μ` as parameters, next to `f`,
+/// `h` & `g` as extra outputs of the same tape. Each outer iteration minimizes it with `inner`,
+/// then updates the multipliers and grows `μ` by `growth` unless the violation dropped by 4×.
+#[derive(Debug, Clone)]
+pub struct AugmentedLagrangian {
+    pub max_outer: usize,
+    /// Tolerance of the constraint violation `max(|h|, max(g, 0))`
+    pub tol: f64,
+    /// Initial penalty `μ`
+    pub penalty: f64,
+    pub growth: f64,
+    pub inner: Lbfgs,
+}
+
+/// Outcome of `AugmentedLagrangian::minimize`
+#[derive(Debug, Clone)]
+pub struct ConstrainedResult {
+    pub x: Vec<f64>,
+    /// Objective `f(x)`
+    pub value: f64,
+    /// Multipliers of the equality constraints
+    pub lambda: Vec<f64>,
+    /// Multipliers of the inequality constraints (zero for inactive ones)
+    pub nu: Vec<f64>,
+    pub violation: f64,
+    /// Outer iterations
+    pub iterations: usize,
+    /// Whether the violation & the inner gradient tolerances were reached
+    pub converged: bool,
+}
+
+impl Default for AugmentedLagrangian {
+    fn default() -> Self {
+        AugmentedLagrangian {
+            max_outer: 50,
+            tol: 1e-8,
+            penalty: 10f64,
+            growth: 10f64,
+            inner: Lbfgs::new(10).with_tol(1e-9),
+        }
+    }
+}
+
+impl AugmentedLagrangian {
+    pub fn with_tol(mut self, tol: f64) -> Self {
+        self.tol = tol;
+        self
+    }
+
+    pub fn with_penalty(mut self, penalty: f64, growth: f64) -> Self {
+        self.penalty = penalty;
+        self.growth = growth;
+        self
+    }
+
+    pub fn with_inner(mut self, inner: Lbfgs) -> Self {
+        self.inner = inner;
+        self
+    }
+
+    /// Minimize `objective` subject to `eq(x) = 0` & `ineq(x) ≤ 0` starting from `x0`
+    pub fn minimize<F, H, G>(&self, objective: F, eq: H, ineq: G, x0: &[f64]) -> ConstrainedResult
+    where
+        F: Fn(&[Expr]) -> Expr,
+        H: Fn(&[Expr]) -> Vec<Expr>,
+        G: Fn(&[Expr]) -> Vec<Expr>,
+    {
+        let mut graph = Graph::default();
+        graph.touch_vars(x0.len());
+        graph.subs_vars_exact(x0);
+        let symbols = graph.get_symbols();
+        let f = objective(&symbols);
+        let h = eq(&symbols);
+        let g = ineq(&symbols);
+
+        let mu = graph.param(self.penalty);
+        let lambda = h.iter().map(|_| graph.param(0f64)).collect::<Vec<_>>();
+        let nu = g.iter().map(|_| graph.param(0f64)).collect::<Vec<_>>();
+        let mu_expr = Expr::Symbol(mu);
+        let mut lagrangian = f.clone();
+        for (h, l) in h.iter().zip(&lambda) {
+            lagrangian = lagrangian + Expr::Symbol(*l) * h.clone() + 0.5 * mu_expr.clone() * h.clone().powi(2);
+        }
+        for (g, n) in g.iter().zip(&nu) {
+            let n = Expr::Symbol(*n);
+            let active = (n.clone() + mu_expr.clone() * g.clone()).relu();
+            lagrangian = lagrangian + (active.powi(2) - n.powi(2)) * (0.5 / mu_expr.clone());
+        }
+        let (n_eq, n_in) = (h.len(), g.len());
+        graph.compile_many(std::iter::once(lagrangian).chain(std::iter::once(f)).chain(h).chain(g).collect());
+
+        let mut penalty = self.penalty;
+        let mut lambda_val = vec![0f64; n_eq];
+        let mut nu_val = vec![0f64; n_in];
+        let mut violation = f64::INFINITY;
+        let mut iterations = 0;
+        let mut converged = false;
+        let mut x = x0.to_vec();
+        let mut value = f64::NAN;
+
+        while iterations < self.max_outer {
+            iterations += 1;
+            let inner = self.inner.minimize(&mut graph);
+            x = inner.x;
+
+            graph.reset();
+            graph.subs_vars_exact(&x);
+            let outputs = graph.forward_all();
+            value = outputs[1];
+            let (h_val, g_val) = outputs[2..].split_at(n_eq);
+            let new_violation = h_val
+                .iter()
+                .map(|h| h.abs())
+                .chain(g_val.iter().map(|g| g.max(0f64)))
+                .fold(0f64, f64::max);
+
+            for (l, h) in lambda_val.iter_mut().zip(h_val) {
+                *l += penalty * h;
+            }
+            for (n, g) in nu_val.iter_mut().zip(g_val) {
+                *n = (*n + penalty * g).max(0f64);
+            }
+            if new_violation <= self.tol && inner.converged {
+                violation = new_violation;
+                converged = true;
+                break;
+            }
+            if new_violation > 0.25 * violation {
+                penalty *= self.growth;
+            }
+            violation = new_violation;
+
+            graph.set_param(mu, penalty);
+            for (index, value) in lambda.iter().zip(&lambda_val).chain(nu.iter().zip(&nu_val)) {
+                graph.set_param(*index, *value);
+            }
+        }
+
+        ConstrainedResult {
+            x,
+            value,
+            lambda: lambda_val,
+            nu: nu_val,
+            violation,
+            iterations,
+            converged,
+        }
+    }
+}
+
+// ┌──────────────────────────────────────────────────────────┐
+//  Least squares
+// └──────────────────────────────────────────────────────────┘
+/// Levenberg-Marquardt for `min ½ Σ r_i(x)²`
+///
+/// The residual vector is compiled once and its Jacobian is assembled row by row from reverse
+/// sweeps. Each step solves `(JᵀJ + λ diag(JᵀJ)) δ = -Jᵀr`, with `λ` updated by Nielsen's gain
+/// ratio rule. Iterations stop once `max |Jᵀr| ≤ tol` or the step stalls.
+#[derive(Debug, Clone)]
+pub struct LevenbergMarquardt {
+    pub max_iter: usize,
+    pub tol: f64,
+    /// Initial damping `λ`
+    pub damping: f64,
+}
+
+impl Default for LevenbergMarquardt {
+    fn default() -> Self {
+        LevenbergMarquardt {
+            max_iter: 200,
+            tol: 1e-10,
+            damping: 1e-3,
+        }
+    }
+}
+
+impl LevenbergMarquardt {
+    pub fn with_max_iter(mut self, max_iter: usize) -> Self {
+        self.max_iter = max_iter;
+        self
+    }
+
+    pub fn with_tol(mut self, tol: f64) -> Self {
+        self.tol = tol;
+        self
+    }
+
+    pub fn with_damping(mut self, damping: f64) -> Self {
+        self.damping = damping;
+        self
+    }
+
+    /// Fit `residuals` starting from `x0` (`value` is `½ Σ r²` and `grad` is `Jᵀr`)
+    pub fn minimize<F: Fn(&[Expr]) -> Vec<Expr>>(&self, residuals: F, x0: &[f64]) -> OptimResult {
+        let mut graph = Graph::default();
+        graph.touch_vars(x0.len());
+        let symbols = graph.get_symbols();
+        graph.compile_many(residuals(&symbols));
+        let n = x0.len();
+
+        let mut x = x0.to_vec();
+        let (mut r, mut jac) = residual_jacobian(&mut graph, &x);
+        let mut cost = 0.5 * dot(&r, &r);
+        let mut lambda = self.damping;
+        let mut nu = 2f64;
+        let mut iterations = 0;
+        let mut grad = jt_r(&jac, &r, n);
+        let mut converged = norm_inf(&grad) <= self.tol;
+
+        while !converged && iterations < self.max_iter {
+            iterations += 1;
+            let jtj = (0..n)
+                .map(|i| (0..n).map(|j| jac.iter().map(|row| row[i] * row[j]).sum()).collect())
+                .collect::<Vec<Vec<f64>>>();
+            let mut a = jtj.clone();
+            for (i, row) in a.iter_mut().enumerate() {
+                row[i] += lambda * jtj[i][i].max(f64::EPSILON);
+            }
+            let rhs = grad.iter().map(|g| -g).collect::<Vec<_>>();
+            let delta = py_matrix(a).solve(&rhs, SolveKind::LU);
+            if norm_inf(&delta) <= f64::EPSILON * (norm_inf(&x) + f64::EPSILON) {
+                break;
+            }
+
+            let mut trial = x.clone();
+            axpy(1f64, &delta, &mut trial);
+            let (trial_r, trial_jac) = residual_jacobian(&mut graph, &trial);
+            let trial_cost = 0.5 * dot(&trial_r, &trial_r);
+            // Decrease predicted by the linear model: ½ δᵀ(λ D δ - Jᵀr)
+            let predicted = 0.5
+                * (0..n)
+                    .map(|i| delta[i] * (lambda * jtj[i][i].max(f64::EPSILON) * delta[i] - grad[i]))
+                    .sum::<f64>();
+            let rho = (cost - trial_cost) / predicted;
+            if trial_cost.is_finite() && rho > 0f64 {
+                x = trial;
+                r = trial_r;
+                jac = trial_jac;
+                cost = trial_cost;
+                grad = jt_r(&jac, &r, n);
+                lambda *= (1f64 - (2f64 * rho - 1f64).powi(3)).max(1f64 / 3f64);
+                nu = 2f64;
+            } else {
+                lambda *= nu;
+                nu *= 2f64;
+            }
+            converged = norm_inf(&grad) <= self.tol;
+        }
+
+        OptimResult {
+            x,
+            value: cost,
+            grad,
+            iterations,
+            converged,
+        }
+    }
+}
+
+/// Levenberg-Marquardt fit of `residuals` from `x0` with default settings
+pub fn lm<F: Fn(&[Expr]) -> Vec<Expr>>(residuals: F, x0: &[f64]) -> OptimResult {
+    LevenbergMarquardt::default().minimize(residuals, x0)
+}
+
+/// Residuals & Jacobian (row per residual) of the outputs of `compile_many` at `x`
+fn residual_jacobian(graph: &mut Graph<f64>, x: &[f64]) -> (Vec<f64>, Vec<Vec<f64>>) {
+    graph.reset();
+    graph.subs_vars_exact(x);
+    let r = graph.forward_all();
+    let jac = (0..r.len())
+        .map(|i| {
+            graph.backward_output(i);
+            graph.get_gradients()
+        })
+        .collect();
+    (r, jac)
+}
+
+fn jt_r(jac: &[Vec<f64>], r: &[f64], n: usize) -> Vec<f64> {
+    (0..n).map(|j| jac.iter().zip(r).map(|(row, r)| row[j] * r).sum()).collect()
+}
+
+// ┌──────────────────────────────────────────────────────────┐
+//  Line searches
+// └──────────────────────────────────────────────────────────┘
+/// Accepted point `x + α d` of a line search
+#[derive(Debug, Clone)]
+pub struct LinePoint {
+    pub alpha: f64,
+    pub value: f64,
+    /// Directional derivative `∇f(x + α d)·d`
+    pub slope: f64,
+    pub x: Vec<f64>,
+    pub grad: Vec<f64>,
+}
+
+/// Value & gradient of the compiled graph at `x + α d`
+fn line_point(graph: &mut Graph<f64>, x: &[f64], d: &[f64], alpha: f64) -> LinePoint {
+    let mut xa = x.to_vec();
+    axpy(alpha, d, &mut xa);
+    let (value, grad) = gradient_cached(graph, &xa);
+    LinePoint {
+        alpha,
+        value,
+        slope: dot(&grad, d),
+        x: xa,
+        grad,
+    }
+}
+
+/// Backtracking (Armijo) line search along the descent direction `d`
+///
+/// `value` & `grad` are taken at `x`. Starting from `α = 1` (scale `d` for another initial step),
+/// `α` is multiplied by `shrink ∈ (0, 1)` until `f(x + α d) ≤ f(x) + c1 α ∇f·d`. Returns `None` if
+/// `d` is not a descent direction or no step is accepted within 60 trials.
+pub fn backtracking(
+    graph: &mut Graph<f64>,
+    x: &[f64],
+    value: f64,
+    grad: &[f64],
+    d: &[f64],
+    c1: f64,
+    shrink: f64,
+) -> Option<LinePoint> {
+    assert!(shrink > 0f64 && shrink < 1f64, "Shrink factor must lie in (0, 1)");
+    let slope0 = dot(grad, d);
+    if slope0.is_nan() || slope0 >= 0f64 {
+        return None;
+    }
+    let mut alpha = 1f64;
+    for _ in 0..60 {
+        let p = line_point(graph, x, d, alpha);
+        if p.value.is_finite() && p.value <= value + c1 * alpha * slope0 {
+            return Some(p);
+        }
+        alpha *= shrink;
+    }
+    None
+}
+
+/// Step length satisfying the strong Wolfe conditions along the descent direction `d`
+/// (Nocedal & Wright, Algorithms 3.5 & 3.6)
+///
+/// `value` & `grad` are taken at `x` and the search starts from `α = 1`, expanding or zooming with
+/// safeguarded quadratic interpolation. Returns `None` if `d` is not a descent direction or no
+/// step with sufficient decrease is found.
+pub fn strong_wolfe(
+    graph: &mut Graph<f64>,
+    x: &[f64],
+    value: f64,
+    grad: &[f64],
+    d: &[f64],
+    c1: f64,
+    c2: f64,
+) -> Option<LinePoint> {
+    let slope0 = dot(grad, d);
+    if slope0.is_nan() || slope0 >= 0f64 {
+        return None;
+    }
+    let mut eval = |alpha: f64| line_point(graph, x, d, alpha);
+    let armijo = |p: &LinePoint| p.value.is_finite() && p.value <= value + c1 * p.alpha * slope0;
+    let curvature = |p: &LinePoint| p.slope.abs() <= -c2 * slope0;
+
+    let mut prev = LinePoint {
+        alpha: 0f64,
+        value,
+        slope: slope0,
+        x: x.to_vec(),
+        grad: grad.to_vec(),
+    };
+    let mut alpha = 1f64;
+    for i in 0..30 {
+        let p = eval(alpha);
+        let (lo, hi) = if !armijo(&p) || (i > 0 && p.value >= prev.value) {
+            (prev, p)
+        } else if curvature(&p) {
+            return Some(p);
+        } else if p.slope >= 0f64 {
+            (p, prev)
+        } else {
+            alpha *= 2f64;
+            prev = p;
+            continue;
+        };
+
+        // Zoom into the bracket between `lo` (sufficient decrease) & `hi`
+        let (mut lo, mut hi) = (lo, hi);
+        for _ in 0..50 {
+            let width = hi.alpha - lo.alpha;
+            // Minimizer of the quadratic through (lo.value, lo.slope) & hi.value, safeguarded
+            let quad = lo.alpha
+                - lo.slope * width * width / (2f64 * (hi.value - lo.value - lo.slope * width));
+            let (a, b) = (lo.alpha.min(hi.alpha), lo.alpha.max(hi.alpha));
+            let trial = if quad.is_finite() && quad > a + 0.1 * (b - a) && quad < b - 0.1 * (b - a) {
+                quad
+            } else {
+                0.5 * (lo.alpha + hi.alpha)
+            };
+            let p = eval(trial);
+            if !armijo(&p) || p.value >= lo.value {
+                hi = p;
+            } else {
+                if curvature(&p) {
+                    return Some(p);
+                }
+                if p.slope * (hi.alpha - lo.alpha) >= 0f64 {
+                    hi = lo;
+                }
+                lo = p;
+            }
+            if (hi.alpha - lo.alpha).abs() <= f64::EPSILON * lo.alpha.abs().max(1f64) {
+                break;
+            }
+        }
+        // Sufficient decrease without curvature is still progress
+        return (lo.alpha > 0f64).then_some(lo);
+    }
+    None
+}
+
+fn dot(a: &[f64], b: &[f64]) -> f64 {
+    a.iter().zip(b).map(|(a, b)| a * b).sum()
+}
+
+/// `y ← y + a x`
+fn axpy(a: f64, x: &[f64], y: &mut [f64]) {
+    y.iter_mut().zip(x).for_each(|(y, x)| *y += a * x);
+}
+
+fn norm_inf(x: &[f64]) -> f64 {
+    x.iter().fold(0f64, |m, x| m.max(x.abs()))
+}
@@ -0,0 +1,519 @@
+use crate::core::{node_key, Expr, Graph, Node};
+use crate::custom::CustomOp;
+use crate::tape::NodeStore;
+use crate::traits::{ActivationFunction, Matrizable};
+use peroxide_num::Numeric;
+use std::collections::HashMap;
+use std::ops::Div;
+use std::sync::Arc;
+
+/// Evaluate a node on scalar operands
+pub(crate) fn eval_scalar<F: Fn(usize) -> f64>(node: &Node, val: F, ops: &[CustomOp]) -> f64 {
+    match node {
+        Node::Var(i) | Node::Param(i) => val(*i),
+        Node::Const(value) => *value,
+        Node::Add(l, r) => val(*l) + val(*r),
+        Node::Sub(l, r) => val(*l) - val(*r),
+        Node::Mul(l, r) | Node::Hadamard(l, r) => val(*l) * val(*r),
+        Node::Fma(a, b, c) => val(*a).mul_add(val(*b), val(*c)),
+        Node::Select(c, a, b) => {
+            if val(*c).is_sign_positive() {
+                val(*a)
+            } else {
+                val(*b)
+            }
+        }
+        Node::Div(l, r) => val(*l) / val(*r),
+        Node::Pow(l, r) => val(*l).powf(val(*r)),
+        Node::Addf(num, i) => val(*i) + num,
+        Node::Subf(i, num) => val(*i) - num,
+        Node::Mulf(num, i) => val(*i) * num,
+        Node::Powf(i, p) => val(*i).powf(*p),
+        Node::Powi(i, n) => val(*i).powi(*n),
+        Node::Transpose(i) | Node::Arg(i) => val(*i),
+        Node::Neg(i) => -val(*i),
+        Node::Recip(i) => 1.0 / val(*i),
+        Node::Exp(i) => val(*i).exp(),
+        Node::Ln(i) => val(*i).ln(),
+        Node::Sin(i) => val(*i).sin(),
+        Node::Cos(i) => val(*i).cos(),
+        Node::Tan(i) => val(*i).tan(),
+        Node::Sinh(i) => val(*i).sinh(),
+        Node::Cosh(i) => val(*i).cosh(),
+        Node::Tanh(i) => val(*i).tanh(),
+        Node::Sigmoid(i) => val(*i).sigmoid(),
+        Node::ReLU(i) => val(*i).relu(),
+        Node::Heaviside(i) => val(*i).heaviside_zero(),
+        Node::Custom(id, i) => ops[*id].eval(val(*i), 0.0),
+        Node::CustomBinary(id, l, r) => ops[*id].eval(val(*l), val(*r)),
+        Node::External(id, first, n) => ops[*id].eval_n(&(*first..*first + *n).map(val).collect::<Vec<_>>()),
+    }
+}
+
+/// Result of a local rewrite
+enum Rewrite {
+    /// Replace the node by another operation
+    Replace(Node),
+    /// The node is equivalent to an existing node
+    Alias(usize),
+}
+
+/// Whether node `x` is known to be non-negative
+fn nonnegative(nodes: &NodeStore, x: usize) -> bool {
+    match nodes.node(x) {
+        Node::Const(c) => c >= 0.0,
+        Node::Powi(_, n) => n % 2 == 0,
+        Node::Exp(_) | Node::Cosh(_) | Node::Sigmoid(_) | Node::ReLU(_) | Node::Heaviside(_) => true,
+        _ => false,
+    }
+}
+
+/// Algebraic peephole rules (operands are looked up in `nodes`)
+fn peephole_rule(node: &Node, nodes: &NodeStore) -> Option<Rewrite> {
+    let rewrite = match *node {
+        Node::Neg(x) => match nodes.node(x) {
+            Node::Neg(y) => Rewrite::Alias(y),
+            Node::Mulf(a, y) => Rewrite::Replace(Node::Mulf(-a, y)),
+            _ => return None,
+        },
+        Node::Recip(x) => match nodes.node(x) {
+            Node::Recip(y) => Rewrite::Alias(y),
+            _ => return None,
+        },
+        Node::Exp(x) => match nodes.node(x) {
+            Node::Ln(y) => Rewrite::Alias(y),
+            _ => return None,
+        },
+        Node::Ln(x) => match nodes.node(x) {
+            Node::Exp(y) => Rewrite::Alias(y),
+            _ => return None,
+        },
+        Node::Add(l, r) => match (nodes.node(l), nodes.node(r)) {
+            (_, Node::Neg(y)) => Rewrite::Replace(Node::Sub(l, y)),
+            (Node::Const(c), _) => Rewrite::Replace(Node::Addf(c, r)),
+            (_, Node::Const(c)) => Rewrite::Replace(Node::Addf(c, l)),
+            _ => return None,
+        },
+        Node::Sub(l, r) => match nodes.node(r) {
+            Node::Neg(y) => Rewrite::Replace(Node::Add(l, y)),
+            Node::Const(c) => Rewrite::Replace(Node::Subf(l, c)),
+            _ => return None,
+        },
+        Node::Mul(l, r) => match (nodes.node(l), nodes.node(r)) {
+            (Node::Const(c), _) => Rewrite::Replace(Node::Mulf(c, r)),
+            (_, Node::Const(c)) => Rewrite::Replace(Node::Mulf(c, l)),
+            _ => return None,
+        },
+        Node::Div(l, r) => match nodes.node(r) {
+            Node::Const(c) => Rewrite::Replace(Node::Mulf(1.0 / c, l)),
+            _ => return None,
+        },
+        Node::Addf(0.0, x) => Rewrite::Alias(x),
+        Node::Addf(a, x) => match nodes.node(x) {
+            Node::Addf(b, y) => Rewrite::Replace(Node::Addf(a + b, y)),
+            Node::Subf(y, b) => Rewrite::Replace(Node::Addf(a - b, y)),
+            _ => return None,
+        },
+        Node::Subf(x, 0.0) => Rewrite::Alias(x),
+        Node::Subf(x, a) => match nodes.node(x) {
+            Node::Addf(b, y) => Rewrite::Replace(Node::Addf(b - a, y)),
+            Node::Subf(y, b) => Rewrite::Replace(Node::Subf(y, a + b)),
+            _ => return None,
+        },
+        Node::Mulf(1.0, x) => Rewrite::Alias(x),
+        Node::Mulf(a, x) => match nodes.node(x) {
+            Node::Mulf(b, y) => Rewrite::Replace(Node::Mulf(a * b, y)),
+            Node::Neg(y) => Rewrite::Replace(Node::Mulf(-a, y)),
+            _ => return None,
+        },
+        Node::Powf(x, 1.0) => Rewrite::Alias(x),
+        // (y^q)^p = y^(pq) only holds for integer exponents or a non-negative base
+        Node::Powf(x, p) => match nodes.node(x) {
+            Node::Powf(y, q) if (p.fract() == 0.0 && q.fract() == 0.0) || nonnegative(nodes, y) => {
+                Rewrite::Replace(Node::Powf(y, p * q))
+            }
+            _ => return None,
+        },
+        Node::Powi(x, 1) => Rewrite::Alias(x),
+        Node::Powi(x, n) => match nodes.node(x) {
+            Node::Powi(y, m) => Rewrite::Replace(Node::Powi(y, n.checked_mul(m)?)),
+            _ => return None,
+        },
+        _ => return None,
+    };
+    Some(rewrite)
+}
+
+// ┌──────────────────────────────────────────────────────────┐
+//  Tape optimization passes
+// └──────────────────────────────────────────────────────────┘
+impl<T: std::fmt::Debug + Numeric<f64> + Default + ActivationFunction + Matrizable> Graph<T>
+where
+    f64: Div<T, Output = T>,
+{
+    /// Constant folding
+    ///
+    /// Every node depending only on constants is evaluated once and replaced by a `Const` node
+    /// (in place, so node indices stay valid). Returns the number of folded nodes.
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(level = "debug", skip_all, fields(nodes = self.nodes.len()), ret)
+    )]
+    pub fn fold_constants(&mut self) -> usize {
+        let order = self.get_topological_order();
+        let mut folded = 0usize;
+        for index in order {
+            let mut children = self.get_children(index).peekable();
+            if children.peek().is_none() || !children.all(|c| matches!(self.nodes.node(c), Node::Const(_))) {
+                continue;
+            }
+            let nodes = &self.nodes;
+            let value = eval_scalar(
+                &nodes.node(index),
+                |c| match nodes.node(c) {
+                    Node::Const(value) => value,
+                    _ => unreachable!(),
+                },
+                &self.custom_ops,
+            );
+            Arc::make_mut(&mut self.nodes).set(index, Node::Const(value));
+            self.buffer[index] = None;
+            folded += 1;
+        }
+        // The topological order stays valid since nodes only lose operands
+        if folded > 0 {
+            self.grad_mask = None;
+        }
+        folded
+    }
+
+    /// Dead-node elimination
+    ///
+    /// Removes every node which is not reachable from a compiled output (e.g. leftovers of
+    /// previous compiles) and re-indexes the tape. Variables & parameters are always kept.
+    /// Returns the map from old to new indices (`None` for removed nodes); indices held outside
+    /// of the graph (including `Expr::Symbol`) must be translated with it.
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(level = "debug", skip_all, fields(nodes = self.nodes.len()))
+    )]
+    pub fn prune(&mut self) -> Vec<Option<usize>> {
+        let n = self.nodes.len();
+        let mut keep = vec![false; n];
+        let mut stack = self.outputs.clone();
+        stack.extend(self.compiled);
+        stack.extend(self.output_names.values().copied());
+        stack.extend(self.value_ics.iter().copied());
+        stack.extend(self.param_ics.iter().copied());
+        while let Some(index) = stack.pop() {
+            if keep[index] {
+                continue;
+            }
+            keep[index] = true;
+            stack.extend(self.get_children(index));
+        }
+
+        let mut map = vec![None; n];
+        let mut next = 0usize;
+        for (old, kept) in keep.iter().enumerate() {
+            if *kept {
+                map[old] = Some(next);
+                next += 1;
+            }
+        }
+        let remap = |i: usize| map[i].unwrap();
+
+        let nodes = std::mem::take(&mut self.nodes);
+        let buffer = std::mem::take(&mut self.buffer);
+        let gradients = std::mem::take(&mut self.gradients);
+        let mut kept_nodes = NodeStore::with_capacity(next);
+        for (((node, value), grad), kept) in nodes.iter().zip(buffer).zip(gradients).zip(&keep) {
+            if *kept {
+                kept_nodes.push(node.map_indices(remap));
+                self.buffer.push(value);
+                self.gradients.push(grad);
+            }
+        }
+        self.nodes = Arc::new(kept_nodes);
+        self.value_ics.iter_mut().for_each(|x| *x = remap(*x));
+        self.param_ics.iter_mut().for_each(|x| *x = remap(*x));
+        self.outputs.iter_mut().for_each(|x| *x = remap(*x));
+        self.output_names.values_mut().for_each(|x| *x = remap(*x));
+        self.groups.values_mut().flatten().for_each(|x| *x = remap(*x));
+        if let Some(origins) = self.provenance.as_mut() {
+            let mut kept = vec![None; next];
+            for (old, origin) in origins.iter().enumerate() {
+                if let Some(new) = map[old] {
+                    kept[new] = *origin;
+                }
+            }
+            *origins = kept;
+        }
+        self.labels = std::mem::take(&mut self.labels)
+            .into_iter()
+            .filter_map(|(index, label)| map[index].map(|index| (index, label)))
+            .collect();
+        self.compiled = self.compiled.map(remap);
+
+        let mut cse = HashMap::new();
+        for (index, node) in self.nodes.iter().enumerate() {
+            if !matches!(node, Node::Var(_) | Node::Param(_)) {
+                cse.entry(node_key(&node)).or_insert(index);
+            }
+        }
+        self.cse = Arc::new(cse);
+        self.topological_order = None;
+        self.grad_mask = None;
+
+        map
+    }
+
+    /// Algebraic peephole optimization
+    ///
+    /// Folds `Neg(Neg(x))`, `Recip(Recip(x))`, `Exp(Ln(x))` & `Ln(Exp(x))`, rewrites division by
+    /// a constant to `Mulf`, merges chained `Addf`/`Subf`/`Mulf`/`Powf`/`Powi` and drops identities.
+    /// Scalar semantics are assumed (e.g. `Exp(Ln(x)) = x` only holds for `x > 0`).
+    /// Bypassed nodes stay on the tape until `prune`. Returns the number of rewrites.
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(level = "debug", skip_all, fields(nodes = self.nodes.len()), ret)
+    )]
+    pub fn peephole(&mut self) -> usize {
+        let order = self.get_topological_order();
+        let mut alias = (0..self.nodes.len()).collect::<Vec<_>>();
+        let mut count = 0usize;
+        for index in order {
+            let node = self.nodes.node(index).map_indices(|i| alias[i]);
+            Arc::make_mut(&mut self.nodes).set(index, node);
+            while let Some(rewrite) = peephole_rule(&self.nodes.node(index), &self.nodes) {
+                count += 1;
+                self.buffer[index] = None;
+                match rewrite {
+                    Rewrite::Replace(node) => Arc::make_mut(&mut self.nodes).set(index, node),
+                    Rewrite::Alias(target) => {
+                        alias[index] = target;
+                        break;
+                    }
+                }
+            }
+        }
+        self.outputs.iter_mut().for_each(|x| *x = alias[*x]);
+        self.output_names.values_mut().for_each(|x| *x = alias[*x]);
+        self.compiled = self.compiled.map(|x| alias[x]);
+        // Labels follow bypassed nodes unless the target already has one
+        let moved = self
+            .labels
+            .keys()
+            .filter(|index| alias[**index] != **index)
+            .copied()
+            .collect::<Vec<_>>();
+        for index in moved {
+            let label = self.labels.remove(&index).unwrap();
+            self.labels.entry(alias[index]).or_insert(label);
+        }
+        if count > 0 {
+            self.topological_order = None;
+            self.grad_mask = None;
+        }
+        count
+    }
+
+    /// Fuse `Add(Mul(a, b), c)` (either operand order) into `Fma(a, b, c)`
+    ///
+    /// Only products without other consumers are fused, so no work is duplicated.
+    /// Returns the number of fused nodes.
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(level = "debug", skip_all, fields(nodes = self.nodes.len()), ret)
+    )]
+    pub fn fuse_fma(&mut self) -> usize {
+        let order = self.get_topological_order();
+        let mut uses = vec![0usize; self.nodes.len()];
+        for &index in order.iter() {
+            for child in self.get_children(index) {
+                uses[child] += 1;
+            }
+        }
+        for &root in self.outputs.iter().chain(self.output_names.values()) {
+            uses[root] += 1;
+        }
+
+        let mut count = 0usize;
+        for index in order {
+            let Node::Add(l, r) = self.nodes.node(index) else {
+                continue;
+            };
+            let fused = match (self.nodes.node(l), self.nodes.node(r)) {
+                (Node::Mul(a, b), _) if uses[l] == 1 => Node::Fma(a, b, r),
+                (_, Node::Mul(a, b)) if uses[r] == 1 => Node::Fma(a, b, l),
+                _ => continue,
+            };
+            Arc::make_mut(&mut self.nodes).set(index, fused);
+            self.buffer[index] = None;
+            count += 1;
+        }
+        if count > 0 {
+            self.grad_mask = None;
+        }
+        count
+    }
+
+    /// Strength reduction of integer powers
+    ///
+    /// `Powi(x, n)` with `2 <= |n| <= 8` is lowered into a square-and-multiply chain of
+    /// `Hadamard` nodes (and a `Recip` for negative `n`), so neither sweep calls `powi`.
+    /// Returns the number of lowered nodes.
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(level = "debug", skip_all, fields(nodes = self.nodes.len()), ret)
+    )]
+    pub fn reduce_powi(&mut self) -> usize {
+        let order = self.get_topological_order();
+        let mut count = 0usize;
+        for index in order {
+            let Node::Powi(x, n) = self.nodes.node(index) else {
+                continue;
+            };
+            let m = n.unsigned_abs();
+            if !(2..=8).contains(&m) && n != -1 {
+                continue;
+            }
+            let node = if n < 0 {
+                Node::Recip(self.pow_index(x, m))
+            } else {
+                self.pow_node(x, m)
+            };
+            Arc::make_mut(&mut self.nodes).set(index, node);
+            self.buffer[index] = None;
+            count += 1;
+        }
+        if count > 0 {
+            self.topological_order = None;
+            self.grad_mask = None;
+        }
+        count
+    }
+
+    /// Node computing `x^m` (`m >= 2`) from a multiply chain
+    fn pow_node(&mut self, x: usize, m: u32) -> Node {
+        if m & 1 == 0 {
+            let half = self.pow_index(x, m / 2);
+            Node::Hadamard(half, half)
+        } else {
+            let rest = self.pow_index(x, m - 1);
+            Node::Hadamard(rest, x)
+        }
+    }
+
+    fn pow_index(&mut self, x: usize, m: u32) -> usize {
+        if m == 1 {
+            x
+        } else {
+            let node = self.pow_node(x, m);
+            self.push_node(node)
+        }
+    }
+
+    /// Partial evaluation
+    ///
+    /// Returns a copy of the graph where each `(var, value)` of `bindings` is turned into a constant,
+    /// followed by constant folding and dead-node elimination. The remaining variables keep their
+    /// order but not their indices (see `get_vars` & `get_outputs` of the result).
+    pub fn specialize(&self, bindings: &[(usize, f64)]) -> Graph<T> {
+        let mut graph = self.clone();
+        for &(var, value) in bindings {
+            assert!(matches!(graph.nodes.node(var), Node::Var(_)), "Not a variable");
+            let order = graph.value_ics.iter().position(|x| *x == var).unwrap();
+            graph.value_ics.remove(order);
+            graph.requires_grad.remove(order);
+            graph.groups.values_mut().for_each(|vars| vars.retain(|x| *x != var));
+            Arc::make_mut(&mut graph.nodes).set(var, Node::Const(value));
+            graph.buffer[var] = None;
+        }
+        graph.invalidate_values();
+        graph.fold_constants();
+        graph.prune();
+        graph
+    }
+
+    /// Run the tape optimization pipeline
+    ///
+    /// Constant folding, peephole rewrites, power lowering, FMA fusion and dead-node elimination.
+    /// Returns the index map of `prune`.
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(level = "debug", skip_all, fields(nodes = self.nodes.len()))
+    )]
+    pub fn optimize(&mut self) -> Vec<Option<usize>> {
+        self.fold_constants();
+        if self.peephole() > 0 {
+            self.fold_constants();
+        }
+        self.reduce_powi();
+        self.fuse_fma();
+        self.prune()
+    }
+}
+
+// ┌──────────────────────────────────────────────────────────┐
+//  Literal promotion
+// └──────────────────────────────────────────────────────────┘
+impl Graph<f64> {
+    /// Promote the literals of `Addf`/`Subf`/`Mulf`/`Powf` nodes into parameters
+    ///
+    /// Each literal reached from a compiled output gets its own parameter and the node becomes the
+    /// matching binary operation, so its sensitivity is `adjoint(param)` after `backward` and its
+    /// value can be changed with `set_param`. Returns the `(node, param)` pairs in tape order.
+    /// Division by a constant is stored as `Mulf` of the reciprocal, which is then what gets tracked.
+    /// Only compiled outputs are visited, so call it after `compile`/`compile_many` (or use
+    /// `compile_tracking_literals`); outputs compiled later keep their literals.
+    pub fn promote_literals(&mut self) -> Vec<(usize, usize)> {
+        let mut reached = vec![false; self.nodes.len()];
+        let mut stack = self.outputs.clone();
+        stack.extend(self.compiled);
+        stack.extend(self.output_names.values().copied());
+        while let Some(index) = stack.pop() {
+            if !reached[index] {
+                reached[index] = true;
+                stack.extend(self.get_children(index));
+            }
+        }
+
+        let mut promoted = Vec::new();
+        for (index, reached) in reached.into_iter().enumerate() {
+            if !reached {
+                continue;
+            }
+            let literal = match self.nodes.node(index) {
+                Node::Addf(c, _) | Node::Subf(_, c) | Node::Mulf(c, _) | Node::Powf(_, c) => c,
+                _ => continue,
+            };
+            let param = self.param(literal);
+            let node = match self.nodes.node(index) {
+                Node::Addf(_, x) => Node::Add(param, x),
+                Node::Subf(x, _) => Node::Sub(x, param),
+                Node::Mulf(_, x) => Node::Mul(param, x),
+                Node::Powf(x, _) => Node::Pow(x, param),
+                _ => unreachable!(),
+            };
+            let cse = Arc::make_mut(&mut self.cse);
+            cse.remove(&node_key(&self.nodes.node(index)));
+            cse.insert(node_key(&node), index);
+            Arc::make_mut(&mut self.nodes).set(index, node);
+            self.buffer[index] = None;
+            promoted.push((index, param));
+        }
+        if !promoted.is_empty() {
+            self.topological_order = None;
+            self.grad_mask = None;
+            self.schedule();
+        }
+        promoted
+    }
+
+    /// `compile` followed by `promote_literals`
+    pub fn compile_tracking_literals(&mut self, expr: Expr) -> Vec<(usize, usize)> {
+        self.compile(expr);
+        self.promote_literals()
+    }
+}
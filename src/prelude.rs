@@ -1,5 +1,37 @@
+pub use crate::active::{trace, trace_gradient, Active, Recorder};
+pub use crate::complex::Complex;
 pub use crate::core::*;
-pub use crate::util::{gradient, gradient_cached};
+pub use crate::custom::CustomOp;
+pub use crate::density::{
+    beta_lpdf, binomial_lpmf, gamma_lpdf, ln_gamma, lognormal_lpdf, normal_lpdf, poisson_lpmf, student_t_lpdf,
+};
+pub use crate::diff::{NodeChange, TapeDiff};
+pub use crate::error::{GraphError, TapeIssue};
+pub use crate::greeks::{Estimate, Greeks, Pathwise};
+pub use crate::implicit::ImplicitSystem;
+pub use crate::lanes::Lanes;
+pub use crate::nn::{Activation, Dense, Mlp};
+pub use crate::objective::{peroxide_model, Objective};
+pub use crate::ode::{Ode, OdeGradient};
+pub use crate::optim::{
+    backtracking, lm, newton_cg, strong_wolfe, Adam, AugmentedLagrangian, Clipped, ConstrainedResult,
+    Forcing, GradClip, Lbfgs, LevenbergMarquardt, LinePoint, Newton, NewtonCg, NewtonSolve, OptimResult,
+    Optimizer, RmsProp, Sgd, TrustRegion,
+};
+pub use crate::profile::{OpStats, Profile};
+pub use crate::provenance::Provenance;
+pub use crate::record::{Record, RecordDiff};
+pub use crate::sample::{Chain, Hmc, Nuts, Warmup};
+pub use crate::sparsity::{SparseMatrix, SparsityPattern};
+pub use crate::stats::{CostEstimate, GraphStats, OpCounts};
+pub use crate::tape::{Tape, Workspace};
+pub use crate::util::{
+    check_gradient, check_gradient_cstep, check_gradient_symbolic, clip_grad_norm, clip_grad_value,
+    diff_integral, diff_root, fold, gauss_legendre, gradient, gradient_cached, hessian_diag, scan,
+    value_and_grad, GradCheckReport, GradFn,
+};
+#[cfg(feature = "parallel")]
+pub use crate::util::gradient_batch;
 pub use crate::traits::*;
 pub use peroxide::fuga::Printable;
 pub use peroxide_num::{ExpLogOps, Numeric, PowOps, TrigOps};
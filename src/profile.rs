@@ -0,0 +1,122 @@
+use crate::core::Graph;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::time::Duration;
+
+// ┌──────────────────────────────────────────────────────────┐
+//  Per-op profiling
+// └──────────────────────────────────────────────────────────┘
+/// Invocation count & accumulated time
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct OpStats {
+    pub calls: u64,
+    pub time: Duration,
+}
+
+impl OpStats {
+    fn record(&mut self, time: Duration) {
+        self.calls += 1;
+        self.time += time;
+    }
+}
+
+/// Timings collected by `forward` & `backward` while profiling is enabled
+/// (see `Graph::enable_profiling`)
+#[derive(Debug, Clone, Default)]
+pub struct Profile {
+    /// Per op kind (see `Node::name`)
+    pub forward: BTreeMap<&'static str, OpStats>,
+    pub backward: BTreeMap<&'static str, OpStats>,
+    /// Per node as `(forward, backward)`, if requested
+    pub nodes: Option<Vec<(OpStats, OpStats)>>,
+}
+
+impl Profile {
+    fn new(per_node: bool) -> Self {
+        Profile {
+            nodes: per_node.then(Vec::new),
+            ..Default::default()
+        }
+    }
+
+    pub fn reset(&mut self) {
+        *self = Profile::new(self.nodes.is_some());
+    }
+
+    fn node(&mut self, index: usize) -> Option<&mut (OpStats, OpStats)> {
+        let nodes = self.nodes.as_mut()?;
+        if nodes.len() <= index {
+            nodes.resize(index + 1, Default::default());
+        }
+        Some(&mut nodes[index])
+    }
+
+    pub(crate) fn record_forward(&mut self, index: usize, name: &'static str, time: Duration) {
+        self.forward.entry(name).or_default().record(time);
+        if let Some(node) = self.node(index) {
+            node.0.record(time);
+        }
+    }
+
+    pub(crate) fn record_backward(&mut self, index: usize, name: &'static str, time: Duration) {
+        self.backward.entry(name).or_default().record(time);
+        if let Some(node) = self.node(index) {
+            node.1.record(time);
+        }
+    }
+
+    /// Op kinds sorted by total (forward + backward) time, most expensive first
+    pub fn ranking(&self) -> Vec<(&'static str, OpStats, OpStats)> {
+        let mut names = self.forward.keys().chain(self.backward.keys()).copied().collect::<Vec<_>>();
+        names.sort_unstable();
+        names.dedup();
+        let mut ranking = names
+            .into_iter()
+            .map(|name| {
+                let f = self.forward.get(name).copied().unwrap_or_default();
+                let b = self.backward.get(name).copied().unwrap_or_default();
+                (name, f, b)
+            })
+            .collect::<Vec<_>>();
+        ranking.sort_by_key(|x| std::cmp::Reverse(x.1.time + x.2.time));
+        ranking
+    }
+}
+
+impl fmt::Display for Profile {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(
+            f,
+            "{:<10} {:>10} {:>14} {:>10} {:>14}",
+            "op", "fwd calls", "fwd time", "bwd calls", "bwd time"
+        )?;
+        for (name, fwd, bwd) in self.ranking() {
+            writeln!(
+                f,
+                "{:<10} {:>10} {:>14?} {:>10} {:>14?}",
+                name, fwd.calls, fwd.time, bwd.calls, bwd.time
+            )?;
+        }
+        Ok(())
+    }
+}
+
+impl<T> Graph<T> {
+    /// Start accumulating op timings in `forward` & `backward` (`per_node` also keeps them per node)
+    pub fn enable_profiling(&mut self, per_node: bool) {
+        self.profile = Some(Profile::new(per_node));
+    }
+
+    /// Stop profiling and return what was collected
+    pub fn disable_profiling(&mut self) -> Option<Profile> {
+        self.profile.take()
+    }
+
+    pub fn profile(&self) -> Option<&Profile> {
+        self.profile.as_ref()
+    }
+
+    pub fn profile_mut(&mut self) -> Option<&mut Profile> {
+        self.profile.as_mut()
+    }
+}
@@ -0,0 +1,55 @@
+use crate::core::Graph;
+use std::fmt;
+use std::panic::Location;
+
+// ┌──────────────────────────────────────────────────────────┐
+//  Node provenance
+// └──────────────────────────────────────────────────────────┘
+/// Origin of a node: the `Expr` constructor it was parsed from and the call which compiled it
+/// (see `Graph::enable_provenance`)
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Provenance {
+    /// `Expr` variant, e.g. `"Ln"`
+    pub op: &'static str,
+    pub location: &'static Location<'static>,
+}
+
+impl fmt::Display for Provenance {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Expr::{} compiled at {}", self.op, self.location)
+    }
+}
+
+impl<T> Graph<T> {
+    /// Start recording the provenance of the nodes created by `compile`, `compile_many` &
+    /// `compile_named`
+    ///
+    /// Nodes shared through CSE keep their first origin; nodes created by passes (e.g. power
+    /// lowering) and through the node-level API have none.
+    pub fn enable_provenance(&mut self) {
+        self.provenance.get_or_insert_with(Vec::new);
+    }
+
+    /// Stop recording and forget the provenance collected so far
+    pub fn disable_provenance(&mut self) {
+        self.provenance = None;
+    }
+
+    /// Origin of a node, if it was recorded
+    pub fn provenance(&self, index: usize) -> Option<Provenance> {
+        self.provenance.as_ref()?.get(index).copied().flatten()
+    }
+
+    /// Attribute the nodes created since `from` to `op` compiled at `location`
+    pub(crate) fn note_provenance(&mut self, from: usize, op: &'static str, location: &'static Location<'static>) {
+        let n = self.nodes.len();
+        if let Some(origins) = self.provenance.as_mut() {
+            if origins.len() < n {
+                origins.resize(n, None);
+            }
+            for origin in origins[from..n].iter_mut() {
+                origin.get_or_insert(Provenance { op, location });
+            }
+        }
+    }
+}
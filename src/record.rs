@@ -0,0 +1,365 @@
+use crate::core::{node_key, Graph, Node};
+use crate::custom::CustomOp;
+use std::collections::HashMap;
+use std::fmt::Write as _;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, BufWriter, Write};
+use std::path::Path;
+use std::sync::Arc;
+
+// ┌──────────────────────────────────────────────────────────┐
+//  Evaluation records (dump & replay)
+// └──────────────────────────────────────────────────────────┘
+/// Complete state of one evaluation: tape, leaf & intermediate values and adjoints
+///
+/// Written as plain text with every `f64` stored as its bit pattern, so a record read back is
+/// bit-identical and two records (e.g. from different platforms) can be compared exactly.
+#[derive(Debug, Clone)]
+pub struct Record {
+    pub nodes: Vec<Node>,
+    pub values: Vec<Option<f64>>,
+    pub adjoints: Vec<f64>,
+    pub root: usize,
+    /// Variables excluded from differentiation (see `Graph::freeze`)
+    pub frozen: Vec<usize>,
+    /// Node labels (see `Graph::label`) sorted by index
+    pub labels: Vec<(usize, String)>,
+    /// Node origins (see `Graph::enable_provenance`) sorted by index, as text
+    pub origins: Vec<(usize, String)>,
+}
+
+/// Node whose value or adjoint differs between two records (see `Record::diff`)
+#[derive(Debug, Clone, PartialEq)]
+pub struct RecordDiff {
+    pub index: usize,
+    pub node: Node,
+    pub values: (Option<f64>, Option<f64>),
+    pub adjoints: (f64, f64),
+}
+
+impl Graph<f64> {
+    /// Snapshot of the current evaluation (call after `forward` & `backward`)
+    pub fn record(&self) -> Record {
+        Record {
+            nodes: self.nodes.to_vec(),
+            values: self.buffer.clone(),
+            adjoints: self.gradients.clone(),
+            root: self.compiled.unwrap(),
+            frozen: self.get_frozen(),
+            labels: {
+                let mut labels = self.labels.iter().map(|(i, x)| (*i, x.clone())).collect::<Vec<_>>();
+                labels.sort();
+                labels
+            },
+            origins: (0..self.nodes.len())
+                .filter_map(|i| self.provenance(i).map(|origin| (i, origin.to_string())))
+                .collect(),
+        }
+    }
+
+    /// Write the current evaluation to `path` (see `Record`)
+    pub fn dump<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        self.record().save(path)
+    }
+}
+
+impl Record {
+    /// Graph with the recorded tape & leaf values (intermediate values are not restored)
+    ///
+    /// Custom ops are recorded by registry index only; use `graph_with` to supply them.
+    pub fn graph(&self) -> Graph<f64> {
+        assert!(
+            !self
+                .nodes
+                .iter()
+                .any(|x| matches!(x, Node::Custom(..) | Node::CustomBinary(..) | Node::External(..))),
+            "Record contains custom ops (use graph_with)"
+        );
+        self.graph_with(&[])
+    }
+
+    /// `graph` with the custom op registry of the recording graph (see `Graph::get_custom_ops`)
+    pub fn graph_with(&self, custom_ops: &[CustomOp]) -> Graph<f64> {
+        let mut graph = Graph::default();
+        let mut cse = HashMap::new();
+        for (index, node) in self.nodes.iter().enumerate() {
+            match node {
+                Node::Var(_) => {
+                    graph.value_ics.push(index);
+                    graph.requires_grad.push(!self.frozen.contains(&index));
+                }
+                Node::Param(_) => graph.param_ics.push(index),
+                _ => {
+                    cse.insert(node_key(node), index);
+                }
+            }
+        }
+        graph.buffer = self
+            .nodes
+            .iter()
+            .zip(self.values.iter())
+            .map(|(node, value)| match node {
+                Node::Var(_) | Node::Param(_) => *value,
+                _ => None,
+            })
+            .collect();
+        graph.gradients = vec![0f64; self.nodes.len()];
+        graph.nodes = Arc::new(self.nodes.iter().copied().collect());
+        graph.cse = Arc::new(cse);
+        graph.custom_ops = Arc::new(custom_ops.to_vec());
+        graph.compiled = Some(self.root);
+        graph.outputs = vec![self.root];
+        graph.labels = self.labels.iter().cloned().collect();
+        graph
+    }
+
+    /// Re-run forward & backward on the recorded inputs
+    pub fn replay(&self) -> Record {
+        let mut graph = self.graph();
+        graph.forward();
+        graph.backward();
+        graph.record()
+    }
+
+    /// Nodes whose value or adjoint is not bit-identical in `other`
+    ///
+    /// Both records have to come from the same tape.
+    pub fn diff(&self, other: &Record) -> Vec<RecordDiff> {
+        assert!(
+            self.nodes.len() == other.nodes.len()
+                && self.nodes.iter().zip(other.nodes.iter()).all(|(x, y)| node_key(x) == node_key(y)),
+            "Records of different tapes"
+        );
+        let bits = |x: Option<f64>| x.map(f64::to_bits);
+        (0..self.nodes.len())
+            .filter(|&i| {
+                bits(self.values[i]) != bits(other.values[i])
+                    || self.adjoints[i].to_bits() != other.adjoints[i].to_bits()
+            })
+            .map(|i| RecordDiff {
+                index: i,
+                node: self.nodes[i],
+                values: (self.values[i], other.values[i]),
+                adjoints: (self.adjoints[i], other.adjoints[i]),
+            })
+            .collect()
+    }
+
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        let mut writer = BufWriter::new(File::create(path)?);
+        self.write(&mut writer)?;
+        writer.flush()
+    }
+
+    pub fn load<P: AsRef<Path>>(path: P) -> io::Result<Record> {
+        Record::read(BufReader::new(File::open(path)?))
+    }
+
+    /// Text format: a header with one `label index name` line per label & one `origin index text`
+    /// line per origin, then one line per node `index op args... = value adjoint # decimal values
+    /// [label] (origin)`
+    pub fn write<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        writeln!(writer, "radient-record 1")?;
+        writeln!(writer, "root {}", self.root)?;
+        let frozen = self.frozen.iter().map(|x| x.to_string()).collect::<Vec<_>>();
+        writeln!(writer, "frozen {}", frozen.join(" "))?;
+        for (index, label) in self.labels.iter() {
+            writeln!(writer, "label {} {}", index, label)?;
+        }
+        for (index, origin) in self.origins.iter() {
+            writeln!(writer, "origin {} {}", index, origin)?;
+        }
+        let labels = self.labels.iter().cloned().collect::<HashMap<_, _>>();
+        let origins = self.origins.iter().cloned().collect::<HashMap<_, _>>();
+        for (index, node) in self.nodes.iter().enumerate() {
+            let value = match self.values[index] {
+                Some(x) => hex(x),
+                None => "-".to_string(),
+            };
+            let adjoint = self.adjoints[index];
+            write!(
+                writer,
+                "{} {} = {} {} # {:?} {:?}",
+                index,
+                encode_node(node),
+                value,
+                hex(adjoint),
+                self.values[index],
+                adjoint
+            )?;
+            if let Some(label) = labels.get(&index) {
+                write!(writer, " [{}]", label)?;
+            }
+            if let Some(origin) = origins.get(&index) {
+                write!(writer, " ({})", origin)?;
+            }
+            writeln!(writer)?;
+        }
+        Ok(())
+    }
+
+    pub fn read<R: BufRead>(reader: R) -> io::Result<Record> {
+        let mut lines = reader.lines();
+        let mut next = || lines.next().unwrap_or_else(|| Err(invalid("unexpected end of record")));
+        if next()?.trim() != "radient-record 1" {
+            return Err(invalid("not a radient record"));
+        }
+        let root = next()?
+            .strip_prefix("root ")
+            .and_then(|x| x.trim().parse().ok())
+            .ok_or_else(|| invalid("missing root"))?;
+        let frozen = next()?
+            .strip_prefix("frozen")
+            .ok_or_else(|| invalid("missing frozen"))?
+            .split_whitespace()
+            .map(|x| x.parse().map_err(|_| invalid(x)))
+            .collect::<io::Result<Vec<usize>>>()?;
+
+        let mut record = Record {
+            nodes: vec![],
+            values: vec![],
+            adjoints: vec![],
+            root,
+            frozen,
+            labels: vec![],
+            origins: vec![],
+        };
+        for line in lines {
+            let line = line?;
+            if let Some(label) = line.strip_prefix("label ") {
+                let (index, name) = label.split_once(' ').ok_or_else(|| invalid(&line))?;
+                let index = index.parse().map_err(|_| invalid(&line))?;
+                record.labels.push((index, name.trim().to_string()));
+                continue;
+            }
+            if let Some(origin) = line.strip_prefix("origin ") {
+                let (index, text) = origin.split_once(' ').ok_or_else(|| invalid(&line))?;
+                let index = index.parse().map_err(|_| invalid(&line))?;
+                record.origins.push((index, text.trim().to_string()));
+                continue;
+            }
+            let line = line.split('#').next().unwrap().trim();
+            if line.is_empty() {
+                continue;
+            }
+            let (node, state) = line.split_once('=').ok_or_else(|| invalid(line))?;
+            let mut node = node.split_whitespace();
+            let index = node.next().and_then(|x| x.parse::<usize>().ok());
+            if index != Some(record.nodes.len()) {
+                return Err(invalid(line));
+            }
+            record.nodes.push(decode_node(node).ok_or_else(|| invalid(line))?);
+            let mut state = state.split_whitespace();
+            let value = match state.next() {
+                Some("-") => None,
+                Some(x) => Some(unhex(x).ok_or_else(|| invalid(line))?),
+                None => return Err(invalid(line)),
+            };
+            let adjoint = state.next().and_then(unhex).ok_or_else(|| invalid(line))?;
+            record.values.push(value);
+            record.adjoints.push(adjoint);
+        }
+        if record.root >= record.nodes.len() {
+            return Err(invalid("root out of bounds"));
+        }
+        if record.labels.iter().any(|(index, _)| *index >= record.nodes.len()) {
+            return Err(invalid("label out of bounds"));
+        }
+        if record.origins.iter().any(|(index, _)| *index >= record.nodes.len()) {
+            return Err(invalid("origin out of bounds"));
+        }
+        Ok(record)
+    }
+}
+
+fn invalid(msg: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, format!("invalid record: {}", msg))
+}
+
+fn hex(x: f64) -> String {
+    format!("{:#018x}", x.to_bits())
+}
+
+fn unhex(x: &str) -> Option<f64> {
+    u64::from_str_radix(x.strip_prefix("0x")?, 16).ok().map(f64::from_bits)
+}
+
+/// `Name arg...` with indices in decimal & `f64` immediates as bit patterns
+fn encode_node(node: &Node) -> String {
+    let mut s = node.name().to_string();
+    let mut push = |x: String| {
+        let _ = write!(s, " {}", x);
+    };
+    match *node {
+        Node::Const(x) => push(hex(x)),
+        Node::Addf(x, i) | Node::Mulf(x, i) => {
+            push(hex(x));
+            push(i.to_string());
+        }
+        Node::Subf(i, x) | Node::Powf(i, x) => {
+            push(i.to_string());
+            push(hex(x));
+        }
+        Node::Powi(i, n) => {
+            push(i.to_string());
+            push(n.to_string());
+        }
+        Node::Var(i) | Node::Param(i) => push(i.to_string()),
+        Node::Custom(id, i) => {
+            push(id.to_string());
+            push(i.to_string());
+        }
+        Node::CustomBinary(id, l, r) | Node::External(id, l, r) => {
+            push(id.to_string());
+            push(l.to_string());
+            push(r.to_string());
+        }
+        _ => node.operands().into_iter().for_each(|i| push(i.to_string())),
+    }
+    s
+}
+
+fn decode_node<'a, I: Iterator<Item = &'a str>>(mut tokens: I) -> Option<Node> {
+    let name = tokens.next()?;
+    let args = tokens.collect::<Vec<_>>();
+    let u = |k: usize| args.get(k)?.parse::<usize>().ok();
+    let f = |k: usize| unhex(args.get(k)?);
+    let node = match (name, args.len()) {
+        ("Var", 1) => Node::Var(u(0)?),
+        ("Param", 1) => Node::Param(u(0)?),
+        ("Const", 1) => Node::Const(f(0)?),
+        ("Add", 2) => Node::Add(u(0)?, u(1)?),
+        ("Addf", 2) => Node::Addf(f(0)?, u(1)?),
+        ("Sub", 2) => Node::Sub(u(0)?, u(1)?),
+        ("Subf", 2) => Node::Subf(u(0)?, f(1)?),
+        ("Mul", 2) => Node::Mul(u(0)?, u(1)?),
+        ("Mulf", 2) => Node::Mulf(f(0)?, u(1)?),
+        ("Hadamard", 2) => Node::Hadamard(u(0)?, u(1)?),
+        ("Fma", 3) => Node::Fma(u(0)?, u(1)?, u(2)?),
+        ("Select", 3) => Node::Select(u(0)?, u(1)?, u(2)?),
+        ("Transpose", 1) => Node::Transpose(u(0)?),
+        ("Div", 2) => Node::Div(u(0)?, u(1)?),
+        ("Pow", 2) => Node::Pow(u(0)?, u(1)?),
+        ("Powf", 2) => Node::Powf(u(0)?, f(1)?),
+        ("Powi", 2) => Node::Powi(u(0)?, args[1].parse().ok()?),
+        ("Neg", 1) => Node::Neg(u(0)?),
+        ("Recip", 1) => Node::Recip(u(0)?),
+        ("Exp", 1) => Node::Exp(u(0)?),
+        ("Ln", 1) => Node::Ln(u(0)?),
+        ("Sin", 1) => Node::Sin(u(0)?),
+        ("Cos", 1) => Node::Cos(u(0)?),
+        ("Tan", 1) => Node::Tan(u(0)?),
+        ("Sinh", 1) => Node::Sinh(u(0)?),
+        ("Cosh", 1) => Node::Cosh(u(0)?),
+        ("Tanh", 1) => Node::Tanh(u(0)?),
+        ("Sigmoid", 1) => Node::Sigmoid(u(0)?),
+        ("ReLU", 1) => Node::ReLU(u(0)?),
+        ("Heaviside", 1) => Node::Heaviside(u(0)?),
+        ("Custom", 2) => Node::Custom(u(0)?, u(1)?),
+        ("CustomBinary", 3) => Node::CustomBinary(u(0)?, u(1)?, u(2)?),
+        ("Arg", 1) => Node::Arg(u(0)?),
+        ("External", 3) => Node::External(u(0)?, u(1)?, u(2)?),
+        _ => return None,
+    };
+    Some(node)
+}
@@ -0,0 +1,546 @@
+use crate::core::Graph;
+use crate::optim::var_values;
+use crate::util::gradient_cached;
+
+// ┌──────────────────────────────────────────────────────────┐
+//  Gradient-based MCMC
+// └──────────────────────────────────────────────────────────┘
+/// Draws of a sampler run (see `Hmc::sample` & `Nuts::sample`)
+#[derive(Debug, Clone)]
+pub struct Chain {
+    /// Post-warmup draws in the order of variables (same as `get_vars`)
+    pub samples: Vec<Vec<f64>>,
+    /// Log-density of every draw
+    pub log_density: Vec<f64>,
+    /// Mean acceptance statistic after warmup
+    pub accept_rate: f64,
+    /// Adapted step size
+    pub step_size: f64,
+    /// Adapted diagonal inverse mass matrix (posterior variance estimate)
+    pub inv_mass: Vec<f64>,
+    /// Post-warmup transitions whose energy error blew up
+    pub divergences: usize,
+    /// Mean tree depth after warmup (`NUTS` only, zero for `HMC`)
+    pub mean_depth: f64,
+}
+
+/// Warmup & adaptation settings shared by `Hmc` & `Nuts`
+///
+/// The step size is tuned by dual averaging towards `target_accept`. With `adapt_mass` and at
+/// least 150 warmup iterations, a diagonal inverse mass matrix is estimated in Stan-style
+/// doubling windows (75 initial & 50 final iterations are left to the step size alone).
+#[derive(Debug, Clone)]
+pub struct Warmup {
+    pub iterations: usize,
+    pub target_accept: f64,
+    pub adapt_mass: bool,
+    /// Initial step size (found by doubling/halving from `1` if `None`)
+    pub step_size: Option<f64>,
+    pub seed: u64,
+}
+
+impl Default for Warmup {
+    fn default() -> Self {
+        Warmup {
+            iterations: 1000,
+            target_accept: 0.8,
+            adapt_mass: true,
+            step_size: None,
+            seed: 42,
+        }
+    }
+}
+
+/// Hamiltonian Monte Carlo with a fixed number of leapfrog steps
+#[derive(Debug, Clone)]
+pub struct Hmc {
+    pub n_leapfrog: usize,
+    pub warmup: Warmup,
+}
+
+/// No-U-Turn sampler (multinomial trajectory sampling, Hoffman & Gelman 2014 / Betancourt 2017)
+#[derive(Debug, Clone)]
+pub struct Nuts {
+    pub max_depth: usize,
+    pub warmup: Warmup,
+}
+
+impl Hmc {
+    pub fn new(n_leapfrog: usize) -> Self {
+        Hmc {
+            n_leapfrog,
+            warmup: Warmup::default(),
+        }
+    }
+
+    pub fn with_warmup(mut self, warmup: Warmup) -> Self {
+        self.warmup = warmup;
+        self
+    }
+
+    /// Draw `n` samples from the density whose log is the compiled expression of `graph`
+    ///
+    /// The chain starts at the current variables, which must have a finite log-density.
+    pub fn sample(&self, graph: &mut Graph<f64>, n: usize) -> Chain {
+        run(graph, &self.warmup, n, |ctx, state, eps| {
+            let p0 = ctx.momentum();
+            let h0 = ctx.hamiltonian(state, &p0);
+            let mut next = State {
+                p: p0,
+                ..state.clone()
+            };
+            for _ in 0..self.n_leapfrog {
+                next = ctx.leapfrog(&next, eps);
+                if !next.logp.is_finite() {
+                    break;
+                }
+            }
+            let delta = ctx.hamiltonian(&next, &next.p) - h0;
+            let accept = if delta.is_nan() { 0f64 } else { (-delta).exp().min(1f64) };
+            let divergent = delta.is_nan() || delta >= 1000f64;
+            let moved = ctx.rng.uniform() < accept;
+            Transition {
+                state: if moved { next } else { state.clone() },
+                accept,
+                divergent,
+                depth: 0,
+            }
+        })
+    }
+}
+
+impl Nuts {
+    pub fn new() -> Self {
+        Nuts {
+            max_depth: 10,
+            warmup: Warmup::default(),
+        }
+    }
+
+    pub fn with_max_depth(mut self, max_depth: usize) -> Self {
+        self.max_depth = max_depth;
+        self
+    }
+
+    pub fn with_warmup(mut self, warmup: Warmup) -> Self {
+        self.warmup = warmup;
+        self
+    }
+
+    /// Draw `n` samples from the density whose log is the compiled expression of `graph`
+    ///
+    /// The chain starts at the current variables, which must have a finite log-density.
+    pub fn sample(&self, graph: &mut Graph<f64>, n: usize) -> Chain {
+        run(graph, &self.warmup, n, |ctx, state, eps| {
+            let p0 = ctx.momentum();
+            let h0 = ctx.hamiltonian(state, &p0);
+            let start = State {
+                p: p0,
+                ..state.clone()
+            };
+            let (mut minus, mut plus) = (start.clone(), start.clone());
+            let mut proposal = state.clone();
+            let mut log_w = 0f64;
+            let (mut sum_accept, mut n_accept) = (0f64, 0usize);
+            let mut divergent = false;
+            let mut depth = 0;
+            while depth < self.max_depth {
+                let forward = ctx.rng.uniform() < 0.5;
+                let edge = if forward { &plus } else { &minus };
+                let tree = ctx.build_tree(edge, if forward { eps } else { -eps }, depth, h0);
+                depth += 1;
+                sum_accept += tree.sum_accept;
+                n_accept += tree.n_accept;
+                divergent |= tree.divergent;
+                if tree.stop {
+                    break;
+                }
+                // Biased progressive sampling favours the new subtree
+                if ctx.rng.uniform().ln() < tree.log_w - log_w {
+                    proposal = State {
+                        p: vec![],
+                        ..tree.proposal.clone()
+                    };
+                }
+                log_w = log_add_exp(log_w, tree.log_w);
+                if forward {
+                    plus = tree.plus;
+                } else {
+                    minus = tree.minus;
+                }
+                if ctx.u_turn(&minus, &plus) {
+                    break;
+                }
+            }
+            Transition {
+                state: proposal,
+                accept: if n_accept == 0 { 0f64 } else { sum_accept / n_accept as f64 },
+                divergent,
+                depth,
+            }
+        })
+    }
+}
+
+impl Default for Nuts {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// ┌──────────────────────────────────────────────────────────┐
+//  Internals
+// └──────────────────────────────────────────────────────────┘
+/// Position, momentum, log-density & its gradient
+#[derive(Debug, Clone)]
+struct State {
+    x: Vec<f64>,
+    p: Vec<f64>,
+    logp: f64,
+    grad: Vec<f64>,
+}
+
+struct Transition {
+    state: State,
+    accept: f64,
+    divergent: bool,
+    depth: usize,
+}
+
+struct Tree {
+    minus: State,
+    plus: State,
+    proposal: State,
+    /// Log of the summed weights `exp(-H)` relative to the initial energy
+    log_w: f64,
+    sum_accept: f64,
+    n_accept: usize,
+    divergent: bool,
+    stop: bool,
+}
+
+struct Context<'a> {
+    graph: &'a mut Graph<f64>,
+    inv_mass: Vec<f64>,
+    rng: Rng,
+}
+
+impl Context<'_> {
+    fn state(&mut self, x: Vec<f64>, p: Vec<f64>) -> State {
+        let (logp, grad) = gradient_cached(self.graph, &x);
+        let logp = if logp.is_nan() { f64::NEG_INFINITY } else { logp };
+        State { x, p, logp, grad }
+    }
+
+    /// `p ~ N(0, M)` with `M = diag(1 / inv_mass)`
+    fn momentum(&mut self) -> Vec<f64> {
+        let inv_mass = self.inv_mass.clone();
+        inv_mass.iter().map(|m| self.rng.normal() / m.sqrt()).collect()
+    }
+
+    fn hamiltonian(&self, state: &State, p: &[f64]) -> f64 {
+        let kinetic = p.iter().zip(&self.inv_mass).map(|(p, m)| m * p * p).sum::<f64>();
+        0.5 * kinetic - state.logp
+    }
+
+    fn leapfrog(&mut self, state: &State, eps: f64) -> State {
+        let half = state.p.iter().zip(&state.grad).map(|(p, g)| p + 0.5 * eps * g).collect::<Vec<_>>();
+        let x = state
+            .x
+            .iter()
+            .zip(&half)
+            .zip(&self.inv_mass)
+            .map(|((x, p), m)| x + eps * m * p)
+            .collect::<Vec<_>>();
+        let mut next = self.state(x, half);
+        let grad = next.grad.clone();
+        next.p.iter_mut().zip(&grad).for_each(|(p, g)| *p += 0.5 * eps * g);
+        next
+    }
+
+    fn u_turn(&self, minus: &State, plus: &State) -> bool {
+        let dx = plus.x.iter().zip(&minus.x).map(|(a, b)| a - b).collect::<Vec<_>>();
+        let velocity = |p: &[f64]| dx.iter().zip(p).zip(&self.inv_mass).map(|((d, p), m)| d * m * p).sum::<f64>();
+        velocity(&minus.p) < 0f64 || velocity(&plus.p) < 0f64
+    }
+
+    /// Subtree of `2^depth` leapfrog steps of size `eps` (negative to go backward) from `edge`
+    fn build_tree(&mut self, edge: &State, eps: f64, depth: usize, h0: f64) -> Tree {
+        if depth == 0 {
+            let next = self.leapfrog(edge, eps);
+            let delta = self.hamiltonian(&next, &next.p) - h0;
+            let delta = if delta.is_nan() { f64::INFINITY } else { delta };
+            let divergent = delta > 1000f64;
+            return Tree {
+                minus: next.clone(),
+                plus: next.clone(),
+                proposal: next,
+                log_w: -delta,
+                sum_accept: (-delta).exp().min(1f64),
+                n_accept: 1,
+                divergent,
+                stop: divergent,
+            };
+        }
+        let first = self.build_tree(edge, eps, depth - 1, h0);
+        if first.stop {
+            return first;
+        }
+        let outer = if eps > 0f64 { &first.plus } else { &first.minus };
+        let second = self.build_tree(&outer.clone(), eps, depth - 1, h0);
+        let log_w = log_add_exp(first.log_w, second.log_w);
+        let take_second = !second.stop && self.rng.uniform().ln() < second.log_w - log_w;
+        let (minus, plus) = if eps > 0f64 {
+            (first.minus, second.plus)
+        } else {
+            (second.minus, first.plus)
+        };
+        let stop = second.stop || self.u_turn(&minus, &plus);
+        Tree {
+            proposal: if take_second { second.proposal } else { first.proposal },
+            minus,
+            plus,
+            log_w,
+            sum_accept: first.sum_accept + second.sum_accept,
+            n_accept: first.n_accept + second.n_accept,
+            divergent: second.divergent,
+            stop,
+        }
+    }
+}
+
+/// Warmup with step size & mass adaptation, then `n` draws
+fn run<F: FnMut(&mut Context, &State, f64) -> Transition>(
+    graph: &mut Graph<f64>,
+    warmup: &Warmup,
+    n: usize,
+    mut transition: F,
+) -> Chain {
+    let x0 = var_values(graph);
+    let dim = x0.len();
+    let mut ctx = Context {
+        graph,
+        inv_mass: vec![1f64; dim],
+        rng: Rng::new(warmup.seed),
+    };
+    let mut state = ctx.state(x0, vec![]);
+    assert!(state.logp.is_finite(), "Log-density is not finite at the initial point");
+
+    let mut eps = warmup.step_size.unwrap_or_else(|| initial_step_size(&mut ctx, &state));
+    let mut dual = DualAveraging::new(eps, warmup.target_accept);
+    let windows = if warmup.adapt_mass && warmup.iterations >= 150 {
+        mass_windows(warmup.iterations)
+    } else {
+        vec![]
+    };
+    let mut welford = Welford::new(dim);
+
+    for m in 0..warmup.iterations {
+        let t = transition(&mut ctx, &state, eps);
+        state = t.state;
+        eps = dual.update(t.accept);
+        if let Some((start, end)) = windows.iter().find(|(s, e)| (*s..*e).contains(&m)) {
+            welford.push(&state.x);
+            if m + 1 == *end {
+                let count = (end - start) as f64;
+                ctx.inv_mass = welford
+                    .variance()
+                    .iter()
+                    .map(|v| (count / (count + 5f64)) * v + 1e-3 * (5f64 / (count + 5f64)))
+                    .collect();
+                welford = Welford::new(dim);
+                eps = initial_step_size(&mut ctx, &state);
+                dual = DualAveraging::new(eps, warmup.target_accept);
+            }
+        }
+    }
+    if warmup.iterations > 0 {
+        eps = dual.final_step_size();
+    }
+
+    let mut chain = Chain {
+        samples: Vec::with_capacity(n),
+        log_density: Vec::with_capacity(n),
+        accept_rate: 0f64,
+        step_size: eps,
+        inv_mass: ctx.inv_mass.clone(),
+        divergences: 0,
+        mean_depth: 0f64,
+    };
+    for _ in 0..n {
+        let t = transition(&mut ctx, &state, eps);
+        state = t.state;
+        chain.accept_rate += t.accept / n as f64;
+        chain.mean_depth += t.depth as f64 / n as f64;
+        chain.divergences += t.divergent as usize;
+        chain.samples.push(state.x.clone());
+        chain.log_density.push(state.logp);
+    }
+    chain
+}
+
+/// Step size at which one leapfrog step crosses acceptance probability `1/2`
+fn initial_step_size(ctx: &mut Context, state: &State) -> f64 {
+    let mut eps = 1f64;
+    let p = ctx.momentum();
+    let start = State {
+        p: p.clone(),
+        ..state.clone()
+    };
+    let h0 = ctx.hamiltonian(state, &p);
+    let log_accept = |ctx: &mut Context, eps: f64| {
+        let next = ctx.leapfrog(&start, eps);
+        let delta = ctx.hamiltonian(&next, &next.p) - h0;
+        if delta.is_nan() { f64::NEG_INFINITY } else { -delta }
+    };
+    let direction = if log_accept(ctx, eps) > 0.5f64.ln() { 1f64 } else { -1f64 };
+    for _ in 0..100 {
+        if direction * log_accept(ctx, eps) <= direction * 0.5f64.ln() {
+            break;
+        }
+        eps *= 2f64.powf(direction);
+    }
+    eps
+}
+
+/// Nesterov dual averaging of `log ε` (Hoffman & Gelman 2014, Algorithm 5)
+struct DualAveraging {
+    mu: f64,
+    target: f64,
+    h_bar: f64,
+    log_eps: f64,
+    log_eps_bar: f64,
+    m: f64,
+}
+
+impl DualAveraging {
+    fn new(eps: f64, target: f64) -> Self {
+        DualAveraging {
+            mu: (10f64 * eps).ln(),
+            target,
+            h_bar: 0f64,
+            log_eps: eps.ln(),
+            log_eps_bar: 0f64,
+            m: 0f64,
+        }
+    }
+
+    fn update(&mut self, accept: f64) -> f64 {
+        const GAMMA: f64 = 0.05;
+        const T0: f64 = 10f64;
+        const KAPPA: f64 = 0.75;
+        self.m += 1f64;
+        let w = 1f64 / (self.m + T0);
+        self.h_bar = (1f64 - w) * self.h_bar + w * (self.target - accept);
+        self.log_eps = self.mu - self.m.sqrt() / GAMMA * self.h_bar;
+        let eta = self.m.powf(-KAPPA);
+        self.log_eps_bar = eta * self.log_eps + (1f64 - eta) * self.log_eps_bar;
+        self.log_eps.exp()
+    }
+
+    fn final_step_size(&self) -> f64 {
+        self.log_eps_bar.exp()
+    }
+}
+
+/// Mass adaptation windows `[start, end)`: 75 initial iterations, doubling windows from 25, and
+/// 50 final iterations left out
+fn mass_windows(iterations: usize) -> Vec<(usize, usize)> {
+    let (init, term) = (75, 50);
+    let last = iterations - term;
+    let mut windows = vec![];
+    let (mut start, mut size) = (init, 25);
+    while start < last {
+        let mut end = start + size;
+        // Stretch the final window over what would be too short for another one
+        if end + 2 * size > last {
+            end = last;
+        }
+        windows.push((start, end));
+        start = end;
+        size *= 2;
+    }
+    windows
+}
+
+/// Running mean & variance
+struct Welford {
+    count: f64,
+    mean: Vec<f64>,
+    m2: Vec<f64>,
+}
+
+impl Welford {
+    fn new(dim: usize) -> Self {
+        Welford {
+            count: 0f64,
+            mean: vec![0f64; dim],
+            m2: vec![0f64; dim],
+        }
+    }
+
+    fn push(&mut self, x: &[f64]) {
+        self.count += 1f64;
+        for ((mean, m2), x) in self.mean.iter_mut().zip(self.m2.iter_mut()).zip(x) {
+            let d = x - *mean;
+            *mean += d / self.count;
+            *m2 += d * (x - *mean);
+        }
+    }
+
+    fn variance(&self) -> Vec<f64> {
+        self.m2.iter().map(|m2| m2 / (self.count - 1f64).max(1f64)).collect()
+    }
+}
+
+fn log_add_exp(a: f64, b: f64) -> f64 {
+    let max = a.max(b);
+    if max == f64::NEG_INFINITY {
+        return max;
+    }
+    max + ((a - max).exp() + (b - max).exp()).ln()
+}
+
+/// xoshiro256** seeded by splitmix64
+#[derive(Debug, Clone)]
+pub(crate) struct Rng {
+    s: [u64; 4],
+}
+
+impl Rng {
+    pub(crate) fn new(seed: u64) -> Self {
+        let mut z = seed;
+        let s = std::array::from_fn(|_| {
+            z = z.wrapping_add(0x9E3779B97F4A7C15);
+            let mut x = z;
+            x = (x ^ (x >> 30)).wrapping_mul(0xBF58476D1CE4E5B9);
+            x = (x ^ (x >> 27)).wrapping_mul(0x94D049BB133111EB);
+            x ^ (x >> 31)
+        });
+        Rng { s }
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        let result = self.s[1].wrapping_mul(5).rotate_left(7).wrapping_mul(9);
+        let t = self.s[1] << 17;
+        self.s[2] ^= self.s[0];
+        self.s[3] ^= self.s[1];
+        self.s[1] ^= self.s[2];
+        self.s[0] ^= self.s[3];
+        self.s[2] ^= t;
+        self.s[3] = self.s[3].rotate_left(45);
+        result
+    }
+
+    /// Uniform on `[0, 1)`
+    pub(crate) fn uniform(&mut self) -> f64 {
+        (self.next_u64() >> 11) as f64 * (1f64 / (1u64 << 53) as f64)
+    }
+
+    /// Standard normal by Box-Muller
+    pub(crate) fn normal(&mut self) -> f64 {
+        let u = 1f64 - self.uniform();
+        let v = self.uniform();
+        (-2f64 * u.ln()).sqrt() * (2f64 * std::f64::consts::PI * v).cos()
+    }
+}
@@ -0,0 +1,432 @@
+use crate::core::{Graph, Node};
+use std::collections::BTreeSet;
+
+// ┌──────────────────────────────────────────────────────────┐
+//  Sparsity patterns
+// └──────────────────────────────────────────────────────────┘
+/// Structurally nonzero entries of a matrix, stored as sorted column indices per row
+///
+/// Patterns are conservative: an entry outside the pattern is zero at every point, while an
+/// entry inside may still vanish at a particular point (e.g. `x * y` at `y = 0`).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SparsityPattern {
+    pub n_rows: usize,
+    pub n_cols: usize,
+    pub rows: Vec<Vec<usize>>,
+}
+
+impl SparsityPattern {
+    /// Number of structural nonzeros
+    pub fn nnz(&self) -> usize {
+        self.rows.iter().map(|row| row.len()).sum()
+    }
+
+    /// `nnz / (n_rows · n_cols)`
+    pub fn density(&self) -> f64 {
+        self.nnz() as f64 / (self.n_rows * self.n_cols).max(1) as f64
+    }
+
+    pub fn contains(&self, row: usize, col: usize) -> bool {
+        self.rows[row].binary_search(&col).is_ok()
+    }
+
+    /// `(row, col)` of every structural nonzero in row-major order
+    pub fn entries(&self) -> Vec<(usize, usize)> {
+        self.rows
+            .iter()
+            .enumerate()
+            .flat_map(|(i, row)| row.iter().map(move |j| (i, *j)))
+            .collect()
+    }
+
+    /// Columns with a structural nonzero in each row (`pattern.transpose().rows[j]` holds the rows of column `j`)
+    pub fn transpose(&self) -> SparsityPattern {
+        let mut rows = vec![vec![]; self.n_cols];
+        for (i, row) in self.rows.iter().enumerate() {
+            for &j in row {
+                rows[j].push(i);
+            }
+        }
+        SparsityPattern {
+            n_rows: self.n_cols,
+            n_cols: self.n_rows,
+            rows,
+        }
+    }
+
+    /// Maximum number of nonzeros in a row
+    pub fn max_row_nnz(&self) -> usize {
+        self.rows.iter().map(|row| row.len()).max().unwrap_or(0)
+    }
+
+    /// Color of every column such that no two columns of a color share a row
+    ///
+    /// Greedy coloring of the column intersection graph, visiting the densest columns first. The
+    /// number of colors is at least `max_row_nnz` (e.g. `2b + 1` for a banded matrix of
+    /// half-bandwidth `b`) and usually close to it.
+    pub fn color_columns(&self) -> Vec<usize> {
+        let columns = self.transpose();
+        let mut order = (0..self.n_cols).collect::<Vec<_>>();
+        order.sort_by_key(|j| std::cmp::Reverse(columns.rows[*j].len()));
+
+        let mut colors = vec![usize::MAX; self.n_cols];
+        // forbidden[c] == j marks color c as taken by a neighbour of column j
+        let mut forbidden = vec![usize::MAX; self.n_cols.max(1)];
+        for j in order {
+            for &i in &columns.rows[j] {
+                for &k in &self.rows[i] {
+                    if colors[k] != usize::MAX {
+                        forbidden[colors[k]] = j;
+                    }
+                }
+            }
+            colors[j] = (0..).find(|c| forbidden[*c] != j).unwrap();
+        }
+        colors
+    }
+}
+
+/// Sparse matrix in compressed sparse row (CSR) form
+#[derive(Debug, Clone, PartialEq)]
+pub struct SparseMatrix {
+    pub n_rows: usize,
+    pub n_cols: usize,
+    /// Entries of row `i` are at `row_ptr[i]..row_ptr[i + 1]`
+    pub row_ptr: Vec<usize>,
+    pub col_idx: Vec<usize>,
+    pub values: Vec<f64>,
+}
+
+impl SparseMatrix {
+    /// Zero matrix with the structure of `pattern`
+    pub fn from_pattern(pattern: &SparsityPattern) -> Self {
+        let mut row_ptr = Vec::with_capacity(pattern.n_rows + 1);
+        row_ptr.push(0);
+        for row in &pattern.rows {
+            row_ptr.push(row_ptr.last().unwrap() + row.len());
+        }
+        SparseMatrix {
+            n_rows: pattern.n_rows,
+            n_cols: pattern.n_cols,
+            row_ptr,
+            col_idx: pattern.rows.concat(),
+            values: vec![0f64; pattern.nnz()],
+        }
+    }
+
+    pub fn nnz(&self) -> usize {
+        self.values.len()
+    }
+
+    /// Entry `(i, j)` (zero outside the structure)
+    pub fn get(&self, i: usize, j: usize) -> f64 {
+        let range = self.row_ptr[i]..self.row_ptr[i + 1];
+        match self.col_idx[range.clone()].binary_search(&j) {
+            Ok(k) => self.values[range.start + k],
+            Err(_) => 0f64,
+        }
+    }
+
+    /// `(row, col, value)` of every stored entry in row-major order
+    pub fn triplets(&self) -> Vec<(usize, usize, f64)> {
+        (0..self.n_rows)
+            .flat_map(|i| (self.row_ptr[i]..self.row_ptr[i + 1]).map(move |k| (i, k)))
+            .map(|(i, k)| (i, self.col_idx[k], self.values[k]))
+            .collect()
+    }
+
+    pub fn to_dense(&self) -> Vec<Vec<f64>> {
+        let mut dense = vec![vec![0f64; self.n_cols]; self.n_rows];
+        for (i, j, value) in self.triplets() {
+            dense[i][j] = value;
+        }
+        dense
+    }
+
+    /// `A·x`
+    pub fn mul_vec(&self, x: &[f64]) -> Vec<f64> {
+        assert_eq!(x.len(), self.n_cols);
+        (0..self.n_rows)
+            .map(|i| {
+                (self.row_ptr[i]..self.row_ptr[i + 1])
+                    .map(|k| self.values[k] * x[self.col_idx[k]])
+                    .sum()
+            })
+            .collect()
+    }
+
+    /// Stored entries grouped by column color: `(position in values, row, col)` per color
+    fn by_color(&self, colors: &[usize]) -> Vec<Vec<(usize, usize, usize)>> {
+        let n_colors = colors.iter().max().map_or(0, |c| c + 1);
+        let mut groups = vec![vec![]; n_colors];
+        for i in 0..self.n_rows {
+            for k in self.row_ptr[i]..self.row_ptr[i + 1] {
+                let j = self.col_idx[k];
+                groups[colors[j]].push((k, i, j));
+            }
+        }
+        groups
+    }
+}
+
+impl<T> Graph<T> {
+    /// Sparsity of the Jacobian of the outputs (rows, in the order of `get_outputs`) w.r.t. the
+    /// variables (columns, in the order of `get_vars`)
+    ///
+    /// Variable-dependency bitsets are propagated along the tape; a set is released after its
+    /// last consumer, so memory stays proportional to the live part of the tape. Nodes whose
+    /// derivative vanishes almost everywhere (`Heaviside`, the condition of `Select`) do not
+    /// propagate dependencies.
+    pub fn jacobian_sparsity(&self) -> SparsityPattern {
+        let outputs = self.sparsity_roots();
+        let n_vars = self.value_ics.len();
+        let deps = self.propagate_dependencies(&outputs, |_, _| {});
+        SparsityPattern {
+            n_rows: outputs.len(),
+            n_cols: n_vars,
+            rows: outputs.iter().map(|i| deps[*i].iter().collect()).collect(),
+        }
+    }
+
+    /// Sparsity of the Hessian of the compiled output (symmetric, every row stored)
+    ///
+    /// Each nonlinear node reachable from the output couples the dependencies of its operands:
+    /// `x * y` couples `deps(x) × deps(y)`, `exp(x)` couples `deps(x) × deps(x)` and so on, while
+    /// linear nodes (`Add`, `Mulf`, `ReLU`, ...) couple nothing.
+    pub fn hessian_sparsity(&self) -> SparsityPattern {
+        let root = self.compiled.expect("Graph is not compiled");
+        let n_vars = self.value_ics.len();
+        let reached = self.reached_from(root);
+        let mut rows = vec![BTreeSet::new(); n_vars];
+        let couple = |rows: &mut Vec<BTreeSet<usize>>, a: &BitSet, b: &BitSet| {
+            for i in a.iter() {
+                rows[i].extend(b.iter());
+            }
+            for j in b.iter() {
+                rows[j].extend(a.iter());
+            }
+        };
+        self.propagate_dependencies(&[], |index, deps| {
+            if !reached[index] {
+                return;
+            }
+            let d = |i: &usize| &deps[*i];
+            match &self.nodes.node(index) {
+                Node::Mul(l, r) | Node::Hadamard(l, r) | Node::Fma(l, r, _) => couple(&mut rows, d(l), d(r)),
+                Node::Div(l, r) => {
+                    couple(&mut rows, d(l), d(r));
+                    couple(&mut rows, d(r), d(r));
+                }
+                Node::Powi(_, k) if *k == 0 || *k == 1 => {}
+                Node::Powf(_, p) if *p == 0f64 || *p == 1f64 => {}
+                Node::Recip(i)
+                | Node::Exp(i)
+                | Node::Ln(i)
+                | Node::Sin(i)
+                | Node::Cos(i)
+                | Node::Tan(i)
+                | Node::Sinh(i)
+                | Node::Cosh(i)
+                | Node::Tanh(i)
+                | Node::Sigmoid(i)
+                | Node::Powi(i, _)
+                | Node::Powf(i, _)
+                | Node::Custom(_, i) => couple(&mut rows, d(i), d(i)),
+                Node::Pow(..) | Node::CustomBinary(..) | Node::External(..) => {
+                    let all = &deps[index];
+                    couple(&mut rows, all, all);
+                }
+                _ => {}
+            }
+        });
+        SparsityPattern {
+            n_rows: n_vars,
+            n_cols: n_vars,
+            rows: rows.into_iter().map(|row| row.into_iter().collect()).collect(),
+        }
+    }
+
+    /// Compiled outputs (or the default output alone)
+    fn sparsity_roots(&self) -> Vec<usize> {
+        if self.outputs.is_empty() {
+            vec![self.compiled.expect("Graph is not compiled")]
+        } else {
+            self.outputs.clone()
+        }
+    }
+
+    /// Nodes on which `root` depends
+    pub(crate) fn reached_from(&self, root: usize) -> Vec<bool> {
+        let mut reached = vec![false; self.nodes.len()];
+        reached[root] = true;
+        for index in self.topological_sort().into_iter().rev() {
+            if reached[index] {
+                for operand in self.nodes.node(index).operand_iter() {
+                    reached[operand] = true;
+                }
+            }
+        }
+        reached
+    }
+
+    /// Forward sweep of dependency sets in topological order
+    ///
+    /// `visit(index, deps)` sees the set of `index` and those of its operands before any of them
+    /// is released. Sets of `keep` nodes are never released & are returned with the others emptied.
+    fn propagate_dependencies<F: FnMut(usize, &[BitSet])>(&self, keep: &[usize], mut visit: F) -> Vec<BitSet> {
+        let n_vars = self.value_ics.len();
+        let mut position = vec![usize::MAX; self.nodes.len()];
+        for (k, var) in self.value_ics.iter().enumerate() {
+            position[*var] = k;
+        }
+        // Passes such as `reduce_powi` append operands after their node, so index order is not enough
+        let order = self.topological_sort();
+        let mut last_use = (0..self.nodes.len()).collect::<Vec<_>>();
+        for &index in order.iter() {
+            for operand in self.nodes.node(index).operand_iter() {
+                last_use[operand] = index;
+            }
+        }
+        for &index in keep {
+            last_use[index] = usize::MAX;
+        }
+
+        let empty = BitSet::new(0);
+        let mut deps = vec![empty.clone(); self.nodes.len()];
+        for index in order {
+            let node = self.nodes.node(index);
+            let mut set = BitSet::new(n_vars);
+            match node {
+                Node::Var(_) if position[index] != usize::MAX => set.insert(position[index]),
+                Node::Heaviside(_) => {}
+                Node::Select(_, a, b) => {
+                    set.union_with(&deps[a]);
+                    set.union_with(&deps[b]);
+                }
+                _ => {
+                    for operand in node.operand_iter() {
+                        set.union_with(&deps[operand]);
+                    }
+                }
+            }
+            deps[index] = set;
+            visit(index, &deps);
+            for operand in node.operand_iter() {
+                if last_use[operand] == index {
+                    deps[operand] = empty.clone();
+                }
+            }
+            if last_use[index] == index {
+                deps[index] = empty.clone();
+            }
+        }
+        deps
+    }
+}
+
+/// Fixed-size set of variable positions
+#[derive(Debug, Clone)]
+struct BitSet {
+    words: Vec<u64>,
+}
+
+impl BitSet {
+    fn new(len: usize) -> Self {
+        BitSet {
+            words: vec![0; len.div_ceil(64)],
+        }
+    }
+
+    fn insert(&mut self, i: usize) {
+        self.words[i / 64] |= 1 << (i % 64);
+    }
+
+    /// Union in place (released sets are empty & have no words)
+    fn union_with(&mut self, other: &BitSet) {
+        for (a, b) in self.words.iter_mut().zip(&other.words) {
+            *a |= b;
+        }
+    }
+
+    fn iter(&self) -> impl Iterator<Item = usize> + '_ {
+        self.words.iter().enumerate().flat_map(|(k, word)| {
+            let mut word = *word;
+            std::iter::from_fn(move || {
+                if word == 0 {
+                    return None;
+                }
+                let bit = word.trailing_zeros() as usize;
+                word &= word - 1;
+                Some(64 * k + bit)
+            })
+        })
+    }
+}
+
+// ┌──────────────────────────────────────────────────────────┐
+//  Compressed Jacobians
+// └──────────────────────────────────────────────────────────┘
+impl Graph<f64> {
+    /// Jacobian of the outputs at the current variables, with one forward tangent sweep per color
+    ///
+    /// Columns sharing no row are seeded together (`color_columns`), so a banded Jacobian costs
+    /// `O(bandwidth)` sweeps however many variables there are.
+    pub fn sparse_jacobian(&mut self) -> SparseMatrix {
+        let pattern = self.jacobian_sparsity();
+        self.sparse_jacobian_with(&pattern)
+    }
+
+    /// `sparse_jacobian` with a precomputed pattern (e.g. reused across Newton iterations)
+    pub fn sparse_jacobian_with(&mut self, pattern: &SparsityPattern) -> SparseMatrix {
+        let colors = pattern.color_columns();
+        let mut jac = SparseMatrix::from_pattern(pattern);
+        let mut seed = vec![0f64; pattern.n_cols];
+        for (color, entries) in jac.by_color(&colors).into_iter().enumerate() {
+            for (s, c) in seed.iter_mut().zip(&colors) {
+                *s = if *c == color { 1f64 } else { 0f64 };
+            }
+            let compressed = self.jvp(&seed);
+            for (k, i, _) in entries {
+                jac.values[k] = compressed[i];
+            }
+        }
+        jac
+    }
+}
+
+// ┌──────────────────────────────────────────────────────────┐
+//  Compressed Hessians
+// └──────────────────────────────────────────────────────────┘
+impl Graph<f64> {
+    /// Hessian of the compiled output at the current variables, stored only at structural nonzeros
+    ///
+    /// Columns are colored on `hessian_sparsity` and each color costs one Hessian-vector product,
+    /// so a dense `n × n` matrix is never formed. Both triangles are stored (use `triplets` and
+    /// keep `i <= j` for the upper one) and symmetrized like `hessian`.
+    pub fn sparse_hessian(&mut self) -> SparseMatrix {
+        let pattern = self.hessian_sparsity();
+        self.sparse_hessian_with(&pattern)
+    }
+
+    /// `sparse_hessian` with a precomputed (symmetric) pattern
+    pub fn sparse_hessian_with(&mut self, pattern: &SparsityPattern) -> SparseMatrix {
+        let colors = pattern.color_columns();
+        let mut hess = SparseMatrix::from_pattern(pattern);
+        let mut seed = vec![0f64; pattern.n_cols];
+        for (color, entries) in hess.by_color(&colors).into_iter().enumerate() {
+            for (s, c) in seed.iter_mut().zip(&colors) {
+                *s = if *c == color { 1f64 } else { 0f64 };
+            }
+            let compressed = self.hvp(&seed);
+            for (k, i, _) in entries {
+                hess.values[k] = compressed[i];
+            }
+        }
+        let symmetric = (0..hess.n_rows)
+            .flat_map(|i| (hess.row_ptr[i]..hess.row_ptr[i + 1]).map(move |k| (i, k)))
+            .map(|(i, k)| 0.5 * (hess.values[k] + hess.get(hess.col_idx[k], i)))
+            .collect();
+        hess.values = symmetric;
+        hess
+    }
+}
@@ -0,0 +1,197 @@
+use crate::core::{Graph, Node};
+use std::collections::BTreeMap;
+use std::mem::size_of;
+use std::ops::Add;
+
+// ┌──────────────────────────────────────────────────────────┐
+//  Graph statistics
+// └──────────────────────────────────────────────────────────┘
+/// Size & shape of a graph (see `Graph::stats`)
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GraphStats {
+    pub nodes: usize,
+    /// Node count per operation (see `Node::name`)
+    pub by_op: BTreeMap<&'static str, usize>,
+    /// Longest operand chain from a leaf (leaves have depth 0)
+    pub depth: usize,
+    pub vars: usize,
+    pub params: usize,
+    pub outputs: usize,
+    /// Estimated bytes of the tape, values, gradients & schedules (shallow: heap storage of `T`
+    /// itself is not counted)
+    pub memory: usize,
+}
+
+impl<T> Graph<T> {
+    pub fn stats(&self) -> GraphStats {
+        let mut by_op = BTreeMap::new();
+        for node in self.nodes.iter() {
+            *by_op.entry(node.name()).or_insert(0) += 1;
+        }
+
+        // Passes may append operands after their node, so follow the topological order
+        let mut depths = vec![0usize; self.nodes.len()];
+        for index in self.topological_sort() {
+            depths[index] = self
+                .nodes
+                .node(index)
+                .operand_iter()
+                .map(|i| depths[i] + 1)
+                .max()
+                .unwrap_or(0);
+        }
+
+        let orders = self.topological_order.as_ref().map_or(0, |x| x.len())
+            + self.reverse_order.as_ref().map_or(0, |x| x.len());
+        let memory = self.nodes.bytes()
+            + self.buffer.len() * size_of::<Option<T>>()
+            + self.gradients.len() * size_of::<T>()
+            + self.grad_mask.as_ref().map_or(0, |x| x.len())
+            + self.requires_grad.len()
+            + orders * size_of::<usize>();
+
+        GraphStats {
+            nodes: self.nodes.len(),
+            by_op,
+            depth: depths.into_iter().max().unwrap_or(0),
+            vars: self.value_ics.len(),
+            params: self.param_ics.len(),
+            outputs: self.outputs.len(),
+            memory,
+        }
+    }
+}
+
+// ┌──────────────────────────────────────────────────────────┐
+//  Cost estimate
+// └──────────────────────────────────────────────────────────┘
+/// Scalar operation counts of a sweep (see `Graph::cost_estimate`)
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct OpCounts {
+    /// Additions, subtractions & negations
+    pub adds: usize,
+    pub muls: usize,
+    /// Divisions & reciprocals
+    pub divs: usize,
+    /// `exp`, `ln`, trigonometric & hyperbolic functions, `sigmoid` and real powers
+    pub transcendentals: usize,
+    /// Comparisons & branches (`ReLU`, `Heaviside`, `Select`) and custom op calls
+    pub other: usize,
+}
+
+impl OpCounts {
+    /// Arithmetic operations of any kind (`other` excluded)
+    pub fn flops(&self) -> usize {
+        self.adds + self.muls + self.divs + self.transcendentals
+    }
+}
+
+impl Add for OpCounts {
+    type Output = OpCounts;
+
+    fn add(self, rhs: OpCounts) -> OpCounts {
+        OpCounts {
+            adds: self.adds + rhs.adds,
+            muls: self.muls + rhs.muls,
+            divs: self.divs + rhs.divs,
+            transcendentals: self.transcendentals + rhs.transcendentals,
+            other: self.other + rhs.other,
+        }
+    }
+}
+
+/// Hardware-independent cost of one forward & one backward sweep (see `Graph::cost_estimate`)
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct CostEstimate {
+    pub forward: OpCounts,
+    pub backward: OpCounts,
+}
+
+impl CostEstimate {
+    pub fn total(&self) -> OpCounts {
+        self.forward + self.backward
+    }
+}
+
+/// `(adds, muls, divs, transcendentals, other)` of evaluating a node & of propagating its adjoint
+fn node_cost(node: &Node) -> ([usize; 5], [usize; 5]) {
+    // Square & multiply
+    let powi = |n: i32| {
+        let n = n.unsigned_abs().max(1);
+        (n.ilog2() + n.count_ones() - 1) as usize
+    };
+    match *node {
+        Node::Var(_) | Node::Param(_) | Node::Const(_) => ([0; 5], [0; 5]),
+        Node::Add(..) | Node::Sub(..) => ([1, 0, 0, 0, 0], [2, 0, 0, 0, 0]),
+        Node::Addf(..) | Node::Subf(..) | Node::Neg(_) => ([1, 0, 0, 0, 0], [1, 0, 0, 0, 0]),
+        Node::Mul(..) | Node::Hadamard(..) => ([0, 1, 0, 0, 0], [2, 2, 0, 0, 0]),
+        Node::Mulf(..) => ([0, 1, 0, 0, 0], [1, 1, 0, 0, 0]),
+        Node::Fma(..) => ([1, 1, 0, 0, 0], [3, 2, 0, 0, 0]),
+        Node::Select(..) => ([0, 0, 0, 0, 1], [2, 0, 0, 0, 2]),
+        Node::Transpose(_) | Node::Arg(_) => ([0; 5], [1, 0, 0, 0, 0]),
+        Node::Div(..) => ([0, 0, 1, 0, 0], [2, 2, 2, 0, 0]),
+        Node::Recip(_) => ([0, 0, 1, 0, 0], [1, 1, 1, 0, 0]),
+        Node::Pow(..) => ([0, 0, 0, 1, 0], [3, 4, 0, 2, 0]),
+        Node::Powf(..) => ([0, 0, 0, 1, 0], [1, 2, 0, 1, 0]),
+        Node::Powi(_, n) => (
+            [0, powi(n), (n < 0) as usize, 0, 0],
+            [1, powi(n - 1) + 2, (n < 1) as usize, 0, 0],
+        ),
+        Node::Ln(_) => ([0, 0, 0, 1, 0], [1, 0, 1, 0, 0]),
+        Node::Exp(_) | Node::Sin(_) | Node::Cos(_) | Node::Sinh(_) | Node::Cosh(_) => {
+            ([0, 0, 0, 1, 0], [1, 1, 0, 1, 0])
+        }
+        Node::Tan(_) => ([0, 0, 0, 1, 0], [2, 2, 0, 1, 0]),
+        Node::Tanh(_) | Node::Sigmoid(_) => ([0, 0, 0, 1, 0], [3, 2, 0, 1, 0]),
+        Node::ReLU(_) => ([0, 0, 0, 0, 1], [1, 1, 0, 0, 1]),
+        Node::Heaviside(_) => ([0, 0, 0, 0, 1], [0; 5]),
+        Node::Custom(..) => ([0, 0, 0, 0, 1], [1, 1, 0, 0, 1]),
+        Node::CustomBinary(..) => ([0, 0, 0, 0, 1], [2, 2, 0, 0, 2]),
+        Node::External(_, _, n) => ([0, 0, 0, 0, 1], [n, n, 0, 0, n]),
+    }
+}
+
+impl From<[usize; 5]> for OpCounts {
+    fn from(x: [usize; 5]) -> Self {
+        OpCounts {
+            adds: x[0],
+            muls: x[1],
+            divs: x[2],
+            transcendentals: x[3],
+            other: x[4],
+        }
+    }
+}
+
+impl<T> Graph<T> {
+    /// Operation counts of one forward & one backward sweep, per scalar element
+    ///
+    /// The forward sweep evaluates every node of the tape; the backward sweep propagates the
+    /// adjoints of the nodes reached from the compiled output (restricted to the current
+    /// `grad_mask`, as `backward` does), including the accumulation into operands. Counts follow
+    /// the formulas of `backward` rather than any particular hardware, so they are meant to
+    /// compare formulations of a model, not to predict timings. With matrix values, each product
+    /// counts as one operation.
+    pub fn cost_estimate(&self) -> CostEstimate {
+        let mut estimate = CostEstimate::default();
+        for node in self.nodes.iter() {
+            estimate.forward = estimate.forward + node_cost(&node).0.into();
+        }
+        let Some(root) = self.compiled else {
+            return estimate;
+        };
+        let active = self.grad_mask.as_ref().filter(|mask| mask.len() == self.nodes.len());
+        let mut reached = vec![false; self.nodes.len()];
+        let mut stack = vec![root];
+        while let Some(index) = stack.pop() {
+            if reached[index] || active.is_some_and(|mask| !mask[index]) {
+                continue;
+            }
+            reached[index] = true;
+            let node = &self.nodes.node(index);
+            estimate.backward = estimate.backward + node_cost(node).1.into();
+            stack.extend(node.operands());
+        }
+        estimate
+    }
+}
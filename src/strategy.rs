@@ -0,0 +1,208 @@
+use crate::core::{rc, Expr};
+use ::proptest::prelude::*;
+
+// ┌──────────────────────────────────────────────────────────┐
+//  proptest strategies
+// └──────────────────────────────────────────────────────────┘
+/// Unary operation of generated expressions
+///
+/// `Ln`, `Sqrt` & `Recip` are applied to `x^2 + 1` so generated trees stay inside their domain.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UnaryOp {
+    Neg,
+    Exp,
+    Ln,
+    Sqrt,
+    Recip,
+    Square,
+    Sin,
+    Cos,
+    Tan,
+    Sinh,
+    Cosh,
+    Tanh,
+    Sigmoid,
+    ReLU,
+    /// `Addf` with a generated constant
+    Addf,
+    /// `Mulf` with a generated constant
+    Mulf,
+}
+
+/// Binary operation of generated expressions
+///
+/// `Div` divides by `r^2 + 1` and `Pow` raises `l^2 + 1` for the same reason as `UnaryOp::Ln`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BinaryOp {
+    Add,
+    Sub,
+    Mul,
+    Div,
+    Pow,
+}
+
+impl UnaryOp {
+    /// Smooth ops which keep values bounded over moderate inputs
+    pub fn smooth() -> Vec<UnaryOp> {
+        use UnaryOp::*;
+        vec![Neg, Ln, Sqrt, Recip, Square, Sin, Cos, Tanh, Sigmoid, Addf, Mulf]
+    }
+
+    pub fn all() -> Vec<UnaryOp> {
+        use UnaryOp::*;
+        vec![Neg, Exp, Ln, Sqrt, Recip, Square, Sin, Cos, Tan, Sinh, Cosh, Tanh, Sigmoid, ReLU, Addf, Mulf]
+    }
+
+    pub fn apply(self, x: Expr, c: f64) -> Expr {
+        let x = rc(x);
+        match self {
+            UnaryOp::Neg => Expr::Neg(x),
+            UnaryOp::Exp => Expr::Exp(x),
+            UnaryOp::Ln => Expr::Ln(rc(positive(x))),
+            UnaryOp::Sqrt => Expr::Powf(rc(positive(x)), 0.5),
+            UnaryOp::Recip => Expr::Recip(rc(positive(x))),
+            UnaryOp::Square => Expr::Powi(x, 2),
+            UnaryOp::Sin => Expr::Sin(x),
+            UnaryOp::Cos => Expr::Cos(x),
+            UnaryOp::Tan => Expr::Tan(x),
+            UnaryOp::Sinh => Expr::Sinh(x),
+            UnaryOp::Cosh => Expr::Cosh(x),
+            UnaryOp::Tanh => Expr::Tanh(x),
+            UnaryOp::Sigmoid => Expr::Sigmoid(x),
+            UnaryOp::ReLU => Expr::ReLU(x),
+            UnaryOp::Addf => Expr::Addf(c, x),
+            UnaryOp::Mulf => Expr::Mulf(c, x),
+        }
+    }
+}
+
+impl BinaryOp {
+    pub fn all() -> Vec<BinaryOp> {
+        use BinaryOp::*;
+        vec![Add, Sub, Mul, Div, Pow]
+    }
+
+    pub fn apply(self, l: Expr, r: Expr) -> Expr {
+        let (l, r) = (rc(l), rc(r));
+        match self {
+            BinaryOp::Add => Expr::Add(l, r),
+            BinaryOp::Sub => Expr::Sub(l, r),
+            BinaryOp::Mul => Expr::Mul(l, r),
+            BinaryOp::Div => Expr::Div(l, rc(positive(r))),
+            BinaryOp::Pow => Expr::Pow(rc(positive(l)), r),
+        }
+    }
+}
+
+/// `x^2 + 1`
+fn positive(x: std::rc::Rc<Expr>) -> Expr {
+    Expr::Addf(1f64, rc(Expr::Powi(x, 2)))
+}
+
+/// Shape of the expressions generated by `expr`
+///
+/// Trees are over `Symbol(0)..Symbol(n_vars)`, so they compile against a graph whose first
+/// `n_vars` nodes are its variables (e.g. a fresh graph after `symbols(n_vars)`).
+#[derive(Debug, Clone)]
+pub struct ExprConfig {
+    pub n_vars: usize,
+    /// Maximum nesting of operations
+    pub depth: u32,
+    /// Target number of nodes (see `Strategy::prop_recursive`)
+    pub size: u32,
+    pub unary: Vec<UnaryOp>,
+    pub binary: Vec<BinaryOp>,
+    /// Relative weights of unary & binary ops
+    pub weights: (u32, u32),
+    /// Range of constants (leaves & literals of `Addf`/`Mulf`)
+    pub consts: (f64, f64),
+}
+
+impl ExprConfig {
+    /// Smooth ops with depth 4, up to 32 nodes & constants in `[-2, 2]`
+    pub fn new(n_vars: usize) -> Self {
+        assert!(n_vars > 0, "Expressions need at least one variable");
+        ExprConfig {
+            n_vars,
+            depth: 4,
+            size: 32,
+            unary: UnaryOp::smooth(),
+            binary: BinaryOp::all(),
+            weights: (1, 1),
+            consts: (-2f64, 2f64),
+        }
+    }
+
+    pub fn with_depth(mut self, depth: u32) -> Self {
+        self.depth = depth;
+        self
+    }
+
+    pub fn with_size(mut self, size: u32) -> Self {
+        self.size = size;
+        self
+    }
+
+    pub fn with_unary(mut self, ops: &[UnaryOp]) -> Self {
+        self.unary = ops.to_vec();
+        self
+    }
+
+    pub fn with_binary(mut self, ops: &[BinaryOp]) -> Self {
+        self.binary = ops.to_vec();
+        self
+    }
+
+    /// Relative weights of unary & binary ops (zero disables a kind)
+    pub fn with_weights(mut self, unary: u32, binary: u32) -> Self {
+        self.weights = (unary, binary);
+        self
+    }
+
+    pub fn with_consts(mut self, lo: f64, hi: f64) -> Self {
+        assert!(lo < hi, "Empty range of constants");
+        self.consts = (lo, hi);
+        self
+    }
+}
+
+/// Random well-formed expression trees
+///
+/// Leaves are symbols (3 in 4) or constants; each interior node is a unary or binary op of the
+/// config. Deep `Exp`, `Sinh`, `Cosh` & `Tan` chains can still overflow, so properties comparing
+/// values should skip non-finite results (`prop_assume!`).
+pub fn expr(config: &ExprConfig) -> BoxedStrategy<Expr> {
+    let (lo, hi) = config.consts;
+    let leaf = prop_oneof![
+        3 => (0..config.n_vars).prop_map(Expr::Symbol),
+        1 => (lo..hi).prop_map(Expr::Const),
+    ];
+    let (unary, binary) = (config.unary.clone(), config.binary.clone());
+    let (w_unary, w_binary) = match (unary.is_empty(), binary.is_empty()) {
+        (true, true) => return leaf.boxed(),
+        (true, false) => (0, config.weights.1.max(1)),
+        (false, true) => (config.weights.0.max(1), 0),
+        (false, false) => config.weights,
+    };
+    assert!(w_unary + w_binary > 0, "Every kind of op is disabled");
+    leaf.prop_recursive(config.depth, config.size, 2, move |inner| {
+        let mut arms = Vec::new();
+        if w_unary > 0 {
+            let node = (::proptest::sample::select(unary.clone()), inner.clone(), lo..hi)
+                .prop_map(|(op, x, c)| op.apply(x, c));
+            arms.push((w_unary, node.boxed()));
+        }
+        if w_binary > 0 {
+            let node = (::proptest::sample::select(binary.clone()), inner.clone(), inner)
+                .prop_map(|(op, l, r)| op.apply(l, r));
+            arms.push((w_binary, node.boxed()));
+        }
+        ::proptest::strategy::Union::new_weighted(arms)
+    })
+    .boxed()
+}
+
+/// Random points in `[lo, hi]^n_vars` to evaluate generated expressions at
+pub fn point(n_vars: usize, lo: f64, hi: f64) -> BoxedStrategy<Vec<f64>> {
+    ::proptest::collection::vec(lo..hi, n_vars).boxed()
+}
@@ -0,0 +1,441 @@
+use crate::core::{rc, Expr};
+use crate::traits::ActivationFunction;
+use peroxide_num::{ExpLogOps, PowOps, TrigOps};
+use std::collections::HashMap;
+
+// ┌──────────────────────────────────────────────────────────┐
+//  Smart constructors (skip trivial terms while building)
+// └──────────────────────────────────────────────────────────┘
+fn is_const(expr: &Expr, value: f64) -> bool {
+    matches!(expr, Expr::Const(x) if *x == value)
+}
+
+fn add(a: Expr, b: Expr) -> Expr {
+    if is_const(&a, 0.0) {
+        b
+    } else if is_const(&b, 0.0) {
+        a
+    } else {
+        a + b
+    }
+}
+
+fn sub(a: Expr, b: Expr) -> Expr {
+    if is_const(&b, 0.0) {
+        a
+    } else if is_const(&a, 0.0) {
+        -b
+    } else {
+        a - b
+    }
+}
+
+fn mul(a: Expr, b: Expr) -> Expr {
+    if is_const(&a, 0.0) || is_const(&b, 0.0) {
+        Expr::Const(0.0)
+    } else if is_const(&a, 1.0) {
+        b
+    } else if is_const(&b, 1.0) {
+        a
+    } else {
+        a * b
+    }
+}
+
+fn scale(num: f64, a: Expr) -> Expr {
+    if num == 0.0 || is_const(&a, 0.0) {
+        Expr::Const(0.0)
+    } else if num == 1.0 {
+        a
+    } else {
+        Expr::Mulf(num, rc(a))
+    }
+}
+
+// ┌──────────────────────────────────────────────────────────┐
+//  Symbolic differentiation
+// └──────────────────────────────────────────────────────────┘
+impl Expr {
+    /// Symbolic derivative with respect to `Symbol(var)`
+    ///
+    /// The result is an ordinary `Expr`, so it can be compiled like any other expression.
+    /// Scalar semantics are assumed (`Mul` is treated as commutative).
+    pub fn diff(&self, var: usize) -> Expr {
+        match self {
+            Expr::Symbol(index) => Expr::Const(if *index == var { 1.0 } else { 0.0 }),
+            Expr::Const(_) => Expr::Const(0.0),
+            Expr::Add(l, r) => add(l.diff(var), r.diff(var)),
+            Expr::Sub(l, r) => sub(l.diff(var), r.diff(var)),
+            Expr::Addf(_, x) | Expr::Subf(x, _) => x.diff(var),
+            Expr::Mulf(num, x) => scale(*num, x.diff(var)),
+            Expr::Mul(l, r) => add(
+                mul(l.diff(var), Expr::clone(r)),
+                mul(Expr::clone(l), r.diff(var)),
+            ),
+            Expr::Hadamard(l, r) => {
+                let dl = l.diff(var);
+                let dr = r.diff(var);
+                let left = if is_const(&dl, 0.0) {
+                    Expr::Const(0.0)
+                } else {
+                    Expr::Hadamard(rc(dl), r.clone())
+                };
+                let right = if is_const(&dr, 0.0) {
+                    Expr::Const(0.0)
+                } else {
+                    Expr::Hadamard(l.clone(), rc(dr))
+                };
+                add(left, right)
+            }
+            Expr::Div(l, r) => {
+                // (l / r)' = l' / r - l r' / r^2
+                let dl = l.diff(var);
+                let dr = r.diff(var);
+                let left = if is_const(&dl, 0.0) {
+                    Expr::Const(0.0)
+                } else {
+                    dl / Expr::clone(r)
+                };
+                let right = mul(mul(Expr::clone(l), dr), r.powi(-2));
+                sub(left, right)
+            }
+            Expr::Pow(l, r) => {
+                // (l^r)' = l^r (r' ln l + r l' / l)
+                let dl = l.diff(var);
+                let dr = r.diff(var);
+                let left = mul(dr, l.ln());
+                let right = if is_const(&dl, 0.0) {
+                    Expr::Const(0.0)
+                } else {
+                    mul(Expr::clone(r), dl) / Expr::clone(l)
+                };
+                mul(self.clone(), add(left, right))
+            }
+            Expr::Powf(x, p) => mul(scale(*p, x.powf(*p - 1.0)), x.diff(var)),
+            Expr::Powi(x, n) => {
+                let dx = x.diff(var);
+                let dpow = match *n {
+                    0 => Expr::Const(0.0),
+                    1 => Expr::Const(1.0),
+                    2 => scale(2.0, Expr::clone(x)),
+                    _ => scale(*n as f64, x.powi(*n - 1)),
+                };
+                mul(dpow, dx)
+            }
+            Expr::Neg(x) => {
+                let dx = x.diff(var);
+                if is_const(&dx, 0.0) {
+                    dx
+                } else {
+                    -dx
+                }
+            }
+            Expr::Recip(x) => {
+                let dx = x.diff(var);
+                if is_const(&dx, 0.0) {
+                    dx
+                } else {
+                    -mul(dx, x.powi(-2))
+                }
+            }
+            Expr::Exp(x) => mul(self.clone(), x.diff(var)),
+            Expr::Ln(x) => {
+                let dx = x.diff(var);
+                if is_const(&dx, 0.0) {
+                    dx
+                } else {
+                    dx / Expr::clone(x)
+                }
+            }
+            Expr::Sin(x) => mul(x.cos(), x.diff(var)),
+            Expr::Cos(x) => {
+                let dx = x.diff(var);
+                if is_const(&dx, 0.0) {
+                    dx
+                } else {
+                    -mul(x.sin(), dx)
+                }
+            }
+            Expr::Tan(x) => mul(1f64 + self.powi(2), x.diff(var)),
+            Expr::Sinh(x) => mul(x.cosh(), x.diff(var)),
+            Expr::Cosh(x) => mul(x.sinh(), x.diff(var)),
+            Expr::Tanh(x) => {
+                let dx = x.diff(var);
+                if is_const(&dx, 0.0) {
+                    dx
+                } else {
+                    // 1 - tanh^2 = -(tanh^2 - 1)
+                    mul(-(self.powi(2) - 1f64), dx)
+                }
+            }
+            Expr::Sigmoid(x) => {
+                let dx = x.diff(var);
+                if is_const(&dx, 0.0) {
+                    dx
+                } else {
+                    // s (1 - s) = s - s^2
+                    mul(self.clone() - self.powi(2), dx)
+                }
+            }
+            Expr::ReLU(x) => mul(x.heaviside_zero(), x.diff(var)),
+            Expr::Heaviside(_) => Expr::Const(0.0),
+            Expr::Select(c, a, b) => c.select(a.diff(var), b.diff(var)),
+        }
+    }
+
+    /// Symbolic gradient with respect to `vars`
+    pub fn grad(&self, vars: &[usize]) -> Vec<Expr> {
+        vars.iter().map(|var| self.diff(*var)).collect()
+    }
+}
+
+// ┌──────────────────────────────────────────────────────────┐
+//  Simplification
+// └──────────────────────────────────────────────────────────┘
+/// Evaluate a node whose operands are all constants
+fn fold(expr: &Expr) -> Option<f64> {
+    let c = |x: &Expr| match x {
+        Expr::Const(value) => Some(*value),
+        _ => None,
+    };
+    let value = match expr {
+        Expr::Symbol(_) | Expr::Const(_) => return None,
+        Expr::Add(l, r) => c(l)? + c(r)?,
+        Expr::Sub(l, r) => c(l)? - c(r)?,
+        Expr::Mul(l, r) | Expr::Hadamard(l, r) => c(l)? * c(r)?,
+        Expr::Div(l, r) => c(l)? / c(r)?,
+        Expr::Pow(l, r) => c(l)?.powf(c(r)?),
+        Expr::Addf(num, x) => c(x)? + num,
+        Expr::Subf(x, num) => c(x)? - num,
+        Expr::Mulf(num, x) => c(x)? * num,
+        Expr::Powf(x, p) => c(x)?.powf(*p),
+        Expr::Powi(x, n) => c(x)?.powi(*n),
+        Expr::Neg(x) => -c(x)?,
+        Expr::Recip(x) => 1.0 / c(x)?,
+        Expr::Exp(x) => c(x)?.exp(),
+        Expr::Ln(x) => c(x)?.ln(),
+        Expr::Sin(x) => c(x)?.sin(),
+        Expr::Cos(x) => c(x)?.cos(),
+        Expr::Tan(x) => c(x)?.tan(),
+        Expr::Sinh(x) => c(x)?.sinh(),
+        Expr::Cosh(x) => c(x)?.cosh(),
+        Expr::Tanh(x) => c(x)?.tanh(),
+        Expr::Sigmoid(x) => c(x)?.sigmoid(),
+        Expr::ReLU(x) => c(x)?.relu(),
+        Expr::Heaviside(x) => c(x)?.heaviside_zero(),
+        Expr::Select(cond, a, b) => {
+            if c(cond)?.is_sign_positive() {
+                c(a)?
+            } else {
+                c(b)?
+            }
+        }
+    };
+    Some(value)
+}
+
+/// Split `x^p` into `(x, p)` (`p = 1` for anything else)
+fn base_exponent(expr: &Expr) -> (&Expr, f64, bool) {
+    match expr {
+        Expr::Powi(x, n) => (x, *n as f64, true),
+        Expr::Powf(x, p) => (x, *p, false),
+        _ => (expr, 1.0, true),
+    }
+}
+
+/// Whether `expr` is known to be non-negative
+fn nonnegative(expr: &Expr) -> bool {
+    match expr {
+        Expr::Const(c) => *c >= 0.0,
+        Expr::Powi(_, n) => n % 2 == 0,
+        Expr::Exp(_) | Expr::Cosh(_) | Expr::Sigmoid(_) | Expr::ReLU(_) => true,
+        _ => false,
+    }
+}
+
+/// Apply a single local rewrite to a node whose children are already simplified
+fn rewrite(expr: &Expr) -> Option<Expr> {
+    if let Some(value) = fold(expr) {
+        return Some(Expr::Const(value));
+    }
+    let simplified = match expr {
+        // Identities
+        Expr::Add(l, r) => match (&**l, &**r) {
+            (Expr::Const(a), _) if *a == 0.0 => Expr::clone(r),
+            (_, Expr::Const(b)) if *b == 0.0 => Expr::clone(l),
+            (Expr::Const(a), _) => Expr::Addf(*a, r.clone()),
+            (_, Expr::Const(b)) => Expr::Addf(*b, l.clone()),
+            _ => return None,
+        },
+        Expr::Sub(l, r) => match (&**l, &**r) {
+            (_, Expr::Const(b)) if *b == 0.0 => Expr::clone(l),
+            (Expr::Const(a), _) if *a == 0.0 => Expr::Neg(r.clone()),
+            (_, Expr::Const(b)) => Expr::Subf(l.clone(), *b),
+            _ => return None,
+        },
+        Expr::Addf(num, x) | Expr::Subf(x, num) if *num == 0.0 => Expr::clone(x),
+        Expr::Addf(a, x) => match &**x {
+            Expr::Addf(b, y) => Expr::Addf(a + b, y.clone()),
+            Expr::Subf(y, b) => Expr::Addf(a - b, y.clone()),
+            _ => return None,
+        },
+        Expr::Subf(x, a) => match &**x {
+            Expr::Addf(b, y) => Expr::Addf(b - a, y.clone()),
+            Expr::Subf(y, b) => Expr::Subf(y.clone(), a + b),
+            _ => return None,
+        },
+        Expr::Mul(l, r) => match (&**l, &**r) {
+            (Expr::Const(a), _) | (_, Expr::Const(a)) if *a == 0.0 => Expr::Const(0.0),
+            (Expr::Const(a), _) if *a == 1.0 => Expr::clone(r),
+            (_, Expr::Const(b)) if *b == 1.0 => Expr::clone(l),
+            (Expr::Const(a), _) => Expr::Mulf(*a, r.clone()),
+            (_, Expr::Const(b)) => Expr::Mulf(*b, l.clone()),
+            (Expr::Mulf(a, x), _) => Expr::Mulf(*a, rc(Expr::Mul(x.clone(), r.clone()))),
+            (_, Expr::Mulf(b, y)) => Expr::Mulf(*b, rc(Expr::Mul(l.clone(), y.clone()))),
+            _ => {
+                // Power merging: x^a * x^b = x^(a + b)
+                let (lb, le, li) = base_exponent(l);
+                let (rb, re, ri) = base_exponent(r);
+                if lb != rb {
+                    return None;
+                }
+                if li && ri {
+                    Expr::Powi(rc(lb.clone()), (le + re) as i32)
+                } else {
+                    Expr::Powf(rc(lb.clone()), le + re)
+                }
+            }
+        },
+        Expr::Mulf(num, _) if *num == 0.0 => Expr::Const(0.0),
+        Expr::Mulf(num, x) if *num == 1.0 => Expr::clone(x),
+        Expr::Mulf(a, x) => match &**x {
+            Expr::Mulf(b, y) => Expr::Mulf(a * b, y.clone()),
+            Expr::Neg(y) => Expr::Mulf(-a, y.clone()),
+            _ => return None,
+        },
+        Expr::Div(l, r) => match (&**l, &**r) {
+            (Expr::Const(a), _) if *a == 0.0 => Expr::Const(0.0),
+            (_, Expr::Const(b)) if *b == 1.0 => Expr::clone(l),
+            (_, Expr::Const(b)) => Expr::Mulf(1.0 / b, l.clone()),
+            (Expr::Const(a), _) if *a == 1.0 => Expr::Recip(r.clone()),
+            _ => return None,
+        },
+        // Double negation
+        Expr::Neg(x) => match &**x {
+            Expr::Neg(y) => Expr::clone(y),
+            Expr::Mulf(a, y) => Expr::Mulf(-a, y.clone()),
+            _ => return None,
+        },
+        Expr::Recip(x) => match &**x {
+            Expr::Recip(y) => Expr::clone(y),
+            _ => return None,
+        },
+        // Powers
+        Expr::Powi(_, 0) => Expr::Const(1.0),
+        Expr::Powi(x, 1) => Expr::clone(x),
+        Expr::Powi(x, n) => match &**x {
+            Expr::Powi(y, m) => Expr::Powi(y.clone(), n.checked_mul(*m)?),
+            _ => return None,
+        },
+        Expr::Powf(_, p) if *p == 0.0 => Expr::Const(1.0),
+        Expr::Powf(x, p) if *p == 1.0 => Expr::clone(x),
+        // (y^q)^p = y^(pq) only holds for integer exponents or a non-negative base
+        Expr::Powf(x, p) => match &**x {
+            Expr::Powf(y, q) if (p.fract() == 0.0 && q.fract() == 0.0) || nonnegative(y) => {
+                Expr::Powf(y.clone(), p * q)
+            }
+            Expr::Powi(y, m) if p.fract() == 0.0 || nonnegative(y) => Expr::Powf(y.clone(), p * *m as f64),
+            _ => return None,
+        },
+        _ => return None,
+    };
+    Some(simplified)
+}
+
+/// Rewrite until fixpoint; operands created by a rewrite are settled as well
+fn settle(expr: Expr) -> Expr {
+    let mut expr = expr;
+    while let Some(next) = rewrite(&expr) {
+        expr = next.map_children(|x| settle(x.clone()));
+    }
+    expr
+}
+
+impl Expr {
+    /// Simplify the expression before compiling it
+    ///
+    /// Eliminates identities (`x + 0`, `x * 1`, `x * 0`, ...), folds constants, removes double negations
+    /// and merges powers. Scalar semantics are assumed, so `x * 0` becomes `0` even if `x` is `NaN`.
+    pub fn simplify(&self) -> Expr {
+        settle(self.map_children(|x| x.simplify()))
+    }
+}
+
+// ┌──────────────────────────────────────────────────────────┐
+//  Direct evaluation
+// └──────────────────────────────────────────────────────────┘
+impl Expr {
+    /// Value of the expression with `Symbol(i)` bound to `values[&i]`, without building a graph
+    ///
+    /// Tree-walking interpreter with the scalar semantics of `forward`. Shared subtrees are
+    /// evaluated once and depth is only bounded by memory. Panics on an unbound symbol.
+    pub fn eval(&self, values: &HashMap<usize, f64>) -> f64 {
+        let mut stack: Vec<(&Expr, bool)> = vec![(self, false)];
+        let mut results: Vec<f64> = Vec::new();
+        let mut evaluated: HashMap<*const Expr, f64> = HashMap::new();
+        while let Some((expr, expanded)) = stack.pop() {
+            let key = expr as *const Expr;
+            if !expanded {
+                if let Some(value) = evaluated.get(&key) {
+                    results.push(*value);
+                    continue;
+                }
+                stack.push((expr, true));
+                stack.extend(expr.children().into_iter().rev().map(|child| (&**child, false)));
+                continue;
+            }
+            let arity = expr.children().len();
+            let args = results.split_off(results.len() - arity);
+            let value = match *expr {
+                Expr::Symbol(index) => match values.get(&index) {
+                    Some(value) => *value,
+                    None => panic!("Symbol {} has no value", index),
+                },
+                Expr::Const(value) => value,
+                Expr::Add(..) => args[0] + args[1],
+                Expr::Sub(..) => args[0] - args[1],
+                Expr::Mul(..) | Expr::Hadamard(..) => args[0] * args[1],
+                Expr::Div(..) => args[0] / args[1],
+                Expr::Pow(..) => args[0].powf(args[1]),
+                Expr::Addf(num, _) => args[0] + num,
+                Expr::Subf(_, num) => args[0] - num,
+                Expr::Mulf(num, _) => args[0] * num,
+                Expr::Powf(_, p) => args[0].powf(p),
+                Expr::Powi(_, n) => args[0].powi(n),
+                Expr::Neg(_) => -args[0],
+                Expr::Recip(_) => 1.0 / args[0],
+                Expr::Exp(_) => args[0].exp(),
+                Expr::Ln(_) => args[0].ln(),
+                Expr::Sin(_) => args[0].sin(),
+                Expr::Cos(_) => args[0].cos(),
+                Expr::Tan(_) => args[0].tan(),
+                Expr::Sinh(_) => args[0].sinh(),
+                Expr::Cosh(_) => args[0].cosh(),
+                Expr::Tanh(_) => args[0].tanh(),
+                Expr::Sigmoid(_) => args[0].sigmoid(),
+                Expr::ReLU(_) => args[0].relu(),
+                Expr::Heaviside(_) => args[0].heaviside_zero(),
+                Expr::Select(..) => {
+                    if args[0].is_sign_positive() {
+                        args[1]
+                    } else {
+                        args[2]
+                    }
+                }
+            };
+            evaluated.insert(key, value);
+            results.push(value);
+        }
+        results.pop().unwrap()
+    }
+}
@@ -0,0 +1,446 @@
+use crate::core::{eval_node, propagate_adjoint, Graph, Node};
+use crate::custom::CustomOp;
+use crate::traits::{ActivationFunction, Matrizable};
+use peroxide_num::Numeric;
+use std::mem::size_of;
+use std::ops::Div;
+use std::sync::Arc;
+
+// ┌──────────────────────────────────────────────────────────┐
+//  Compact node storage
+// └──────────────────────────────────────────────────────────┘
+/// Nodes of a graph as a struct of arrays with `u32` indices
+///
+/// Every node is an opcode byte, a `u32` operand and a `u64` payload (second & third operand
+/// packed as two `u32`, or the bits of an `f64` immediate), i.e. 13 bytes per node instead of the
+/// 32 bytes of a padded `Node`. Nodes are decoded into `Node` values on access, so tapes are
+/// limited to `u32::MAX` nodes.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct NodeStore {
+    ops: Vec<Op>,
+    lhs: Vec<u32>,
+    rhs: Vec<u64>,
+}
+
+/// Opcode of a compact node (see `NodeStore`)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+enum Op {
+    Var,
+    Param,
+    Const,
+    Add,
+    Addf,
+    Sub,
+    Subf,
+    Mul,
+    Mulf,
+    Hadamard,
+    Fma,
+    Select,
+    Transpose,
+    Div,
+    Pow,
+    Powf,
+    Powi,
+    Neg,
+    Recip,
+    Exp,
+    Ln,
+    Sin,
+    Cos,
+    Tan,
+    Sinh,
+    Cosh,
+    Tanh,
+    Sigmoid,
+    ReLU,
+    Heaviside,
+    Custom,
+    CustomBinary,
+    Arg,
+    External,
+}
+
+fn narrow(index: usize) -> u32 {
+    u32::try_from(index).expect("Tape is too long for u32 indices")
+}
+
+fn pack(x: usize, y: usize) -> u64 {
+    narrow(x) as u64 | (narrow(y) as u64) << 32
+}
+
+fn unpack(b: u64) -> (usize, usize) {
+    ((b as u32) as usize, (b >> 32) as usize)
+}
+
+fn encode(node: &Node) -> (Op, u32, u64) {
+    let i = |x: usize| narrow(x) as u64;
+    match *node {
+        Node::Var(x) => (Op::Var, narrow(x), 0),
+        Node::Param(x) => (Op::Param, narrow(x), 0),
+        Node::Const(x) => (Op::Const, 0, x.to_bits()),
+        Node::Add(l, r) => (Op::Add, narrow(l), i(r)),
+        Node::Addf(x, a) => (Op::Addf, narrow(a), x.to_bits()),
+        Node::Sub(l, r) => (Op::Sub, narrow(l), i(r)),
+        Node::Subf(a, x) => (Op::Subf, narrow(a), x.to_bits()),
+        Node::Mul(l, r) => (Op::Mul, narrow(l), i(r)),
+        Node::Mulf(x, a) => (Op::Mulf, narrow(a), x.to_bits()),
+        Node::Hadamard(l, r) => (Op::Hadamard, narrow(l), i(r)),
+        Node::Fma(x, y, z) => (Op::Fma, narrow(x), pack(y, z)),
+        Node::Select(c, x, y) => (Op::Select, narrow(c), pack(x, y)),
+        Node::Transpose(a) => (Op::Transpose, narrow(a), 0),
+        Node::Div(l, r) => (Op::Div, narrow(l), i(r)),
+        Node::Pow(l, r) => (Op::Pow, narrow(l), i(r)),
+        Node::Powf(a, x) => (Op::Powf, narrow(a), x.to_bits()),
+        Node::Powi(a, k) => (Op::Powi, narrow(a), k as u32 as u64),
+        Node::Neg(a) => (Op::Neg, narrow(a), 0),
+        Node::Recip(a) => (Op::Recip, narrow(a), 0),
+        Node::Exp(a) => (Op::Exp, narrow(a), 0),
+        Node::Ln(a) => (Op::Ln, narrow(a), 0),
+        Node::Sin(a) => (Op::Sin, narrow(a), 0),
+        Node::Cos(a) => (Op::Cos, narrow(a), 0),
+        Node::Tan(a) => (Op::Tan, narrow(a), 0),
+        Node::Sinh(a) => (Op::Sinh, narrow(a), 0),
+        Node::Cosh(a) => (Op::Cosh, narrow(a), 0),
+        Node::Tanh(a) => (Op::Tanh, narrow(a), 0),
+        Node::Sigmoid(a) => (Op::Sigmoid, narrow(a), 0),
+        Node::ReLU(a) => (Op::ReLU, narrow(a), 0),
+        Node::Heaviside(a) => (Op::Heaviside, narrow(a), 0),
+        Node::Custom(id, a) => (Op::Custom, narrow(a), i(id)),
+        Node::CustomBinary(id, l, r) => (Op::CustomBinary, narrow(id), pack(l, r)),
+        Node::Arg(a) => (Op::Arg, narrow(a), 0),
+        Node::External(id, first, n) => (Op::External, narrow(id), pack(first, n)),
+    }
+}
+
+impl NodeStore {
+    pub fn new() -> Self {
+        NodeStore::default()
+    }
+
+    pub fn with_capacity(capacity: usize) -> Self {
+        NodeStore {
+            ops: Vec::with_capacity(capacity),
+            lhs: Vec::with_capacity(capacity),
+            rhs: Vec::with_capacity(capacity),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.ops.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.ops.is_empty()
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.ops.capacity()
+    }
+
+    /// Bytes held by the stored nodes (excluding spare capacity)
+    pub fn bytes(&self) -> usize {
+        self.len() * (size_of::<Op>() + size_of::<u32>() + size_of::<u64>())
+    }
+
+    /// Decode node `index` (panics if out of bounds)
+    #[inline]
+    pub fn node(&self, index: usize) -> Node {
+        let (a, b) = (self.lhs[index] as usize, self.rhs[index]);
+        let imm = f64::from_bits(b);
+        let r = b as usize;
+        match self.ops[index] {
+            Op::Var => Node::Var(a),
+            Op::Param => Node::Param(a),
+            Op::Const => Node::Const(imm),
+            Op::Add => Node::Add(a, r),
+            Op::Addf => Node::Addf(imm, a),
+            Op::Sub => Node::Sub(a, r),
+            Op::Subf => Node::Subf(a, imm),
+            Op::Mul => Node::Mul(a, r),
+            Op::Mulf => Node::Mulf(imm, a),
+            Op::Hadamard => Node::Hadamard(a, r),
+            Op::Fma => {
+                let (y, z) = unpack(b);
+                Node::Fma(a, y, z)
+            }
+            Op::Select => {
+                let (x, y) = unpack(b);
+                Node::Select(a, x, y)
+            }
+            Op::Transpose => Node::Transpose(a),
+            Op::Div => Node::Div(a, r),
+            Op::Pow => Node::Pow(a, r),
+            Op::Powf => Node::Powf(a, imm),
+            Op::Powi => Node::Powi(a, b as u32 as i32),
+            Op::Neg => Node::Neg(a),
+            Op::Recip => Node::Recip(a),
+            Op::Exp => Node::Exp(a),
+            Op::Ln => Node::Ln(a),
+            Op::Sin => Node::Sin(a),
+            Op::Cos => Node::Cos(a),
+            Op::Tan => Node::Tan(a),
+            Op::Sinh => Node::Sinh(a),
+            Op::Cosh => Node::Cosh(a),
+            Op::Tanh => Node::Tanh(a),
+            Op::Sigmoid => Node::Sigmoid(a),
+            Op::ReLU => Node::ReLU(a),
+            Op::Heaviside => Node::Heaviside(a),
+            Op::Custom => Node::Custom(r, a),
+            Op::CustomBinary => {
+                let (l, r) = unpack(b);
+                Node::CustomBinary(a, l, r)
+            }
+            Op::Arg => Node::Arg(a),
+            Op::External => {
+                let (first, n) = unpack(b);
+                Node::External(a, first, n)
+            }
+        }
+    }
+
+    pub fn get(&self, index: usize) -> Option<Node> {
+        (index < self.len()).then(|| self.node(index))
+    }
+
+    /// Whether node `index` is a variable or a parameter (without decoding it)
+    #[inline]
+    pub fn is_leaf(&self, index: usize) -> bool {
+        matches!(self.ops[index], Op::Var | Op::Param)
+    }
+
+    pub fn iter(&self) -> impl DoubleEndedIterator<Item = Node> + ExactSizeIterator + '_ {
+        (0..self.len()).map(|index| self.node(index))
+    }
+
+    pub fn to_vec(&self) -> Vec<Node> {
+        self.iter().collect()
+    }
+
+    pub fn push(&mut self, node: Node) {
+        let (op, a, b) = encode(&node);
+        self.ops.push(op);
+        self.lhs.push(a);
+        self.rhs.push(b);
+    }
+
+    /// Replace node `index`
+    pub fn set(&mut self, index: usize, node: Node) {
+        let (op, a, b) = encode(&node);
+        self.ops[index] = op;
+        self.lhs[index] = a;
+        self.rhs[index] = b;
+    }
+
+    pub fn truncate(&mut self, len: usize) {
+        self.ops.truncate(len);
+        self.lhs.truncate(len);
+        self.rhs.truncate(len);
+    }
+
+    pub fn clear(&mut self) {
+        self.truncate(0);
+    }
+
+    pub fn reserve(&mut self, additional: usize) {
+        self.ops.reserve(additional);
+        self.lhs.reserve(additional);
+        self.rhs.reserve(additional);
+    }
+}
+
+impl FromIterator<Node> for NodeStore {
+    fn from_iter<I: IntoIterator<Item = Node>>(iter: I) -> Self {
+        let mut nodes = NodeStore::new();
+        for node in iter {
+            nodes.push(node);
+        }
+        nodes
+    }
+}
+
+// ┌──────────────────────────────────────────────────────────┐
+//  Immutable tape & per-thread workspace
+// └──────────────────────────────────────────────────────────┘
+/// Compiled model shared between threads (nodes, schedule & roots)
+///
+/// Obtained from `Graph::tape`, which shares the graph's node storage & schedule instead of
+/// copying them (a graph recording further nodes copies on write). A `Tape` never changes, so
+/// it is `Send + Sync`; each thread evaluates it with its own `Workspace`.
+#[derive(Debug, Clone)]
+pub struct Tape {
+    nodes: Arc<NodeStore>,
+    order: Arc<Vec<usize>>,
+    value_ics: Vec<usize>,
+    outputs: Vec<usize>,
+    compiled: usize,
+    grad_mask: Option<Vec<bool>>,
+    custom_ops: Arc<Vec<CustomOp>>,
+}
+
+/// Values & adjoints of one evaluation of a `Tape`
+#[derive(Debug, Clone)]
+pub struct Workspace<T> {
+    buffer: Vec<Option<T>>,
+    gradients: Vec<T>,
+}
+
+impl<T: std::fmt::Debug + Numeric<f64> + Default + ActivationFunction + Matrizable> Graph<T>
+where
+    f64: Div<T, Output = T>,
+{
+    /// Immutable snapshot of the compiled tape
+    pub fn tape(&mut self) -> Tape {
+        if self.grad_mask.as_ref().is_none_or(|mask| mask.len() != self.nodes.len()) {
+            self.update_grad_mask();
+        }
+        self.schedule();
+        Tape {
+            nodes: Arc::clone(&self.nodes),
+            order: Arc::clone(self.topological_order.as_ref().unwrap()),
+            value_ics: self.value_ics.clone(),
+            outputs: self.outputs.clone(),
+            compiled: self.compiled.unwrap(),
+            grad_mask: self.grad_mask.clone(),
+            custom_ops: Arc::clone(&self.custom_ops),
+        }
+    }
+
+    /// Workspace holding the current values of variables & parameters
+    pub fn workspace(&self) -> Workspace<T> {
+        let buffer = self
+            .nodes
+            .iter()
+            .zip(self.buffer.iter())
+            .map(|(node, value)| match node {
+                Node::Var(_) | Node::Param(_) => value.clone(),
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+        Workspace {
+            buffer,
+            gradients: vec![T::default(); self.nodes.len()],
+        }
+    }
+}
+
+impl Tape {
+    pub fn get_vars(&self) -> Vec<usize> {
+        self.value_ics.clone()
+    }
+
+    pub fn get_outputs(&self) -> Vec<usize> {
+        self.outputs.clone()
+    }
+
+    /// Number of nodes
+    pub fn len(&self) -> usize {
+        self.nodes.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.nodes.is_empty()
+    }
+
+    /// Decode a node
+    pub fn node(&self, index: usize) -> Node {
+        self.nodes.node(index)
+    }
+
+    fn is_leaf(&self, index: usize) -> bool {
+        self.nodes.is_leaf(index)
+    }
+
+    /// Evaluation order
+    pub(crate) fn order(&self) -> impl DoubleEndedIterator<Item = usize> + '_ {
+        self.order.iter().copied()
+    }
+
+    pub(crate) fn root(&self) -> usize {
+        self.compiled
+    }
+
+    pub(crate) fn grad_mask(&self) -> Option<&[bool]> {
+        self.grad_mask.as_deref()
+    }
+
+    pub(crate) fn custom_ops(&self) -> &[CustomOp] {
+        &self.custom_ops
+    }
+
+    /// Substitute variables in the order of `get_vars`
+    pub fn subs_vars<T: Clone>(&self, ws: &mut Workspace<T>, vals: &[T]) {
+        assert_eq!(self.value_ics.len(), vals.len(), "Expected one value per variable");
+        for (i, val) in self.value_ics.iter().zip(vals) {
+            ws.buffer[*i] = Some(val.clone());
+        }
+    }
+
+    /// Evaluate every operation node and return the value of the compiled output
+    pub fn forward<T: std::fmt::Debug + Numeric<f64> + Default + ActivationFunction + Matrizable>(
+        &self,
+        ws: &mut Workspace<T>,
+    ) -> T
+    where
+        f64: Div<T, Output = T>,
+    {
+        for index in self.order() {
+            if !self.is_leaf(index) {
+                ws.buffer[index] = Some(eval_node(&self.node(index), &ws.buffer, index, &self.custom_ops));
+            }
+        }
+        ws.buffer[self.compiled].clone().unwrap()
+    }
+
+    /// Reverse sweep of the compiled output (after `forward` on the same workspace)
+    pub fn backward<T: std::fmt::Debug + Numeric<f64> + Default + ActivationFunction + Matrizable>(
+        &self,
+        ws: &mut Workspace<T>,
+    ) where
+        f64: Div<T, Output = T>,
+    {
+        let root = self.compiled;
+        for (grad, val) in ws.gradients.iter_mut().zip(ws.buffer.iter()) {
+            *grad = val.as_ref().unwrap().zeros_like();
+        }
+        ws.gradients[root] = ws.buffer[root].as_ref().unwrap().ones_like();
+
+        let mut reached = vec![false; self.len()];
+        reached[root] = true;
+        for index in self.order().rev() {
+            if !reached[index] || self.grad_mask.as_ref().is_some_and(|mask| !mask[index]) {
+                continue;
+            }
+            let node = self.node(index);
+            for child_index in node.operand_iter() {
+                reached[child_index] = true;
+            }
+            propagate_adjoint(&node, &ws.buffer, &mut ws.gradients, index, &self.custom_ops);
+        }
+
+        // Discard adjoints leaked into pruned operands
+        if let Some(mask) = self.grad_mask.as_ref() {
+            for (grad, active) in ws.gradients.iter_mut().zip(mask) {
+                if !active {
+                    *grad = grad.zeros_like();
+                }
+            }
+        }
+    }
+
+    /// Gradients of the variables in the order of `get_vars`
+    pub fn get_gradients<T: Clone>(&self, ws: &Workspace<T>) -> Vec<T> {
+        self.value_ics.iter().map(|x| ws.gradients[*x].clone()).collect()
+    }
+}
+
+impl<T: Clone> Workspace<T> {
+    pub fn get_value(&self, index: usize) -> Option<T> {
+        self.buffer[index].clone()
+    }
+
+    pub fn get_gradient(&self, index: usize) -> T {
+        self.gradients[index].clone()
+    }
+}
@@ -0,0 +1,274 @@
+use crate::core::{Graph, Node};
+use crate::traits::ActivationFunction;
+
+// ┌──────────────────────────────────────────────────────────┐
+//  Truncated Taylor series arithmetic
+// └──────────────────────────────────────────────────────────┘
+// Every series is a slice of coefficients `[c_0, c_1, ..., c_K]` of `t^k`.
+
+fn series_mul(a: &[f64], b: &[f64]) -> Vec<f64> {
+    (0..a.len())
+        .map(|k| (0..=k).map(|j| a[j] * b[k - j]).sum())
+        .collect()
+}
+
+fn series_div(a: &[f64], b: &[f64]) -> Vec<f64> {
+    let mut h = vec![0f64; a.len()];
+    for k in 0..a.len() {
+        let s: f64 = (0..k).map(|j| h[j] * b[k - j]).sum();
+        h[k] = (a[k] - s) / b[0];
+    }
+    h
+}
+
+fn series_exp(u: &[f64]) -> Vec<f64> {
+    let mut h = vec![0f64; u.len()];
+    h[0] = u[0].exp();
+    for k in 1..u.len() {
+        let s: f64 = (1..=k).map(|j| j as f64 * u[j] * h[k - j]).sum();
+        h[k] = s / k as f64;
+    }
+    h
+}
+
+fn series_ln(u: &[f64]) -> Vec<f64> {
+    let mut h = vec![0f64; u.len()];
+    h[0] = u[0].ln();
+    for k in 1..u.len() {
+        let s: f64 = (1..k).map(|j| j as f64 * h[j] * u[k - j]).sum();
+        h[k] = (u[k] - s / k as f64) / u[0];
+    }
+    h
+}
+
+/// `(sin u, cos u)` or `(sinh u, cosh u)` when `hyperbolic`
+fn series_sin_cos(u: &[f64], hyperbolic: bool) -> (Vec<f64>, Vec<f64>) {
+    let mut s = vec![0f64; u.len()];
+    let mut c = vec![0f64; u.len()];
+    if hyperbolic {
+        s[0] = u[0].sinh();
+        c[0] = u[0].cosh();
+    } else {
+        s[0] = u[0].sin();
+        c[0] = u[0].cos();
+    }
+    let sign = if hyperbolic { 1f64 } else { -1f64 };
+    for k in 1..u.len() {
+        let ds: f64 = (1..=k).map(|j| j as f64 * u[j] * c[k - j]).sum();
+        let dc: f64 = (1..=k).map(|j| j as f64 * u[j] * s[k - j]).sum();
+        s[k] = ds / k as f64;
+        c[k] = sign * dc / k as f64;
+    }
+    (s, c)
+}
+
+/// Series of `h` satisfying `h' = q(h) u'` where `q(h) = a + b h + c h^2`
+fn series_riccati(u: &[f64], h0: f64, (a, b, c): (f64, f64, f64)) -> Vec<f64> {
+    let n = u.len();
+    let mut h = vec![0f64; n];
+    let mut q = vec![0f64; n];
+    h[0] = h0;
+    q[0] = a + b * h0 + c * h0 * h0;
+    for k in 1..n {
+        let s: f64 = (1..=k).map(|j| j as f64 * u[j] * q[k - j]).sum();
+        h[k] = s / k as f64;
+        let hh: f64 = (0..=k).map(|j| h[j] * h[k - j]).sum();
+        q[k] = b * h[k] + c * hh;
+    }
+    h
+}
+
+fn series_powf(u: &[f64], p: f64) -> Vec<f64> {
+    let mut h = vec![0f64; u.len()];
+    h[0] = u[0].powf(p);
+    for k in 1..u.len() {
+        let s: f64 = (1..=k)
+            .map(|j| ((p + 1.0) * j as f64 - k as f64) * u[j] * h[k - j])
+            .sum();
+        h[k] = s / (k as f64 * u[0]);
+    }
+    h
+}
+
+fn series_powi(u: &[f64], n: i32) -> Vec<f64> {
+    // Square-and-multiply keeps the result valid even when u_0 = 0
+    let mut result = vec![0f64; u.len()];
+    result[0] = 1.0;
+    let mut base = u.to_vec();
+    let mut e = n.unsigned_abs();
+    while e > 0 {
+        if e & 1 == 1 {
+            result = series_mul(&result, &base);
+        }
+        base = series_mul(&base, &base);
+        e >>= 1;
+    }
+    if n < 0 {
+        let mut one = vec![0f64; u.len()];
+        one[0] = 1.0;
+        series_div(&one, &result)
+    } else {
+        result
+    }
+}
+
+fn series_scale(u: &[f64], s: f64) -> Vec<f64> {
+    u.iter().map(|x| x * s).collect()
+}
+
+fn series_shift(u: &[f64], s: f64) -> Vec<f64> {
+    let mut h = u.to_vec();
+    h[0] += s;
+    h
+}
+
+// ┌──────────────────────────────────────────────────────────┐
+//  Taylor mode evaluation
+// └──────────────────────────────────────────────────────────┘
+impl Graph<f64> {
+    /// Taylor coefficients `[f_0, ..., f_K]` of the compiled expression along a path
+    ///
+    /// `path[i]` contains the Taylor coefficients of the `i`-th variable, i.e.
+    /// `x_i(t) = path[i][0] + path[i][1] t + path[i][2] t^2 + ...`.
+    /// Missing coefficients are treated as zero. The buffer is not touched.
+    pub fn taylor(&mut self, path: &[Vec<f64>], order: usize) -> Vec<f64> {
+        assert_eq!(self.value_ics.len(), path.len());
+        let order_ics = self.get_topological_order();
+        let n = order + 1;
+        let mut series: Vec<Vec<f64>> = vec![vec![]; self.nodes.len()];
+        for (var, coeffs) in self.value_ics.iter().zip(path) {
+            let mut s = vec![0f64; n];
+            for (c, x) in s.iter_mut().zip(coeffs) {
+                *c = *x;
+            }
+            series[*var] = s;
+        }
+
+        for index in order_ics {
+            let s = match &self.nodes.node(index) {
+                Node::Var(_) => continue,
+                Node::Param(_) => {
+                    let mut s = vec![0f64; n];
+                    s[0] = self.buffer[index].unwrap();
+                    s
+                }
+                Node::Const(value) => {
+                    let mut s = vec![0f64; n];
+                    s[0] = *value;
+                    s
+                }
+                Node::Add(l, r) => series[*l].iter().zip(&series[*r]).map(|(a, b)| a + b).collect(),
+                Node::Sub(l, r) => series[*l].iter().zip(&series[*r]).map(|(a, b)| a - b).collect(),
+                Node::Addf(num, i) => series_shift(&series[*i], *num),
+                Node::Subf(i, num) => series_shift(&series[*i], -*num),
+                Node::Mul(l, r) | Node::Hadamard(l, r) => series_mul(&series[*l], &series[*r]),
+                Node::Select(c, a, b) => {
+                    if series[*c][0].is_sign_positive() {
+                        series[*a].clone()
+                    } else {
+                        series[*b].clone()
+                    }
+                }
+                Node::Fma(a, b, c) => series_mul(&series[*a], &series[*b])
+                    .into_iter()
+                    .zip(&series[*c])
+                    .map(|(x, y)| x + y)
+                    .collect(),
+                Node::Mulf(num, i) => series_scale(&series[*i], *num),
+                Node::Div(l, r) => series_div(&series[*l], &series[*r]),
+                Node::Pow(l, r) => series_exp(&series_mul(&series[*r], &series_ln(&series[*l]))),
+                Node::Powf(i, p) => series_powf(&series[*i], *p),
+                Node::Powi(i, p) => series_powi(&series[*i], *p),
+                Node::Neg(i) => series_scale(&series[*i], -1.0),
+                Node::Transpose(i) | Node::Arg(i) => series[*i].clone(),
+                Node::Recip(i) => {
+                    let mut one = vec![0f64; n];
+                    one[0] = 1.0;
+                    series_div(&one, &series[*i])
+                }
+                Node::Exp(i) => series_exp(&series[*i]),
+                Node::Ln(i) => series_ln(&series[*i]),
+                Node::Sin(i) => series_sin_cos(&series[*i], false).0,
+                Node::Cos(i) => series_sin_cos(&series[*i], false).1,
+                Node::Sinh(i) => series_sin_cos(&series[*i], true).0,
+                Node::Cosh(i) => series_sin_cos(&series[*i], true).1,
+                Node::Tan(i) => {
+                    let u = &series[*i];
+                    series_riccati(u, u[0].tan(), (1.0, 0.0, 1.0))
+                }
+                Node::Tanh(i) => {
+                    let u = &series[*i];
+                    series_riccati(u, u[0].tanh(), (1.0, 0.0, -1.0))
+                }
+                Node::Sigmoid(i) => {
+                    let u = &series[*i];
+                    series_riccati(u, 1.0 / (1.0 + (-u[0]).exp()), (0.0, 1.0, -1.0))
+                }
+                Node::ReLU(i) => {
+                    let h = series[*i][0].heaviside_zero();
+                    series[*i].iter().map(|x| h * x).collect()
+                }
+                Node::Heaviside(i) => {
+                    let mut s = vec![0f64; n];
+                    s[0] = series[*i][0].heaviside_zero();
+                    s
+                }
+                Node::Custom(id, i) => {
+                    assert!(n <= 2, "Custom ops only support first order Taylor coefficients");
+                    let (u, op) = (&series[*i], &self.custom_ops[*id]);
+                    let mut s = vec![op.eval(u[0], 0.0); n];
+                    if n == 2 {
+                        s[1] = op.partials(u[0], 0.0).0 * u[1];
+                    }
+                    s
+                }
+                Node::CustomBinary(id, l, r) => {
+                    assert!(n <= 2, "Custom ops only support first order Taylor coefficients");
+                    let (u, w, op) = (&series[*l], &series[*r], &self.custom_ops[*id]);
+                    let mut s = vec![op.eval(u[0], w[0]); n];
+                    if n == 2 {
+                        let (fu, fw) = op.partials(u[0], w[0]);
+                        s[1] = fu * u[1] + fw * w[1];
+                    }
+                    s
+                }
+                Node::External(id, first, k) => {
+                    assert!(n <= 2, "Custom ops only support first order Taylor coefficients");
+                    let op = &self.custom_ops[*id];
+                    let x = series[*first..*first + *k].iter().map(|u| u[0]).collect::<Vec<_>>();
+                    let mut s = vec![op.eval_n(&x); n];
+                    if n == 2 {
+                        s[1] = (0..*k).map(|j| op.partial_n(&x, j) * series[*first + j][1]).sum();
+                    }
+                    s
+                }
+            };
+            series[index] = s;
+        }
+
+        series[self.compiled.unwrap()].clone()
+    }
+
+    /// Directional derivatives `[f, df/dt, d²f/dt², ..., d^K f/dt^K]` along `x(t) = x + t v`
+    ///
+    /// `x` is the current value of the variables and `v` is the direction.
+    pub fn directional_derivatives(&mut self, direction: &[f64], order: usize) -> Vec<f64> {
+        let path = self
+            .value_ics
+            .iter()
+            .zip(direction)
+            .map(|(var, v)| vec![self.buffer[*var].unwrap(), *v])
+            .collect::<Vec<_>>();
+        let mut factorial = 1f64;
+        self.taylor(&path, order)
+            .into_iter()
+            .enumerate()
+            .map(|(k, c)| {
+                if k > 0 {
+                    factorial *= k as f64;
+                }
+                c * factorial
+            })
+            .collect()
+    }
+}
@@ -1,5 +1,5 @@
-use peroxide::fuga::{Matrix, matrix, FPMatrix};
-use crate::core::Expr;
+use peroxide::fuga::{Matrix, matrix, FPMatrix, Col};
+use crate::core::{rc, Expr};
 
 pub trait Matrizable {
     fn hadamard(&self, rhs: &Self) -> Self;
@@ -7,6 +7,23 @@ pub trait Matrizable {
     fn ones_like(&self) -> Self;
 
     fn zeros_like(&self) -> Self;
+
+    /// `self * b + c` (fused for scalars)
+    fn mul_add(&self, b: &Self, c: &Self) -> Self;
+
+    /// Constant of this type (a `1 x 1` matrix for `Matrix`)
+    fn from_f64(x: f64) -> Self;
+
+    /// Apply a scalar function to every element
+    fn map_elements<F: Fn(f64) -> f64>(&self, f: F) -> Self;
+
+    /// Apply a scalar function to every pair of elements
+    fn zip_elements<F: Fn(f64, f64) -> f64>(&self, rhs: &Self, f: F) -> Self;
+
+    /// Apply a function of all arguments elementwise (arguments share one shape)
+    fn map_many<F: Fn(&[f64]) -> f64>(args: &[&Self], f: F) -> Self
+    where
+        Self: Sized;
 }
 
 impl Matrizable for f64 {
@@ -25,6 +42,26 @@ impl Matrizable for f64 {
     fn zeros_like(&self) -> Self {
         0.0
     }
+
+    fn mul_add(&self, b: &Self, c: &Self) -> Self {
+        f64::mul_add(*self, *b, *c)
+    }
+
+    fn from_f64(x: f64) -> Self {
+        x
+    }
+
+    fn map_elements<F: Fn(f64) -> f64>(&self, f: F) -> Self {
+        f(*self)
+    }
+
+    fn zip_elements<F: Fn(f64, f64) -> f64>(&self, rhs: &Self, f: F) -> Self {
+        f(*self, *rhs)
+    }
+
+    fn map_many<F: Fn(&[f64]) -> f64>(args: &[&Self], f: F) -> Self {
+        f(&args.iter().map(|x| **x).collect::<Vec<_>>())
+    }
 }
 
 impl Matrizable for Matrix {
@@ -43,6 +80,36 @@ impl Matrizable for Matrix {
     fn zeros_like(&self) -> Self {
         matrix(vec![0.0; self.row * self.col], self.row, self.col, self.shape)
     }
+
+    fn mul_add(&self, b: &Self, c: &Self) -> Self {
+        self.clone() * b.clone() + c.clone()
+    }
+
+    fn from_f64(x: f64) -> Self {
+        matrix(vec![x], 1, 1, Col)
+    }
+
+    fn map_elements<F: Fn(f64) -> f64>(&self, f: F) -> Self {
+        self.fmap(f)
+    }
+
+    fn zip_elements<F: Fn(f64, f64) -> f64>(&self, rhs: &Self, f: F) -> Self {
+        self.zip_with(f, rhs)
+    }
+
+    fn map_many<F: Fn(&[f64]) -> f64>(args: &[&Self], f: F) -> Self {
+        let first = args[0];
+        let mut point = vec![0f64; args.len()];
+        let data = (0..first.data.len())
+            .map(|k| {
+                for (x, arg) in point.iter_mut().zip(args) {
+                    *x = arg.data[k];
+                }
+                f(&point)
+            })
+            .collect();
+        matrix(data, first.row, first.col, first.shape)
+    }
 }
 
 pub trait ActivationFunction {
@@ -71,15 +138,15 @@ impl ActivationFunction for f64 {
 
 impl ActivationFunction for Expr {
     fn sigmoid(&self) -> Self {
-        Expr::Sigmoid(Box::new(self.clone()))
+        Expr::Sigmoid(rc(self.clone()))
     }
 
     fn relu(&self) -> Self {
-        Expr::ReLU(Box::new(self.clone()))
+        Expr::ReLU(rc(self.clone()))
     }
 
     fn heaviside_zero(&self) -> Self {
-        unimplemented!()
+        Expr::Heaviside(rc(self.clone()))
     }
 }
 
@@ -0,0 +1,242 @@
+use crate::core::{Expr, Graph};
+use crate::traits::ActivationFunction;
+use peroxide_num::{ExpLogOps, PowOps, TrigOps};
+use std::marker::PhantomData;
+use std::ops::{Add, Div, Mul, Neg, Sub};
+use uom::si::f64::Ratio;
+
+// ┌──────────────────────────────────────────────────────────┐
+//  Unit-aware expressions (uom)
+// └──────────────────────────────────────────────────────────┘
+/// `uom` quantity with `f64` storage, converted through SI base units
+pub trait SiValue {
+    fn to_si(&self) -> f64;
+    fn from_si(value: f64) -> Self;
+}
+
+impl<D, U> SiValue for uom::si::Quantity<D, U, f64>
+where
+    D: uom::si::Dimension + ?Sized,
+    U: uom::si::Units<f64> + ?Sized,
+{
+    fn to_si(&self) -> f64 {
+        self.value
+    }
+
+    fn from_si(value: f64) -> Self {
+        uom::si::Quantity {
+            dimension: PhantomData,
+            units: PhantomData,
+            value,
+        }
+    }
+}
+
+/// Expression carrying the dimension of the `uom` quantity type `Q` (e.g. `Dim<Energy>`)
+///
+/// Sums & differences need equal dimensions and products & quotients get the dimension `uom`
+/// gives to the product or quotient of the quantities, so dimensional errors fail to compile.
+/// Transcendental functions only take dimensionless (`Ratio`) expressions. Values are in SI
+/// base units on the tape.
+pub struct Dim<Q: ?Sized> {
+    expr: Expr,
+    quantity: PhantomData<Q>,
+}
+
+impl<Q: ?Sized> Clone for Dim<Q> {
+    fn clone(&self) -> Self {
+        Dim::from_expr(self.expr.clone())
+    }
+}
+
+impl<Q: ?Sized> std::fmt::Debug for Dim<Q> {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        self.expr.fmt(f)
+    }
+}
+
+impl<Q: ?Sized> Dim<Q> {
+    /// Attach a dimension to an expression in SI base units (unchecked)
+    pub fn from_expr(expr: Expr) -> Self {
+        Dim {
+            expr,
+            quantity: PhantomData,
+        }
+    }
+
+    pub fn expr(&self) -> &Expr {
+        &self.expr
+    }
+
+    pub fn into_expr(self) -> Expr {
+        self.expr
+    }
+
+    pub fn squared(self) -> Dim<<Q as Mul>::Output>
+    where
+        Q: Mul + Sized,
+    {
+        Dim::from_expr(self.expr.clone() * self.expr)
+    }
+}
+
+impl<Q: SiValue> Dim<Q> {
+    pub fn constant(value: Q) -> Self {
+        Dim::from_expr(Expr::Const(value.to_si()))
+    }
+}
+
+impl Dim<Ratio> {
+    pub fn exp(&self) -> Self {
+        Dim::from_expr(self.expr.exp())
+    }
+
+    pub fn ln(&self) -> Self {
+        Dim::from_expr(self.expr.ln())
+    }
+
+    pub fn powf(&self, p: f64) -> Self {
+        Dim::from_expr(self.expr.powf(p))
+    }
+
+    pub fn sin(&self) -> Self {
+        Dim::from_expr(self.expr.sin())
+    }
+
+    pub fn cos(&self) -> Self {
+        Dim::from_expr(self.expr.cos())
+    }
+
+    pub fn tanh(&self) -> Self {
+        Dim::from_expr(self.expr.tanh())
+    }
+
+    pub fn sigmoid(&self) -> Self {
+        Dim::from_expr(self.expr.sigmoid())
+    }
+}
+
+impl<Q: ?Sized> Add for Dim<Q> {
+    type Output = Dim<Q>;
+
+    fn add(self, rhs: Dim<Q>) -> Dim<Q> {
+        Dim::from_expr(self.expr + rhs.expr)
+    }
+}
+
+impl<Q: ?Sized> Sub for Dim<Q> {
+    type Output = Dim<Q>;
+
+    fn sub(self, rhs: Dim<Q>) -> Dim<Q> {
+        Dim::from_expr(self.expr - rhs.expr)
+    }
+}
+
+impl<Q: ?Sized> Neg for Dim<Q> {
+    type Output = Dim<Q>;
+
+    fn neg(self) -> Dim<Q> {
+        Dim::from_expr(-self.expr)
+    }
+}
+
+impl<A: Mul<B>, B> Mul<Dim<B>> for Dim<A> {
+    type Output = Dim<<A as Mul<B>>::Output>;
+
+    fn mul(self, rhs: Dim<B>) -> Self::Output {
+        Dim::from_expr(self.expr * rhs.expr)
+    }
+}
+
+impl<A: Div<B>, B> Div<Dim<B>> for Dim<A> {
+    type Output = Dim<<A as Div<B>>::Output>;
+
+    fn div(self, rhs: Dim<B>) -> Self::Output {
+        Dim::from_expr(self.expr / rhs.expr)
+    }
+}
+
+impl<Q: ?Sized> Mul<f64> for Dim<Q> {
+    type Output = Dim<Q>;
+
+    fn mul(self, rhs: f64) -> Dim<Q> {
+        Dim::from_expr(self.expr * rhs)
+    }
+}
+
+impl<Q: ?Sized> Mul<Dim<Q>> for f64 {
+    type Output = Dim<Q>;
+
+    fn mul(self, rhs: Dim<Q>) -> Dim<Q> {
+        Dim::from_expr(self * rhs.expr)
+    }
+}
+
+impl<Q: ?Sized> Div<f64> for Dim<Q> {
+    type Output = Dim<Q>;
+
+    fn div(self, rhs: f64) -> Dim<Q> {
+        Dim::from_expr(self.expr / rhs)
+    }
+}
+
+/// Variable index of a `Dim` returned by `var_dim`
+fn var_index<Q: ?Sized>(var: &Dim<Q>) -> usize {
+    match var.expr {
+        Expr::Symbol(index) => index,
+        _ => panic!("Not a variable"),
+    }
+}
+
+impl Graph<f64> {
+    /// New variable holding `value`
+    pub fn var_dim<Q: SiValue>(&mut self, value: Q) -> Dim<Q> {
+        Dim::from_expr(Expr::Symbol(self.var(value.to_si())))
+    }
+
+    pub fn subs_var_dim<Q: SiValue>(&mut self, var: &Dim<Q>, value: Q) {
+        self.subs_var(var_index(var), value.to_si());
+    }
+
+    /// Compile a dimensioned expression; the returned handle types `get_value_dim` &
+    /// `get_gradient_dim`
+    #[track_caller]
+    pub fn compile_dim<Y>(&mut self, expr: Dim<Y>) -> Dim<Y> {
+        self.compile(expr.into_expr());
+        Dim::from_expr(Expr::Symbol(self.compiled.unwrap()))
+    }
+
+    /// Value of a compiled output (after `forward`)
+    pub fn get_value_dim<Y: SiValue>(&self, output: &Dim<Y>) -> Y {
+        Y::from_si(self.buffer[var_index(output)].expect("Output is not evaluated"))
+    }
+
+    /// `∂output/∂var` typed as the quotient of their quantities (after `backward` of `output`),
+    /// e.g. a `Force` for an `Energy` output and a `Length` variable
+    pub fn get_gradient_dim<Y, X>(&self, output: &Dim<Y>, var: &Dim<X>) -> <Y as Div<X>>::Output
+    where
+        Y: Div<X>,
+        <Y as Div<X>>::Output: SiValue,
+    {
+        assert_eq!(self.compiled, Some(var_index(output)), "Gradients are of the compiled output");
+        <Y as Div<X>>::Output::from_si(self.get_gradient(var_index(var)))
+    }
+}
+
+/// `gradient` of a dimensioned function of quantities of the same kind
+pub fn gradient_dim<X, Y, F>(f: F, x: &[X]) -> (Y, Vec<<Y as Div<X>>::Output>)
+where
+    X: SiValue,
+    Y: SiValue + Div<X>,
+    <Y as Div<X>>::Output: SiValue,
+    F: Fn(&[Dim<X>]) -> Dim<Y>,
+{
+    let mut graph = Graph::default();
+    let vars = x.iter().map(|x| graph.var_dim(X::from_si(x.to_si()))).collect::<Vec<_>>();
+    let output = graph.compile_dim(f(&vars));
+    graph.forward();
+    graph.backward();
+
+    let grads = vars.iter().map(|x| graph.get_gradient_dim(&output, x)).collect();
+    (graph.get_value_dim(&output), grads)
+}
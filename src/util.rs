@@ -1,7 +1,12 @@
 use crate::core::{Expr, Graph};
+use crate::implicit::ImplicitSystem;
+use peroxide::fuga::{matrix, Matrix, Shape};
 use peroxide_num::Numeric;
+use std::cell::{RefCell, RefMut};
 use std::ops::Div;
 use crate::traits::{ActivationFunction, Matrizable};
+#[cfg(feature = "parallel")]
+use rayon::prelude::*;
 
 pub fn gradient<F: Fn(&[Expr]) -> Expr>(f: F, x: &[f64]) -> (f64, Vec<f64>) {
     let mut graph = Graph::default();
@@ -30,10 +35,368 @@ where
     f64: Div<T, Output = T>,
 {
     g.reset();
-    g.subs_vars(x);
+    g.subs_vars_exact(x);
     let result = g.forward();
     g.backward();
     let grads = g.get_gradients();
 
     (result, grads)
 }
+
+/// Compiled `f` behind closures of plain `f64` slices (see `value_and_grad`)
+///
+/// The graph is compiled on the first call and recompiled only when the number of variables
+/// changes. Implementing the `Fn` traits directly is not possible on stable Rust, so `as_fn` &
+/// `as_value_fn` hand out closures for solvers taking `Fn(&[f64]) -> ...`.
+pub struct GradFn<F> {
+    f: F,
+    graph: RefCell<Option<Graph<f64>>>,
+}
+
+/// `f` as a reusable value & gradient function
+///
+/// E.g. `let rosen = value_and_grad(|x| (1f64 - &x[0]).powi(2) + 100f64 * (x[1].clone() - x[0].powi(2)).powi(2));`
+/// then `rosen.call(&[1.0, 2.0])` or `solver(rosen.as_fn())`.
+pub fn value_and_grad<F: Fn(&[Expr]) -> Expr>(f: F) -> GradFn<F> {
+    GradFn {
+        f,
+        graph: RefCell::new(None),
+    }
+}
+
+impl<F: Fn(&[Expr]) -> Expr> GradFn<F> {
+    /// `(f(x), ∇f(x))`
+    pub fn call(&self, x: &[f64]) -> (f64, Vec<f64>) {
+        let mut graph = self.compiled(x.len());
+        gradient_cached(graph.as_mut().unwrap(), x)
+    }
+
+    /// `f(x)` without the reverse sweep
+    pub fn value(&self, x: &[f64]) -> f64 {
+        let mut graph = self.compiled(x.len());
+        let graph = graph.as_mut().unwrap();
+        graph.reset();
+        graph.subs_vars_exact(x);
+        graph.forward()
+    }
+
+    pub fn grad(&self, x: &[f64]) -> Vec<f64> {
+        self.call(x).1
+    }
+
+    pub fn as_fn(&self) -> impl Fn(&[f64]) -> (f64, Vec<f64>) + '_ {
+        move |x| self.call(x)
+    }
+
+    pub fn as_value_fn(&self) -> impl Fn(&[f64]) -> f64 + '_ {
+        move |x| self.value(x)
+    }
+
+    /// The compiled graph (if `f` has been called)
+    pub fn into_graph(self) -> Option<Graph<f64>> {
+        self.graph.into_inner()
+    }
+
+    fn compiled(&self, n: usize) -> RefMut<'_, Option<Graph<f64>>> {
+        let mut graph = self.graph.borrow_mut();
+        if graph.as_ref().is_none_or(|g| g.get_vars().len() != n) {
+            let mut g = Graph::default();
+            g.touch_vars(n);
+            let symbols = g.get_symbols();
+            g.compile((self.f)(&symbols));
+            *graph = Some(g);
+        }
+        graph
+    }
+}
+
+/// Values & gradients of a compiled graph at many points, evaluated in parallel
+///
+/// Every point is evaluated on a shared `Tape` with its own `Workspace`, so `g` is left untouched.
+#[cfg(feature = "parallel")]
+pub fn gradient_batch<T: std::fmt::Debug + Numeric<f64> + Default + ActivationFunction + Matrizable + Send + Sync>(
+    g: &mut Graph<T>,
+    points: &[Vec<T>],
+) -> Vec<(T, Vec<T>)>
+where
+    f64: Div<T, Output = T>,
+{
+    let tape = g.tape();
+    let workspace = g.workspace();
+    points
+        .par_iter()
+        .map(|x| {
+            let mut ws = workspace.clone();
+            tape.subs_vars(&mut ws, x);
+            let result = tape.forward(&mut ws);
+            tape.backward(&mut ws);
+            (result, tape.get_gradients(&ws))
+        })
+        .collect()
+}
+
+impl Graph<f64> {
+    /// Values & gradients of the compiled expression at every row of `points` (`n_points × n_vars`)
+    ///
+    /// Row `k` of the returned gradient matrix belongs to row `k` of `points`. With `parallel`,
+    /// rows are spread over threads as in `gradient_batch` and the graph is left untouched.
+    pub fn gradients_matrix(&mut self, points: &Matrix) -> (Vec<f64>, Matrix) {
+        let n_vars = self.value_ics.len();
+        assert_eq!(points.col, n_vars, "Points must have one column per variable");
+        let rows = (0..points.row).map(|i| points.row(i)).collect::<Vec<_>>();
+        #[cfg(feature = "parallel")]
+        let results = gradient_batch(self, &rows);
+        #[cfg(not(feature = "parallel"))]
+        let results = rows.iter().map(|x| gradient_cached(self, x)).collect::<Vec<_>>();
+        let (values, grads): (Vec<_>, Vec<_>) = results.into_iter().unzip();
+        (values, matrix(grads.concat(), points.row, n_vars, Shape::Row))
+    }
+
+    /// Stream values & gradients of the compiled expression over `points`
+    ///
+    /// Every point is substituted & swept (as in `gradient_cached`) only when the iterator is
+    /// advanced, so neither the inputs nor the results are materialized.
+    pub fn eval_iter<'a, I>(&'a mut self, points: I) -> impl Iterator<Item = (f64, Vec<f64>)> + 'a
+    where
+        I: IntoIterator<Item = Vec<f64>>,
+        I::IntoIter: 'a,
+    {
+        points.into_iter().map(move |x| gradient_cached(self, &x))
+    }
+}
+
+/// Final state of `state = f(state, x)` threaded over `xs`
+pub fn fold<F: Fn(&Expr, &Expr) -> Expr>(init: Expr, xs: &[Expr], f: F) -> Expr {
+    xs.iter().fold(init, |state, x| f(&state, x))
+}
+
+/// Every intermediate state of `fold` (`result[i]` is the state after `xs[i]`)
+///
+/// E.g. a GARCH(1,1) variance path: `scan(s0, &r2, |s, r| w + a * r + b * s)`.
+pub fn scan<F: Fn(&Expr, &Expr) -> Expr>(init: Expr, xs: &[Expr], f: F) -> Vec<Expr> {
+    let mut state = init;
+    xs.iter()
+        .map(|x| {
+            state = f(&state, x);
+            state.clone()
+        })
+        .collect()
+}
+
+/// Root of `f(x, params) = 0` inside `bracket` as a differentiable node of `graph`
+///
+/// The root is re-solved by safeguarded Newton-bisection whenever `params` change, and its
+/// gradient follows from implicit differentiation `dx*/dp = -(∂f/∂p) / (∂f/∂x)`.
+/// E.g. an implied volatility: `diff_root(&mut g, |s, p| bs_call(s, &p[0]) - &p[1], (1e-4, 5.0), &[spot, price])`.
+pub fn diff_root<F: Fn(&Expr, &[Expr]) -> Expr>(
+    graph: &mut Graph<f64>,
+    f: F,
+    bracket: (f64, f64),
+    params: &[usize],
+) -> usize {
+    assert!(!params.is_empty(), "Root without parameters");
+    let system = ImplicitSystem::bracketed(bracket, params.len(), f);
+    graph.push_implicit(params, system)[0]
+}
+
+/// Nodes & weights of the `n`-point Gauss-Legendre rule on `[-1, 1]`
+///
+/// Exact for polynomials of degree `2n - 1`. Nodes are the roots of `P_n`, polished by Newton's method.
+pub fn gauss_legendre(n: usize) -> (Vec<f64>, Vec<f64>) {
+    assert!(n > 0, "Gauss-Legendre rule needs at least one node");
+    let mut nodes = vec![0f64; n];
+    let mut weights = vec![0f64; n];
+    for i in 0..n.div_ceil(2) {
+        // Chebyshev-like initial guess of the i-th largest root
+        let mut x = (std::f64::consts::PI * (i as f64 + 0.75) / (n as f64 + 0.5)).cos();
+        let mut dp = 0f64;
+        for _ in 0..100 {
+            // Three-term recurrence for P_n(x) & P_{n-1}(x)
+            let (mut p0, mut p1) = (1f64, x);
+            for k in 2..=n {
+                let p2 = ((2 * k - 1) as f64 * x * p1 - (k - 1) as f64 * p0) / k as f64;
+                p0 = p1;
+                p1 = p2;
+            }
+            dp = n as f64 * (x * p1 - p0) / (x * x - 1f64);
+            let dx = p1 / dp;
+            x -= dx;
+            if dx.abs() <= f64::EPSILON {
+                break;
+            }
+        }
+        let w = 2f64 / ((1f64 - x * x) * dp * dp);
+        nodes[i] = x;
+        nodes[n - 1 - i] = -x;
+        weights[i] = w;
+        weights[n - 1 - i] = w;
+    }
+    (nodes, weights)
+}
+
+/// `∫_a^b f(t) dt` by the `n`-point Gauss-Legendre rule, unrolled into an expression
+///
+/// Parameters captured by `f` are differentiated through the rule, and symbolic limits give the
+/// Leibniz terms of the rule (`t = (a + b)/2 + (b - a)/2 ξ` depends on them).
+/// E.g. `diff_integral(|t| (-t * k.clone()).exp(), 0.0, x[0].clone(), 16)`.
+pub fn diff_integral<F: Fn(&Expr) -> Expr, A: Into<Expr>, B: Into<Expr>>(f: F, a: A, b: B, n: usize) -> Expr {
+    let (a, b) = (a.into(), b.into());
+    let half = 0.5 * (b.clone() - a.clone());
+    let mid = 0.5 * (a + b);
+    let (nodes, weights) = gauss_legendre(n);
+    let sum = nodes
+        .iter()
+        .zip(weights)
+        .map(|(x, w)| w * f(&(mid.clone() + *x * half.clone())))
+        .sum::<Expr>();
+    half * sum
+}
+
+/// Rescale `grads` so that its Euclidean norm is at most `max_norm`
+///
+/// Returns the norm before clipping. Non-finite norms leave `grads` untouched.
+pub fn clip_grad_norm(grads: &mut [f64], max_norm: f64) -> f64 {
+    let norm = grads.iter().map(|g| g * g).sum::<f64>().sqrt();
+    if norm.is_finite() && norm > max_norm {
+        let scale = max_norm / norm;
+        grads.iter_mut().for_each(|g| *g *= scale);
+    }
+    norm
+}
+
+/// Clamp every component of `grads` to `[-max, max]`
+pub fn clip_grad_value(grads: &mut [f64], max: f64) {
+    grads.iter_mut().for_each(|g| *g = g.clamp(-max, max));
+}
+
+/// Diagonal of the Hessian of `f` at `x`
+pub fn hessian_diag<F: Fn(&[Expr]) -> Expr>(f: F, x: &[f64]) -> Vec<f64> {
+    let mut graph = Graph::default();
+    graph.touch_vars(x.len());
+    let symbols = graph.get_symbols();
+    graph.compile(f(&symbols));
+    graph.subs_vars_exact(x);
+
+    graph.hessian_diag()
+}
+
+/// Comparison of reverse-mode gradients against a reference: central differences, complex steps
+/// or the symbolic derivative (see `check_gradient`, `check_gradient_cstep` &
+/// `check_gradient_symbolic`)
+#[derive(Debug, Clone)]
+pub struct GradCheckReport {
+    pub value: f64,
+    pub analytic: Vec<f64>,
+    /// Reference gradient
+    pub numeric: Vec<f64>,
+    /// `|analytic - numeric| / max(1, |analytic|, |numeric|)` per component
+    pub rel_errors: Vec<f64>,
+    pub max_rel_error: f64,
+    pub tol: f64,
+}
+
+impl GradCheckReport {
+    pub fn passed(&self) -> bool {
+        self.failures().is_empty()
+    }
+
+    /// Components whose relative error exceeds the tolerance
+    pub fn failures(&self) -> Vec<usize> {
+        self.rel_errors
+            .iter()
+            .enumerate()
+            .filter(|(_, e)| e.is_nan() || **e > self.tol)
+            .map(|(i, _)| i)
+            .collect()
+    }
+}
+
+/// Check the gradient of `f` at `x` against central differences
+///
+/// The step of component `i` is `ε^(1/3) · max(1, |x_i|)`, so agreement to about `1e-6` is the
+/// best one can expect from a smooth `f`.
+pub fn check_gradient<F: Fn(&[Expr]) -> Expr>(f: F, x: &[f64], tol: f64) -> GradCheckReport {
+    let mut graph = Graph::default();
+    graph.touch_vars(x.len());
+    let symbols = graph.get_symbols();
+    graph.compile(f(&symbols));
+    let (value, analytic) = gradient_cached(&mut graph, x);
+
+    let mut point = x.to_vec();
+    let numeric = (0..x.len())
+        .map(|i| {
+            let h = f64::EPSILON.cbrt() * x[i].abs().max(1f64);
+            point[i] = x[i] + h;
+            graph.reset();
+            graph.subs_vars_exact(&point);
+            let upper = graph.forward();
+            point[i] = x[i] - h;
+            graph.reset();
+            graph.subs_vars_exact(&point);
+            let lower = graph.forward();
+            point[i] = x[i];
+            (upper - lower) / (2f64 * h)
+        })
+        .collect::<Vec<_>>();
+
+    grad_check_report(value, analytic, numeric, tol)
+}
+
+/// Check the gradient of `f` at `x` against complex-step derivatives
+///
+/// The reference is accurate to machine precision, so `tol` is set to `1e-10`; inspect
+/// `rel_errors` for a tighter comparison. `f` has to be analytic around `x` (no `ReLU` kinks).
+pub fn check_gradient_cstep<F: Fn(&[Expr]) -> Expr>(f: F, x: &[f64]) -> GradCheckReport {
+    let mut graph = Graph::default();
+    graph.touch_vars(x.len());
+    let symbols = graph.get_symbols();
+    graph.compile(f(&symbols));
+    let (value, analytic) = gradient_cached(&mut graph, x);
+
+    let numeric = (0..x.len())
+        .map(|i| graph.complex_step(i, 1e-30))
+        .collect::<Vec<_>>();
+
+    grad_check_report(value, analytic, numeric, 1e-10)
+}
+
+/// Check the reverse-mode gradient of `f` at `x` against its compiled symbolic derivative
+///
+/// `Expr::diff` and `backward` are independent implementations of the same chain rule, so
+/// agreement is expected up to rounding (`tol` is `1e-10`). Any component listed by `failures`
+/// points at a bug in one of them or in the passes applied at compile time.
+pub fn check_gradient_symbolic<F: Fn(&[Expr]) -> Expr>(f: F, x: &[f64]) -> GradCheckReport {
+    let mut graph = Graph::default();
+    graph.touch_vars(x.len());
+    let symbols = graph.get_symbols();
+    let expr = f(&symbols);
+    graph.compile(expr.clone());
+    let (value, analytic) = gradient_cached(&mut graph, x);
+
+    let mut symbolic = Graph::default();
+    symbolic.touch_vars(x.len());
+    let vars = symbolic.get_vars();
+    symbolic.compile_many(expr.grad(&vars));
+    symbolic.subs_vars_exact(x);
+    let numeric = symbolic.forward_all();
+
+    grad_check_report(value, analytic, numeric, 1e-10)
+}
+
+fn grad_check_report(value: f64, analytic: Vec<f64>, numeric: Vec<f64>, tol: f64) -> GradCheckReport {
+    let rel_errors = analytic
+        .iter()
+        .zip(numeric.iter())
+        .map(|(a, n)| (a - n).abs() / 1f64.max(a.abs()).max(n.abs()))
+        .collect::<Vec<_>>();
+    let max_rel_error = rel_errors.iter().cloned().fold(0f64, f64::max);
+
+    GradCheckReport {
+        value,
+        analytic,
+        numeric,
+        rel_errors,
+        max_rel_error,
+        tol,
+    }
+}